@@ -3,244 +3,352 @@ use std::time::Instant;
 
 use crate::Ray;
 use crate::RenderStats;
-use crate::three_d::Object;
-use crate::three_d::Plane;
+use crate::simd::LeafInterceptFn;
+use crate::simd::LeafTriangles;
+use crate::simd::SimdLevel;
+use crate::simd::get_leaf_intercept;
 use crate::three_d::Triangle;
-use crate::three_d::Triangles;
 use crate::vec3::Float;
 use crate::vec3::Point;
-use crate::vec3::Vec3;
 
-const MAX_NUM_TRIANGLES: usize = 30;
-const MAX_DEPTH: u32 = 8;
+const MAX_LEAF_TRIANGLES: usize = 4;
+pub const MAX_DEPTH: u32 = 48;
+const SAH_NUM_BINS: usize = 12;
+// cost of visiting a node, relative to one triangle test
+const SAH_TRAVERSAL_COST: Float = 1.0;
 
 /*
  * Axis-Aligned Bounding Box
  */
 
-type AABBTriangle = usize;
-
+#[derive(Clone, Copy, Debug)]
 pub struct AABB {
     pub p_min: Point,
     pub p_max: Point,
-    pub is_leaf: bool,
-    pub aabbs: Option<Vec<AABB>>,
-    pub triangles: Vec<AABBTriangle>,
-    triangles_root: Arc<Vec<Triangle>>,
-    triangles_soa: Arc<Triangles>,
+}
+
+fn axis_of(p: Point, axis: usize) -> Float {
+    match axis {
+        0 => p.x,
+        1 => p.y,
+        _ => p.z,
+    }
 }
 
 impl AABB {
-    pub fn new(triangles: Arc<Vec<Triangle>>, triangles_soa: Arc<Triangles>) -> AABB {
+    pub fn empty() -> AABB {
         Self {
-            p_min: Point::zero(),
-            p_max: Point::zero(),
-            is_leaf: false,
-            triangles: vec![],
-            aabbs: None,
-            triangles_root: triangles,
-            triangles_soa,
+            p_min: Point::new(Float::MAX, Float::MAX, Float::MAX),
+            p_max: Point::new(Float::MIN, Float::MIN, Float::MIN),
         }
     }
-    fn init_with_point(p_min: &mut Point, p_max: &mut Point, point: &Point) {
-        p_min.x = p_min.x.min(point.x);
-        p_min.y = p_min.y.min(point.y);
-        p_min.z = p_min.z.min(point.z);
-
-        p_max.x = p_max.x.max(point.x);
-        p_max.y = p_max.y.max(point.y);
-        p_max.z = p_max.z.max(point.z);
+    pub fn from_triangle(triangle: &Triangle) -> AABB {
+        let mut aabb = AABB::empty();
+        triangle.points.iter().for_each(|p| aabb.grow_point(*p));
+        aabb
     }
-    fn init_with_triangle(p_min: &mut Point, p_max: &mut Point, triangle: &Triangle) {
-        triangle.points.iter().for_each(|p| {
-            Self::init_with_point(p_min, p_max, p);
-        });
+    pub fn grow_point(&mut self, point: Point) {
+        self.p_min.x = self.p_min.x.min(point.x);
+        self.p_min.y = self.p_min.y.min(point.y);
+        self.p_min.z = self.p_min.z.min(point.z);
+
+        self.p_max.x = self.p_max.x.max(point.x);
+        self.p_max.y = self.p_max.y.max(point.y);
+        self.p_max.z = self.p_max.z.max(point.z);
     }
-    fn find_bounds(&self, p_min: &mut Point, p_max: &mut Point) {
-        let mut init = false;
-        self.triangles_root.iter().for_each(|triangle| {
-            if !init {
-                *p_min = triangle.points[0];
-                *p_max = triangle.points[0];
-                init = true;
-            }
-            Self::init_with_triangle(p_min, p_max, triangle);
-        });
+    pub fn grow(&mut self, other: &AABB) {
+        self.p_min.x = self.p_min.x.min(other.p_min.x);
+        self.p_min.y = self.p_min.y.min(other.p_min.y);
+        self.p_min.z = self.p_min.z.min(other.p_min.z);
+
+        self.p_max.x = self.p_max.x.max(other.p_max.x);
+        self.p_max.y = self.p_max.y.max(other.p_max.y);
+        self.p_max.z = self.p_max.z.max(other.p_max.z);
     }
-    fn point_inside(&self, p: Point) -> bool {
-        p.x >= self.p_min.x
-            && p.x <= self.p_max.x
-            && p.y >= self.p_min.y
-            && p.y <= self.p_max.y
-            && p.z >= self.p_min.z
-            && p.z <= self.p_max.z
+    pub fn get_center(&self) -> Point {
+        (self.p_min + self.p_max) / 2.0
     }
-    fn triangle_inside(&self, t: &Triangle) -> bool {
-        if self.point_inside(t.points[0])
-            || self.point_inside(t.points[1])
-            || self.point_inside(t.points[2])
-        {
-            return true;
-        }
-        let ray0 = Ray::new(t.points[0], t.points[1] - t.points[0]);
-        let ray1 = Ray::new(t.points[1], t.points[2] - t.points[1]);
-        let ray2 = Ray::new(t.points[2], t.points[0] - t.points[2]);
-        /*
-         * XXX: not correct if the AABB doesn't touch an edge!!
-         */
-        let mut t0 = 0.0;
-        self.check_intersect(&ray0, 1.0, &mut t0)
-            || self.check_intersect(&ray1, 1.0, &mut t0)
-            || self.check_intersect(&ray2, 1.0, &mut t0)
+    pub fn contains_point(&self, p: Point) -> bool {
+        (self.p_min.x..=self.p_max.x).contains(&p.x)
+            && (self.p_min.y..=self.p_max.y).contains(&p.y)
+            && (self.p_min.z..=self.p_max.z).contains(&p.z)
     }
-    fn setup_node(&mut self, p_min: Point, p_max: Point, triangles: &[AABBTriangle], depth: u32) {
-        self.p_min = p_min;
-        self.p_max = p_max;
-
-        let mut v_triangles = vec![];
-        if triangles.is_empty() {
-            self.triangles_root
-                .iter()
-                .filter(|t| self.triangle_inside(t))
-                .for_each(|t| v_triangles.push(t.mesh_id));
-        } else {
-            triangles
-                .iter()
-                .filter(|&&tid| self.triangle_inside(&self.triangles_root[tid]))
-                .for_each(|tid| v_triangles.push(*tid));
+    pub fn contains(&self, other: &AABB) -> bool {
+        self.contains_point(other.p_min) && self.contains_point(other.p_max)
+    }
+    pub fn is_flat(&self) -> bool {
+        let d = self.p_max - self.p_min;
+        d.x <= 0.0 || d.y <= 0.0 || d.z <= 0.0
+    }
+    pub fn get_surface_area(&self) -> Float {
+        let d = self.p_max - self.p_min;
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
         }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /*
+     * Slab test: t where the ray enters and leaves the box, the box is
+     * missed when the first is past the second.
+     * https://tavianator.com/cgit/dimension.git/tree/libdimension/bvh/bvh.c#n194
+     */
+    #[inline]
+    pub fn get_slab_range(&self, ray: &Ray) -> (Float, Float) {
+        let tx1 = (self.p_min.x - ray.orig.x) * ray.inv_dir.x;
+        let tx2 = (self.p_max.x - ray.orig.x) * ray.inv_dir.x;
+
+        let ty1 = (self.p_min.y - ray.orig.y) * ray.inv_dir.y;
+        let ty2 = (self.p_max.y - ray.orig.y) * ray.inv_dir.y;
+
+        let tz1 = (self.p_min.z - ray.orig.z) * ray.inv_dir.z;
+        let tz2 = (self.p_max.z - ray.orig.z) * ray.inv_dir.z;
+
+        let tx_min = tx1.min(tx2);
+        let tx_max = tx1.max(tx2);
+
+        let ty_min = ty1.min(ty2);
+        let ty_max = ty1.max(ty2);
 
-        if depth >= MAX_DEPTH || v_triangles.len() < MAX_NUM_TRIANGLES {
-            self.is_leaf = true;
-            self.triangles = v_triangles;
-            return;
+        let tz_min = tz1.min(tz2);
+        let tz_max = tz1.max(tz2);
+
+        let t_min = tx_min.max(ty_min).max(tz_min);
+        let t_max = tx_max.min(ty_max).min(tz_max);
+        (t_min, t_max)
+    }
+
+    pub fn check_intersect(&self, ray: &Ray, tmax: Float, t: &mut Float) -> bool {
+        let (t_min, t_max) = self.get_slab_range(ray);
+
+        if t_max >= t_min.max(0.0) && t_min < tmax {
+            *t = t_min;
+            return true;
         }
-        /*
-         *      +---+---+
-         *     / 6 / 7 /|
-         *    +---+---+ +
-         *   / 4 / 5 / /
-         *  +---+---+ +
-         *  |   |   |/
-         *  +---+---+
-         *
-         *      +---+---+    ^ z  ^ y
-         *     / 2 / 3 /|    |   /
-         *    +---+---+ +    |  /
-         *   / 0 / 1 / /     | /
-         *  +---+---+ +      |/
-         *  |   |   |/       +---------> x
-         *  +---+---+
-         * orig
-         */
-        let inc = (p_max - p_min) / 2.0;
-        assert!(inc.x != 0.0 && inc.y != 0.0 && inc.z != 0.0);
-        let hx = Vec3 {
-            x: inc.x,
-            y: 0.0,
-            z: 0.0,
-        };
-        let hy = Vec3 {
-            x: 0.0,
-            y: inc.y,
-            z: 0.0,
-        };
-        let hz = Vec3 {
-            x: 0.0,
-            y: 0.0,
-            z: inc.z,
-        };
+        false
+    }
+}
 
-        let mut v_min = [Point::zero(); 8];
-        let mut v_max = [Point::zero(); 8];
-
-        v_min[0] = p_min;
-        v_max[0] = p_min + inc;
-        v_min[1] = p_min + hx;
-        v_max[1] = p_min + hx + inc;
-        v_min[2] = p_min + hy;
-        v_max[2] = p_min + hy + inc;
-        v_min[3] = p_min + hx + hy;
-        v_max[3] = p_min + hx + hy + inc;
-
-        for i in 0..4 {
-            v_min[4 + i] = v_min[i] + hz;
-            v_max[4 + i] = v_max[i] + hz;
+/*
+ * Bounding Volume Hierarchy over the triangles of a mesh, built with the
+ * surface area heuristic.
+ *
+ * Nodes are stored depth-first in a flat array: the first child of an inner
+ * node immediately follows it, `offset` is the index of the second one.
+ * For a leaf, `offset` is the start of its `count` triangles in
+ * `Bvh::triangles`, and in `leaf_triangles` which packs them in the same
+ * order for the intersection kernel picked by SimdLevel::detect().
+ */
+
+#[derive(Clone, Copy, Debug)]
+pub struct BvhNode {
+    pub aabb: AABB,
+    pub offset: usize,
+    pub count: usize,
+    pub axis: usize,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SahBin {
+    aabb: AABB,
+    count: usize,
+}
+
+/*
+ * The nodes over the items of bounds, and the order of the items that makes
+ * each leaf a contiguous range: shared by the mesh triangles and the
+ * objects of the scene.
+ */
+pub fn build_nodes(bounds: &[AABB]) -> (Vec<BvhNode>, Vec<usize>) {
+    let centroids: Vec<Point> = bounds.iter().map(|b| b.get_center()).collect();
+    let mut items: Vec<usize> = (0..bounds.len()).collect();
+    let mut nodes = Vec::with_capacity(2 * bounds.len() / MAX_LEAF_TRIANGLES + 1);
+    if !items.is_empty() {
+        setup_node(&mut nodes, bounds, &centroids, &mut items, 0, 0);
+    }
+    (nodes, items)
+}
+
+pub fn get_depth(nodes: &[BvhNode], idx: usize) -> u32 {
+    match nodes.get(idx) {
+        None => 0,
+        Some(node) if node.is_leaf() => 0,
+        Some(node) => 1 + get_depth(nodes, idx + 1).max(get_depth(nodes, node.offset)),
+    }
+}
+
+/*
+ * Builds the node for triangles[start..] and its children, with binned
+ * SAH over the triangle centroids.
+ */
+fn setup_node(
+    nodes: &mut Vec<BvhNode>,
+    bounds: &[AABB],
+    centroids: &[Point],
+    triangles: &mut [usize],
+    start: usize,
+    depth: u32,
+) {
+    let mut aabb = AABB::empty();
+    let mut centroid_aabb = AABB::empty();
+    for &tid in triangles.iter() {
+        aabb.grow(&bounds[tid]);
+        centroid_aabb.grow_point(centroids[tid]);
+    }
+    let idx = nodes.len();
+    nodes.push(BvhNode {
+        aabb,
+        offset: start,
+        count: triangles.len(),
+        axis: 0,
+    });
+    if triangles.len() <= MAX_LEAF_TRIANGLES || depth >= MAX_DEPTH {
+        return;
+    }
+
+    let mut best_cost = Float::MAX;
+    let mut best_axis = 0;
+    let mut best_split = 0;
+    for axis in 0..3 {
+        let c_min = axis_of(centroid_aabb.p_min, axis);
+        let extent = axis_of(centroid_aabb.p_max, axis) - c_min;
+        if extent <= 0.0 {
+            continue;
+        }
+        let mut bins = [SahBin {
+            aabb: AABB::empty(),
+            count: 0,
+        }; SAH_NUM_BINS];
+        for &tid in triangles.iter() {
+            let b = get_bin(axis_of(centroids[tid], axis), c_min, extent);
+            bins[b].aabb.grow(&bounds[tid]);
+            bins[b].count += 1;
+        }
+        // area and count on the right of each split, accumulated backwards
+        let mut right = [(0.0, 0); SAH_NUM_BINS];
+        let mut acc = AABB::empty();
+        let mut count = 0;
+        for i in (1..SAH_NUM_BINS).rev() {
+            acc.grow(&bins[i].aabb);
+            count += bins[i].count;
+            right[i] = (acc.get_surface_area(), count);
         }
-        self.is_leaf = false;
-        self.aabbs = Some(Vec::with_capacity(8));
-        for i in 0..8 {
-            let mut aabb = AABB::new(self.triangles_root.clone(), self.triangles_soa.clone());
-            aabb.setup_node(v_min[i], v_max[i], &v_triangles, depth + 1);
-            self.aabbs.as_mut().unwrap().push(aabb);
+        let mut acc = AABB::empty();
+        let mut count = 0;
+        for split in 1..SAH_NUM_BINS {
+            acc.grow(&bins[split - 1].aabb);
+            count += bins[split - 1].count;
+            let (right_area, right_count) = right[split];
+            if count == 0 || right_count == 0 {
+                continue;
+            }
+            let cost = acc.get_surface_area() * count as Float + right_area * right_count as Float;
+            if cost < best_cost {
+                best_cost = cost;
+                best_axis = axis;
+                best_split = split;
+            }
         }
     }
-    fn count_leaves(&self) -> u32 {
-        if self.is_leaf {
-            return 1;
+    if best_split == 0 {
+        // all the centroids are at the same point
+        return;
+    }
+    let leaf_cost = triangles.len() as Float;
+    let area = aabb.get_surface_area().max(Float::MIN_POSITIVE);
+    let split_cost = SAH_TRAVERSAL_COST + best_cost / area;
+    if split_cost >= leaf_cost {
+        return;
+    }
+
+    let c_min = axis_of(centroid_aabb.p_min, best_axis);
+    let extent = axis_of(centroid_aabb.p_max, best_axis) - c_min;
+    let mut mid = 0;
+    for i in 0..triangles.len() {
+        let c = axis_of(centroids[triangles[i]], best_axis);
+        if get_bin(c, c_min, extent) < best_split {
+            triangles.swap(i, mid);
+            mid += 1;
         }
-        self.aabbs
-            .as_ref()
-            .unwrap()
-            .iter()
-            .map(|v| v.count_leaves())
-            .sum()
     }
-    fn get_depth(&self) -> u32 {
-        if self.is_leaf {
-            return 0;
+    let (left, right) = triangles.split_at_mut(mid);
+    setup_node(nodes, bounds, centroids, left, start, depth + 1);
+    let second = nodes.len();
+    setup_node(nodes, bounds, centroids, right, start + mid, depth + 1);
+
+    let node = &mut nodes[idx];
+    node.offset = second;
+    node.count = 0;
+    node.axis = best_axis;
+}
+fn get_bin(c: Float, c_min: Float, extent: Float) -> usize {
+    let b = ((c - c_min) / extent * SAH_NUM_BINS as Float) as usize;
+    b.min(SAH_NUM_BINS - 1)
+}
+
+pub struct Bvh {
+    pub nodes: Vec<BvhNode>,
+    pub triangles: Vec<usize>,
+    triangles_root: Arc<Vec<Triangle>>,
+    leaf_triangles: LeafTriangles,
+    simd_level: SimdLevel,
+    leaf_intercept: LeafInterceptFn,
+}
+
+impl Bvh {
+    pub fn new(triangles: Arc<Vec<Triangle>>) -> Bvh {
+        let simd_level = SimdLevel::detect();
+        Self {
+            nodes: vec![],
+            triangles: vec![],
+            triangles_root: triangles,
+            leaf_triangles: LeafTriangles::default(),
+            simd_level,
+            leaf_intercept: get_leaf_intercept(simd_level),
         }
-        1 + self
-            .aabbs
-            .as_ref()
-            .unwrap()
-            .iter()
-            .map(|v| v.get_depth())
-            .max()
-            .unwrap()
+    }
+    pub fn get_simd_level(&self) -> SimdLevel {
+        self.simd_level
+    }
+    // falls back to the scalar kernel when the CPU lacks the level
+    pub fn set_simd_level(&mut self, level: SimdLevel) {
+        self.simd_level = if level.is_available() {
+            level
+        } else {
+            SimdLevel::Scalar
+        };
+        self.leaf_intercept = get_leaf_intercept(self.simd_level);
     }
     pub fn init(&mut self) {
-        let mut p_min = Vec3::zero();
-        let mut p_max = Vec3::zero();
-        self.find_bounds(&mut p_min, &mut p_max);
-
         let start_time = Instant::now();
-        self.setup_node(p_min, p_max, &[], 0);
-        let elapsed = start_time.elapsed();
 
+        let bounds: Vec<AABB> = self
+            .triangles_root
+            .iter()
+            .map(AABB::from_triangle)
+            .collect();
+        let (nodes, triangles) = build_nodes(&bounds);
+        self.nodes = nodes;
+        self.leaf_triangles = LeafTriangles::new(&self.triangles_root, &triangles);
+        self.triangles = triangles;
+
+        let elapsed = start_time.elapsed();
         if elapsed.as_millis() as Float > 0.1 {
             println!(
-                "-- aabb: depth: {}/{} num_leaves={} max_num_triangles={} -- {:.2} sec",
-                self.get_depth(),
-                MAX_DEPTH,
-                self.count_leaves(),
-                MAX_NUM_TRIANGLES,
+                "-- bvh: depth: {} num_nodes={} num_leaves={} -- {:.2} sec",
+                get_depth(&self.nodes, 0),
+                self.nodes.len(),
+                self.nodes.iter().filter(|n| n.is_leaf()).count(),
                 elapsed.as_millis() as Float / 1000.0
             );
         }
-        //println!("-- aabb: p_min: {:?}", p_min);
-        //println!("-- aabb: p_max: {:?}", p_max);
     }
-
-    fn nearest_node(&self, p: Point, mid: Point) -> usize {
-        let op = p - mid;
-        let x_test = op.x.is_sign_positive();
-        let y_test = op.y.is_sign_positive();
-        let z_test = op.z.is_sign_positive();
-
-        let mut v = 0;
-        if x_test {
-            v = 1 << 0;
-        }
-        if y_test {
-            v += 1 << 1;
-        }
-        if z_test {
-            v += 1 << 2;
-        }
-        v
-    }
-
     pub fn intercept(
         &self,
         stats: &mut RenderStats,
@@ -250,123 +358,58 @@ impl AABB {
         any: bool,
         oid: &mut usize,
     ) -> bool {
-        let mut t_aabb = *tmax;
-
-        if self.is_leaf && self.triangles.is_empty() {
-            return false;
-        }
-        stats.num_intersects_aabb += 1;
-        if !self.check_intersect(ray, *tmax, &mut t_aabb) {
+        if self.nodes.is_empty() {
             return false;
         }
+        let dir_is_neg = [ray.dir.x < 0.0, ray.dir.y < 0.0, ray.dir.z < 0.0];
+        let mut stack = [0usize; MAX_DEPTH as usize + 2];
+        let mut num_stack = 1;
+        let mut hit = false;
 
-        /*
-         * If any interception exists and it's closer to the entry point into
-         * this node, we're done.
-         */
-        if t_aabb < tmin {
-            return false;
-        }
+        while num_stack > 0 {
+            num_stack -= 1;
+            let idx = stack[num_stack];
+            let node = &self.nodes[idx];
 
-        let mut oid0 = 0;
-        let mut hit = false;
+            let mut t_aabb = *tmax;
+            stats.count(|s| s.num_intersects_aabb += 1);
+            if !node.aabb.check_intersect(ray, *tmax, &mut t_aabb) {
+                continue;
+            }
 
-        if self.is_leaf {
-            for triangle_id in &self.triangles {
-                let t = self.triangles_soa.get_triangle(*triangle_id);
-                if t.intercept(stats, ray, tmin, tmax, any, &mut oid0) {
+            if node.is_leaf() {
+                stats.count(|s| s.num_intersects_triangle += node.count as u64);
+                let leaf_hit = (self.leaf_intercept)(
+                    &self.leaf_triangles,
+                    ray,
+                    node.offset,
+                    node.count,
+                    tmin,
+                    tmax,
+                    any,
+                );
+                if let Some(idx) = leaf_hit {
                     hit = true;
-                    *oid = *triangle_id;
+                    *oid = self.triangles[idx];
                     if any {
-                        break;
+                        return true;
                     }
                 }
+                continue;
             }
-            return hit;
-        } else {
-            let mid = (self.p_max + self.p_min) / 2.0;
-            let plane_yz = Plane::new(mid, Vec3::unity_x(), 0);
-            let plane_xz = Plane::new(mid, Vec3::unity_y(), 0);
-            let plane_xy = Plane::new(mid, Vec3::unity_z(), 0);
-            let mut close_idx = self.nearest_node(ray.orig + ray.dir * t_aabb, mid);
-            let mut tmin0 = tmin;
-
-            for _i in 0..4 {
-                if self.aabbs.as_ref().unwrap()[close_idx]
-                    .intercept(stats, ray, tmin, tmax, any, oid)
-                {
-                    return true;
-                }
-
-                let mut t_yz = Float::MAX;
-                let mut t_xz = t_yz;
-                let mut t_xy = t_yz;
-                let mut p = [false; 3];
-
-                p[0] = plane_yz.intercept(stats, ray, tmin0, &mut t_yz, false, &mut oid0);
-                p[1] = plane_xz.intercept(stats, ray, tmin0, &mut t_xz, false, &mut oid0);
-                p[2] = plane_xy.intercept(stats, ray, tmin0, &mut t_xy, false, &mut oid0);
-
-                p[0] = p[0] && t_yz > t_aabb;
-                p[1] = p[1] && t_xz > t_aabb;
-                p[2] = p[2] && t_xy > t_aabb;
-
-                // if the intersection is before the aabb, discard
-                if t_yz <= t_aabb {
-                    t_yz = Float::MAX;
-                }
-                if t_xy <= t_aabb {
-                    t_xy = Float::MAX;
-                }
-                if t_xz <= t_aabb {
-                    t_xz = Float::MAX;
-                }
-
-                p[0] = p[0] && t_yz <= t_xz && t_yz <= t_xy;
-                p[1] = p[1] && t_xz <= t_yz && t_xz <= t_xy;
-                p[2] = p[2] && t_xy <= t_xz && t_xy <= t_yz;
-
-                if !p.iter().any(|&x| x) {
-                    break;
-                }
-
-                tmin0 = t_yz.min(t_xy).min(t_xz);
-                close_idx ^= 1 << p.iter().position(|&x| x).unwrap();
-            }
+            /*
+             * Visit first the child on the side the ray comes from: its hits
+             * shrink tmax and let the far child be culled.
+             */
+            let (near, far) = if dir_is_neg[node.axis] {
+                (node.offset, idx + 1)
+            } else {
+                (idx + 1, node.offset)
+            };
+            stack[num_stack] = far;
+            stack[num_stack + 1] = near;
+            num_stack += 2;
         }
         hit
     }
-
-    // https://tavianator.com/cgit/dimension.git/tree/libdimension/bvh/bvh.c#n194
-    fn check_intersect(&self, ray: &Ray, tmax: Float, t: &mut Float) -> bool {
-        let tx1 = (self.p_min.x - ray.orig.x) * ray.inv_dir.x;
-        let tx2 = (self.p_max.x - ray.orig.x) * ray.inv_dir.x;
-
-        let ty1 = (self.p_min.y - ray.orig.y) * ray.inv_dir.y;
-        let ty2 = (self.p_max.y - ray.orig.y) * ray.inv_dir.y;
-
-        let tz1 = (self.p_min.z - ray.orig.z) * ray.inv_dir.z;
-        let tz2 = (self.p_max.z - ray.orig.z) * ray.inv_dir.z;
-
-        let tx_min = tx1.min(tx2);
-        let tx_max = tx1.max(tx2);
-
-        let ty_min = ty1.min(ty2);
-        let ty_max = ty1.max(ty2);
-
-        let tz_min = tz1.min(tz2);
-        let tz_max = tz1.max(tz2);
-
-        let mut t_min = tx_min.max(ty_min);
-        let mut t_max = tx_max.min(ty_max);
-
-        t_min = t_min.max(tz_min);
-        t_max = t_max.min(tz_max);
-
-        if t_max >= t_min.max(0.0) && t_min < tmax {
-            *t = t_min;
-            return true;
-        }
-        false
-    }
 }
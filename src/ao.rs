@@ -0,0 +1,145 @@
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::Ray;
+use crate::RenderStats;
+use crate::three_d::Mesh;
+use crate::three_d::Object;
+use crate::vec3::EPSILON;
+use crate::vec3::Float;
+use crate::vec3::Point;
+use crate::vec3::Vec3;
+
+/*
+ * Ambient occlusion baked at the vertices of a mesh: the fraction of a
+ * cosine-weighted hemisphere around the vertex normal that is not blocked
+ * within a short distance. It is interpolated over the faces like smooth
+ * normals and darkens the ambient light, so that renders of a static mesh
+ * get contact shadows without tracing any extra ray.
+ */
+
+// rays per vertex by default
+pub const AO_NUM_SAMPLES: usize = 64;
+// occlusion radius, relative to the diagonal of the mesh bounds
+pub const AO_DISTANCE_RATIO: Float = 0.1;
+
+/*
+ * The last byte is the version of the cache, bumped whenever the baked
+ * values change. 2: the "RFAO" caches were baked with the skewed
+ * directions of gen_rnd_sphere before its fix.
+ */
+const AO_CACHE_MAGIC: &[u8; 4] = b"RFA2";
+
+/*
+ * The cache sits next to the scene, one file per mesh, since the occlusion
+ * depends on the rest of the scene and on the placement of the mesh.
+ */
+pub fn get_ao_cache_path(scene_file: &Path, mesh_idx: u32) -> PathBuf {
+    scene_file.with_extension(format!("mesh.{mesh_idx}.ao"))
+}
+
+pub fn get_ao_distance(positions: &[Point]) -> Float {
+    let mut p_min = Point::new(Float::MAX, Float::MAX, Float::MAX);
+    let mut p_max = Point::new(Float::MIN, Float::MIN, Float::MIN);
+    for p in positions {
+        p_min = Point::new(p_min.x.min(p.x), p_min.y.min(p.y), p_min.z.min(p.z));
+        p_max = Point::new(p_max.x.max(p.x), p_max.y.max(p.y), p_max.z.max(p.z));
+    }
+    if positions.is_empty() {
+        return 0.0;
+    }
+    (p_max - p_min).norm() * AO_DISTANCE_RATIO
+}
+
+/*
+ * Occlusion of each vertex by the mesh itself and by the other objects.
+ * The random sequence of a vertex only depends on its index, so that a
+ * bake is reproducible.
+ */
+pub fn bake_vertex_ao(
+    objects: &[Arc<dyn Object + 'static + Send + Sync>],
+    mesh: &Mesh,
+    positions: &[Point],
+    normals: &[Vec3],
+    distance: Float,
+    num_samples: usize,
+) -> Vec<Float> {
+    positions
+        .par_iter()
+        .zip(normals.par_iter())
+        .enumerate()
+        .map(|(idx, (&point, &normal))| {
+            if normal.norm() < EPSILON {
+                return 1.0;
+            }
+            let normal = normal.normalize();
+            let mut stats = RenderStats::default();
+            let mut rnd_state = idx as u64;
+            let mut num_open = 0;
+            for _ in 0..num_samples {
+                let dir = normal + Vec3::gen_rnd_sphere(&mut rnd_state);
+                if dir.norm() < EPSILON {
+                    num_open += 1;
+                    continue;
+                }
+                let dir = dir.normalize();
+                let ray = Ray::new(Ray::offset_origin(point, normal, dir), dir);
+                let mut oid = 0;
+                let occluded = std::iter::once(mesh as &dyn Object)
+                    .chain(objects.iter().map(|obj| obj.as_ref() as &dyn Object))
+                    .any(|obj| {
+                        let mut tmax = distance;
+                        obj.intercept(&mut stats, &ray, EPSILON, &mut tmax, true, &mut oid)
+                    });
+                if !occluded {
+                    num_open += 1;
+                }
+            }
+            num_open as Float / num_samples as Float
+        })
+        .collect()
+}
+
+// little endian: magic, number of values as u64, then the values
+pub fn save_ao_cache(path: &Path, ao: &[Float]) -> std::io::Result<()> {
+    let mut data = Vec::with_capacity(12 + size_of_val(ao));
+    data.extend_from_slice(AO_CACHE_MAGIC);
+    data.extend_from_slice(&(ao.len() as u64).to_le_bytes());
+    for v in ao {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    fs::write(path, data)
+}
+
+pub fn load_ao_cache(path: &Path) -> std::io::Result<Vec<Float>> {
+    let data = fs::read(path)?;
+    let invalid = || {
+        let msg = format!("{}: bad AO cache", path.display());
+        Error::new(ErrorKind::InvalidData, msg)
+    };
+    if data.len() < 12 || data[0..3] != AO_CACHE_MAGIC[0..3] {
+        return Err(invalid());
+    }
+    if data[3] != AO_CACHE_MAGIC[3] {
+        let msg = format!(
+            "{}: AO cache of another version, run --bake-ao again",
+            path.display()
+        );
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    let n = u64::from_le_bytes(data[4..12].try_into().unwrap()) as usize;
+    let size = size_of::<Float>();
+    if data.len() != 12 + size * n {
+        return Err(invalid());
+    }
+    Ok(data[12..]
+        .chunks_exact(size)
+        .map(|b| Float::from_le_bytes(b.try_into().unwrap()))
+        .collect())
+}
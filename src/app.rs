@@ -2,104 +2,329 @@ use egui::Color32;
 use egui::ColorImage;
 use egui::TextureHandle;
 use egui::load::SizedTexture;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
+use crate::ProgressEta;
+use crate::camera::CameraKeyframe;
+use crate::image::HISTOGRAM_MAX_EV;
+use crate::image::HISTOGRAM_MIN_EV;
+use crate::image::Histogram;
+use crate::image::ToneMap;
+use crate::image::luminance_histogram;
+use crate::render::BounceConfig;
+use crate::render::Integrator;
+use crate::render::PROGRESSIVE_PASSES;
 use crate::render::RenderConfig;
 use crate::scene::load_scene;
 
+use colored::Colorize;
 use log::info;
+use serde::{Deserialize, Serialize};
 
 const WIDTH: usize = 600;
 const HEIGHT: usize = 600;
 const SIDE_PANEL_WIDTH: usize = 250;
+const HISTOGRAM_BINS: usize = 56; // 4 bins per stop
+const HISTOGRAM_HEIGHT: f32 = 60.0;
+// coalesce settings changes (eg. dragging a slider) into a single render
+const RENDER_DEBOUNCE: Duration = Duration::from_millis(300);
+// radians per width or height of the image dragged across
+const ORBIT_SPEED: f32 = std::f32::consts::PI;
+// dolly factor of exp(-DOLLY_SPEED) per point scrolled
+const DOLLY_SPEED: f32 = 0.002;
+// the choices of the scene picker, in scenes/
+const SCENES: [&str; 8] = [
+    "cornell-box",
+    "trolley",
+    "cow",
+    "teapot",
+    "buddha",
+    "sphere-box",
+    "sphere-nobox",
+    "test",
+];
 
-pub struct RayflexApp {
+/*
+ * What the side panel sets, saved to settings_path() on every change and
+ * loaded back on launch. Keys missing from the file keep their defaults.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
     scene_file: String,
     output_file: String,
     height: usize,
     width: usize,
     use_antialias: bool,
     use_gamma: bool,
+    tonemap: ToneMap,
+    use_smooth_normals: bool,
     do_path_tracing: bool,
+    use_progressive: bool,
     path_level: u32,
-    progress: Arc<Mutex<f32>>,
-    texture_handle: Option<TextureHandle>,
-    rendering_active: Arc<AtomicBool>,
-    rendering_needs_stop: Arc<AtomicBool>,
-    scene_choice: usize,
+    max_bounces: u32,
+    firefly_clamp: f32, // 0: off
 }
 
-impl Default for RayflexApp {
+impl Default for AppSettings {
     fn default() -> Self {
         Self {
             scene_file: "scenes/cornell-box.json".to_owned(),
             output_file: "pic.png".to_owned(),
-            progress: Arc::new(Mutex::new(0.0)),
+            height: HEIGHT,
+            width: WIDTH,
             use_antialias: false,
             use_gamma: true,
-            width: WIDTH,
-            height: HEIGHT,
+            tonemap: ToneMap::Clamp,
+            use_smooth_normals: false,
             do_path_tracing: true,
+            use_progressive: true,
             path_level: 200,
+            max_bounces: 5,
+            firefly_clamp: 0.0,
+        }
+    }
+}
+
+impl AppSettings {
+    pub fn load(file: &Path) -> std::io::Result<Self> {
+        let s = std::fs::read_to_string(file)?;
+        serde_json::from_str(&s).map_err(std::io::Error::other)
+    }
+    pub fn save(&self, file: &Path) -> std::io::Result<()> {
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let s = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(file, s)
+    }
+}
+
+// $XDG_CONFIG_HOME/rayflex/settings.json, else under ~/.config
+pub fn settings_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("rayflex").join("settings.json"))
+}
+
+// the share of the render done, and its times once it started
+#[derive(Default, Clone, Copy)]
+struct RenderProgress {
+    fraction: f32,
+    eta: Option<ProgressEta>,
+}
+
+pub struct RayflexApp {
+    settings: AppSettings,
+    // as last written to settings_path()
+    saved_settings: AppSettings,
+    progress: Arc<Mutex<RenderProgress>>,
+    histogram: Arc<Mutex<Option<Histogram>>>,
+    // why the last job failed, shown under the Start button
+    error: Arc<Mutex<Option<String>>>,
+    texture_handle: Option<TextureHandle>,
+    rendering_active: Arc<AtomicBool>,
+    rendering_needs_stop: Arc<AtomicBool>,
+    scene_choice: usize,
+    // settings of the last job started or queued: None until the first render
+    last_settings: Option<AppSettings>,
+    // moved around with the mouse, None for the camera of the scene
+    view: Option<CameraKeyframe>,
+    // the viewpoint of the last job, where a move starts from
+    scene_view: Arc<Mutex<Option<CameraKeyframe>>>,
+    queued_at: Option<Instant>,
+}
+
+impl Default for RayflexApp {
+    fn default() -> Self {
+        Self {
+            settings: AppSettings::default(),
+            saved_settings: AppSettings::default(),
+            progress: Arc::new(Mutex::new(RenderProgress::default())),
+            histogram: Arc::new(Mutex::new(None)),
+            error: Arc::new(Mutex::new(None)),
             texture_handle: None,
             rendering_active: Arc::new(AtomicBool::new(false)),
             rendering_needs_stop: Arc::new(AtomicBool::new(false)),
             scene_choice: 0,
+            last_settings: None,
+            view: None,
+            scene_view: Arc::new(Mutex::new(None)),
+            queued_at: None,
         }
     }
 }
 
 fn start_rendering(
-    rendering_active: Arc<AtomicBool>,
     rendering_needs_stop: Arc<AtomicBool>,
     cfg: RenderConfig,
-    progress: Arc<Mutex<f32>>,
+    progress: Arc<Mutex<RenderProgress>>,
+    histogram: Arc<Mutex<Option<Histogram>>>,
     texture: TextureHandle,
     ctx: egui::Context,
-) {
-    let res = load_scene(cfg);
-    let mut job = res.unwrap();
+    scene_view: Arc<Mutex<Option<CameraKeyframe>>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut job = load_scene(cfg)?;
+    *scene_view.lock().unwrap() = Some(job.camera.get_view());
 
     job.alloc_image();
-    let img = job.image.lock().unwrap().get_img();
+    let image = job.image.clone();
+    let hdr = job.image.lock().unwrap().get_hdr();
 
+    let progress_clone = progress.clone();
+    job.set_eta_func(Box::new(move |_, eta| {
+        progress_clone.lock().unwrap().eta = Some(eta)
+    }));
     let update_func = move |pct: f32| {
-        *progress.lock().unwrap() = pct.min(1.0);
+        progress.lock().unwrap().fraction = pct.min(1.0);
+        let h = luminance_histogram(&hdr.lock().unwrap(), HISTOGRAM_BINS);
+        *histogram.lock().unwrap() = Some(h);
         let mut texture_handle = texture.clone();
 
-        texture_handle.set(img.lock().unwrap().clone(), Default::default());
+        // encoded like the saved file
+        texture_handle.set(image.lock().unwrap().to_color_image(), Default::default());
         ctx.request_repaint();
     };
     job.set_progress_func(Box::new(update_func.clone()));
     job.render_scene(rendering_needs_stop.clone());
     job.print_stats();
-    // call it one last time to refresh texture
-    update_func(1.0);
-    job.save_image().expect("output file");
-
-    rendering_active.store(false, Ordering::SeqCst);
-    rendering_needs_stop.store(false, Ordering::SeqCst);
+    // call it one last time to refresh texture, short of 1.0 if stopped
+    update_func(job.get_progress());
+    job.save_image()?;
+    Ok(())
 }
 
 impl RayflexApp {
+    /*
+     * The settings of the last session, if any: a file that cannot be
+     * read leaves the defaults, with a warning.
+     */
     pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Default::default()
+        let mut app = Self::default();
+        let Some(file) = settings_path() else {
+            return app;
+        };
+        match AppSettings::load(&file) {
+            Ok(settings) => {
+                app.scene_choice = SCENES
+                    .iter()
+                    .position(|s| settings.scene_file == format!("scenes/{s}.json"))
+                    .unwrap_or(0);
+                app.saved_settings = settings.clone();
+                app.settings = settings;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => println!("{}: {}: {e}", "warning".yellow(), file.display()),
+        }
+        app
+    }
+
+    // on every change, eg. each step of a slider being dragged
+    fn save_settings(&mut self) {
+        if self.settings == self.saved_settings {
+            return;
+        }
+        self.saved_settings = self.settings.clone();
+        if let Some(file) = settings_path()
+            && let Err(e) = self.settings.save(&file)
+        {
+            println!("{}: {}: {e}", "warning".yellow(), file.display());
+        }
     }
 
     fn stop_async(&mut self) {
         self.rendering_needs_stop.store(true, Ordering::SeqCst);
     }
 
+    /*
+     * Once a render has been started, any settings change cancels the
+     * current job and queues a new one. Only the latest settings are kept,
+     * and the job starts once they have been stable for RENDER_DEBOUNCE and
+     * the previous job has wound down.
+     */
+    fn update_queue(&mut self, ctx: &egui::Context) {
+        let settings = self.settings.clone();
+        if let Some(last) = &self.last_settings
+            && *last != settings
+        {
+            // another scene starts from its own camera
+            if last.scene_file != settings.scene_file {
+                self.view = None;
+                *self.scene_view.lock().unwrap() = None;
+            }
+            self.last_settings = Some(settings);
+            self.queue_render();
+        }
+        if let Some(ts) = self.queued_at {
+            let elapsed = ts.elapsed();
+            if elapsed >= RENDER_DEBOUNCE && !self.rendering_active.load(Ordering::SeqCst) {
+                self.start_async(ctx);
+            } else {
+                ctx.request_repaint_after(RENDER_DEBOUNCE.saturating_sub(elapsed));
+            }
+        }
+    }
+
+    fn queue_render(&mut self) {
+        self.queued_at = Some(Instant::now());
+        if self.rendering_active.load(Ordering::SeqCst) {
+            self.stop_async();
+        }
+    }
+
+    /*
+     * Drag to orbit around look_at, right-drag to pan, scroll to dolly in
+     * or out. A drag renders once released, the scroll steps go through
+     * the debounce of the queue like the settings.
+     */
+    fn move_camera(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let Some(view) = self.view.or(*self.scene_view.lock().unwrap()) else {
+            return;
+        };
+        let size = response.rect.size();
+        let drag = response.drag_delta();
+        if response.dragged_by(egui::PointerButton::Primary) {
+            let (yaw, pitch) = (-drag.x / size.x, drag.y / size.y);
+            self.view = Some(view.orbit(yaw * ORBIT_SPEED, pitch * ORBIT_SPEED));
+        } else if response.dragged_by(egui::PointerButton::Secondary) {
+            self.view = Some(view.pan(drag.x / size.y, drag.y / size.y));
+        }
+        if response.drag_stopped() {
+            self.queue_render();
+        }
+        let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+        if response.hovered() && scroll != 0.0 {
+            let view = self.view.unwrap_or(view);
+            self.view = Some(view.dolly((-scroll * DOLLY_SPEED).exp()));
+            self.queue_render();
+        }
+    }
+
     fn start_async(&mut self, ctx: &egui::Context) {
+        // a stop meant for the previous render
+        self.rendering_needs_stop.store(false, Ordering::SeqCst);
         self.rendering_active.store(true, Ordering::SeqCst);
+        self.last_settings = Some(self.settings.clone());
+        self.queued_at = None;
         info!("start_async");
         let ctx_clone = ctx.clone();
+        *self.progress.lock().unwrap() = RenderProgress::default();
         let value_clone = self.progress.clone();
+        let histogram_clone = self.histogram.clone();
+        let scene_view_clone = self.scene_view.clone();
+        *self.histogram.lock().unwrap() = None;
+        *self.error.lock().unwrap() = None;
+        let error_clone = self.error.clone();
         let rendering_active_clone = self.rendering_active.clone();
         let rendering_needs_stop_clone = self.rendering_needs_stop.clone();
 
@@ -107,42 +332,94 @@ impl RayflexApp {
         {
             texture_handle = ctx.load_texture(
                 "rendered_pixels",
-                ColorImage::new([self.width, self.height], Color32::BLACK),
+                ColorImage::new([self.settings.width, self.settings.height], Color32::BLACK),
                 Default::default(),
             );
             self.texture_handle = Some(texture_handle.clone());
             info!("texture");
         }
+        let integrator = if self.settings.do_path_tracing {
+            Integrator::Path
+        } else {
+            Integrator::Whitted
+        };
         let cfg = RenderConfig {
-            path_tracing: self.path_level,
-            use_gamma: self.use_gamma,
-            use_adaptive_sampling: self.use_antialias,
-            res_x: self.width as u32,
-            res_y: self.height as u32,
-            reflection_max_depth: 5,
-            adaptive_max_depth: 2,
-            use_lines: false,
+            use_gamma: self.settings.use_gamma,
+            tonemap: self.settings.tonemap,
+            use_smooth_normals: self.settings.use_smooth_normals,
+            use_adaptive_sampling: self.settings.use_antialias,
+            bounces: BounceConfig {
+                reflection_max_depth: 5,
+                path_max_bounces: self.settings.max_bounces,
+                ..Default::default()
+            },
             use_hashmap: true,
-            scene_file: PathBuf::from(self.scene_file.clone()),
-            image_file: PathBuf::from(self.output_file.clone()),
-        };
+            progressive: self.settings.use_progressive.then_some(PROGRESSIVE_PASSES),
+            firefly_clamp: Some(self.settings.firefly_clamp).filter(|&max| max > 0.0),
+            camera_view: self.view,
+            ..Default::default()
+        }
+        .with_integrator(integrator, self.settings.path_level)
+        .with_resolution(self.settings.width as u32, self.settings.height as u32)
+        .with_scene_file(self.settings.scene_file.clone())
+        .with_image_file(self.settings.output_file.clone());
 
         info!("before-thread-spawn");
         thread::spawn(move || {
             info!("start-rendering");
-            start_rendering(
-                rendering_active_clone,
-                rendering_needs_stop_clone,
+            let res = start_rendering(
+                rendering_needs_stop_clone.clone(),
                 cfg,
                 value_clone,
+                histogram_clone,
                 texture_handle,
-                ctx_clone,
-            )
+                ctx_clone.clone(),
+                scene_view_clone,
+            );
+            // a queued render can start as soon as this one is inactive: its
+            // stop request must be gone by then
+            rendering_needs_stop_clone.store(false, Ordering::SeqCst);
+            rendering_active_clone.store(false, Ordering::SeqCst);
+            if let Err(e) = res {
+                *error_clone.lock().unwrap() = Some(e.to_string());
+                ctx_clone.request_repaint();
+            }
         });
         info!("after-thread-spawn");
     }
 }
 
+/*
+ * Bars of the luminance histogram, with a red mark at the white point:
+ * a render pushed against it needs less exposure.
+ */
+fn draw_histogram(ui: &mut egui::Ui, h: &Histogram) {
+    let size = egui::vec2(ui.available_width(), HISTOGRAM_HEIGHT);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(30));
+
+    let max = h.bins.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let w = rect.width() / h.bins.len() as f32;
+    for (i, &n) in h.bins.iter().enumerate() {
+        let x = rect.left() + i as f32 * w;
+        let y = rect.bottom() - rect.height() * n as f32 / max;
+        let bar = egui::Rect::from_min_max(egui::pos2(x, y), egui::pos2(x + w, rect.bottom()));
+        painter.rect_filled(bar, 0.0, Color32::LIGHT_GRAY);
+    }
+    let white = -HISTOGRAM_MIN_EV / (HISTOGRAM_MAX_EV - HISTOGRAM_MIN_EV);
+    let x = rect.left() + rect.width() * white;
+    painter.line_segment(
+        [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+        egui::Stroke::new(1.0, Color32::RED),
+    );
+    ui.label(format!(
+        "mean {:.1} EV, {:.1}% clipped",
+        h.mean_ev,
+        100.0 * h.clipped
+    ));
+}
+
 pub fn egui_main() {
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -159,32 +436,21 @@ pub fn egui_main() {
 
 impl eframe::App for RayflexApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        let vec_str = [
-            "cornell-box".to_owned(),
-            "trolley".to_owned(),
-            "cow".to_owned(),
-            "teapot".to_owned(),
-            "buddha".to_owned(),
-            "sphere-box".to_owned(),
-            "sphere-nobox".to_owned(),
-            "test".to_owned(),
-        ];
-
         egui::SidePanel::left("side_panel")
             .max_width(SIDE_PANEL_WIDTH as f32)
             .show(ctx, |ui| {
                 ui.heading("Settings");
 
                 egui::ComboBox::from_label("Pick scene")
-                    .selected_text(vec_str[self.scene_choice].clone())
+                    .selected_text(SCENES[self.scene_choice])
                     .show_ui(ui, |ui| {
-                        for (i, s) in vec_str.iter().enumerate() {
-                            let value = ui.selectable_value(&mut self.scene_choice, i, s);
+                        for (i, s) in SCENES.iter().enumerate() {
+                            let value = ui.selectable_value(&mut self.scene_choice, i, *s);
                             if value.clicked() {
                                 self.scene_choice = i;
-                                self.scene_file = format!("scenes/{}.json", vec_str[i]);
-                                self.do_path_tracing = i == 0;
-                                self.use_gamma = i == 0;
+                                self.settings.scene_file = format!("scenes/{}.json", SCENES[i]);
+                                self.settings.do_path_tracing = i == 0;
+                                self.settings.use_gamma = i == 0;
                             }
                         }
                     });
@@ -192,18 +458,21 @@ impl eframe::App for RayflexApp {
                 ui.horizontal(|ui| {
                     ui.label("scene file: ");
                     ui.add(
-                        egui::TextEdit::singleline(&mut self.scene_file)
+                        egui::TextEdit::singleline(&mut self.settings.scene_file)
                             .hint_text("scene-file.json"),
                     );
                 });
                 ui.add(egui::Separator::default());
                 ui.horizontal(|ui| {
                     ui.label("output file: ");
-                    ui.add(egui::TextEdit::singleline(&mut self.output_file).hint_text("pic.png"));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.settings.output_file)
+                            .hint_text("pic.png"),
+                    );
                 });
                 ui.horizontal(|ui| {
                     ui.add(
-                        egui::Slider::new(&mut self.width, 32..=2048)
+                        egui::Slider::new(&mut self.settings.width, 32..=2048)
                             .text("width")
                             .suffix(" px")
                             .step_by(64.0),
@@ -211,34 +480,70 @@ impl eframe::App for RayflexApp {
                 });
                 ui.horizontal(|ui| {
                     ui.add(
-                        egui::Slider::new(&mut self.height, 32..=2048)
+                        egui::Slider::new(&mut self.settings.height, 32..=2048)
                             .text("height")
                             .suffix(" px")
                             .step_by(64.0),
                     );
                 });
                 ui.add(egui::Separator::default());
-                ui.checkbox(&mut self.do_path_tracing, "use path-tracing");
-                if !self.do_path_tracing {
-                    self.path_level = 1;
-                    self.use_antialias = false;
+                ui.checkbox(&mut self.settings.do_path_tracing, "use path-tracing");
+                if !self.settings.do_path_tracing {
+                    self.settings.path_level = 1;
+                    self.settings.use_antialias = false;
                 }
                 ui.add_enabled(
-                    self.do_path_tracing,
-                    egui::Slider::new(&mut self.path_level, 2..=4096).text("Iterations"),
+                    self.settings.do_path_tracing,
+                    egui::Slider::new(&mut self.settings.path_level, 2..=4096).text("Iterations"),
+                );
+                ui.add_enabled(
+                    self.settings.do_path_tracing,
+                    egui::Slider::new(&mut self.settings.max_bounces, 1..=32).text("Bounces"),
+                );
+                ui.add_enabled(
+                    self.settings.do_path_tracing,
+                    egui::Slider::new(&mut self.settings.firefly_clamp, 0.0..=20.0)
+                        .text("Firefly clamp")
+                        .custom_formatter(|v, _| match v {
+                            0.0 => "off".to_owned(),
+                            _ => format!("{v:.1}"),
+                        }),
+                );
+                ui.add_enabled(
+                    self.settings.do_path_tracing,
+                    egui::Checkbox::new(&mut self.settings.use_progressive, "progressive"),
                 );
 
                 ui.vertical(|ui| {
-                    ui.checkbox(&mut self.use_gamma, "gamma correction");
+                    ui.checkbox(&mut self.settings.use_gamma, "gamma correction");
+                    egui::ComboBox::from_label("tone mapping")
+                        .selected_text(format!("{:?}", self.settings.tonemap))
+                        .show_ui(ui, |ui| {
+                            for t in [ToneMap::Clamp, ToneMap::Reinhard, ToneMap::ACESFilmic] {
+                                let tonemap = &mut self.settings.tonemap;
+                                ui.selectable_value(tonemap, t, format!("{t:?}"));
+                            }
+                        });
+                    ui.checkbox(&mut self.settings.use_smooth_normals, "smooth normals");
                     ui.add_enabled(
-                        !self.do_path_tracing,
-                        egui::Checkbox::new(&mut self.use_antialias, "adaptive antialiasing"),
+                        !self.settings.do_path_tracing,
+                        egui::Checkbox::new(
+                            &mut self.settings.use_antialias,
+                            "adaptive antialiasing",
+                        ),
                     );
                 });
+                if ui.button("Reset to defaults").clicked() {
+                    self.settings = AppSettings::default();
+                    self.scene_choice = 0;
+                    if self.view.take().is_some() {
+                        self.queue_render();
+                    }
+                }
                 ui.add(egui::Separator::default());
 
                 let mut txt;
-                let v = *self.progress.lock().unwrap();
+                let RenderProgress { fraction: v, eta } = *self.progress.lock().unwrap();
                 if v >= 1.0 {
                     txt = "done".to_owned();
                 } else if v > 0.0 {
@@ -246,7 +551,19 @@ impl eframe::App for RayflexApp {
                 } else {
                     txt = "".to_owned();
                 }
+                if let Some(eta) = eta {
+                    let elapsed = eta.elapsed.as_secs_f32();
+                    match eta.remaining {
+                        Some(left) if v < 1.0 => {
+                            txt += &format!(" -- {elapsed:.1}s, {:.1}s left", left.as_secs_f32())
+                        }
+                        _ => txt += &format!(" -- {elapsed:.1}s"),
+                    }
+                }
                 ui.add(egui::ProgressBar::new(v).text(txt));
+                if let Some(h) = self.histogram.lock().unwrap().as_ref() {
+                    draw_histogram(ui, h);
+                }
                 ui.add(egui::Separator::default());
                 if self.rendering_active.load(Ordering::SeqCst) {
                     txt = "Stop".to_owned()
@@ -254,22 +571,34 @@ impl eframe::App for RayflexApp {
                     txt = "Start".to_owned()
                 };
                 if ui
-                    .add_sized([SIDE_PANEL_WIDTH as f32, 30.], egui::Button::new(txt.to_owned()))
+                    .add_sized(
+                        [SIDE_PANEL_WIDTH as f32, 30.],
+                        egui::Button::new(txt.to_owned()),
+                    )
                     .clicked()
                 {
                     if self.rendering_active.load(Ordering::SeqCst) {
+                        self.queued_at = None;
                         self.stop_async();
                     } else {
                         self.start_async(ctx);
                     }
                 }
+                if let Some(e) = self.error.lock().unwrap().as_ref() {
+                    ui.colored_label(Color32::RED, e);
+                }
                 egui::warn_if_debug_build(ui);
             });
 
+        self.save_settings();
+        self.update_queue(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(texture) = &self.texture_handle {
-                let t = SizedTexture::new(texture, ui.available_size());
-                ui.image(t);
+            if let Some(texture) = self.texture_handle.clone() {
+                let t = SizedTexture::new(&texture, ui.available_size());
+                let image = egui::Image::new(t).sense(egui::Sense::click_and_drag());
+                let response = ui.add(image);
+                self.move_camera(ui, &response);
             }
         });
     }
@@ -1,16 +1,75 @@
 use crate::Ray;
+use crate::vec3::EPSILON;
 use crate::vec3::Float;
 use crate::vec3::Point;
+use crate::vec3::Vec2;
 use crate::vec3::Vec3;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::ops::Add;
+use std::ops::Mul;
+use std::ops::Sub;
+use std::path::Path;
+
+/*
+ * Ray differentials of a primary ray: offset to the neighboring pixel ray
+ * in u and v, of the origin (dodu, dodv) and per unit of ray parameter t
+ * (dpdu, dpdv). Meant for texture LOD.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct PixelFootprint {
+    pub dodu: Vec3,
+    pub dodv: Vec3,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+}
+
+impl PixelFootprint {
+    // world-space width/height of the pixel at orig + dir * t
+    pub fn at(&self, t: Float) -> Vec2 {
+        Vec2 {
+            x: (self.dodu + self.dpdu * t).norm(),
+            y: (self.dodv + self.dpdv * t).norm(),
+        }
+    }
+}
+
+/*
+ * Orthographic: parallel rays along dir, scale is the height of the view
+ * in world units.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    Orthographic {
+        scale: Float,
+    },
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Camera {
     pub pos: Point,
     pub look_at: Point,
+    #[serde(default = "default_up")]
     pub up: Vec3,
     pub vfov: Float,
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default)]
+    pub shutter_time: Float,
+    // lens diameter, 0 for a pinhole camera
+    #[serde(default)]
+    pub aperture: Float,
+    // distance to the plane in focus, 0 for the distance to look_at
+    #[serde(default)]
+    pub focus_dist: Float,
+    // aperture shape: 0 for a disk, else a regular polygon with N blades
+    #[serde(default)]
+    pub aperture_blades: u32,
+    // chromatic aberration, 0 for none: see get_channel_scale()
+    #[serde(default)]
+    pub dispersion: Float,
     #[serde(skip)]
     pub dir: Vec3,
     #[serde(skip)]
@@ -21,13 +80,48 @@ pub struct Camera {
     pub screen_v: Vec3,
 }
 
+fn default_up() -> Vec3 {
+    Vec3::unity_z()
+}
+
+// below this, up is considered parallel to the view direction
+const MIN_UP_SIN: Float = 1e-3;
+
 impl Camera {
+    /*
+     * up only needs to be roughly vertical: its component along dir is
+     * removed (Gram-Schmidt). A zero up, or one parallel to dir, falls back
+     * to the world axis the least aligned with dir.
+     */
+    fn get_orthogonal_up(&self) -> Vec3 {
+        let up = self.up - self.dir * self.up.dot(self.dir);
+        if up.is_finite() && up.norm() > MIN_UP_SIN * self.up.norm() && up.norm() > 0.0 {
+            return up.normalize();
+        }
+        println!(
+            "{} camera up {:?} is zero or parallel to the view direction",
+            "warning:".yellow().bold(),
+            self.up
+        );
+        let axis = [Vec3::unity_z(), Vec3::unity_y(), Vec3::unity_x()]
+            .into_iter()
+            .min_by(|a, b| {
+                let da = a.dot(self.dir).abs();
+                let db = b.dot(self.dir).abs();
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        (axis - self.dir * axis.dot(self.dir)).normalize()
+    }
     pub fn init(&mut self) {
         self.dir = (self.look_at - self.pos).normalize();
-        let theta = self.vfov.to_radians();
-        let half_height = (theta / 2.0).tan();
+        let half_height = match self.projection {
+            Projection::Perspective => (self.vfov.to_radians() / 2.0).tan(),
+            Projection::Orthographic { scale } => scale / 2.0,
+        };
         let half_width = self.aspect * half_height;
-        let u = self.up.cross(self.dir).normalize();
+        let up = self.get_orthogonal_up();
+        let u = up.cross(self.dir).normalize();
         let v = self.dir.cross(u).normalize();
 
         self.screen_u = u * 2.0 * half_width;
@@ -44,6 +138,12 @@ impl Camera {
             up,
             vfov,
             aspect,
+            projection: Projection::Perspective,
+            shutter_time: 0.0,
+            aperture: 0.0,
+            focus_dist: 0.0,
+            aperture_blades: 0,
+            dispersion: 0.0,
         };
         c.init();
         c
@@ -51,8 +151,118 @@ impl Camera {
     // u: -0.5 .. 0.5
     // v: -0.5 .. 0.5
     pub fn get_ray(&self, u: Float, v: Float) -> Ray {
-        let pixel = self.pos + self.dir + self.screen_u * u + self.screen_v * v;
-        Ray::new(self.pos, pixel - self.pos)
+        let offset = self.screen_u * u + self.screen_v * v;
+        match self.projection {
+            Projection::Perspective => Ray::new(self.pos, self.dir + offset),
+            Projection::Orthographic { .. } => Ray::new(self.pos + offset, self.dir),
+        }
+    }
+    /*
+     * Lateral chromatic aberration: each channel of the image is scaled
+     * about its center, red outward and blue inward by dispersion, so that
+     * the edges away from the center get colored fringes. The u, v of the
+     * rays of a channel are multiplied by its scale.
+     */
+    pub fn get_channel_scale(&self, channel: usize) -> Float {
+        let spread: [Float; 3] = [1.0, 0.0, -1.0];
+        1.0 + self.dispersion * spread[channel]
+    }
+    pub fn get_focus_dist(&self) -> Float {
+        if self.focus_dist > 0.0 {
+            self.focus_dist
+        } else {
+            (self.look_at - self.pos).norm()
+        }
+    }
+    /*
+     * Depth of field: the ray starts from a point of the lens picked by
+     * lens_u, lens_v (0..1) and goes through the point of the focal plane
+     * seen by the pixel, which stays sharp.
+     */
+    pub fn get_lens_ray(&self, u: Float, v: Float, lens_u: Float, lens_v: Float) -> Ray {
+        let ray = self.get_ray(u, v);
+        if self.aperture <= 0.0 {
+            return ray;
+        }
+        let focus = ray.orig + ray.dir * self.get_focus_dist();
+        let lens = self.sample_aperture(lens_u, lens_v);
+        let radius = self.aperture / 2.0;
+        let orig = ray.orig
+            + self.screen_u.normalize() * (lens.x * radius)
+            + self.screen_v.normalize() * (lens.y * radius);
+        Ray::new(orig, focus - orig)
+    }
+    /*
+     * u, v: 0..1
+     * Uniform point on the unit aperture, ie. the disk or the polygon
+     * inscribed in it. Out-of-focus highlights take this shape.
+     */
+    pub fn sample_aperture(&self, u: Float, v: Float) -> Vec2 {
+        let pi = std::f64::consts::PI as Float;
+        let n = self.aperture_blades;
+        if n < 3 {
+            let r = u.sqrt();
+            let phi = 2.0 * pi * v;
+            return Vec2 {
+                x: r * phi.cos(),
+                y: r * phi.sin(),
+            };
+        }
+        // pick a blade, then a uniform point in its triangle with the center
+        let k = ((u * n as Float) as u32).min(n - 1);
+        let u = u * n as Float - k as Float;
+        let a0 = 2.0 * pi * k as Float / n as Float;
+        let a1 = 2.0 * pi * (k + 1) as Float / n as Float;
+        let su = u.sqrt();
+        let b0 = su * (1.0 - v);
+        let b1 = su * v;
+        Vec2 {
+            x: b0 * a0.cos() + b1 * a1.cos(),
+            y: b0 * a0.sin() + b1 * a1.sin(),
+        }
+    }
+    // du, dv: size of a pixel in the same units as u, v
+    pub fn pixel_footprint(&self, du: Float, dv: Float) -> PixelFootprint {
+        let (du, dv) = (self.screen_u * du, self.screen_v * dv);
+        match self.projection {
+            Projection::Perspective => PixelFootprint {
+                dodu: Vec3::zero(),
+                dodv: Vec3::zero(),
+                dpdu: du,
+                dpdv: dv,
+            },
+            Projection::Orthographic { .. } => PixelFootprint {
+                dodu: du,
+                dodv: dv,
+                dpdu: Vec3::zero(),
+                dpdv: Vec3::zero(),
+            },
+        }
+    }
+    pub fn get_ray_footprint(
+        &self,
+        u: Float,
+        v: Float,
+        du: Float,
+        dv: Float,
+    ) -> (Ray, PixelFootprint) {
+        (self.get_ray(u, v), self.pixel_footprint(du, dv))
+    }
+    // the viewpoint, without the lens and the projection
+    pub fn get_view(&self) -> CameraKeyframe {
+        CameraKeyframe {
+            pos: self.pos,
+            look_at: self.look_at,
+            up: self.up,
+            vfov: self.vfov,
+        }
+    }
+    pub fn set_view(&mut self, view: CameraKeyframe) {
+        self.pos = view.pos;
+        self.look_at = view.look_at;
+        self.up = view.up;
+        self.vfov = view.vfov;
+        self.init();
     }
     pub fn display(&self) {
         {
@@ -68,3 +278,184 @@ impl Camera {
         }
     }
 }
+
+/*
+ * A viewpoint of a camera fly-through. The keyframes of a path are spread
+ * evenly over the frames of the animation.
+ */
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    pub pos: Point,
+    pub look_at: Point,
+    #[serde(default = "default_up")]
+    pub up: Vec3,
+    pub vfov: Float,
+}
+
+// closest the orbit gets to the up axis, in radians
+const ORBIT_MIN_POLAR: Float = 0.01;
+// closest a dolly gets to look_at
+const DOLLY_MIN_DIST: Float = 1e-3;
+
+/*
+ * The moves of the interactive viewer. up stays the world up of the
+ * scene: the orbit turns around it and stops short of its poles.
+ */
+impl CameraKeyframe {
+    // turn pos around look_at: yaw about up, pitch toward it, in radians
+    pub fn orbit(self, yaw: Float, pitch: Float) -> Self {
+        let up = self.up.normalize();
+        let offset = self.pos - self.look_at;
+        let dist = offset.norm();
+        if dist < EPSILON {
+            return self;
+        }
+        let polar = (offset.dot(up) / dist).clamp(-1.0, 1.0).acos();
+        let pi = std::f64::consts::PI as Float;
+        let polar = (polar - pitch).clamp(ORBIT_MIN_POLAR, pi - ORBIT_MIN_POLAR);
+        let e1 = offset - up * offset.dot(up);
+        let e1 = if e1.norm() > EPSILON {
+            e1.normalize()
+        } else {
+            // looking straight along up: any horizontal direction
+            let axis = if up.x.abs() < 0.9 {
+                Vec3::unity_x()
+            } else {
+                Vec3::unity_y()
+            };
+            (axis - up * axis.dot(up)).normalize()
+        };
+        let e2 = up.cross(e1);
+        let horizontal = e1 * yaw.cos() + e2 * yaw.sin();
+        let offset = (up * polar.cos() + horizontal * polar.sin()) * dist;
+        Self {
+            pos: self.look_at + offset,
+            ..self
+        }
+    }
+    // scale the distance to look_at along the view direction: < 1 moves in
+    pub fn dolly(self, factor: Float) -> Self {
+        let offset = self.pos - self.look_at;
+        if offset.norm() < EPSILON {
+            return self;
+        }
+        let factor = factor.max(DOLLY_MIN_DIST / offset.norm());
+        Self {
+            pos: self.look_at + offset * factor,
+            ..self
+        }
+    }
+    /*
+     * Slide pos and look_at together in the view plane, by dx to the right
+     * and dy down, in heights of the view at look_at: the scene follows.
+     */
+    pub fn pan(self, dx: Float, dy: Float) -> Self {
+        let camera = Camera::new(self.pos, self.look_at, self.up, self.vfov, 1.0);
+        let height = camera.screen_v.norm() * (self.look_at - self.pos).norm();
+        // screen_u points to the left of the image
+        let right = -camera.screen_u.normalize();
+        let up = camera.screen_v.normalize();
+        let delta = (up * dy - right * dx) * height;
+        Self {
+            pos: self.pos + delta,
+            look_at: self.look_at + delta,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PathInterpolation {
+    #[default]
+    Linear,
+    CatmullRom,
+}
+
+impl std::str::FromStr for PathInterpolation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(PathInterpolation::Linear),
+            "catmull-rom" => Ok(PathInterpolation::CatmullRom),
+            _ => Err(format!(
+                "unknown interpolation '{s}' (expected linear or catmull-rom)"
+            )),
+        }
+    }
+}
+
+/*
+ * Catmull-Rom spline through p1 (t = 0) and p2 (t = 1), its tangents taken
+ * from the neighbors p0 and p3.
+ */
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: Float) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Float, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+    pub interpolation: PathInterpolation,
+}
+
+impl CameraPath {
+    pub fn new(
+        keyframes: Vec<CameraKeyframe>,
+        interpolation: PathInterpolation,
+    ) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("camera path without keyframes".to_owned());
+        }
+        Ok(Self {
+            keyframes,
+            interpolation,
+        })
+    }
+
+    // a json list of keyframes
+    pub fn load(file: &Path, interpolation: PathInterpolation) -> std::io::Result<Self> {
+        let data = std::fs::read_to_string(file)?;
+        let keyframes: Vec<CameraKeyframe> = serde_json::from_str(&data)?;
+        Self::new(keyframes, interpolation).map_err(std::io::Error::other)
+    }
+
+    // t: 0 at the first keyframe .. 1 at the last one
+    pub fn at(&self, t: Float) -> CameraKeyframe {
+        let last = self.keyframes.len() - 1;
+        let s = t.clamp(0.0, 1.0) * last as Float;
+        let i = (s as usize).min(last.saturating_sub(1));
+        let t = s - i as Float;
+        // the end keyframes are repeated as their own neighbors
+        let key = |k: usize| self.keyframes[k.min(last)];
+        let (k0, k1, k2, k3) = (key(i.saturating_sub(1)), key(i), key(i + 1), key(i + 2));
+
+        match self.interpolation {
+            PathInterpolation::Linear => CameraKeyframe {
+                pos: Vec3::lerp(k1.pos, k2.pos, t),
+                look_at: Vec3::lerp(k1.look_at, k2.look_at, t),
+                up: Vec3::lerp(k1.up, k2.up, t),
+                vfov: k1.vfov + (k2.vfov - k1.vfov) * t,
+            },
+            PathInterpolation::CatmullRom => CameraKeyframe {
+                pos: catmull_rom(k0.pos, k1.pos, k2.pos, k3.pos, t),
+                look_at: catmull_rom(k0.look_at, k1.look_at, k2.look_at, k3.look_at, t),
+                up: catmull_rom(k0.up, k1.up, k2.up, k3.up, t),
+                vfov: catmull_rom(k0.vfov, k1.vfov, k2.vfov, k3.vfov, t),
+            },
+        }
+    }
+
+    // move the camera to t along the path, the lens and projection are kept
+    pub fn apply(&self, camera: &mut Camera, t: Float) {
+        camera.set_view(self.at(t));
+    }
+}
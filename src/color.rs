@@ -30,12 +30,6 @@ impl Add for RGB {
 impl Mul<RGB> for RGB {
     type Output = RGB;
     fn mul(self, rhs: RGB) -> RGB {
-        assert!(self.r >= 0.0);
-        assert!(self.g >= 0.0);
-        assert!(self.b >= 0.0);
-        assert!(rhs.r >= 0.0);
-        assert!(rhs.g >= 0.0);
-        assert!(rhs.b >= 0.0);
         RGB {
             r: self.r * rhs.r,
             g: self.g * rhs.g,
@@ -47,10 +41,6 @@ impl Mul<RGB> for RGB {
 impl Div<f32> for RGB {
     type Output = RGB;
     fn div(self, rhs: f32) -> RGB {
-        assert!(self.r >= 0.0);
-        assert!(self.g >= 0.0);
-        assert!(self.b >= 0.0);
-        assert!(rhs >= 0.0);
         RGB {
             r: self.r / rhs,
             g: self.g / rhs,
@@ -87,9 +77,33 @@ impl RGB {
     pub fn new(r: f32, g: f32, b: f32) -> Self {
         RGB { r, g, b }
     }
+    pub fn one() -> Self {
+        RGB::new(1.0, 1.0, 1.0)
+    }
+    /*
+     * Rounding can leave a channel slightly below zero along the way: the
+     * arithmetic lets it through, and the pixel is clamped once, when it is
+     * stored. A NaN channel becomes 0 as well.
+     */
+    pub fn clamp_non_negative(&self) -> RGB {
+        RGB::new(self.r.max(0.0), self.g.max(0.0), self.b.max(0.0))
+    }
     pub fn is_zero(&self) -> bool {
         self.r == 0.0 && self.g == 0.0 && self.b == 0.0
     }
+    // Rec. 709 relative luminance
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+    // scaled down to a luminance of at most max, keeping the hue
+    pub fn clamp_luminance(&self, max: f32) -> RGB {
+        let lum = self.luminance();
+        if lum > max {
+            *self * (max / lum)
+        } else {
+            *self
+        }
+    }
     pub fn difference(c00: RGB, c01: RGB, c10: RGB, c11: RGB) -> f32 {
         let avg = (c00 + c01 + c10 + c11) * 0.25;
         avg.distance2(c00) + avg.distance2(c01) + avg.distance2(c10) + avg.distance2(c11)
@@ -98,18 +112,75 @@ impl RGB {
         let m = f32::max((self.r - rhs.r).abs(), (self.g - rhs.g).abs());
         f32::max((self.b - rhs.b).abs(), m)
     }
+    pub fn lerp(a: RGB, b: RGB, t: f32) -> RGB {
+        a * (1.0 - t) + b * t
+    }
+    // lerp with a weight per channel, each in 0 --> 1
+    pub fn lerp_rgb(a: RGB, b: RGB, t: RGB) -> RGB {
+        RGB::new(
+            a.r * (1.0 - t.r) + b.r * t.r,
+            a.g * (1.0 - t.g) + b.g * t.g,
+            a.b * (1.0 - t.b) + b.b * t.b,
+        )
+    }
+    pub fn max_component(&self) -> f32 {
+        self.r.max(self.g).max(self.b)
+    }
     pub fn distance(&self, rhs: RGB) -> f32 {
         ((self.r - rhs.r).powi(2) + (self.g - rhs.g).powi(2) + (self.b - rhs.b).powi(2)).sqrt()
     }
     pub fn add(&mut self, rgb: &RGB) {
-        assert!(self.r >= 0.0);
-        assert!(self.g >= 0.0);
-        assert!(self.b >= 0.0);
-        assert!(rgb.r >= 0.0);
-        assert!(rgb.g >= 0.0);
-        assert!(rgb.b >= 0.0);
         self.r += rgb.r;
         self.g += rgb.g;
         self.b += rgb.b;
     }
 }
+
+/*
+ * Piecewise-linear color ramp: stops are (position, color) pairs sorted by
+ * position. Values outside the first/last stop are clamped.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Gradient {
+    pub stops: Vec<(f32, RGB)>,
+}
+
+impl Gradient {
+    pub fn new(stops: Vec<(f32, RGB)>) -> Self {
+        let gradient = Self { stops };
+        if let Err(e) = gradient.validate() {
+            panic!("{e}");
+        }
+        gradient
+    }
+    // eval() needs a stop, and the stops in order
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stops.is_empty() {
+            return Err("no stops".to_owned());
+        }
+        if let Some((t, _)) = self.stops.iter().find(|(t, _)| !t.is_finite()) {
+            return Err(format!("stop at {t}"));
+        }
+        if self.stops.windows(2).any(|w| w[0].0 > w[1].0) {
+            return Err("stops out of order".to_owned());
+        }
+        Ok(())
+    }
+    pub fn two_stops(c0: RGB, c1: RGB) -> Self {
+        Self::new(vec![(0.0, c0), (1.0, c1)])
+    }
+    pub fn eval(&self, t: f32) -> RGB {
+        let first = self.stops[0];
+        let last = self.stops[self.stops.len() - 1];
+        if t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+        let i = self.stops.iter().position(|s| s.0 > t).unwrap();
+        let (t0, c0) = self.stops[i - 1];
+        let (t1, c1) = self.stops[i];
+        RGB::lerp(c0, c1, (t - t0) / (t1 - t0))
+    }
+}
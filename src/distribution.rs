@@ -0,0 +1,112 @@
+use crate::vec3::Float;
+
+/*
+ * Piecewise-constant distributions used to importance-sample images such
+ * as an environment map: pixels are picked proportionally to their weight
+ * (typically luminance) by inverting a CDF with a binary search.
+ */
+
+#[derive(Debug)]
+pub struct Distribution1D {
+    func: Vec<Float>,
+    cdf: Vec<Float>,
+    func_int: Float,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[Float]) -> Self {
+        assert!(!func.is_empty());
+        let n = func.len();
+        let func: Vec<Float> = func.iter().map(|f| f.abs()).collect();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n {
+            cdf.push(cdf[i] + func[i] / n as Float);
+        }
+        let func_int = cdf[n];
+        if func_int == 0.0 {
+            // all zero: fall back to a uniform distribution
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as Float / n as Float;
+            }
+        } else {
+            cdf.iter_mut().for_each(|c| *c /= func_int);
+        }
+        Self {
+            func,
+            cdf,
+            func_int,
+        }
+    }
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+    pub fn integral(&self) -> Float {
+        self.func_int
+    }
+    /*
+     * u: 0..1
+     * Returns the sampled position in 0..1, its pdf and the segment index.
+     */
+    pub fn sample(&self, u: Float) -> (Float, Float, usize) {
+        // last index such that cdf[idx] <= u
+        let idx = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+        let mut du = u - self.cdf[idx];
+        let width = self.cdf[idx + 1] - self.cdf[idx];
+        if width > 0.0 {
+            du /= width;
+        }
+        let x = (idx as Float + du) / self.count() as Float;
+        (x, self.pdf_at(idx), idx)
+    }
+    pub fn pdf_at(&self, idx: usize) -> Float {
+        if self.func_int == 0.0 {
+            return 1.0;
+        }
+        self.func[idx] / self.func_int
+    }
+}
+
+#[derive(Debug)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    // func: row-major, width * height weights
+    pub fn new(func: &[Float], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height);
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(&func[y * width..(y + 1) * width]))
+            .collect();
+        let marginal_func: Vec<Float> = conditional.iter().map(|d| d.integral()).collect();
+        Self {
+            conditional,
+            marginal: Distribution1D::new(&marginal_func),
+        }
+    }
+    /*
+     * u, v: 0..1
+     * Returns a point in [0..1]^2 and its pdf with respect to that domain.
+     */
+    pub fn sample(&self, u: Float, v: Float) -> (Float, Float, Float) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.conditional[row].sample(u);
+        (x, y, pdf_x * pdf_y)
+    }
+    pub fn pdf(&self, x: Float, y: Float) -> Float {
+        let width = self.conditional[0].count();
+        let height = self.marginal.count();
+        let ix = ((x * width as Float) as usize).min(width - 1);
+        let iy = ((y * height as Float) as usize).min(height - 1);
+        if self.marginal.integral() == 0.0 {
+            return 1.0;
+        }
+        self.conditional[iy].func[ix] / self.marginal.integral()
+    }
+}
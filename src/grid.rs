@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use crate::Ray;
+use crate::RenderStats;
+use crate::aabb::AABB;
+use crate::render::Hit;
+use crate::three_d::Object;
+use crate::vec3::EPSILON;
+use crate::vec3::Float;
+use crate::vec3::Vec3;
+
+// target number of cells per bounded object
+const GRID_CELLS_PER_OBJECT: Float = 2.0;
+const GRID_MAX_RES: usize = 256;
+
+type Objects = [Arc<dyn Object + 'static + Send + Sync>];
+
+/*
+ * Uniform grid over the loose objects of the scene, walked cell by cell
+ * along the ray (3D DDA, Amanatides & Woo) so that only the objects of the
+ * cells it crosses get tested. The objects without bounds (planes) or in
+ * motion are kept apart and tested against every ray. The cells are
+ * stored flat: cell i lists cell_objects[cell_start[i]..cell_start[i + 1]].
+ */
+pub struct UniformGrid {
+    bounds: AABB,
+    res: [usize; 3],
+    cell_size: Vec3,
+    cell_start: Vec<u32>,
+    cell_objects: Vec<u32>,
+    unbounded: Vec<usize>,
+}
+
+fn axis_of(v: Vec3, axis: usize) -> Float {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+impl UniformGrid {
+    pub fn new(objects: &Objects) -> Self {
+        let mut bounds = AABB::empty();
+        let mut boxes = vec![];
+        let mut unbounded = vec![];
+        for (id, obj) in objects.iter().enumerate() {
+            match obj.get_aabb() {
+                Some(aabb) if obj.get_velocity() == Vec3::zero() => {
+                    bounds.grow(&aabb);
+                    boxes.push((id, aabb));
+                }
+                _ => unbounded.push(id),
+            }
+        }
+        if boxes.is_empty() {
+            bounds = AABB {
+                p_min: Vec3::zero(),
+                p_max: Vec3::zero(),
+            };
+        }
+        // room for the hits that land right on the outer faces
+        let extent = bounds.p_max - bounds.p_min;
+        let margin = extent.norm() * 1e-4 + EPSILON;
+        let margin = Vec3::new(margin, margin, margin);
+        bounds.p_min = bounds.p_min - margin;
+        bounds.p_max += margin;
+
+        let extent = bounds.p_max - bounds.p_min;
+        let volume = extent.x * extent.y * extent.z;
+        let scale = (GRID_CELLS_PER_OBJECT * boxes.len().max(1) as Float / volume).cbrt();
+        let res = [0, 1, 2].map(|axis| {
+            let n = (axis_of(extent, axis) * scale).round() as usize;
+            n.clamp(1, GRID_MAX_RES)
+        });
+        let cell_size = Vec3::new(
+            extent.x / res[0] as Float,
+            extent.y / res[1] as Float,
+            extent.z / res[2] as Float,
+        );
+        let mut grid = Self {
+            bounds,
+            res,
+            cell_size,
+            cell_start: vec![],
+            cell_objects: vec![],
+            unbounded,
+        };
+
+        // count, then fill
+        let num_cells = res[0] * res[1] * res[2];
+        let mut counts = vec![0u32; num_cells + 1];
+        for (_, aabb) in &boxes {
+            grid.for_each_cell(aabb, |i| counts[i] += 1);
+        }
+        let mut start = 0;
+        for c in counts.iter_mut() {
+            let n = *c;
+            *c = start;
+            start += n;
+        }
+        let mut fill = counts.clone();
+        let mut cell_objects = vec![0; start as usize];
+        for (id, aabb) in &boxes {
+            grid.for_each_cell(aabb, |i| {
+                cell_objects[fill[i] as usize] = *id as u32;
+                fill[i] += 1;
+            });
+        }
+        grid.cell_start = counts;
+        grid.cell_objects = cell_objects;
+        grid
+    }
+    pub fn get_res(&self) -> [usize; 3] {
+        self.res
+    }
+    fn get_cell_coord(&self, v: Float, axis: usize) -> usize {
+        let d = (v - axis_of(self.bounds.p_min, axis)) / axis_of(self.cell_size, axis);
+        (d.max(0.0) as usize).min(self.res[axis] - 1)
+    }
+    fn get_cell_index(&self, c: [usize; 3]) -> usize {
+        (c[2] * self.res[1] + c[1]) * self.res[0] + c[0]
+    }
+    // visits the cells overlapped by aabb
+    fn for_each_cell(&self, aabb: &AABB, mut f: impl FnMut(usize)) {
+        let lo = [0, 1, 2].map(|axis| self.get_cell_coord(axis_of(aabb.p_min, axis), axis));
+        let hi = [0, 1, 2].map(|axis| self.get_cell_coord(axis_of(aabb.p_max, axis), axis));
+        for z in lo[2]..=hi[2] {
+            for y in lo[1]..=hi[1] {
+                for x in lo[0]..=hi[0] {
+                    f(self.get_cell_index([x, y, z]));
+                }
+            }
+        }
+    }
+    /*
+     * Walks the cells along the ray up to tmax, in order: visit gets the
+     * objects of each cell and the t at which the ray leaves it, and
+     * returns true to stop there.
+     */
+    fn traverse(&self, ray: &Ray, tmax: Float, mut visit: impl FnMut(&[u32], Float) -> bool) {
+        let (t_enter, t_exit) = self.bounds.get_slab_range(ray);
+        let t_enter = t_enter.max(0.0);
+        if t_enter > t_exit.min(tmax) {
+            return;
+        }
+        let p = ray.orig + ray.dir * t_enter;
+        let mut cell = [0, 1, 2].map(|axis| self.get_cell_coord(axis_of(p, axis), axis));
+        let mut step = [0isize; 3];
+        let mut t_next = [Float::MAX; 3];
+        let mut t_delta = [Float::MAX; 3];
+        for axis in 0..3 {
+            let d = axis_of(ray.dir, axis);
+            let size = axis_of(self.cell_size, axis);
+            let o = axis_of(ray.orig, axis) - axis_of(self.bounds.p_min, axis);
+            if d > 0.0 {
+                step[axis] = 1;
+                t_next[axis] = ((cell[axis] + 1) as Float * size - o) / d;
+                t_delta[axis] = size / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                t_next[axis] = (cell[axis] as Float * size - o) / d;
+                t_delta[axis] = -size / d;
+            }
+        }
+        loop {
+            let axis = if t_next[0] < t_next[1] {
+                if t_next[0] < t_next[2] { 0 } else { 2 }
+            } else if t_next[1] < t_next[2] {
+                1
+            } else {
+                2
+            };
+            let i = self.get_cell_index(cell);
+            let objects =
+                &self.cell_objects[self.cell_start[i] as usize..self.cell_start[i + 1] as usize];
+            let t_cell_exit = t_next[axis];
+            if visit(objects, t_cell_exit) || t_cell_exit >= tmax || step[axis] == 0 {
+                return;
+            }
+            let c = cell[axis] as isize + step[axis];
+            if c < 0 || c >= self.res[axis] as isize {
+                return;
+            }
+            cell[axis] = c as usize;
+            t_next[axis] += t_delta[axis];
+        }
+    }
+    // same result as render::get_nearest_hit()
+    pub fn get_nearest_hit(
+        &self,
+        objects: &Objects,
+        stats: &mut RenderStats,
+        ray: &Ray,
+    ) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        let mut best_t = Float::MAX;
+        let mut test = |id: usize, best: &mut Option<Hit>, best_t: &mut Float| {
+            let mut t = *best_t;
+            let mut oid = 0;
+            if objects[id].intercept(stats, ray, EPSILON, &mut t, false, &mut oid) && t < *best_t {
+                *best_t = t;
+                *best = Some(Hit {
+                    object: id,
+                    t,
+                    s_id: oid,
+                });
+            }
+        };
+        for &id in &self.unbounded {
+            test(id, &mut best, &mut best_t);
+        }
+        self.traverse(ray, Float::MAX, |cell, t_cell_exit| {
+            for &id in cell {
+                test(id as usize, &mut best, &mut best_t);
+            }
+            // a hit beyond the cell may still be beaten in the next ones
+            best_t <= t_cell_exit
+        });
+        best
+    }
+    // whether an object other than skip blocks the ray before tmax
+    pub fn is_occluded(
+        &self,
+        objects: &Objects,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmax: Float,
+        skip: Option<usize>,
+    ) -> bool {
+        let mut test = |id: usize| {
+            let mut t = tmax;
+            let mut oid = 0;
+            Some(id) != skip && objects[id].intercept(stats, ray, EPSILON, &mut t, true, &mut oid)
+        };
+        if self.unbounded.iter().any(|&id| test(id)) {
+            return true;
+        }
+        let mut occluded = false;
+        self.traverse(ray, tmax, |cell, _| {
+            occluded = cell.iter().any(|&id| test(id as usize));
+            occluded
+        });
+        occluded
+    }
+}
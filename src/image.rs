@@ -1,71 +1,349 @@
+use crate::color::Gradient;
 use crate::color::RGB;
+use crate::vec3::Vec3;
 use colored::Colorize;
 use egui::Color32;
 use egui::ColorImage;
-use image::{Rgb, RgbImage};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops;
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Instant;
 
-const GAMMA: f32 = 2.2;
+pub const GAMMA: f32 = 2.2;
 
+// of the rendered image, from the extension of its file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Ppm,
+    Exr, // the linear values, not tone mapped
+}
+
+impl OutputFormat {
+    pub fn from_path(file: &Path) -> std::io::Result<Self> {
+        let ext = file
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        match ext.as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpg" | "jpeg" => Ok(OutputFormat::Jpeg),
+            "ppm" => Ok(OutputFormat::Ppm),
+            "exr" => Ok(OutputFormat::Exr),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: unknown image format, not .png, .jpg, .ppm or .exr",
+                    file.display()
+                ),
+            )),
+        }
+    }
+}
+
+// binary P6: the header in ascii, then the rgb bytes row by row
+pub fn write_ppm(out: &mut impl Write, img: &RgbImage) -> std::io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", img.width(), img.height())?;
+    out.write_all(img.as_raw())
+}
+
+/*
+ * Maps the linear radiance to the displayable 0..1 range, before gamma.
+ * Clamp cuts everything above 1; Reinhard and ACESFilmic roll off the
+ * highlights instead.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ToneMap {
+    #[default]
+    Clamp,
+    Reinhard,
+    ACESFilmic,
+}
+
+impl std::str::FromStr for ToneMap {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::ACESFilmic),
+            _ => Err(format!(
+                "unknown tone mapper '{s}' (expected clamp, reinhard or aces)"
+            )),
+        }
+    }
+}
+
+impl ToneMap {
+    fn map(&self, v: f32) -> f32 {
+        let v = v.max(0.0);
+        match self {
+            ToneMap::Clamp => v.min(1.0),
+            ToneMap::Reinhard => v / (1.0 + v),
+            // Narkowicz's fit of the ACES filmic curve
+            ToneMap::ACESFilmic => {
+                let num = v * (2.51 * v + 0.03);
+                let den = v * (2.43 * v + 0.59) + 0.14;
+                (num / den).clamp(0.0, 1.0)
+            }
+        }
+    }
+    pub fn apply(&self, c: RGB) -> RGB {
+        RGB::new(self.map(c.r), self.map(c.g), self.map(c.b))
+    }
+}
+
+/*
+ * Look-dev grade of the tone mapped colors, before gamma: a white balance
+ * shift (warmer for a positive temperature), then saturation around the
+ * luminance and a power-law contrast pivoting on mid-gray. The default
+ * values leave the image unchanged.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grade {
+    pub saturation: f32,  // 0 is grayscale
+    pub contrast: f32,    // exponent around the pivot
+    pub temperature: f32, // -1 --> 1
+}
+
+const GRADE_PIVOT: f32 = 0.18;
+// red/blue gain at a temperature of 1
+const GRADE_TEMPERATURE_GAIN: f32 = 0.2;
+
+impl Default for Grade {
+    fn default() -> Self {
+        Self {
+            saturation: 1.0,
+            contrast: 1.0,
+            temperature: 0.0,
+        }
+    }
+}
+
+impl Grade {
+    pub fn is_identity(&self) -> bool {
+        *self == Grade::default()
+    }
+    pub fn apply(&self, c: RGB) -> RGB {
+        if self.is_identity() {
+            return c;
+        }
+        let gain = GRADE_TEMPERATURE_GAIN * self.temperature;
+        let c = RGB::new(c.r * (1.0 + gain), c.g, c.b * (1.0 - gain));
+        let lum = c.luminance();
+        let c = RGB::new(
+            lum + (c.r - lum) * self.saturation,
+            lum + (c.g - lum) * self.saturation,
+            lum + (c.b - lum) * self.saturation,
+        );
+        let contrast = |v: f32| GRADE_PIVOT * (v.max(0.0) / GRADE_PIVOT).powf(self.contrast);
+        RGB::new(contrast(c.r), contrast(c.g), contrast(c.b))
+    }
+}
+
+/*
+ * The rendered pixels are kept linear only: the preview and the saved
+ * file are both encoded from them by to_color_image(), tone mapping, grade
+ * then gamma, so that what the UI shows is what lands on disk.
+ * A progressive render adds its passes into the sums and sample counts,
+ * the linear pixels then being their running mean.
+ */
 pub struct Image {
     use_gamma: bool,
+    tonemap: ToneMap,
+    grade: Grade,
+    gamma_lut: GammaLut,
     res_x: u32,
     res_y: u32,
-    img_buffer: Arc<Mutex<ColorImage>>,
+    hdr_buffer: Arc<Mutex<Vec<RGB>>>,
+    sums: Vec<RGB>,
+    sample_counts: Vec<u32>,
+}
+
+pub fn gamma_encode(linear: f32, gamma: f32) -> f32 {
+    linear.powf(1.0 / gamma)
 }
 
-fn gamma_encode(linear: f32) -> f32 {
-    linear.powf(1.0 / GAMMA)
+/*
+ * Precomputed gamma encoding to avoid a powf() per channel per pixel.
+ * The table is indexed by sqrt(linear): the encoding curve is steep close to
+ * zero, and a table indexed by the linear value would lose several levels in
+ * the dark tones. sqrt() is still much cheaper than powf().
+ */
+pub struct GammaLut {
+    gamma: f32,
+    table: Vec<u16>,
+}
+
+impl GammaLut {
+    // bits: output bit-depth, eg. 8 for a 4096-entry table.
+    pub fn new(gamma: f32, bits: u32) -> Self {
+        assert!((1..=16).contains(&bits));
+        let n = 1usize << (bits + 4).min(20);
+        let max = ((1u32 << bits) - 1) as f32;
+        let table = (0..n)
+            .map(|i| {
+                let s = i as f32 / (n - 1) as f32;
+                (max * gamma_encode(s * s, gamma)).clamp(0.0, max) as u16
+            })
+            .collect();
+        Self { gamma, table }
+    }
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+    pub fn encode(&self, linear: f32) -> u16 {
+        let s = linear.clamp(0.0, 1.0).sqrt();
+        let idx = (s * (self.table.len() - 1) as f32).round() as usize;
+        self.table[idx]
+    }
 }
 
 impl Image {
-    pub fn get_img(&mut self) -> Arc<Mutex<ColorImage>> {
-        self.img_buffer.clone()
+    // linear colors, before gamma encoding and clamping
+    pub fn get_hdr(&mut self) -> Arc<Mutex<Vec<RGB>>> {
+        self.hdr_buffer.clone()
+    }
+    // a copy of the linear colors, row by row from the top-left pixel
+    pub fn pixels(&self) -> Vec<RGB> {
+        self.hdr_buffer.lock().unwrap().clone()
     }
     pub fn new(use_gamma: bool, res_x: u32, res_y: u32) -> Self {
         Self {
             use_gamma,
+            tonemap: ToneMap::Clamp,
+            grade: Grade::default(),
+            gamma_lut: GammaLut::new(GAMMA, 8),
             res_x,
             res_y,
-            img_buffer: Arc::new(Mutex::new(ColorImage::new(
-                [res_x as usize, res_y as usize],
-                Color32::BLACK,
-            ))),
+            hdr_buffer: Arc::new(Mutex::new(vec![RGB::zero(); (res_x * res_y) as usize])),
+            sums: vec![RGB::zero(); (res_x * res_y) as usize],
+            sample_counts: vec![0; (res_x * res_y) as usize],
         }
     }
+    // the LUT is only rebuilt when the gamma value actually changes
+    pub fn set_gamma(&mut self, gamma: f32) {
+        if gamma != self.gamma_lut.gamma() {
+            self.gamma_lut = GammaLut::new(gamma, 8);
+        }
+    }
+    pub fn set_tonemap(&mut self, tonemap: ToneMap) {
+        self.tonemap = tonemap;
+    }
+    pub fn grade(&mut self, grade: Grade) {
+        self.grade = grade;
+    }
+    fn encode(&self, c: RGB) -> Color32 {
+        let ldr = self.grade.apply(self.tonemap.apply(c));
+        let (r, g, b) = if self.use_gamma {
+            (
+                self.gamma_lut.encode(ldr.r) as u8,
+                self.gamma_lut.encode(ldr.g) as u8,
+                self.gamma_lut.encode(ldr.b) as u8,
+            )
+        } else {
+            (
+                (255.0 * ldr.r).clamp(0.0, 255.0) as u8,
+                (255.0 * ldr.g).clamp(0.0, 255.0) as u8,
+                (255.0 * ldr.b).clamp(0.0, 255.0) as u8,
+            )
+        };
+        Color32::from_rgb(r, g, b)
+    }
+    // replaces the pixel, and whatever samples it accumulated
     pub fn push_pixel(&mut self, x: u32, y: u32, c: RGB) {
-        let mut rf = c.r;
-        let mut gf = c.g;
-        let mut bf = c.b;
-
-        if self.use_gamma {
-            rf = gamma_encode(rf);
-            gf = gamma_encode(gf);
-            bf = gamma_encode(bf);
+        let i = (y * self.res_x + x) as usize;
+        let c = c.clamp_non_negative();
+        self.sums[i] = c;
+        self.sample_counts[i] = 1;
+        self.hdr_buffer.lock().unwrap()[i] = c;
+    }
+    // adds the mean c of num_samples samples to the pixel: returns its new mean
+    pub fn accumulate_pixel(&mut self, x: u32, y: u32, c: RGB, num_samples: u32) -> RGB {
+        let i = (y * self.res_x + x) as usize;
+        self.sums[i] += c.clamp_non_negative() * num_samples as f32;
+        self.sample_counts[i] += num_samples;
+        let mean = self.sums[i] / self.sample_counts[i].max(1) as f32;
+        self.hdr_buffer.lock().unwrap()[i] = mean;
+        mean
+    }
+    // the samples added into the pixel so far, 1 once pushed
+    pub fn get_sample_count(&self, x: u32, y: u32) -> u32 {
+        self.sample_counts[(y * self.res_x + x) as usize]
+    }
+    // the displayable 8-bit pixels, for the preview and the saved file
+    pub fn to_color_image(&self) -> ColorImage {
+        let hdr = self.hdr_buffer.lock().unwrap();
+        ColorImage {
+            size: [self.res_x as usize, self.res_y as usize],
+            pixels: hdr.iter().map(|c| self.encode(*c)).collect(),
         }
-        let r = (255.0 * rf).clamp(0.0, 255.0) as u8;
-        let g = (255.0 * gf).clamp(0.0, 255.0) as u8;
-        let b = (255.0 * bf).clamp(0.0, 255.0) as u8;
-
-        self.img_buffer.lock().unwrap().pixels[(y * self.res_x + x) as usize] =
-            Color32::from_rgb(r, g, b);
     }
-    pub fn save_image(&mut self, file: &Path) -> std::io::Result<()> {
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let ldr = self.to_color_image();
+        RgbImage::from_fn(self.res_x, self.res_y, |x, y| {
+            let c = ldr.pixels[(y * self.res_x + x) as usize];
+            Rgb([c.r(), c.g(), c.b()])
+        })
+    }
+    /*
+     * In the format of the extension of file: jpeg_quality only applies to
+     * .jpg, and .exr goes through save_hdr().
+     */
+    pub fn save_image(&mut self, file: &Path, jpeg_quality: u8) -> std::io::Result<()> {
+        let format = OutputFormat::from_path(file)?;
+        if format == OutputFormat::Exr {
+            return self.save_hdr(file);
+        }
         let start_time = Instant::now();
 
-        let mut img = RgbImage::new(self.res_x, self.res_y);
-
-        for y in 0..self.res_y {
-            for x in 0..self.res_x {
-                let c = self.img_buffer.lock().unwrap().pixels[(y * self.res_x + x) as usize];
-                img.put_pixel(x, y, Rgb([c.r(), c.g(), c.b()]));
+        let img = self.to_rgb_image();
+        let to_io_error = |e| std::io::Error::other(format!("{}: {e}", file.display()));
+        match format {
+            OutputFormat::Ppm => {
+                let mut out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                write_ppm(&mut out, &img)?;
+                out.flush()?;
+            }
+            OutputFormat::Jpeg => {
+                let mut out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                let (w, h) = img.dimensions();
+                JpegEncoder::new_with_quality(&mut out, jpeg_quality)
+                    .encode(img.as_raw(), w, h, image::ColorType::Rgb8)
+                    .map_err(to_io_error)?;
+                out.flush()?;
             }
+            _ => img.save(file).map_err(to_io_error)?,
         }
+        let elapsed = start_time.elapsed();
+        let lat_msec = elapsed.as_millis() as f64 / 1000.0;
+        println!(
+            "writing '{}' took {} sec",
+            file.display().to_string().bold(),
+            lat_msec
+        );
+        Ok(())
+    }
+    // OpenEXR with the raw linear values: no tone mapping, no gamma
+    pub fn save_hdr(&mut self, file: &Path) -> std::io::Result<()> {
+        let start_time = Instant::now();
+        let res_x = self.res_x as usize;
+        let hdr = self.hdr_buffer.lock().unwrap();
+
+        exr::prelude::write_rgb_file(file, res_x, self.res_y as usize, |x, y| {
+            let c = hdr[y * res_x + x];
+            (c.r, c.g, c.b)
+        })
+        .map_err(|e| std::io::Error::other(format!("{}: {e}", file.display())))?;
 
-        img.save(file).expect("png write");
         let elapsed = start_time.elapsed();
         let lat_msec = elapsed.as_millis() as f64 / 1000.0;
         println!(
@@ -76,3 +354,365 @@ impl Image {
         Ok(())
     }
 }
+
+pub const HISTOGRAM_MIN_EV: f32 = -10.0;
+pub const HISTOGRAM_MAX_EV: f32 = 4.0;
+
+/*
+ * Luminance histogram in exposure stops, ie. log2(luminance): 0 EV is the
+ * white point, anything above clips. Black pixels, which include the ones
+ * not rendered yet, are left out.
+ */
+pub struct Histogram {
+    pub bins: Vec<u32>,
+    pub mean_ev: f32,
+    pub clipped: f32, // fraction of the counted pixels
+}
+
+pub fn luminance_histogram(pixels: &[RGB], num_bins: usize) -> Histogram {
+    assert!(num_bins > 0);
+    let mut bins = vec![0; num_bins];
+    let mut sum_ev = 0.0;
+    let mut num_clipped = 0;
+    let mut n = 0;
+    let range = HISTOGRAM_MAX_EV - HISTOGRAM_MIN_EV;
+    for c in pixels {
+        let lum = c.luminance();
+        if lum <= 0.0 {
+            continue;
+        }
+        let ev = lum.log2();
+        let idx = ((ev - HISTOGRAM_MIN_EV) / range * num_bins as f32)
+            .clamp(0.0, (num_bins - 1) as f32) as usize;
+        bins[idx] += 1;
+        sum_ev += ev;
+        if lum > 1.0 {
+            num_clipped += 1;
+        }
+        n += 1;
+    }
+    let (mean_ev, clipped) = if n > 0 {
+        (sum_ev / n as f32, num_clipped as f32 / n as f32)
+    } else {
+        (0.0, 0.0)
+    };
+    Histogram {
+        bins,
+        mean_ev,
+        clipped,
+    }
+}
+
+/*
+ * Write per-pixel values as a false-color image: 0 is blue, the max value
+ * is red.
+ */
+pub fn save_heatmap(file: &Path, res_x: u32, res_y: u32, values: &[f32]) -> std::io::Result<()> {
+    assert_eq!(values.len(), (res_x * res_y) as usize);
+    let ramp = Gradient::new(vec![
+        (0.0, RGB::new(0.0, 0.0, 1.0)),
+        (0.5, RGB::new(0.0, 1.0, 0.0)),
+        (1.0, RGB::new(1.0, 0.0, 0.0)),
+    ]);
+    let max = values.iter().cloned().fold(0.0, f32::max);
+    let mut img = RgbImage::new(res_x, res_y);
+
+    for y in 0..res_y {
+        for x in 0..res_x {
+            let v = values[(y * res_x + x) as usize];
+            let c = ramp.eval(if max > 0.0 { v / max } else { 0.0 });
+            let r = (255.0 * c.r).clamp(0.0, 255.0) as u8;
+            let g = (255.0 * c.g).clamp(0.0, 255.0) as u8;
+            let b = (255.0 * c.b).clamp(0.0, 255.0) as u8;
+            img.put_pixel(x, y, Rgb([r, g, b]));
+        }
+    }
+    img.save(file).map_err(std::io::Error::other)?;
+    println!("writing '{}'", file.display().to_string().bold());
+    Ok(())
+}
+
+// write an image from the 8-bit rgb of each pixel index
+fn save_pixels(
+    file: &Path,
+    res_x: u32,
+    res_y: u32,
+    rgb: impl Fn(usize) -> [u8; 3],
+) -> std::io::Result<()> {
+    let mut img = RgbImage::new(res_x, res_y);
+    for y in 0..res_y {
+        for x in 0..res_x {
+            img.put_pixel(x, y, Rgb(rgb((y * res_x + x) as usize)));
+        }
+    }
+    img.save(file).map_err(std::io::Error::other)?;
+    println!("writing '{}'", file.display().to_string().bold());
+    Ok(())
+}
+
+/*
+ * Depth pass: the inverse distance relative to the nearest hit, which is
+ * white, so that a far horizon doesn't flatten the range of the rest.
+ * Misses are black.
+ */
+pub fn save_depth(
+    file: &Path,
+    res_x: u32,
+    res_y: u32,
+    depth: &[Option<f32>],
+) -> std::io::Result<()> {
+    assert_eq!(depth.len(), (res_x * res_y) as usize);
+    let min = depth.iter().flatten().cloned().fold(f32::MAX, f32::min);
+    save_pixels(file, res_x, res_y, |i| match depth[i] {
+        Some(d) => [(16.0 + 239.0 * min / d.max(min)).round() as u8; 3],
+        None => [0; 3],
+    })
+}
+
+// normal pass: -1..1 per axis to 0..255, misses are black
+pub fn save_normals(
+    file: &Path,
+    res_x: u32,
+    res_y: u32,
+    normals: &[Option<Vec3>],
+) -> std::io::Result<()> {
+    assert_eq!(normals.len(), (res_x * res_y) as usize);
+    let encode = |v: f32| (127.5 * (v + 1.0)).round().clamp(0.0, 255.0) as u8;
+    save_pixels(file, res_x, res_y, |i| match normals[i] {
+        Some(n) => [encode(n.x), encode(n.y), encode(n.z)],
+        None => [0; 3],
+    })
+}
+
+// matte: the coverage of each pixel, 0..1 to a single 8-bit channel
+pub fn save_matte(file: &Path, res_x: u32, res_y: u32, coverage: &[f32]) -> std::io::Result<()> {
+    assert_eq!(coverage.len(), (res_x * res_y) as usize);
+    let img = GrayImage::from_fn(res_x, res_y, |x, y| {
+        let v = coverage[(y * res_x + x) as usize];
+        Luma([(255.0 * v).round().clamp(0.0, 255.0) as u8])
+    });
+    img.save(file).map_err(std::io::Error::other)?;
+    println!("writing '{}'", file.display().to_string().bold());
+    Ok(())
+}
+
+// contact sheet: the thumbnails fit in a square cell, the label goes below
+const THUMB_SIZE: u32 = 160;
+const SHEET_MARGIN: u32 = 4;
+const LABEL_SCALE: u32 = 2;
+const LABEL_HEIGHT: u32 = 5 * LABEL_SCALE + 2 * SHEET_MARGIN;
+
+/*
+ * 3x5 glyphs for the labels, one row per byte with the left pixel in bit 2.
+ * Lowercase is drawn as uppercase, other characters as '?'.
+ */
+const FONT: [(char, [u8; 5]); 41] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+// text in white from the top-left corner x, y, cut at max_x
+fn draw_label(img: &mut RgbImage, x: u32, y: u32, max_x: u32, text: &str) {
+    let advance = 4 * LABEL_SCALE;
+    for (i, ch) in text.chars().enumerate() {
+        let x0 = x + i as u32 * advance;
+        if x0 + 3 * LABEL_SCALE > max_x {
+            break;
+        }
+        let ch = ch.to_ascii_uppercase();
+        let glyph = FONT
+            .iter()
+            .find(|(c, _)| *c == ch)
+            .unwrap_or(&FONT[FONT.len() - 1])
+            .1;
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let px = x0 + col * LABEL_SCALE + dx;
+                        let py = y + row as u32 * LABEL_SCALE + dy;
+                        img.put_pixel(px, py, Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/*
+ * One image tiling a thumbnail of each frame of a batch, with its file
+ * name below, to review the batch at a glance. The thumbnails are taken
+ * from the image buffers as they are saved.
+ */
+#[derive(Default)]
+pub struct ContactSheet {
+    thumbs: Vec<(String, RgbImage)>,
+}
+
+impl ContactSheet {
+    pub fn add(&mut self, label: &str, image: &Image) {
+        let scale = THUMB_SIZE as f32 / image.res_x.max(image.res_y) as f32;
+        let w = ((image.res_x as f32 * scale).round() as u32).max(1);
+        let h = ((image.res_y as f32 * scale).round() as u32).max(1);
+        let thumb = imageops::thumbnail(&image.to_rgb_image(), w, h);
+        self.thumbs.push((label.to_owned(), thumb));
+    }
+    pub fn len(&self) -> usize {
+        self.thumbs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.thumbs.is_empty()
+    }
+    // as square a grid as the number of thumbnails allows
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let n = self.thumbs.len() as u32;
+        let cols = (n as f32).sqrt().ceil().max(1.0) as u32;
+        let rows = n.div_ceil(cols).max(1);
+        let cell_w = THUMB_SIZE + SHEET_MARGIN;
+        let cell_h = THUMB_SIZE + LABEL_HEIGHT;
+        let background = Rgb([32, 32, 32]);
+        let mut img = RgbImage::from_pixel(
+            cols * cell_w + SHEET_MARGIN,
+            rows * cell_h + SHEET_MARGIN,
+            background,
+        );
+        for (i, (label, thumb)) in self.thumbs.iter().enumerate() {
+            let x = SHEET_MARGIN + (i as u32 % cols) * cell_w;
+            let y = SHEET_MARGIN + (i as u32 / cols) * cell_h;
+            // centered in the cell
+            let dx = (THUMB_SIZE - thumb.width()) / 2;
+            let dy = (THUMB_SIZE - thumb.height()) / 2;
+            imageops::replace(&mut img, thumb, (x + dx) as i64, (y + dy) as i64);
+            draw_label(
+                &mut img,
+                x,
+                y + THUMB_SIZE + SHEET_MARGIN,
+                x + THUMB_SIZE,
+                label,
+            );
+        }
+        img
+    }
+    pub fn save(&self, file: &Path) -> std::io::Result<()> {
+        self.to_rgb_image()
+            .save(file)
+            .map_err(std::io::Error::other)?;
+        println!(
+            "writing '{}': {} thumbnails",
+            file.display().to_string().bold(),
+            self.thumbs.len()
+        );
+        Ok(())
+    }
+}
+
+/*
+ * Expand an output filename template: {scene} is the scene file stem,
+ * {frame} and {spp} are numbers that accept a zero-padded width, eg.
+ * "{scene}_{frame:04}_{spp}.png". Unknown fields are left untouched.
+ */
+pub fn expand_template(template: &str, scene: &str, frame: u32, spp: u32) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            // an unclosed brace is kept as is
+            out.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+        let field = &rest[start + 1..start + len];
+        let (name, width) = match field.split_once(':') {
+            Some((name, fmt)) => (name, fmt.parse::<usize>().ok()),
+            None => (field, None),
+        };
+        let number = match name {
+            "frame" => Some(frame),
+            "spp" => Some(spp),
+            _ => None,
+        };
+        match (name, number) {
+            ("scene", _) => out.push_str(scene),
+            (_, Some(n)) => out.push_str(&format!("{:0w$}", n, w = width.unwrap_or(0))),
+            _ => out.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// the frame of an animation in the file name, eg. pic.png -> pic_0007.png
+pub fn frame_file_name(file: &Path, frame: u32) -> PathBuf {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match file.extension() {
+        Some(ext) => format!("{stem}_{frame:04}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{frame:04}"),
+    };
+    file.with_file_name(name)
+}
+
+pub fn output_path(
+    dir: Option<&Path>,
+    file: &Path,
+    template: Option<&str>,
+    scene_file: &Path,
+    frame: u32,
+    spp: u32,
+) -> PathBuf {
+    let name = match template {
+        Some(t) => {
+            let scene = scene_file.file_stem().unwrap_or_default().to_string_lossy();
+            PathBuf::from(expand_template(t, &scene, frame, spp))
+        }
+        None => file.to_path_buf(),
+    };
+    match dir {
+        Some(d) => d.join(name),
+        None => name,
+    }
+}
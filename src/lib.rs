@@ -1,22 +1,41 @@
 pub mod aabb;
+pub mod ao;
 pub mod app;
 pub mod camera;
 pub mod color;
+pub mod distribution;
+pub mod grid;
 pub mod image;
 pub mod light;
 pub mod material;
+pub mod model;
+pub mod object_bvh;
 pub mod scene;
+pub mod simd;
+pub mod texture;
 pub mod three_d;
 pub mod vec3;
 
 pub mod render;
 pub use app::egui_main;
 
+use vec3::Float;
 use vec3::Point;
 use vec3::Vec3;
 
+const RAY_OFFSET: Float = 1e-4;
+
 pub struct ProgressFunc {
     pub func: Box<dyn Fn(f32) + Send + Sync>,
+    // called along with func when set, with the times of the render
+    pub eta_func: Option<Box<dyn Fn(f32, ProgressEta) + Send + Sync>>,
+}
+
+// time spent on the render so far, and the time left once it can be told
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressEta {
+    pub elapsed: std::time::Duration,
+    pub remaining: Option<std::time::Duration>,
 }
 
 #[derive(Debug)]
@@ -24,6 +43,7 @@ pub struct Ray {
     pub orig: Point,
     pub dir: Vec3,
     pub inv_dir: Vec3, // aabb optimization
+    pub time: Float,   // motion blur: offset within the camera shutter
 }
 
 impl Ray {
@@ -33,10 +53,33 @@ impl Ray {
             orig: point,
             dir,
             inv_dir,
+            time: 0.0,
         }
     }
+    pub fn new_at(point: Point, dir: Vec3, time: Float) -> Ray {
+        let mut ray = Ray::new(point, dir);
+        ray.time = time;
+        ray
+    }
     pub fn get_reflection(&self, point: Point, normal: Vec3) -> Ray {
-        Ray::new(point, self.dir.reflect(normal))
+        let dir = self.dir.reflect(normal);
+        Ray::new_at(Ray::offset_origin(point, normal, dir), dir, self.time)
+    }
+    /*
+     * Origin of a secondary ray leaving the surface at point along dir,
+     * nudged off the surface so rounding errors don't make it hit the
+     * surface it starts from.
+     */
+    pub fn offset_origin(point: Point, normal: Vec3, dir: Vec3) -> Point {
+        Ray::offset_origin_by(point, normal, dir, RAY_OFFSET)
+    }
+    // the same, by offset along the normal, eg. the shadow bias
+    pub fn offset_origin_by(point: Point, normal: Vec3, dir: Vec3, offset: Float) -> Point {
+        if normal.dot(dir) < 0.0 {
+            point - normal * offset
+        } else {
+            point + normal * offset
+        }
     }
 }
 
@@ -48,7 +91,10 @@ pub struct RenderStats {
     pub num_rays_reflection_max: u64,
     pub num_intersects_plane: u64,
     pub num_intersects_sphere: u64,
+    pub num_intersects_cylinder: u64,
+    pub num_intersects_torus: u64,
     pub num_intersects_triangle: u64,
+    pub num_intersects_box: u64,
     pub num_intersects_aabb: u64,
 }
 
@@ -59,8 +105,21 @@ impl RenderStats {
         self.num_rays_reflection += other.num_rays_reflection;
         self.num_rays_reflection_max += other.num_rays_reflection_max;
         self.num_intersects_sphere += other.num_intersects_sphere;
+        self.num_intersects_cylinder += other.num_intersects_cylinder;
+        self.num_intersects_torus += other.num_intersects_torus;
         self.num_intersects_plane += other.num_intersects_plane;
         self.num_intersects_triangle += other.num_intersects_triangle;
+        self.num_intersects_box += other.num_intersects_box;
         self.num_intersects_aabb += other.num_intersects_aabb;
     }
+    /*
+     * For the intersection counters, bumped in the innermost loops: a
+     * no-op that compiles out in a build without the "stats" feature.
+     */
+    #[inline(always)]
+    pub fn count(&mut self, f: impl FnOnce(&mut Self)) {
+        if cfg!(feature = "stats") {
+            f(self);
+        }
+    }
 }
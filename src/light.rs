@@ -1,10 +1,16 @@
 use crate::Ray;
 use crate::color::RGB;
 use crate::material::Material;
+use crate::three_d::Object;
+use crate::three_d::Sphere;
+use crate::vec3::EPSILON;
+use crate::vec3::Float;
 use crate::vec3::Point;
 use crate::vec3::Vec3;
+use crate::vec3::fast_rand_float;
 use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[derive(Serialize, Deserialize)]
 pub struct AmbientLight {
@@ -21,6 +27,26 @@ pub struct SpotLight {
     pub intensity: f32,
 }
 
+/*
+ * Rectangle spanned by edge_u and edge_v from corner, lit on both sides.
+ * Shading is computed from its center, while the shadow rays aim at random
+ * points of its surface: the fraction that gets through gives the penumbra.
+ * The scene builder also adds the rectangle as an emissive object, so that
+ * the camera and the bounces see it; object_id is that object.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct AreaLight {
+    #[serde(skip)]
+    pub name: String,
+    pub corner: Point,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub rgb: RGB,
+    pub intensity: f32,
+    #[serde(skip)]
+    pub object_id: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct VectorLight {
     #[serde(skip)]
@@ -30,6 +56,163 @@ pub struct VectorLight {
     pub intensity: f32,
 }
 
+/*
+ * Debug aid: a small sphere drawn at a light position. It is only tested
+ * against camera rays so it neither casts shadows nor adds light.
+ */
+pub struct LightMarker {
+    pub sphere: Sphere,
+    pub rgb: RGB,
+}
+
+impl LightMarker {
+    pub fn new(pos: Point, radius: Float, rgb: RGB) -> Result<Self, String> {
+        Ok(Self {
+            sphere: Sphere::new(pos, radius, 0)?,
+            rgb,
+        })
+    }
+}
+
+/*
+ * Adapter exposing an emissive object as a Light, so that the ray-tracer
+ * (which only knows about lights) is lit by the same geometry as the
+ * path-tracer (which only knows about ke). The emitter is approximated by a
+ * single point: the average of a few stratified surface samples.
+ */
+pub struct ObjectLight {
+    pub name: String,
+    pub object: Arc<dyn Object + 'static + Send + Sync>,
+    pub object_id: usize,
+    pub ke: RGB,
+    pos: Point,
+    normal: Vec3, // zero when the emitter faces all directions, eg. a sphere
+    area: Float,
+}
+
+const OBJECT_LIGHT_SAMPLES: usize = 4;
+
+impl ObjectLight {
+    pub fn new(
+        name: String,
+        object: Arc<dyn Object + 'static + Send + Sync>,
+        object_id: usize,
+        ke: RGB,
+    ) -> Option<Self> {
+        let n = OBJECT_LIGHT_SAMPLES;
+        let mut pos = Vec3::zero();
+        let mut normal = Vec3::zero();
+        for i in 0..n {
+            for j in 0..n {
+                let u = (i as Float + 0.5) / n as Float;
+                let v = (j as Float + 0.5) / n as Float;
+                let (p, nrm) = object.sample_surface(u, v)?;
+                pos += p;
+                normal += nrm;
+            }
+        }
+        pos = pos / (n * n) as Float;
+        if normal.norm() > 0.5 * (n * n) as Float {
+            normal = normal.normalize();
+        } else {
+            normal = Vec3::zero();
+        }
+        let area = object.get_area();
+        Some(Self {
+            name,
+            object,
+            object_id,
+            ke,
+            pos,
+            normal,
+            area,
+        })
+    }
+}
+
+impl Light for ObjectLight {
+    fn get_contrib(
+        &self,
+        _ray: &Ray,
+        _mat: &Material,
+        kd: RGB,
+        obj_point: Point,
+        obj_normal: Vec3,
+    ) -> RGB {
+        let pi = std::f64::consts::PI as Float;
+        let light_vec = self.pos - obj_point;
+        let dist_sq = light_vec.length_squared();
+        let light_vec_norm = light_vec / dist_sq.sqrt();
+        let cos_surface = obj_normal.dot(light_vec_norm).max(0.0);
+        // emitters are two-sided; an omni-directional one shows a quarter of its area
+        let projected_area = if self.normal == Vec3::zero() {
+            self.area / 4.0
+        } else {
+            self.area * self.normal.dot(light_vec_norm).abs()
+        };
+        kd * self.ke * (cos_surface * projected_area / (pi * dist_sq))
+    }
+    fn display(&self) {
+        let s = format!("{:?} area={:.3} {:?}", self.pos, self.area, self.ke).dimmed();
+        println!("-- {:12}: {s}", self.name.blue());
+    }
+    fn get_vector(&self, point: Point) -> Vec3 {
+        point - self.pos
+    }
+    fn get_intensity(&self) -> f32 {
+        1.0
+    }
+    fn get_color(&self) -> RGB {
+        self.ke
+    }
+    fn is_ambient(&self) -> bool {
+        false
+    }
+    fn is_vector(&self) -> bool {
+        false
+    }
+    // positional: gets a shadow ray
+    fn is_spot(&self) -> bool {
+        true
+    }
+    fn get_position(&self) -> Option<Point> {
+        Some(self.pos)
+    }
+    fn get_object_id(&self) -> Option<usize> {
+        Some(self.object_id)
+    }
+    // a point of the surface, uniform over its area
+    // the density of the samples is only known when they are uniform
+    fn is_sampled(&self) -> bool {
+        self.object.is_sampled_uniformly()
+    }
+    fn sample(
+        &self,
+        point: Point,
+        time: Float,
+        rnd_state: &mut u64,
+    ) -> Option<(Vec3, Float, Float, RGB)> {
+        let u = fast_rand_float(rnd_state);
+        let v = fast_rand_float(rnd_state);
+        let (light_point, normal) = self.object.sample_surface(u, v)?;
+        // where a moving emitter is at the time of the ray
+        let light_point = light_point + self.object.get_velocity() * time;
+        let light_vec = light_point - point;
+        let dist = light_vec.norm();
+        if dist <= EPSILON {
+            return None;
+        }
+        let dir = light_vec / dist;
+        // two-sided, like the emitters the paths run into
+        let cos_light = normal.dot(dir).abs();
+        if cos_light <= EPSILON {
+            return None;
+        }
+        let pdf = dist * dist / (self.area * cos_light);
+        Some((dir, dist, pdf, self.ke))
+    }
+}
+
 pub trait Light {
     fn display(&self);
     fn get_vector(&self, point: Point) -> Vec3;
@@ -38,22 +221,203 @@ pub trait Light {
     fn is_ambient(&self) -> bool;
     fn is_vector(&self) -> bool;
     fn is_spot(&self) -> bool;
-    fn get_contrib(&self, ray: &Ray, mat: &Material, obj_point: Point, obj_normal: Vec3) -> RGB;
+    fn get_position(&self) -> Option<Point> {
+        None
+    }
+    // index in the scene objects of the geometry emitting this light
+    fn get_object_id(&self) -> Option<usize> {
+        None
+    }
+    // number of shadow rays to average, num_samples being the configured one
+    fn get_num_samples(&self, _num_samples: usize) -> usize {
+        1
+    }
+    // like get_vector, but towards a random point of the light surface
+    fn sample_vector(&self, point: Point, _rnd_state: &mut u64) -> Vec3 {
+        self.get_vector(point)
+    }
+    // whether the path-tracer aims at the light with sample()
+    fn is_sampled(&self) -> bool {
+        false
+    }
+    // a point light: its samples have no density, and no bounce can hit it
+    fn is_delta(&self) -> bool {
+        false
+    }
+    /*
+     * A random point of the light at time, for the path-tracer to aim at:
+     * the unit direction from point to it, its distance, the density of the
+     * sample per solid angle seen from point, and the radiance towards
+     * point. None when this sample cannot reach point.
+     */
+    fn sample(
+        &self,
+        _point: Point,
+        _time: Float,
+        _rnd_state: &mut u64,
+    ) -> Option<(Vec3, Float, Float, RGB)> {
+        None
+    }
+    // kd: the diffuse color at the point, the texture of mat applied
+    fn get_contrib(
+        &self,
+        ray: &Ray,
+        mat: &Material,
+        kd: RGB,
+        obj_point: Point,
+        obj_normal: Vec3,
+    ) -> RGB;
+}
+
+impl AreaLight {
+    pub fn get_center(&self) -> Point {
+        self.corner + (self.edge_u + self.edge_v) * 0.5
+    }
+    pub fn get_normal(&self) -> Vec3 {
+        self.edge_u.cross(self.edge_v).normalize()
+    }
+    pub fn get_area(&self) -> Float {
+        self.edge_u.cross(self.edge_v).norm()
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if self.get_area() <= 0.0 {
+            return Err("edge_u and edge_v span no area".to_owned());
+        }
+        if self.intensity < 0.0 {
+            return Err(format!("negative intensity {}", self.intensity));
+        }
+        Ok(())
+    }
+    pub fn sample_point(&self, u: Float, v: Float) -> Point {
+        self.corner + self.edge_u * u + self.edge_v * v
+    }
+}
+
+impl Light for AreaLight {
+    fn get_object_id(&self) -> Option<usize> {
+        self.object_id
+    }
+    fn get_contrib(
+        &self,
+        ray: &Ray,
+        mat: &Material,
+        kd: RGB,
+        obj_point: Point,
+        obj_normal: Vec3,
+    ) -> RGB {
+        let light_vec = self.get_center() - obj_point;
+        let dist_sq = light_vec.length_squared();
+        let light_vec_norm = light_vec / dist_sq.sqrt();
+        let cos_light = self.get_normal().dot(light_vec_norm).abs();
+        let mut c_res = kd * obj_normal.dot(light_vec_norm).max(0.0);
+
+        let reflected_ray = ray.get_reflection(obj_point, obj_normal);
+        let dir = reflected_ray.dir.normalize();
+        c_res += self.rgb * mat.ks * light_vec_norm.dot(dir).max(0.0).powi(80);
+
+        c_res * (self.intensity * cos_light / (1.0 + dist_sq))
+    }
+    fn display(&self) {
+        let s = format!(
+            "{:3} {:?} area={:.3} {:?}",
+            self.intensity,
+            self.get_center(),
+            self.get_area(),
+            self.rgb
+        )
+        .dimmed();
+        println!("-- {:12}: {s}", self.name.blue());
+    }
+    fn get_vector(&self, point: Point) -> Vec3 {
+        point - self.get_center()
+    }
+    fn get_intensity(&self) -> f32 {
+        self.intensity
+    }
+    fn get_color(&self) -> RGB {
+        self.rgb
+    }
+    fn is_ambient(&self) -> bool {
+        false
+    }
+    fn is_vector(&self) -> bool {
+        false
+    }
+    // positional: gets shadow rays
+    fn is_spot(&self) -> bool {
+        true
+    }
+    fn get_position(&self) -> Option<Point> {
+        Some(self.get_center())
+    }
+    fn get_num_samples(&self, num_samples: usize) -> usize {
+        num_samples.max(1)
+    }
+    fn is_sampled(&self) -> bool {
+        true
+    }
+    // uniform over the rectangle, which emits rgb * intensity on both sides
+    fn sample(
+        &self,
+        point: Point,
+        _time: Float,
+        rnd_state: &mut u64,
+    ) -> Option<(Vec3, Float, Float, RGB)> {
+        let u = fast_rand_float(rnd_state);
+        let v = fast_rand_float(rnd_state);
+        let light_vec = self.sample_point(u, v) - point;
+        let dist = light_vec.norm();
+        if dist <= EPSILON {
+            return None;
+        }
+        let dir = light_vec / dist;
+        let cos_light = self.get_normal().dot(dir).abs();
+        if cos_light <= EPSILON {
+            return None;
+        }
+        let pdf = dist * dist / (self.get_area() * cos_light);
+        Some((dir, dist, pdf, self.rgb * self.intensity))
+    }
+    fn sample_vector(&self, point: Point, rnd_state: &mut u64) -> Vec3 {
+        let u = fast_rand_float(rnd_state);
+        let v = fast_rand_float(rnd_state);
+        point - self.sample_point(u, v)
+    }
 }
 
 impl Light for SpotLight {
-    fn get_contrib(&self, ray: &Ray, mat: &Material, obj_point: Point, obj_normal: Vec3) -> RGB {
+    fn get_contrib(
+        &self,
+        ray: &Ray,
+        mat: &Material,
+        kd: RGB,
+        obj_point: Point,
+        obj_normal: Vec3,
+    ) -> RGB {
         let mut c_res;
 
         let light_vec = self.pos - obj_point;
-        let dist_sq = light_vec.dot(light_vec);
+        let dist_sq = light_vec.length_squared();
         let light_vec_norm = light_vec / dist_sq.sqrt();
-        c_res = mat.kd * obj_normal.dot(light_vec_norm).max(0.0);
+        c_res = kd * obj_normal.dot(light_vec_norm).max(0.0);
 
         {
             let reflected_ray = ray.get_reflection(obj_point, obj_normal);
             let dir = reflected_ray.dir.normalize();
             c_res += self.rgb * mat.ks * light_vec_norm.dot(dir).powi(80);
+            if mat.clearcoat > 0.0 {
+                /*
+                 * White highlight of the varnish, on top of the base. The
+                 * lobe is normalized: the smoother the coat, the tighter and
+                 * brighter the highlight.
+                 */
+                let pi = std::f64::consts::PI as Float;
+                let cos_theta = -ray.dir.normalize().dot(obj_normal);
+                let kc = mat.get_clearcoat_weight(cos_theta.abs());
+                let n = mat.get_clearcoat_exponent();
+                let spec = light_vec_norm.dot(dir).max(0.0).powf(n) * (n + 2.0) / (2.0 * pi);
+                c_res += self.rgb * (kc * spec);
+            }
         }
 
         c_res * self.intensity / (1.0 + dist_sq)
@@ -84,11 +448,42 @@ impl Light for SpotLight {
     fn is_spot(&self) -> bool {
         true
     }
+    fn get_position(&self) -> Option<Point> {
+        Some(self.pos)
+    }
+    fn is_sampled(&self) -> bool {
+        true
+    }
+    fn is_delta(&self) -> bool {
+        true
+    }
+    // the one point of the light, falling off with the square of the distance
+    fn sample(
+        &self,
+        point: Point,
+        _time: Float,
+        _rnd_state: &mut u64,
+    ) -> Option<(Vec3, Float, Float, RGB)> {
+        let light_vec = self.pos - point;
+        let dist = light_vec.norm();
+        if dist <= EPSILON {
+            return None;
+        }
+        let radiance = self.rgb * (self.intensity / (dist * dist));
+        Some((light_vec / dist, dist, 1.0, radiance))
+    }
 }
 
 impl Light for AmbientLight {
-    fn get_contrib(&self, _ray: &Ray, mat: &Material, _obj_point: Point, _obj_normal: Vec3) -> RGB {
-        mat.kd * self.rgb * self.intensity
+    fn get_contrib(
+        &self,
+        _ray: &Ray,
+        _mat: &Material,
+        kd: RGB,
+        _obj_point: Point,
+        _obj_normal: Vec3,
+    ) -> RGB {
+        kd * self.rgb * self.intensity
     }
     fn display(&self) {
         let s = format!("{:3} {:?}", self.intensity, self.rgb).dimmed();
@@ -119,9 +514,16 @@ impl Light for AmbientLight {
 }
 
 impl Light for VectorLight {
-    fn get_contrib(&self, _ray: &Ray, mat: &Material, obj_point: Point, obj_normal: Vec3) -> RGB {
-        let c_res = mat.kd * self.rgb * self.intensity;
-        let light_vec = self.get_vector(obj_point) * -1.0;
+    fn get_contrib(
+        &self,
+        _ray: &Ray,
+        _mat: &Material,
+        kd: RGB,
+        obj_point: Point,
+        obj_normal: Vec3,
+    ) -> RGB {
+        let c_res = kd * self.rgb * self.intensity;
+        let light_vec = -self.get_vector(obj_point);
         let v_prod = obj_normal.dot(light_vec).min(0.0);
 
         c_res * v_prod.powi(4)
@@ -134,7 +536,7 @@ impl Light for VectorLight {
         println!("-- {:12}: {s}", self.name.blue());
     }
     fn get_vector(&self, _point: Point) -> Vec3 {
-        self.dir * -1.0
+        -self.dir
     }
     fn get_intensity(&self) -> f32 {
         self.intensity
@@ -1,5 +1,6 @@
 use colored::Colorize;
 use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
 
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -7,15 +8,43 @@ use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use structopt::StructOpt;
 
+use rayflex::camera::CameraPath;
+use rayflex::camera::PathInterpolation;
+use rayflex::color::RGB;
+use rayflex::image::ContactSheet;
+use rayflex::image::Grade;
+use rayflex::image::OutputFormat;
+use rayflex::image::ToneMap;
+use rayflex::image::frame_file_name;
+use rayflex::render::Aov;
+use rayflex::render::BounceConfig;
+use rayflex::render::Integrator;
 use rayflex::render::RenderConfig;
+use rayflex::render::TILE_SPLIT_TIME;
+use rayflex::scene::generate_furnace_scene;
 use rayflex::scene::generate_scene;
 use rayflex::scene::load_scene;
+use rayflex::simd::SimdLevel;
+use rayflex::vec3::Float;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "rayflex", about = "ray/path-tracer")]
 struct Options {
-    #[structopt(long, default_value = "pic.png")]
+    #[structopt(
+        long,
+        default_value = "pic.png",
+        help = "a .png, .jpg, .ppm or .exr file"
+    )]
     img_file: PathBuf,
+    #[structopt(long, default_value = "90", help = "quality of a .jpg image, 1 to 100")]
+    jpeg_quality: u8,
+    #[structopt(long, help = "directory for the output image(s)")]
+    output_dir: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "output filename template, eg. {scene}_{frame:04}_{spp}.png"
+    )]
+    output_template: Option<String>,
     #[structopt(short = "l", long, default_value = "scene.json")]
     scene_file: PathBuf,
     #[structopt(short = "x", long, default_value = "0")]
@@ -28,45 +57,326 @@ struct Options {
     adaptive_max_depth: u32,
     #[structopt(long, default_value = "6")]
     reflection_max_depth: u32,
+    #[structopt(
+        long,
+        help = "bounces of the path tracer [default: --reflection-max-depth]"
+    )]
+    max_bounces: Option<u32>,
+    #[structopt(long, help = "end the paths at random past this many bounces")]
+    rr_min_depth: Option<u32>,
+    #[structopt(long, default_value = "64", help = "rays per vertex of --bake-ao")]
+    ao_samples: usize,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "stop reflecting once the ray weight falls below this"
+    )]
+    reflection_cutoff: f32,
     #[structopt(short = "b", long, default_value = "1")]
     add_box: u32,
     #[structopt(short = "g", long, help = "use gamma correction")]
     use_gamma: bool,
+    #[structopt(
+        long,
+        default_value = "clamp",
+        help = "tone mapping of the LDR output: clamp, reinhard or aces"
+    )]
+    tonemap: ToneMap,
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "color saturation of the output, 0 is gray"
+    )]
+    saturation: f32,
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "contrast of the output around mid-gray"
+    )]
+    contrast: f32,
+    #[structopt(
+        long,
+        default_value = "0",
+        help = "white balance shift, -1 (cool) to 1 (warm)"
+    )]
+    temperature: f32,
     #[structopt(short = "a", long)]
     use_adaptive_sampling: bool,
     #[structopt(long, help = "scan per line vs box")]
     use_lines: bool,
     #[structopt(long, help = "use hashmap to speed-up antialiasing")]
     use_hashmap: bool,
-    #[structopt(short = "-p", long, help = "do path tracing", default_value = "1")]
+    #[structopt(long, help = "blend the neighbouring pixels in, with -a")]
+    use_bilinear: bool,
+    #[structopt(
+        short = "-p",
+        long,
+        help = "deprecated: path tracing w/ N samples if N > 1",
+        default_value = "1"
+    )]
     path_tracing: u32,
+    #[structopt(long, help = "path or whitted")]
+    integrator: Option<Integrator>,
+    #[structopt(long, alias = "sample-count", help = "samples per pixel")]
+    spp: Option<u32>,
     #[structopt(short = "-u", long, help = "use ui")]
     use_ui: bool,
+    #[structopt(long, help = "save per-tile render time as <img>-timing.png")]
+    timing_heatmap: bool,
+    #[structopt(long, help = "draw a marker at each spot-light position")]
+    show_lights: bool,
+    #[structopt(long, help = "warn about scene keys that were not loaded")]
+    warn_unused_keys: bool,
+    #[structopt(
+        long,
+        help = "skip the meshes whose file cannot be loaded, with a warning"
+    )]
+    skip_missing_meshes: bool,
+    #[structopt(long, help = "interpolate mesh vertex normals")]
+    smooth_normals: bool,
+    #[structopt(long, help = "flip the mesh triangles that face inward")]
+    fix_winding: bool,
+    #[structopt(long, help = "supersample only the pixels on edges")]
+    edge_aa: bool,
+    #[structopt(
+        long,
+        help = "light the path-traced scene with the background gradient"
+    )]
+    sky_light: bool,
+    #[structopt(
+        long,
+        help = "find the emitters by the path-tracer bounces only, without aiming"
+    )]
+    no_light_sampling: bool,
+    #[structopt(long, help = "uniform grid over the loose objects, eg. for -n scenes")]
+    grid: bool,
+    #[structopt(
+        long,
+        help = "test every object against every ray, without the object bvh"
+    )]
+    no_bvh: bool,
+    #[structopt(
+        long,
+        help = "keep slow tiles whole instead of splitting them across threads"
+    )]
+    no_tile_split: bool,
+    #[structopt(
+        long,
+        help = "path trace in N passes over the whole image, each adding samples"
+    )]
+    progressive: Option<u32>,
+    #[structopt(
+        long,
+        help = "clamp the luminance of each path sample, against fireflies"
+    )]
+    firefly_clamp: Option<f32>,
+    #[structopt(long, default_value = "0", help = "index of the scene camera")]
+    camera: usize,
+    #[structopt(
+        long,
+        alias = "frame-start",
+        default_value = "0",
+        help = "frame number of an animation, the first one with --frames"
+    )]
+    frame: u32,
+    #[structopt(long, default_value = "1", help = "number of frames to render")]
+    frames: u32,
+    #[structopt(long, help = "json list of camera keyframes {pos, look_at, up, vfov}")]
+    camera_path: Option<PathBuf>,
+    #[structopt(
+        long,
+        default_value = "linear",
+        help = "interpolation of the camera path: linear or catmull-rom"
+    )]
+    camera_interp: PathInterpolation,
+    #[structopt(long, help = "seed of the random sampling, for reproducible noise")]
+    seed: Option<u64>,
+    #[structopt(
+        long,
+        help = "mix the frame number into --seed: the noise changes per frame"
+    )]
+    seed_per_frame: bool,
+    #[structopt(long, default_value = "16", help = "shadow rays per area light")]
+    shadow_samples: usize,
+    #[structopt(
+        long,
+        default_value = "0.001",
+        help = "offset of the shadow rays off the surface, against shadow acne"
+    )]
+    shadow_bias: f32,
+    #[structopt(long, help = "bake the ambient occlusion of the meshes and cache it")]
+    bake_ao: bool,
+    #[structopt(
+        long,
+        help = "trace and print the stats without storing or saving the image"
+    )]
+    stats_only: bool,
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "extra outputs next to the image: depth, normal, eg. --aov depth,normal"
+    )]
+    aov: Vec<Aov>,
+    #[structopt(
+        long,
+        help = "save the anti-aliased matte of one object next to the image, eg. --matte sphere.1"
+    )]
+    matte: Option<String>,
+    #[structopt(
+        long,
+        help = "tile a thumbnail of each saved frame in one image, eg. grid.png"
+    )]
+    contact_sheet: Option<PathBuf>,
+    #[structopt(long, help = "check the path-tracer conserves energy")]
+    furnace_test: bool,
+    #[structopt(long, help = "print the version, build and cpu info, then exit")]
+    info: bool,
 }
 
-fn print_opt(opt: &Options) {
+/*
+ * --path-tracing N used to mean both "use the path tracer" (N > 1) and the
+ * number of samples per pixel: it is still honored when --integrator and
+ * --spp are not given.
+ */
+fn get_integrator(opt: &Options) -> (Integrator, u32) {
+    if opt.path_tracing > 1 {
+        println!(
+            "{}: --path-tracing is deprecated, use --integrator path --spp {}",
+            "warning".yellow(),
+            opt.path_tracing
+        );
+    }
+    let integrator = opt.integrator.unwrap_or(if opt.path_tracing > 1 {
+        Integrator::Path
+    } else {
+        Integrator::Whitted
+    });
+    let spp = opt.spp.unwrap_or(opt.path_tracing.max(1));
+    (integrator, spp)
+}
+
+fn print_opt(opt: &Options, integrator: Integrator, spp: u32) {
     println!(
-        "{}: gamma={} sampling-depth={} reflection-depth={}",
+        "{}: gamma={} tonemap={:?} sampling-depth={} reflection-depth={} bounces={}",
         "option".yellow(),
         opt.use_gamma,
+        opt.tonemap,
         opt.adaptive_max_depth,
         opt.reflection_max_depth,
+        opt.max_bounces.unwrap_or(opt.reflection_max_depth),
     );
     println!(
-        "{}: lines={} hashmap={} path_tracing={}",
+        "{}: lines={} hashmap={} integrator={:?} spp={} simd={:?}",
         "option".yellow(),
         opt.use_lines,
         opt.use_hashmap,
-        opt.path_tracing,
+        integrator,
+        spp,
+        SimdLevel::detect(),
     );
     let s = format!("num_threads: {}", rayon::current_num_threads()).red();
     println!("{s}");
 }
 
+// cpu features that matter to the kernels, or may soon
+fn get_cpu_features() -> Vec<&'static str> {
+    #[cfg(target_arch = "x86_64")]
+    let features = [
+        ("sse4.1", is_x86_feature_detected!("sse4.1")),
+        ("avx", is_x86_feature_detected!("avx")),
+        ("avx2", is_x86_feature_detected!("avx2")),
+        ("fma", is_x86_feature_detected!("fma")),
+        ("avx512f", is_x86_feature_detected!("avx512f")),
+    ];
+    #[cfg(not(target_arch = "x86_64"))]
+    let features: [(&str, bool); 0] = [];
+    features.iter().filter(|f| f.1).map(|f| f.0).collect()
+}
+
+/*
+ * Build and runtime facts for bug reports, without loading any scene.
+ */
+fn print_info() {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    println!("rayflex {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "build: {profile} {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+    println!("float: f{}", 8 * std::mem::size_of::<Float>());
+    println!("simd: {:?}", SimdLevel::detect());
+    println!(
+        "stats: {}",
+        if cfg!(feature = "stats") { "on" } else { "off" }
+    );
+    let features = get_cpu_features();
+    if features.is_empty() {
+        println!("cpu features: none detected");
+    } else {
+        println!("cpu features: {}", features.join(" "));
+    }
+    println!("num_threads: {}", rayon::current_num_threads());
+}
+
+// max relative error of the furnace test
+const FURNACE_TOLERANCE: f32 = 0.02;
+// deep enough for the energy lost to path truncation to be negligible
+const FURNACE_MAX_DEPTH: u32 = 50;
+/*
+ * The light sampling makes the estimate noisy, where the bounces alone
+ * always found the emitter: enough samples to stay well within the
+ * tolerance, and a seed so that the outcome is the same from run to run.
+ */
+const FURNACE_SPP: u32 = 64;
+const FURNACE_SEED: u64 = 1;
+
+/*
+ * Render the furnace scene with the path-tracer and compare the average
+ * luminance to the emitter's: a ratio away from 1 means the throughput
+ * gains or loses energy. Exits with an error on failure.
+ */
+fn furnace_test(opt: &Options, exit_req: Arc<AtomicBool>) -> std::io::Result<()> {
+    let emission = RGB::new(0.5, 0.5, 0.5);
+    let scene_file = std::env::temp_dir().join("rayflex-furnace.json");
+    generate_furnace_scene(scene_file.clone(), emission)?;
+
+    let cfg = RenderConfig {
+        bounces: BounceConfig {
+            path_max_bounces: FURNACE_MAX_DEPTH,
+            rr_min_depth: opt.rr_min_depth,
+            ..Default::default()
+        },
+        seed: Some(opt.seed.unwrap_or(FURNACE_SEED)),
+        ..Default::default()
+    }
+    .with_integrator(Integrator::Path, opt.spp.unwrap_or(FURNACE_SPP))
+    .with_scene_file(scene_file);
+    let mut job = load_scene(cfg)?;
+    job.alloc_image();
+    job.render_scene(exit_req);
+
+    let ratio = job.average_luminance() / emission.luminance();
+    if (ratio - 1.0).abs() > FURNACE_TOLERANCE {
+        println!("furnace test: ratio={ratio:.4} {}", "FAIL".red().bold());
+        std::process::exit(1);
+    }
+    println!("furnace test: ratio={ratio:.4} {}", "PASS".green().bold());
+    Ok(())
+}
+
 fn main() -> std::io::Result<()> {
     tracing_subscriber::fmt::init();
 
     let opt = Options::from_args();
+    if opt.info {
+        print_info();
+        return Ok(());
+    }
     let exit_req = Arc::new(AtomicBool::new(false));
     let exit_req_clone = exit_req.clone();
 
@@ -84,35 +394,229 @@ fn main() -> std::io::Result<()> {
         return generate_scene(opt.num_spheres_to_generate, opt.scene_file, opt.add_box > 0);
     }
 
-    print_opt(&opt);
+    if opt.furnace_test {
+        return furnace_test(&opt, exit_req);
+    }
+
+    let (integrator, spp) = get_integrator(&opt);
+    print_opt(&opt, integrator, spp);
+    if opt.seed_per_frame && opt.seed.is_none() {
+        println!(
+            "{}: --seed-per-frame has no effect without --seed",
+            "warning".yellow()
+        );
+    }
+    if opt.frames == 0 {
+        eprintln!("{} --frames must be at least 1", "error:".red().bold());
+        std::process::exit(1);
+    }
+    if opt.frames > 1
+        && let Some(t) = &opt.output_template
+        && !t.contains("{frame")
+    {
+        println!(
+            "{}: --output-template has no {{frame}}, each frame overwrites the last",
+            "warning".yellow()
+        );
+    }
+    if !opt.aov.is_empty() && opt.stats_only {
+        println!("{}: --aov is ignored with --stats-only", "warning".yellow());
+    }
+    if opt.matte.is_some() && opt.stats_only {
+        println!(
+            "{}: --matte is ignored with --stats-only",
+            "warning".yellow()
+        );
+    }
+    if opt.contact_sheet.is_some() && opt.stats_only {
+        println!(
+            "{}: --contact-sheet is ignored with --stats-only",
+            "warning".yellow()
+        );
+    }
+    if opt.edge_aa && opt.stats_only {
+        println!(
+            "{}: --edge-aa is ignored with --stats-only",
+            "warning".yellow()
+        );
+    }
+    if opt.edge_aa && integrator == Integrator::Path {
+        println!(
+            "{}: --edge-aa is ignored by the path integrator",
+            "warning".yellow()
+        );
+    }
+    if opt
+        .firefly_clamp
+        .is_some_and(|max| max.is_nan() || max <= 0.0)
+    {
+        eprintln!("{} --firefly-clamp must be positive", "error:".red().bold());
+        std::process::exit(1);
+    }
+    if !(opt.shadow_bias >= 0.0 && opt.shadow_bias.is_finite()) {
+        eprintln!(
+            "{} --shadow-bias must not be negative",
+            "error:".red().bold()
+        );
+        std::process::exit(1);
+    }
+    if opt.firefly_clamp.is_some() && integrator != Integrator::Path {
+        println!(
+            "{}: --firefly-clamp is ignored by the whitted integrator",
+            "warning".yellow()
+        );
+    }
+    if !(1..=100).contains(&opt.jpeg_quality) {
+        eprintln!("{} --jpeg-quality must be 1 to 100", "error:".red().bold());
+        std::process::exit(1);
+    }
+    if opt.progressive.is_some() && integrator != Integrator::Path {
+        println!(
+            "{}: --progressive is ignored by the whitted integrator",
+            "warning".yellow()
+        );
+    }
 
     let cfg = RenderConfig {
         use_adaptive_sampling: opt.use_adaptive_sampling,
         use_gamma: opt.use_gamma,
-        reflection_max_depth: opt.reflection_max_depth,
+        tonemap: opt.tonemap,
+        grade: Grade {
+            saturation: opt.saturation,
+            contrast: opt.contrast,
+            temperature: opt.temperature,
+        },
+        timing_heatmap: opt.timing_heatmap,
+        show_lights: opt.show_lights,
+        warn_unused_keys: opt.warn_unused_keys,
+        skip_missing_meshes: opt.skip_missing_meshes,
+        use_smooth_normals: opt.smooth_normals,
+        fix_winding: opt.fix_winding,
+        use_edge_aa: opt.edge_aa,
+        use_sky_light: opt.sky_light,
+        use_light_sampling: !opt.no_light_sampling,
+        use_grid: opt.grid,
+        use_bvh: !opt.no_bvh,
+        tile_split_time: (!opt.no_tile_split).then_some(TILE_SPLIT_TIME),
+        progressive: opt.progressive,
+        firefly_clamp: opt.firefly_clamp,
+        camera: opt.camera,
+        bounces: BounceConfig {
+            reflection_max_depth: opt.reflection_max_depth,
+            // the path tracer used to share the whitted depth
+            path_max_bounces: opt.max_bounces.unwrap_or(opt.reflection_max_depth),
+            rr_min_depth: opt.rr_min_depth,
+            ao_num_samples: opt.ao_samples,
+        },
+        reflection_cutoff: opt.reflection_cutoff,
         adaptive_max_depth: opt.adaptive_max_depth,
         res_x: opt.res_x,
         res_y: opt.res_y,
         use_lines: opt.use_lines,
         use_hashmap: opt.use_hashmap,
-        path_tracing: opt.path_tracing,
+        use_bilinear: opt.use_bilinear,
+        integrator,
+        samples_per_pixel: spp,
         scene_file: opt.scene_file,
         image_file: opt.img_file,
+        jpeg_quality: opt.jpeg_quality,
+        output_dir: opt.output_dir,
+        output_template: opt.output_template,
+        frame: opt.frame,
+        seed: opt.seed,
+        seed_per_frame: opt.seed_per_frame,
+        num_shadow_samples: opt.shadow_samples,
+        shadow_bias: opt.shadow_bias,
+        bake_ao: opt.bake_ao,
+        stats_only: opt.stats_only,
+        aovs: opt.aov.clone(),
+        matte: opt.matte.clone(),
+        // adaptive_threshold: set by the scene only
+        ..Default::default()
     };
 
-    let res = load_scene(cfg);
-    let mut job = res.unwrap();
+    let camera_path = match &opt.camera_path {
+        Some(file) => match CameraPath::load(file, opt.camera_interp) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("{} {}: {e}", "error:".red().bold(), file.display());
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let mut job = match load_scene(cfg) {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("{} {e}", "error:".red().bold());
+            std::process::exit(1);
+        }
+    };
 
-    let pb = Arc::new(ProgressBar::new(1000));
-    let pb_clone = pb.clone();
-    job.set_progress_func(Box::new(move |pct| {
-        pb_clone.set_position((pct * 1000.0) as u64);
-    }));
-    job.alloc_image();
-    job.render_scene(exit_req);
-    pb.finish_and_clear();
-    job.print_stats();
-    job.save_image()?;
+    // rather than after the render
+    if !opt.stats_only
+        && let Err(e) = OutputFormat::from_path(&job.output_path())
+    {
+        eprintln!("{} {e}", "error:".red().bold());
+        std::process::exit(1);
+    }
+
+    // the scene is loaded once, only the camera and the frame change
+    let img_file = job.cfg.image_file.clone();
+    let mut contact_sheet = ContactSheet::default();
+    for i in 0..opt.frames {
+        let frame = opt.frame + i;
+        job.set_frame(frame);
+        if let Some(path) = &camera_path {
+            let t = i as f32 / (opt.frames - 1).max(1) as f32;
+            path.apply(&mut job.camera, t);
+        }
+        if opt.frames > 1 {
+            println!("{} {}/{}", "frame".green(), i + 1, opt.frames);
+            job.cfg.image_file = frame_file_name(&img_file, frame);
+        }
+
+        let pb = Arc::new(ProgressBar::new(1000));
+        pb.set_style(ProgressStyle::with_template("{wide_bar} {percent:>3}% {msg}").unwrap());
+        let pb_clone = pb.clone();
+        job.set_progress_func(Box::new(move |pct| {
+            pb_clone.set_position((pct * 1000.0) as u64);
+        }));
+        let pb_clone = pb.clone();
+        job.set_eta_func(Box::new(move |_, eta| {
+            let elapsed = eta.elapsed.as_secs_f32();
+            pb_clone.set_message(match eta.remaining {
+                Some(left) => format!("{elapsed:.1}s, {:.1}s left", left.as_secs_f32()),
+                None => format!("{elapsed:.1}s"),
+            });
+        }));
+        job.alloc_image();
+        job.render_scene(exit_req.clone());
+        pb.finish_and_clear();
+        job.print_stats();
+
+        // an interrupted frame of a sequence is incomplete, it is not saved
+        let interrupted = exit_req.load(Ordering::SeqCst);
+        if !opt.stats_only && (!interrupted || opt.frames == 1) {
+            job.save_image()?;
+            if opt.contact_sheet.is_some() {
+                let file = job.output_path();
+                let label = file.file_name().unwrap_or_default().to_string_lossy();
+                contact_sheet.add(&label, &job.image.lock().unwrap());
+            }
+        }
+        if interrupted {
+            if opt.frames > 1 {
+                println!("{}: interrupted at frame {frame}", "warning".yellow());
+            }
+            break;
+        }
+    }
+    if let Some(file) = &opt.contact_sheet
+        && !contact_sheet.is_empty()
+    {
+        contact_sheet.save(file)?;
+    }
 
     Ok(())
 }
@@ -1,10 +1,16 @@
 use crate::color::RGB;
+use crate::texture::Texture;
+use crate::vec3::EPSILON;
 use crate::vec3::Vec2;
-use serde::{Deserialize, Serialize};
+use crate::vec3::Vec3;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// the default, like an empty JSON material, is black
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Material {
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_ks")]
     pub ks: RGB,
     #[serde(default)]
     pub kd: RGB,
@@ -14,12 +20,147 @@ pub struct Material {
     pub shininess: f32, // 0 --> ~1000
     #[serde(default)]
     pub checkered: bool,
+    #[serde(default)]
+    pub ior: f32, // index of refraction, 0 is treated as 1
+    #[serde(default)]
+    pub transparency: f32, // 0 --> 1
+    #[serde(default)]
+    pub clearcoat: f32, // 0 --> 1
+    #[serde(default)]
+    pub clearcoat_roughness: f32, // 0 --> 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diffuse_map: Option<PathBuf>,
+    // loaded from diffuse_map
+    #[serde(skip)]
+    pub diffuse_texture: Option<Arc<Texture>>,
+    // tangent space: rgb along u, v and the normal of the surface
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normal_map: Option<PathBuf>,
+    // loaded from normal_map
+    #[serde(skip)]
+    pub normal_texture: Option<Arc<Texture>>,
+    // lets the objects say "material": "red" instead of a material_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
 }
 
 impl Material {
     pub fn do_checker(&self, c: RGB, text2d: Vec2) -> RGB {
         assert!(self.checkered);
-        let pattern = ((text2d.x * 4.0).fract() > 0.5) ^ ((text2d.y * 4.0).fract() > 0.5);
+        let pattern =
+            ((text2d.x * CHECKER_FREQ).fract() > 0.5) ^ ((text2d.y * CHECKER_FREQ).fract() > 0.5);
         if pattern { c / 3.0 } else { c }
     }
+    // width in texture space of the cells of a procedural pattern, if any
+    pub fn get_pattern_cell(&self) -> Option<f32> {
+        self.checkered.then_some(0.5 / CHECKER_FREQ)
+    }
+    // diffuse color at texture coordinates uv
+    pub fn get_kd(&self, uv: Vec2) -> RGB {
+        match &self.diffuse_texture {
+            Some(texture) => self.kd * texture.sample(uv),
+            None => self.kd,
+        }
+    }
+    /*
+     * The normal bent by the normal map at uv: its rgb in 0..1 maps to
+     * -1..1 along the tangent, the bitangent and the normal, oriented by
+     * tangents, ie. dP/du and dP/dv. Unchanged without a normal map or
+     * tangents.
+     */
+    pub fn get_shading_normal(&self, normal: Vec3, tangents: Option<[Vec3; 2]>, uv: Vec2) -> Vec3 {
+        let (Some(texture), Some([dpdu, dpdv])) = (&self.normal_texture, tangents) else {
+            return normal;
+        };
+        let c = texture.sample(uv);
+        let t = (dpdu - normal * normal.dot(dpdu)).normalize();
+        // keeps the handedness of the uvs, which may be mirrored
+        let b = normal.cross(t);
+        let b = if b.dot(dpdv) < 0.0 { -b } else { b };
+        let n = t * (2.0 * c.r - 1.0) + b * (2.0 * c.g - 1.0) + normal * (2.0 * c.b - 1.0);
+        if n.is_finite() && n.norm() > EPSILON {
+            n.normalize()
+        } else {
+            normal
+        }
+    }
+    pub fn get_ior(&self) -> f32 {
+        if self.ior > 0.0 { self.ior } else { 1.0 }
+    }
+    /*
+     * Reflectance at normal incidence: from the ior for dielectrics, from
+     * ks per channel otherwise, so that a colored ks tints the reflection.
+     * Clamped to 1: a reflection never adds energy.
+     */
+    pub fn get_f0(&self) -> RGB {
+        if self.transparency > 0.0 {
+            let r = (self.get_ior() - 1.0) / (self.get_ior() + 1.0);
+            RGB::new(r * r, r * r, r * r)
+        } else {
+            let ks = self.ks;
+            RGB::new(
+                ks.r.clamp(0.0, 1.0),
+                ks.g.clamp(0.0, 1.0),
+                ks.b.clamp(0.0, 1.0),
+            )
+        }
+    }
+    // share of the light reflected per channel for cos_theta
+    pub fn get_reflectance(&self, cos_theta: f32) -> RGB {
+        let f0 = self.get_f0();
+        RGB::new(
+            fresnel_schlick(cos_theta, f0.r),
+            fresnel_schlick(cos_theta, f0.g),
+            fresnel_schlick(cos_theta, f0.b),
+        )
+    }
+    /*
+     * Phong exponent of the clearcoat highlight, from its roughness: the
+     * usual alpha = roughness^2 mapping, capped for a perfect mirror.
+     */
+    pub fn get_clearcoat_exponent(&self) -> f32 {
+        let alpha = self.clearcoat_roughness * self.clearcoat_roughness;
+        (2.0 / alpha.max(1e-3) - 2.0).max(1.0)
+    }
+    // share of the light reflected by the clearcoat for cos_theta
+    pub fn get_clearcoat_weight(&self, cos_theta: f32) -> f32 {
+        if self.clearcoat <= 0.0 {
+            return 0.0;
+        }
+        self.clearcoat * fresnel_schlick(cos_theta, CLEARCOAT_F0)
+    }
+}
+
+/*
+ * ks used to be a single value: a scalar is still accepted and applies to
+ * the three channels, as is an [r, g, b] array besides the usual object.
+ */
+fn deserialize_ks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RGB, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ks {
+        Scalar(f32),
+        Array([f32; 3]),
+        Rgb(RGB),
+    }
+    Ok(match Ks::deserialize(deserializer)? {
+        Ks::Scalar(v) => RGB::new(v, v, v),
+        Ks::Array([r, g, b]) => RGB::new(r, g, b),
+        Ks::Rgb(c) => c,
+    })
+}
+
+// checker periods per unit of texture coordinates
+pub const CHECKER_FREQ: f32 = 4.0;
+
+// reflectance at normal incidence of a clear varnish (ior 1.5)
+pub const CLEARCOAT_F0: f32 = 0.04;
+
+/*
+ * Schlick's approximation of the Fresnel reflectance.
+ * cos_theta: cosine between the incoming ray and the normal, 0 --> 1
+ */
+pub fn fresnel_schlick(cos_theta: f32, f0: f32) -> f32 {
+    let m = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (1.0 - f0) * m
 }
@@ -0,0 +1,516 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+use colored::Colorize;
+
+use crate::color::RGB;
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::vec3::Float;
+use crate::vec3::Matrix4;
+use crate::vec3::Point;
+use crate::vec3::Vec2;
+use crate::vec3::Vec3;
+
+/*
+ * Meshes read from a model file, before they become triangles of the
+ * scene: OBJ files through tobj, glTF 2.0 files through the gltf crate.
+ */
+
+/*
+ * One mesh of a model file, in the coordinates of the file. Vertex normals
+ * and texture coordinates are given per triangle, since OBJ files index
+ * them apart from the positions. material_id indexes the materials loaded
+ * along with the models.
+ */
+pub struct Model {
+    pub name: String,
+    pub positions: Vec<Point>,
+    // three per triangle
+    pub indices: Vec<u32>,
+    // empty when the file has none
+    pub normals: Vec<Option<[Vec3; 3]>>,
+    pub uvs: Vec<Option<[Vec2; 3]>>,
+    pub material_id: Option<usize>,
+}
+
+impl Model {
+    pub fn num_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+    // the position of the k-th vertex of the i-th triangle
+    pub fn get_point(&self, i: usize, k: usize) -> Point {
+        self.positions[self.indices[3 * i + k] as usize]
+    }
+    // reverses the winding of the i-th triangle, and its per-vertex data
+    fn flip_triangle(&mut self, i: usize) {
+        self.indices.swap(3 * i + 1, 3 * i + 2);
+        if let Some(Some(n)) = self.normals.get_mut(i) {
+            n.swap(1, 2);
+        }
+        if let Some(Some(uv)) = self.uvs.get_mut(i) {
+            uv.swap(1, 2);
+        }
+    }
+
+    // the signed volume of the part as oriented by flip, if it is closed
+    fn get_closed_volume(
+        &self,
+        part: &[usize],
+        flip: &[Option<bool>],
+        edges: &HashMap<(u32, u32), Vec<(usize, bool)>>,
+        get_edge: impl Fn(usize, usize) -> (u32, u32),
+    ) -> Option<Float> {
+        let mut volume = 0.0;
+        for &i in part {
+            for k in 0..3 {
+                let (a, b) = get_edge(i, k);
+                if edges
+                    .get(&(a.min(b), a.max(b)))
+                    .is_none_or(|e| e.len() != 2)
+                {
+                    return None;
+                }
+            }
+            let v = self
+                .get_point(i, 0)
+                .dot(self.get_point(i, 1).cross(self.get_point(i, 2)));
+            volume += if flip[i].unwrap() { -v } else { v };
+        }
+        Some(volume)
+    }
+
+    /*
+     * Makes the winding consistent and the faces point outward, returns the
+     * number of triangles flipped. A flood fill across the shared edges
+     * orients each connected part like its first triangle: two neighbors
+     * agree when they run along their edge in opposite directions. A closed
+     * part is then turned over as a whole when its signed volume is negative,
+     * it faces inward. An open one has no inside: it keeps the winding of
+     * most of its triangles.
+     */
+    pub fn fix_winding(&mut self) -> usize {
+        let n = self.num_triangles();
+        // the files may repeat a position per face: weld them
+        let mut welded: HashMap<[u32; 3], u32> = HashMap::new();
+        let vertex: Vec<u32> = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                *welded
+                    .entry([p.x, p.y, p.z].map(Float::to_bits))
+                    .or_insert(i as u32)
+            })
+            .collect();
+        let get_edge = |i: usize, k: usize| {
+            let a = vertex[self.indices[3 * i + k] as usize];
+            let b = vertex[self.indices[3 * i + (k + 1) % 3] as usize];
+            (a, b)
+        };
+        // each undirected edge, and the triangles along it with the way
+        // they run: true from the lower vertex to the higher one
+        let mut edges: HashMap<(u32, u32), Vec<(usize, bool)>> = HashMap::new();
+        for i in 0..n {
+            for k in 0..3 {
+                let (a, b) = get_edge(i, k);
+                if a != b {
+                    edges
+                        .entry((a.min(b), a.max(b)))
+                        .or_default()
+                        .push((i, a < b));
+                }
+            }
+        }
+
+        let mut flip: Vec<Option<bool>> = vec![None; n];
+        let mut to_flip = vec![];
+        for seed in 0..n {
+            if flip[seed].is_some() {
+                continue;
+            }
+            flip[seed] = Some(false);
+            let mut part = vec![];
+            let mut queue = VecDeque::from([seed]);
+            while let Some(i) = queue.pop_front() {
+                part.push(i);
+                let flip_i = flip[i].unwrap();
+                for k in 0..3 {
+                    let (a, b) = get_edge(i, k);
+                    let Some(others) = edges.get(&(a.min(b), a.max(b))) else {
+                        continue;
+                    };
+                    let dir = (a < b) != flip_i;
+                    for &(j, dir_j) in others {
+                        if flip[j].is_none() {
+                            // j runs the other way once oriented
+                            flip[j] = Some(dir_j == dir);
+                            queue.push_back(j);
+                        }
+                    }
+                }
+            }
+
+            let num_reversed = part.iter().filter(|&&i| flip[i].unwrap()).count();
+            let turn_over = match self.get_closed_volume(&part, &flip, &edges, get_edge) {
+                Some(volume) if volume != 0.0 => volume < 0.0,
+                // most of the triangles are assumed right
+                _ => 2 * num_reversed > part.len(),
+            };
+            to_flip.extend(part.into_iter().filter(|&i| flip[i].unwrap() != turn_over));
+        }
+        for &i in &to_flip {
+            self.flip_triangle(i);
+        }
+        to_flip.len()
+    }
+}
+
+fn invalid_data(path: &Path, msg: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{}: {msg}", path.display()))
+}
+
+// dispatches on the extension: .gltf and .glb files, OBJ otherwise
+pub fn load_model_file(path: &Path) -> std::io::Result<(Vec<Model>, Vec<Material>)> {
+    // the loaders' own errors don't tell a missing file from a bad one
+    std::fs::metadata(path)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match ext.to_ascii_lowercase().as_str() {
+        "gltf" | "glb" => load_gltf(path),
+        _ => load_obj(path),
+    }
+}
+
+/*
+ * Vertex normals of a mesh without any: each vertex gets the sum of the
+ * normals of the faces sharing it, weighted by their area.
+ */
+pub fn compute_vertex_normals(positions: &[Point], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for face in indices.chunks_exact(3) {
+        let p = |k: usize| positions[face[k] as usize];
+        let n = (p(1) - p(0)).cross(p(2) - p(0));
+        for &i in face {
+            normals[i as usize] += n;
+        }
+    }
+    normals
+}
+
+/*
+ * "d" is the opacity, and "Tr" its complement that some exporters write
+ * instead: tobj only parses the former, and defaults it to 1.
+ */
+fn get_mtl_transparency(m: &tobj::Material) -> f32 {
+    let tr = m
+        .unknown_param
+        .get("Tr")
+        .and_then(|v| v.trim().parse::<f32>().ok());
+    let transparency = match tr {
+        Some(tr) if m.dissolve >= 1.0 => tr,
+        _ => 1.0 - m.dissolve,
+    };
+    transparency.clamp(0.0, 1.0)
+}
+
+fn load_obj(path: &Path) -> std::io::Result<(Vec<Model>, Vec<Material>)> {
+    let opt = tobj::LoadOptions {
+        triangulate: true, // converts polygon into triangles
+        ignore_lines: true,
+        ignore_points: true,
+        ..Default::default()
+    };
+    let (models, materials) = tobj::load_obj(path, &opt).map_err(|e| invalid_data(path, e))?;
+    let mut mats = vec![];
+    match materials {
+        Ok(materials) => {
+            for m in materials.iter() {
+                println!("-- material {} -- {:?}", m.name.green(), m);
+                // texture paths are relative to the OBJ file
+                let diffuse_map = (!m.diffuse_texture.is_empty())
+                    .then(|| path.with_file_name(&m.diffuse_texture));
+                let diffuse_texture = match &diffuse_map {
+                    Some(p) => Some(Arc::new(Texture::load(p)?)),
+                    None => None,
+                };
+                mats.push(Material {
+                    shininess: m.shininess, // floating point?
+                    ks: RGB::new(m.specular[0], m.specular[1], m.specular[2]),
+                    // Ni, only used once the material is transparent
+                    ior: m.optical_density,
+                    transparency: get_mtl_transparency(m),
+                    diffuse_map,
+                    diffuse_texture,
+                    kd: RGB::new(m.diffuse[0], m.diffuse[1], m.diffuse[2]),
+                    name: Some(m.name.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+        Err(e) => println!("{} {:?}", "Error loading materials:".red().bold(), e),
+    }
+
+    let models = models
+        .into_iter()
+        .map(|m| {
+            let mesh = &m.mesh;
+            assert!(mesh.indices.len() % 3 == 0);
+            let n = mesh.indices.len() / 3;
+            let normals = if mesh.normals.is_empty() {
+                vec![]
+            } else {
+                (0..n).map(|i| get_obj_normals(mesh, i)).collect()
+            };
+            let uvs = if mesh.texcoords.is_empty() {
+                vec![]
+            } else {
+                (0..n).map(|i| get_obj_uvs(mesh, i)).collect()
+            };
+            Model {
+                name: m.name,
+                positions: mesh
+                    .positions
+                    .chunks_exact(3)
+                    .map(|p| Point::new(p[0] as Float, p[1] as Float, p[2] as Float))
+                    .collect(),
+                indices: mesh.indices.clone(),
+                normals,
+                uvs,
+                material_id: mesh.material_id,
+            }
+        })
+        .collect();
+    Ok((models, mats))
+}
+
+// normals of the vertices of the i-th face
+fn get_obj_normals(mesh: &tobj::Mesh, i: usize) -> Option<[Vec3; 3]> {
+    let indices = if mesh.normal_indices.is_empty() {
+        &mesh.indices
+    } else {
+        &mesh.normal_indices
+    };
+    let normal = |k: usize| -> Option<Vec3> {
+        let idx = 3 * *indices.get(3 * i + k)? as usize;
+        let n = mesh.normals.get(idx..idx + 3)?;
+        Some(Vec3::new(n[0], n[1], n[2]))
+    };
+    Some([normal(0)?, normal(1)?, normal(2)?])
+}
+
+// texture coordinates of the vertices of the i-th face
+fn get_obj_uvs(mesh: &tobj::Mesh, i: usize) -> Option<[Vec2; 3]> {
+    let indices = if mesh.texcoord_indices.is_empty() {
+        &mesh.indices
+    } else {
+        &mesh.texcoord_indices
+    };
+    let uv = |k: usize| -> Option<Vec2> {
+        let idx = 2 * *indices.get(3 * i + k)? as usize;
+        let t = mesh.texcoords.get(idx..idx + 2)?;
+        Some(Vec2 { x: t[0], y: t[1] })
+    };
+    Some([uv(0)?, uv(1)?, uv(2)?])
+}
+
+/*
+ * Meshes of the default scene, or of the first one, placed by the
+ * transforms of their nodes. Files without scenes list their meshes as is.
+ */
+fn load_gltf(path: &Path) -> std::io::Result<(Vec<Model>, Vec<Material>)> {
+    let (doc, buffers, images) = gltf::import(path).map_err(|e| invalid_data(path, e))?;
+    let textures: Vec<Option<Arc<Texture>>> = images
+        .iter()
+        .map(|img| get_gltf_texture(path, img))
+        .collect();
+    let materials: Vec<Material> = doc
+        .materials()
+        .map(|m| {
+            println!("-- material {}", m.name().unwrap_or("").green());
+            get_gltf_material(&m, &textures)
+        })
+        .collect();
+
+    let mut models = vec![];
+    match doc.default_scene().or_else(|| doc.scenes().next()) {
+        Some(scene) => {
+            for node in scene.nodes() {
+                load_gltf_node(path, &node, &Matrix4::identity(), &buffers, &mut models)?;
+            }
+        }
+        None => {
+            for mesh in doc.meshes() {
+                load_gltf_mesh(path, &mesh, &Matrix4::identity(), &buffers, &mut models)?;
+            }
+        }
+    }
+    Ok((models, materials))
+}
+
+fn load_gltf_node(
+    path: &Path,
+    node: &gltf::Node,
+    parent: &Matrix4,
+    buffers: &[gltf::buffer::Data],
+    models: &mut Vec<Model>,
+) -> std::io::Result<()> {
+    let matrix = *parent * Matrix4::from_cols_array(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        load_gltf_mesh(path, &mesh, &matrix, buffers, models)?;
+    }
+    for child in node.children() {
+        load_gltf_node(path, &child, &matrix, buffers, models)?;
+    }
+    Ok(())
+}
+
+fn load_gltf_mesh(
+    path: &Path,
+    mesh: &gltf::Mesh,
+    matrix: &Matrix4,
+    buffers: &[gltf::buffer::Data],
+    models: &mut Vec<Model>,
+) -> std::io::Result<()> {
+    let name = mesh
+        .name()
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("mesh{}", mesh.index()));
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            let mode = primitive.mode();
+            println!(
+                "{}: {name}: skipping {mode:?} primitive",
+                "warning".yellow()
+            );
+            continue;
+        }
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|data| &data.0[..]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let positions: Vec<Point> = positions
+            .map(|p| matrix.transform_point(Point::new(p[0], p[1], p[2])))
+            .collect();
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        indices.truncate(indices.len() / 3 * 3);
+        if indices.iter().any(|&i| i as usize >= positions.len()) {
+            return Err(invalid_data(
+                path,
+                format!("{name}: vertex index out of range"),
+            ));
+        }
+
+        let normals = match reader.read_normals() {
+            Some(normals) => {
+                let normals: Vec<Vec3> = normals
+                    .map(|n| matrix.transform_normal(Vec3::new(n[0], n[1], n[2])))
+                    .collect();
+                per_triangle(&indices, &normals)
+            }
+            None => vec![],
+        };
+        // glTF puts v = 0 at the top of the image, our textures at the bottom
+        let uvs = match reader.read_tex_coords(0) {
+            Some(uvs) => {
+                let uvs: Vec<Vec2> = uvs
+                    .into_f32()
+                    .map(|t| Vec2 {
+                        x: t[0],
+                        y: 1.0 - t[1],
+                    })
+                    .collect();
+                per_triangle(&indices, &uvs)
+            }
+            None => vec![],
+        };
+        models.push(Model {
+            name: name.clone(),
+            positions,
+            indices,
+            normals,
+            uvs,
+            material_id: primitive.material().index(),
+        });
+    }
+    Ok(())
+}
+
+// per-vertex values gathered for each triangle
+fn per_triangle<T: Copy>(indices: &[u32], values: &[T]) -> Vec<Option<[T; 3]>> {
+    let get = |i: u32| values.get(i as usize).copied();
+    indices
+        .chunks_exact(3)
+        .map(|f| Some([get(f[0])?, get(f[1])?, get(f[2])?]))
+        .collect()
+}
+
+// 8-bit images only, with the same decoding as Texture::load()
+fn get_gltf_texture(path: &Path, img: &gltf::image::Data) -> Option<Arc<Texture>> {
+    use gltf::image::Format;
+    let stride = match img.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        format => {
+            let msg = format!("unsupported texture format {format:?}");
+            println!("{}: {}: {msg}", "warning".yellow(), path.display());
+            return None;
+        }
+    };
+    if img.width == 0 || img.height == 0 {
+        return None;
+    }
+    let pixels = img
+        .pixels
+        .chunks_exact(stride)
+        .map(|p| match stride {
+            1 | 2 => RGB::new(p[0] as f32, p[0] as f32, p[0] as f32) / 255.0,
+            _ => RGB::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0,
+        })
+        .collect();
+    Some(Arc::new(Texture::new(img.width, img.height, pixels)))
+}
+
+/*
+ * Metallic-roughness to our model: metals reflect their base color and
+ * have no diffuse part, dielectrics the opposite. The roughness maps to a
+ * Phong exponent as for the clearcoat. Blended alpha becomes transparency.
+ */
+fn get_gltf_material(m: &gltf::Material, textures: &[Option<Arc<Texture>>]) -> Material {
+    let pbr = m.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let base = RGB::new(r, g, b);
+    let metallic = pbr.metallic_factor().clamp(0.0, 1.0);
+    let roughness = pbr.roughness_factor().clamp(0.0, 1.0);
+    let alpha = roughness * roughness;
+    let [er, eg, eb] = m.emissive_factor();
+    let diffuse_texture = pbr.base_color_texture().and_then(|info| {
+        textures
+            .get(info.texture().source().index())
+            .cloned()
+            .flatten()
+    });
+    let transparency = match m.alpha_mode() {
+        gltf::material::AlphaMode::Blend => 1.0 - a.clamp(0.0, 1.0),
+        _ => 0.0,
+    };
+    Material {
+        kd: base * (1.0 - metallic),
+        ks: base * metallic,
+        ke: RGB::new(er, eg, eb),
+        shininess: (2.0 / alpha.max(1e-3) - 2.0).clamp(1.0, 1000.0),
+        transparency,
+        diffuse_texture,
+        name: m.name().map(str::to_owned),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::Ray;
+use crate::RenderStats;
+use crate::aabb::AABB;
+use crate::aabb::BvhNode;
+use crate::aabb::MAX_DEPTH;
+use crate::aabb::build_nodes;
+use crate::aabb::get_depth;
+use crate::render::Hit;
+use crate::three_d::Object;
+use crate::vec3::EPSILON;
+use crate::vec3::Float;
+use crate::vec3::Vec3;
+
+type Objects = [Arc<dyn Object + 'static + Send + Sync>];
+
+/*
+ * Bounding Volume Hierarchy over the objects of the scene, by their world
+ * bounds: the same SAH build and node layout as the one over the triangles
+ * of a mesh, with objects in the leaves. As with the uniform grid, the
+ * objects without bounds (planes) or in motion are kept apart and tested
+ * against every ray.
+ */
+pub struct ObjectBvh {
+    nodes: Vec<BvhNode>,
+    objects: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl ObjectBvh {
+    pub fn new(objects: &Objects) -> Self {
+        let start_time = Instant::now();
+        let mut ids = vec![];
+        let mut bounds = vec![];
+        let mut unbounded = vec![];
+        for (id, obj) in objects.iter().enumerate() {
+            match obj.get_aabb() {
+                Some(aabb) if obj.get_velocity() == Vec3::zero() => {
+                    ids.push(id);
+                    bounds.push(aabb);
+                }
+                _ => unbounded.push(id),
+            }
+        }
+        let (nodes, order) = build_nodes(&bounds);
+        let bvh = Self {
+            nodes,
+            objects: order.into_iter().map(|i| ids[i]).collect(),
+            unbounded,
+        };
+        if bvh.objects.len() > 1 {
+            println!(
+                "-- object bvh: {} objects, {} unbounded, depth: {} num_nodes={} -- {} msec",
+                bvh.objects.len(),
+                bvh.unbounded.len(),
+                get_depth(&bvh.nodes, 0),
+                bvh.nodes.len(),
+                start_time.elapsed().as_millis()
+            );
+        }
+        bvh
+    }
+    /*
+     * Walks the nodes that the ray crosses before tmax, the nearer child
+     * first: test gets the object ids of each leaf along with the current
+     * tmax, and returns the new one, or None to stop there.
+     */
+    fn traverse(
+        &self,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        mut tmax: Float,
+        mut test: impl FnMut(&mut RenderStats, &[usize], Float) -> Option<Float>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let dir_is_neg = [ray.dir.x < 0.0, ray.dir.y < 0.0, ray.dir.z < 0.0];
+        let mut stack = [0usize; MAX_DEPTH as usize + 2];
+        let mut num_stack = 1;
+        while num_stack > 0 {
+            num_stack -= 1;
+            let idx = stack[num_stack];
+            let node = &self.nodes[idx];
+
+            stats.count(|s| s.num_intersects_aabb += 1);
+            if !check_node(&node.aabb, ray, tmax) {
+                continue;
+            }
+            if node.is_leaf() {
+                let leaf = &self.objects[node.offset..node.offset + node.count];
+                match test(stats, leaf, tmax) {
+                    Some(t) => tmax = t,
+                    None => return,
+                }
+                continue;
+            }
+            let (near, far) = if dir_is_neg[node.axis] {
+                (node.offset, idx + 1)
+            } else {
+                (idx + 1, node.offset)
+            };
+            stack[num_stack] = far;
+            stack[num_stack + 1] = near;
+            num_stack += 2;
+        }
+    }
+    // same result as render::get_nearest_hit()
+    pub fn get_nearest_hit(
+        &self,
+        objects: &Objects,
+        stats: &mut RenderStats,
+        ray: &Ray,
+    ) -> Option<Hit> {
+        let mut best: Option<Hit> = None;
+        let mut best_t = Float::MAX;
+        let mut test = |stats: &mut RenderStats, id: usize, best_t: &mut Float| {
+            let mut t = *best_t;
+            let mut oid = 0;
+            if objects[id].intercept(stats, ray, EPSILON, &mut t, false, &mut oid) && t < *best_t {
+                *best_t = t;
+                best = Some(Hit {
+                    object: id,
+                    t,
+                    s_id: oid,
+                });
+            }
+        };
+        for &id in &self.unbounded {
+            test(stats, id, &mut best_t);
+        }
+        self.traverse(stats, ray, best_t, |stats, leaf, mut tmax| {
+            for &id in leaf {
+                test(stats, id, &mut tmax);
+            }
+            Some(tmax)
+        });
+        best
+    }
+    // whether an object other than skip blocks the ray before tmax
+    pub fn is_occluded(
+        &self,
+        objects: &Objects,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmax: Float,
+        skip: Option<usize>,
+    ) -> bool {
+        let test = |stats: &mut RenderStats, id: usize| {
+            let mut t = tmax;
+            let mut oid = 0;
+            Some(id) != skip && objects[id].intercept(stats, ray, EPSILON, &mut t, true, &mut oid)
+        };
+        if self.unbounded.iter().any(|&id| test(stats, id)) {
+            return true;
+        }
+        let mut occluded = false;
+        self.traverse(stats, ray, tmax, |stats, leaf, tmax| {
+            occluded = leaf.iter().any(|&id| test(stats, id));
+            (!occluded).then_some(tmax)
+        });
+        occluded
+    }
+}
+
+/*
+ * The node boxes are tight around the objects: a ray that starts on a
+ * surface, or hits one right on a face of the box, must not miss it to
+ * rounding.
+ */
+fn check_node(aabb: &AABB, ray: &Ray, tmax: Float) -> bool {
+    let (t_min, t_max) = aabb.get_slab_range(ray);
+    t_max + EPSILON >= t_min.max(0.0) && t_min < tmax + EPSILON
+}
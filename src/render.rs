@@ -1,200 +1,1040 @@
 use colored::Colorize;
 use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use rayon::prelude::*;
 use std::collections::HashMap;
 
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::time::Instant;
 
+use crate::ProgressEta;
 use crate::ProgressFunc;
 use crate::Ray;
 use crate::RenderStats;
+use crate::ao::AO_NUM_SAMPLES;
 use crate::camera::Camera;
+use crate::camera::CameraKeyframe;
+use crate::color::Gradient;
 use crate::color::RGB;
+use crate::grid::UniformGrid;
+use crate::image::Grade;
 use crate::image::Image;
+use crate::image::ToneMap;
+use crate::image::output_path;
+use crate::image::save_depth;
+use crate::image::save_heatmap;
+use crate::image::save_matte;
+use crate::image::save_normals;
 use crate::light::Light;
+use crate::light::LightMarker;
 use crate::material::Material;
+use crate::object_bvh::ObjectBvh;
+use crate::texture::Environment;
 use crate::three_d::Object;
 use crate::vec3::EPSILON;
 use crate::vec3::Float;
+use crate::vec3::Point;
 use crate::vec3::Vec3;
+use crate::vec3::fast_rand;
+use crate::vec3::fast_rand_float;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Integrator {
+    Whitted,
+    Path,
+}
+
+impl std::str::FromStr for Integrator {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whitted" => Ok(Integrator::Whitted),
+            "path" => Ok(Integrator::Path),
+            _ => Err(format!(
+                "unknown integrator '{s}' (expected path or whitted)"
+            )),
+        }
+    }
+}
+
+/*
+ * Auxiliary outputs, saved next to the image for compositing: the distance
+ * to the primary hit and its world-space normal.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aov {
+    Depth,
+    Normal,
+}
+
+impl std::str::FromStr for Aov {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "depth" => Ok(Aov::Depth),
+            "normal" => Ok(Aov::Normal),
+            _ => Err(format!("unknown aov '{s}' (expected depth or normal)")),
+        }
+    }
+}
+
+/*
+ * How deep each integrator goes, so that they no longer share a single
+ * depth. The path tracer stops at path_max_bounces, and past rr_min_depth
+ * it ends the paths at random (russian roulette).
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BounceConfig {
+    pub reflection_max_depth: u32, // whitted reflections and refractions
+    pub path_max_bounces: u32,
+    pub rr_min_depth: Option<u32>,
+    pub ao_num_samples: usize, // rays per vertex of the mesh ao bake
+}
+
+impl Default for BounceConfig {
+    fn default() -> Self {
+        Self {
+            reflection_max_depth: 6,
+            path_max_bounces: 6,
+            rr_min_depth: None,
+            ao_num_samples: AO_NUM_SAMPLES,
+        }
+    }
+}
 
 pub struct RenderConfig {
-    pub path_tracing: u32,
+    pub integrator: Integrator,
+    pub samples_per_pixel: u32,
     pub use_lines: bool,
     pub use_hashmap: bool,
     pub use_adaptive_sampling: bool,
+    pub use_bilinear: bool, // blend the adaptive corners of the neighbouring pixels in
     pub use_gamma: bool,
+    pub tonemap: ToneMap,
+    pub grade: Grade,
+    pub timing_heatmap: bool,
+    pub show_lights: bool,
+    pub warn_unused_keys: bool,
+    pub skip_missing_meshes: bool, // warn instead of failing the load
+    pub use_smooth_normals: bool,
+    pub fix_winding: bool, // orient the mesh triangles outward
+    pub use_edge_aa: bool,
+    pub use_sky_light: bool, // the path tracer sees the background gradient
+    pub use_light_sampling: bool, // the path tracer aims at the emitters
+    pub use_grid: bool,      // uniform grid over the loose objects
+    pub use_bvh: bool,       // bvh over the objects, unless use_grid
+    pub tile_split_time: Option<Duration>, // None: the tiles are never split
+    pub progressive: Option<u32>, // path tracing in this many passes over the whole image
+    pub firefly_clamp: Option<f32>, // max luminance of a path sample, None: unclamped
+    pub camera: usize,       // index of the scene camera to render from
+    pub camera_view: Option<CameraKeyframe>, // replaces the viewpoint of that camera
     pub adaptive_max_depth: u32,
-    pub reflection_max_depth: u32,
+    pub adaptive_threshold: f32, // color difference that subdivides a box
+    pub bounces: BounceConfig,
+    pub reflection_cutoff: f32,
     pub res_x: u32,
     pub res_y: u32,
     pub scene_file: PathBuf,
-    pub image_file: PathBuf,
+    pub image_file: PathBuf, // .png, .jpg, .ppm or .exr
+    pub jpeg_quality: u8,    // 1 to 100, for a .jpg image_file
+    pub output_dir: Option<PathBuf>,
+    pub output_template: Option<String>,
+    pub frame: u32,
+    pub seed: Option<u64>,         // None: different noise on every run
+    pub seed_per_frame: bool,      // mix the frame into the seed
+    pub num_shadow_samples: usize, // shadow rays per area light
+    pub shadow_bias: Float,        // offset of the shadow rays off the surface
+    pub bake_ao: bool,             // recompute the mesh AO caches
+    pub stats_only: bool,          // trace without storing the pixels
+    pub aovs: Vec<Aov>,
+    pub matte: Option<String>, // object whose coverage is saved, eg. "sphere.1"
+}
+
+// matches the command line defaults
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            integrator: Integrator::Whitted,
+            samples_per_pixel: 1,
+            use_lines: false,
+            use_hashmap: false,
+            use_adaptive_sampling: false,
+            use_bilinear: false,
+            use_gamma: false,
+            tonemap: ToneMap::Clamp,
+            grade: Grade::default(),
+            timing_heatmap: false,
+            show_lights: false,
+            warn_unused_keys: false,
+            skip_missing_meshes: false,
+            use_smooth_normals: false,
+            fix_winding: false,
+            use_edge_aa: false,
+            use_sky_light: false,
+            use_light_sampling: true,
+            use_grid: false,
+            use_bvh: true,
+            tile_split_time: Some(TILE_SPLIT_TIME),
+            progressive: None,
+            firefly_clamp: None,
+            camera: 0,
+            camera_view: None,
+            adaptive_max_depth: 2,
+            adaptive_threshold: 0.3,
+            bounces: BounceConfig::default(),
+            reflection_cutoff: 0.0,
+            res_x: 0,
+            res_y: 0,
+            scene_file: PathBuf::from("scene.json"),
+            image_file: PathBuf::from("pic.png"),
+            jpeg_quality: 90,
+            output_dir: None,
+            output_template: None,
+            frame: 0,
+            seed: None,
+            seed_per_frame: false,
+            num_shadow_samples: 16,
+            shadow_bias: SHADOW_BIAS,
+            bake_ao: false,
+            stats_only: false,
+            aovs: vec![],
+            matte: None,
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn with_scene_file(mut self, scene_file: impl Into<PathBuf>) -> Self {
+        self.scene_file = scene_file.into();
+        self
+    }
+    pub fn with_image_file(mut self, image_file: impl Into<PathBuf>) -> Self {
+        self.image_file = image_file.into();
+        self
+    }
+    pub fn with_resolution(mut self, res_x: u32, res_y: u32) -> Self {
+        self.res_x = res_x;
+        self.res_y = res_y;
+        self
+    }
+    pub fn with_integrator(mut self, integrator: Integrator, samples_per_pixel: u32) -> Self {
+        self.integrator = integrator;
+        self.samples_per_pixel = samples_per_pixel;
+        self
+    }
+}
+
+// adaptive levels past adaptive_max_depth allowed for a fine pattern
+const PATTERN_EXTRA_DEPTH: u32 = 1;
+
+// edge anti-aliasing: flagged pixels get N x N stratified samples
+const EDGE_AA_GRID: u32 = 4;
+const EDGE_DEPTH_THRESHOLD: Float = 0.05; // relative depth difference
+const EDGE_NORMAL_THRESHOLD: Float = 0.9; // cosine between the normals
+const EDGE_COLOR_THRESHOLD: f32 = 0.3;
+
+// a tile still rendering after this splits the rows it has left
+pub const TILE_SPLIT_TIME: Duration = Duration::from_millis(50);
+// smaller tiles are not worth splitting
+const TILE_SPLIT_MIN_PIXELS: u32 = 16;
+
+// passes of a progressive render in the ui
+pub const PROGRESSIVE_PASSES: u32 = 16;
+
+/*
+ * Shadow rays start this far off the surface, along its geometric normal:
+ * far from the origin the hit point is only precise to a few ulps of its
+ * coordinates and the surface would shadow itself (shadow acne).
+ */
+pub const SHADOW_BIAS: Float = 1e-3;
+
+/*
+ * What the primary ray of a pixel sees: used to find the geometry edges.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelGeom {
+    pub object: Option<usize>,
+    pub depth: Float,
+    pub normal: Vec3,
+}
+
+impl PixelGeom {
+    pub fn miss() -> Self {
+        Self {
+            object: None,
+            depth: Float::MAX,
+            normal: Vec3::zero(),
+        }
+    }
+    fn is_edge(&self, other: &PixelGeom) -> bool {
+        if self.object != other.object {
+            return true;
+        }
+        if self.object.is_none() {
+            return false;
+        }
+        let depth_diff = (self.depth - other.depth).abs() / self.depth.min(other.depth);
+        depth_diff > EDGE_DEPTH_THRESHOLD || self.normal.dot(other.normal) < EDGE_NORMAL_THRESHOLD
+    }
+}
+
+/*
+ * Flags the pixels that differ from a neighbor in object, depth, normal or
+ * color. Both pixels on each side of an edge are flagged.
+ */
+pub fn detect_edges(geom: &[PixelGeom], colors: &[RGB], res_x: u32, res_y: u32) -> Vec<bool> {
+    let (res_x, res_y) = (res_x as usize, res_y as usize);
+    let mut edges = vec![false; res_x * res_y];
+    let mut check = |a: usize, b: usize| {
+        if geom[a].is_edge(&geom[b]) || colors[a].distance2(colors[b]) > EDGE_COLOR_THRESHOLD {
+            edges[a] = true;
+            edges[b] = true;
+        }
+    };
+    for y in 0..res_y {
+        for x in 0..res_x {
+            let i = y * res_x + x;
+            if x + 1 < res_x {
+                check(i, i + 1);
+            }
+            if y + 1 < res_y {
+                check(i, i + res_x);
+            }
+        }
+    }
+    edges
+}
+
+/*
+ * Offset of sample i of n within a du x dv pixel: the first k^2 samples,
+ * k = floor(sqrt(n)), are jittered in the cells of a k x k grid, the
+ * remaining ones are uniformly random over the pixel.
+ */
+pub fn get_stratified_offset(
+    rng: &mut impl Rng,
+    i: u32,
+    n: u32,
+    du: Float,
+    dv: Float,
+) -> (Float, Float) {
+    let k = n.isqrt();
+    if i >= k * k {
+        return (rng.gen_range(0.0..du), rng.gen_range(0.0..dv));
+    }
+    let cell_u = (i % k) as Float + rng.gen_range(0.0..1.0);
+    let cell_v = (i / k) as Float + rng.gen_range(0.0..1.0);
+    (cell_u * du / k as Float, cell_v * dv / k as Float)
+}
+
+/*
+ * The work done so far, one pixel per pixel and per pass, and the
+ * num_rays_sampling that they took: see report_progress().
+ */
+#[derive(Default)]
+pub struct Progress {
+    pixels: u64,
+    rays: u64,
+    reported: f32,
+}
+
+impl Progress {
+    /*
+     * The rays done against those expected in all: the pixels left take as
+     * many rays each as the pixels done, which start with a row at
+     * prior_rays per pixel before anything is known.
+     */
+    fn get_fraction(&self, num_pixels: u64, row_len: u64, prior_rays: f32) -> f32 {
+        let pixels_left = num_pixels.saturating_sub(self.pixels);
+        if pixels_left == 0 {
+            return 1.0;
+        }
+        let rays = self.rays as f32;
+        let rays_per_pixel = (rays + prior_rays * row_len as f32) / (self.pixels + row_len) as f32;
+        let rays_left = pixels_left as f32 * rays_per_pixel.max(EPSILON);
+        rays / (rays + rays_left)
+    }
+}
+
+pub struct TileTiming {
+    pub x: u32,
+    pub y: u32,
+    pub sz_x: u32,
+    pub sz_y: u32,
+    pub usec: u128,
 }
 
 pub struct RenderJob {
     pub camera: Camera,
     pub objects: Vec<Arc<dyn Object + 'static + Send + Sync>>,
+    pub object_names: Vec<String>, // the scene keys, eg. "sphere.1"
     pub lights: Vec<Arc<dyn Light + 'static + Send + Sync>>,
+    pub light_markers: Vec<LightMarker>,
     pub materials: Vec<Arc<Material>>,
     pub image: Arc<Mutex<Image>>,
     pub cfg: RenderConfig,
-    pub progress_total: Mutex<usize>,
+    pub progress: Mutex<Progress>,
     pub progress_func: ProgressFunc,
+    pub tile_func: Option<TileFunc>,
     pub start_ts: Instant,
     pub total_stats: Mutex<RenderStats>,
+    pub tile_timings: Mutex<Vec<TileTiming>>,
+    pub background: Gradient,
+    // replaces the background and lights the path tracer when set
+    pub environment: Option<Environment>,
+    pub grid: Option<UniformGrid>,
+    pub bvh: Option<ObjectBvh>,
+    // primary hit of each pixel, for the edge-aa and the aovs
+    pub geom: Vec<PixelGeom>,
+}
+
+/*
+ * The objects a matte selects: by scene key, eg. "sphere.1" or "obj.0" for
+ * every mesh of that file, or by index as "object.3" like the scene
+ * warnings name them.
+ */
+pub fn get_matte_objects(names: &[String], matte: &str) -> Vec<usize> {
+    (0..names.len())
+        .filter(|&id| names[id] == matte || format!("object.{id}") == matte)
+        .collect()
+}
+
+/*
+ * Closest intersection along a ray: the object index, the distance and the
+ * sub-object id (face, cap, side...) that the object needs for its normal
+ * and material. Each object reports into its own oid so that one which
+ * touches it on a miss cannot corrupt the id of the nearest hit.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub object: usize,
+    pub t: Float,
+    pub s_id: usize,
+}
+
+pub fn get_nearest_hit(
+    objects: &[Arc<dyn Object + 'static + Send + Sync>],
+    stats: &mut RenderStats,
+    ray: &Ray,
+) -> Option<Hit> {
+    let mut best: Option<Hit> = None;
+    let mut best_t = Float::MAX;
+    for (id, obj) in objects.iter().enumerate() {
+        let mut t = best_t;
+        let mut oid = 0;
+        if obj.intercept(stats, ray, EPSILON, &mut t, false, &mut oid) && t < best_t {
+            best_t = t;
+            best = Some(Hit {
+                object: id,
+                t,
+                s_id: oid,
+            });
+        }
+    }
+    best
+}
+
+/*
+ * A finished tile of the main pass, clipped to the image: its linear
+ * colors row by row, before tone mapping. The edge-aa pass may still
+ * refine some of its pixels afterwards.
+ */
+pub struct Tile<'a> {
+    pub x: u32,
+    pub y: u32,
+    pub sz_x: u32,
+    pub sz_y: u32,
+    pub pixels: &'a [RGB],
+}
+
+// called from the render threads as each tile completes
+pub type TileFunc = Box<dyn Fn(&Tile) + Send + Sync>;
+
+pub fn default_background() -> Gradient {
+    let cyan = RGB::new(0.4, 0.6, 0.9);
+    let cmax = RGB::new(1.0, 1.0, 1.0);
+    Gradient::two_stops(cyan, cmax)
+}
+
+// weight of a sample of density pdf, when another strategy had pdf_other
+fn power_heuristic(pdf: Float, pdf_other: Float) -> Float {
+    let (a, b) = (pdf * pdf, pdf_other * pdf_other);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
 }
 
 impl RenderJob {
     pub fn set_progress_func(&mut self, func: Box<dyn Fn(f32) + Send + Sync>) {
         self.progress_func.func = func;
     }
-    fn report_progress(&self, v: u32) {
-        let denom = self.cfg.res_x * self.cfg.res_y;
-        let mut total = self.progress_total.lock().unwrap();
-        let before = (*total).div_euclid((denom / 128) as usize);
-        *total += v as usize;
-        let after = (*total).div_euclid((denom / 128) as usize);
-        let d = before != after || 100 * (denom as i32 - *total as i32).unsigned_abs() / denom < 1;
-        if d {
-            let pct = *total as f32 / denom as f32;
-            (self.progress_func.func)(pct.min(1.0));
+    pub fn set_eta_func(&mut self, func: Box<dyn Fn(f32, ProgressEta) + Send + Sync>) {
+        self.progress_func.eta_func = Some(func);
+    }
+    fn get_nearest_hit(&self, stats: &mut RenderStats, ray: &Ray) -> Option<Hit> {
+        match (&self.grid, &self.bvh) {
+            (Some(grid), _) => grid.get_nearest_hit(&self.objects, stats, ray),
+            (None, Some(bvh)) => bvh.get_nearest_hit(&self.objects, stats, ray),
+            (None, None) => get_nearest_hit(&self.objects, stats, ray),
+        }
+    }
+    // whether the ray is blocked before t = 1, by another object than skip
+    fn is_occluded(&self, stats: &mut RenderStats, ray: &Ray, skip: Option<usize>) -> bool {
+        if let Some(grid) = &self.grid {
+            return grid.is_occluded(&self.objects, stats, ray, 1.0, skip);
+        }
+        if let Some(bvh) = &self.bvh {
+            return bvh.is_occluded(&self.objects, stats, ray, 1.0, skip);
+        }
+        self.objects.iter().enumerate().any(|(id, obj)| {
+            let mut tmax = 1.0;
+            let mut oid = 0;
+            Some(id) != skip && obj.intercept(stats, ray, EPSILON, &mut tmax, true, &mut oid)
+        })
+    }
+    pub fn set_tile_func(&mut self, func: TileFunc) {
+        self.tile_func = Some(func);
+    }
+    // samples per pixel and per pass: the least a pixel takes
+    fn get_prior_rays(&self) -> f32 {
+        match self.cfg.integrator {
+            Integrator::Path => self.cfg.samples_per_pixel as f32 / self.get_num_passes() as f32,
+            Integrator::Whitted => 1.0,
+        }
+    }
+    fn get_fraction(&self, progress: &Progress) -> f32 {
+        let num_pixels = self.cfg.res_x as u64 * self.cfg.res_y as u64;
+        let num_pixels = num_pixels * self.get_num_passes() as u64;
+        progress.get_fraction(num_pixels, self.cfg.res_x as u64, self.get_prior_rays())
+    }
+    // the share of the render done, only what was traced if it was stopped
+    pub fn get_progress(&self) -> f32 {
+        self.get_fraction(&self.progress.lock().unwrap())
+    }
+    /*
+     * The pixels just done and the sampling rays they took: a pixel of
+     * dense adaptive sampling counts for more than a plain one. The lock
+     * is held over the callbacks, which then see the values in order.
+     */
+    fn report_progress(&self, pixels: u32, rays: u64) {
+        let mut progress = self.progress.lock().unwrap();
+        progress.pixels += pixels as u64;
+        progress.rays += rays;
+        let pct = self.get_fraction(&progress);
+        if (pct * 256.0) as u32 == (progress.reported * 256.0) as u32 && pct < 1.0 {
+            return;
+        }
+        progress.reported = pct;
+        self.notify_progress(pct);
+    }
+    fn notify_progress(&self, pct: f32) {
+        (self.progress_func.func)(pct);
+        if let Some(func) = &self.progress_func.eta_func {
+            let elapsed = self.start_ts.elapsed();
+            let remaining = (pct > 0.0).then(|| elapsed.mul_f32((1.0 - pct) / pct));
+            func(pct, ProgressEta { elapsed, remaining });
         }
     }
 
-    fn trace_ray(&self, stats: &mut RenderStats, ray: &Ray, depth: u32) -> RGB {
-        if depth > self.cfg.reflection_max_depth {
+    fn hit_light_marker(&self, stats: &mut RenderStats, ray: &Ray, t: Float) -> Option<RGB> {
+        let mut t = t;
+        let mut oid = 0;
+        self.light_markers
+            .iter()
+            .filter(|m| {
+                m.sphere
+                    .intercept(stats, ray, EPSILON, &mut t, false, &mut oid)
+            })
+            .last()
+            .map(|m| m.rgb)
+    }
+
+    /*
+     * weight: product of the reflection coefficients along the path so far,
+     * ie. how much this ray contributes to the final pixel.
+     */
+    fn trace_ray(
+        &self,
+        stats: &mut RenderStats,
+        rnd_state: &mut u64,
+        ray: &Ray,
+        depth: u32,
+        weight: f32,
+    ) -> RGB {
+        if depth > self.cfg.bounces.reflection_max_depth {
             stats.num_rays_reflection_max += 1;
             return RGB::zero();
         }
-        let mut s_id = 0;
-        let mut t = Float::MAX;
+        let hit = self.get_nearest_hit(stats, ray);
 
-        let hit_obj_opt = self
-            .objects
-            .iter()
-            .filter(|obj| obj.intercept(stats, ray, EPSILON, &mut t, false, &mut s_id))
-            .last();
+        if depth == 0
+            && let Some(c) = self.hit_light_marker(stats, ray, hit.map_or(Float::MAX, |h| h.t))
+        {
+            return c;
+        }
 
-        if let Some(hit_obj) = hit_obj_opt {
+        if let Some(Hit { object, t, s_id }) = hit {
+            let hit_obj = &self.objects[object];
             let hit_point = ray.orig + ray.dir * t;
-            let hit_normal = hit_obj.get_normal(hit_point, s_id);
-            let hit_mat_id = hit_obj.get_material_id();
+            let rest_point = hit_point - hit_obj.get_velocity() * ray.time;
+            // the normal map only bends the normal of the shading
+            let geom_normal = hit_obj.get_normal(rest_point, s_id);
+            let mut hit_normal = geom_normal;
+            let hit_mat_id = hit_obj.get_face_material_id(s_id);
+            let hit_text2d = hit_obj.get_texture_2d(rest_point, s_id);
             let hit_material = &self.materials[hit_mat_id];
+            let hit_kd = hit_material.get_kd(hit_text2d);
+            if hit_material.normal_texture.is_some() {
+                let tangents = hit_obj.get_tangents(rest_point, s_id);
+                hit_normal = hit_material.get_shading_normal(hit_normal, tangents, hit_text2d);
+            }
 
             let mut c = self.lights.iter().fold(RGB::zero(), |acc, light| {
                 let mut c_light = RGB::zero();
 
-                if !light.is_spot() {
-                    c_light = light.get_contrib(ray, hit_material, hit_point, hit_normal);
+                if light.is_ambient() {
+                    let ao = hit_obj.get_ao(rest_point, s_id);
+                    c_light =
+                        light.get_contrib(ray, hit_material, hit_kd, hit_point, hit_normal) * ao;
+                } else if !light.is_spot() {
+                    c_light = light.get_contrib(ray, hit_material, hit_kd, hit_point, hit_normal);
                 } else {
-                    let light_vec = light.get_vector(hit_point) * -1.0;
-                    let light_ray = Ray::new(hit_point, light_vec);
-                    if !self.objects.iter().any(|obj| {
-                        let mut tmax0 = 1.0;
-                        let mut oid0 = 0;
-                        obj.intercept(stats, &light_ray, EPSILON, &mut tmax0, true, &mut oid0)
-                    }) {
-                        c_light = light.get_contrib(ray, hit_material, hit_point, hit_normal)
+                    // area lights average the visibility of several points
+                    let num_samples = light.get_num_samples(self.cfg.num_shadow_samples);
+                    let emitter = light.get_object_id();
+                    let mut num_visible = 0;
+                    for _ in 0..num_samples {
+                        let light_dir = light.sample_vector(hit_point, rnd_state) * -1.0;
+                        let light_orig = Ray::offset_origin_by(
+                            hit_point,
+                            geom_normal,
+                            light_dir,
+                            self.cfg.shadow_bias,
+                        );
+                        let light_vec = light_dir + (hit_point - light_orig);
+                        let light_ray = Ray::new_at(light_orig, light_vec, ray.time);
+                        if !self.is_occluded(stats, &light_ray, emitter) {
+                            num_visible += 1;
+                        }
+                    }
+                    if num_visible > 0 {
+                        let visibility = num_visible as f32 / num_samples as f32;
+                        c_light =
+                            light.get_contrib(ray, hit_material, hit_kd, hit_point, hit_normal)
+                                * visibility;
                     }
                 }
                 acc + c_light
             });
 
             if hit_material.checkered {
-                let hit_text2d = hit_obj.get_texture_2d(hit_point);
                 c = hit_material.do_checker(c, hit_text2d);
             }
+            c += hit_material.ke;
 
-            if !hit_material.ks.is_zero() {
+            let kt = hit_material.transparency;
+            if kt > 0.0 && weight * kt >= self.cfg.reflection_cutoff {
+                stats.num_rays_reflection += 1;
+                let c_refract = self.trace_refraction(
+                    stats,
+                    rnd_state,
+                    ray,
+                    hit_point,
+                    hit_normal,
+                    hit_obj.is_inside_hit(s_id),
+                    hit_material,
+                    depth,
+                    weight * kt,
+                );
+                c = c * (1.0 - kt) + c_refract * kt;
+            }
+
+            let is_glossy = !hit_material.ks.is_zero() || hit_material.transparency > 0.0;
+            let cos_theta = ray.dir.normalize().dot(hit_normal).abs();
+            let ks = hit_material.get_reflectance(cos_theta);
+            let ks_max = ks.max_component();
+            if is_glossy && weight * ks_max >= self.cfg.reflection_cutoff {
                 stats.num_rays_reflection += 1;
                 let reflected_ray = ray.get_reflection(hit_point, hit_normal);
-                let c_reflect = self.trace_ray(stats, &reflected_ray, depth + 1);
-                let ks = 0.1;
-                c = c * (1.0 - ks) + c_reflect * ks;
+                let c_reflect =
+                    self.trace_ray(stats, rnd_state, &reflected_ray, depth + 1, weight * ks_max);
+                c = RGB::lerp_rgb(c, c_reflect, ks);
+            }
+            let kc = hit_material.get_clearcoat_weight(cos_theta);
+            if kc > 0.0 && weight * kc >= self.cfg.reflection_cutoff {
+                stats.num_rays_reflection += 1;
+                let coat_ray = ray.get_reflection(hit_point, hit_normal);
+                let c_coat = self.trace_ray(stats, rnd_state, &coat_ray, depth + 1, weight * kc);
+                c = c * (1.0 - kc) + c_coat * kc;
             }
             c
         } else {
-            let screen_v = self.camera.screen_v.normalize();
-            let s = ray.dir.dot(screen_v).abs() / ray.dir.norm();
-            let cmax = RGB::new(1.0, 1.0, 1.0);
-            let cyan = RGB::new(0.4, 0.6, 0.9);
-            cmax * s + cyan * (1.0 - s)
+            self.get_background(ray)
+        }
+    }
+    // what a ray that misses the scene sees
+    fn get_background(&self, ray: &Ray) -> RGB {
+        if let Some(env) = &self.environment {
+            return env.sample(ray.dir);
         }
+        let screen_v = self.camera.screen_v.normalize();
+        let s = ray.dir.dot(screen_v).abs() / ray.dir.norm();
+        self.background.eval(s)
+    }
+    /*
+     * Follow the ray through a transparent surface. The ray leaves the
+     * object when it hits from inside, or when the normal points along it;
+     * total internal reflection falls back to the mirror direction.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn trace_refraction(
+        &self,
+        stats: &mut RenderStats,
+        rnd_state: &mut u64,
+        ray: &Ray,
+        hit_point: Point,
+        hit_normal: Vec3,
+        inside: bool,
+        hit_material: &Material,
+        depth: u32,
+        weight: f32,
+    ) -> RGB {
+        let dir = ray.dir.normalize();
+        let facing = dir.dot(hit_normal) < 0.0;
+        // against the incoming ray
+        let normal = if facing {
+            hit_normal
+        } else {
+            hit_normal * -1.0
+        };
+        let eta = if inside || !facing {
+            hit_material.get_ior()
+        } else {
+            1.0 / hit_material.get_ior()
+        };
+        let refracted_ray = match dir.refract(normal, eta) {
+            Some(refracted) => {
+                let orig = Ray::offset_origin(hit_point, normal, refracted);
+                Ray::new_at(orig, refracted, ray.time)
+            }
+            None => ray.get_reflection(hit_point, normal),
+        };
+        self.trace_ray(stats, rnd_state, &refracted_ray, depth + 1, weight)
+    }
+    // the emitters sample_lights() aims at: the objects behind a light
+    fn is_sampled_emitter(&self, object: usize) -> bool {
+        self.lights
+            .iter()
+            .any(|l| l.is_sampled() && l.get_object_id() == Some(object))
+    }
+    // the environment map, when it can be importance-sampled
+    fn get_sampled_environment(&self) -> Option<&Environment> {
+        self.environment
+            .as_ref()
+            .filter(|env| env.distribution.is_some())
+    }
+    // what sample_lights() picks from: the sampled lights, then the environment
+    fn get_num_sampled_lights(&self) -> usize {
+        let num_env = usize::from(self.get_sampled_environment().is_some());
+        self.lights.iter().filter(|l| l.is_sampled()).count() + num_env
     }
+    /*
+     * Next event estimation: the light reaching a diffuse point straight
+     * from a random point of one of the emitters, through a shadow ray.
+     * The bounces scatter around lobe, the mirror direction, with a density
+     * of cos/pi to it: the light is weighted by that density over the odds
+     * of its own sample, and times kd this is the direct lighting.
+     *
+     * The environment is one more light, sampled by its brightness. The
+     * bounces that miss the scene also see it: the two estimates are
+     * blended by multiple importance sampling, as each is the better one
+     * somewhere: the light sampling for a small sun, the bounce for a broad
+     * and even sky.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn sample_lights(
+        &self,
+        stats: &mut RenderStats,
+        rnd_state: &mut u64,
+        hit_point: Point,
+        geom_normal: Vec3,
+        normal: Vec3,
+        lobe: Vec3,
+        time: Float,
+    ) -> RGB {
+        let num_lights = self.get_num_sampled_lights();
+        if num_lights == 0 {
+            return RGB::zero();
+        }
+        let pick = (fast_rand_float(rnd_state) * num_lights as Float) as usize;
+        let light = self.lights.iter().filter(|l| l.is_sampled()).nth(pick);
+        let is_delta = light.is_some_and(|l| l.is_delta());
+        let sample = match (light, self.get_sampled_environment()) {
+            (Some(light), _) => light.sample(hit_point, time, rnd_state),
+            (None, Some(env)) => {
+                let (u, v) = (fast_rand_float(rnd_state), fast_rand_float(rnd_state));
+                let sample = env.sample_dir(u, v);
+                sample.map(|(dir, pdf, radiance)| (dir, Float::INFINITY, pdf, radiance))
+            }
+            (None, None) => None,
+        };
+        let Some((dir, dist, pdf, radiance)) = sample else {
+            return RGB::zero();
+        };
+        // the bounces below the surface run into it rather than into a light
+        let cos_theta = lobe.dot(dir);
+        if cos_theta <= 0.0 || normal.dot(dir) <= 0.0 {
+            return RGB::zero();
+        }
+        let pi = std::f64::consts::PI as Float;
+        let bias = self.cfg.shadow_bias;
+        let orig = Ray::offset_origin_by(hit_point, geom_normal, dir, bias);
+        if dist == Float::INFINITY {
+            // the environment shows where a bounce would miss the scene
+            if self
+                .get_nearest_hit(stats, &Ray::new_at(orig, dir, time))
+                .is_some()
+            {
+                return RGB::zero();
+            }
+            let (pdf_light, pdf_bounce) = (pdf / num_lights as Float, cos_theta / pi);
+            let weight = power_heuristic(pdf_light, pdf_bounce);
+            return radiance * (weight * pdf_bounce / pdf_light);
+        }
+        // stops short of the emitter, which would otherwise hide its own point
+        let light_vec = hit_point + dir * dist - orig;
+        let len = light_vec.norm();
+        if len <= bias {
+            return RGB::zero();
+        }
+        let light_ray = Ray::new_at(orig, light_vec * ((len - bias) / len), time);
+        if self.is_occluded(stats, &light_ray, None) {
+            return RGB::zero();
+        }
+        // no bounce reaches a point light: nothing to weigh it against
+        if is_delta {
+            return radiance * (cos_theta * num_lights as Float / pi);
+        }
+        radiance * (cos_theta * num_lights as Float / (pi * pdf))
+    }
+    /*
+     * bounce_pdf: the density of the ray's direction, when the bounce
+     * before already sampled the lights. Hitting an emitter then adds
+     * nothing, or its light would count twice, and the environment gets
+     * the share of multiple importance sampling left by sample_lights().
+     */
     fn trace_ray_path(
         &self,
         stats: &mut RenderStats,
         rnd_state: &mut u64,
         ray: &Ray,
         depth: u32,
+        bounce_pdf: Option<Float>,
     ) -> RGB {
-        if depth > self.cfg.reflection_max_depth {
+        if depth > self.cfg.bounces.path_max_bounces {
             stats.num_rays_reflection_max += 1;
             return RGB::zero();
         }
-        let mut s_id = 0;
-        let mut t = Float::MAX;
+        let hit = self.get_nearest_hit(stats, ray);
 
-        let hit_obj = self
-            .objects
-            .iter()
-            .filter(|obj| obj.intercept(stats, ray, EPSILON, &mut t, false, &mut s_id))
-            .last();
+        if depth == 0
+            && let Some(c) = self.hit_light_marker(stats, ray, hit.map_or(Float::MAX, |h| h.t))
+        {
+            return c;
+        }
 
-        if hit_obj.is_none() {
+        let Some(Hit { object, t, s_id }) = hit else {
+            if self.environment.is_some() || self.cfg.use_sky_light {
+                let c = self.get_background(ray);
+                if let (Some(pdf_bounce), Some(env)) = (bounce_pdf, self.get_sampled_environment())
+                {
+                    let pdf_light = env.pdf(ray.dir) / self.get_num_sampled_lights() as Float;
+                    return c * power_heuristic(pdf_bounce, pdf_light);
+                }
+                return c;
+            }
             return RGB::zero();
-        }
+        };
+        let hit_obj = &self.objects[object];
 
-        let hit_mat_id = hit_obj.unwrap().get_material_id();
+        let hit_mat_id = hit_obj.get_face_material_id(s_id);
         let hit_material = &self.materials[hit_mat_id];
 
         if !hit_material.ke.is_zero() {
+            if bounce_pdf.is_some() && self.is_sampled_emitter(object) {
+                return RGB::zero();
+            }
             return hit_material.ke;
         }
 
         let hit_point = ray.orig + ray.dir * t;
-        let hit_normal = hit_obj.unwrap().get_normal(hit_point, s_id);
+        let rest_point = hit_point - hit_obj.get_velocity() * ray.time;
+        let geom_normal = hit_obj.get_normal(rest_point, s_id);
+        let mut hit_normal = geom_normal;
+        if hit_material.normal_texture.is_some() {
+            let uv = hit_obj.get_texture_2d(rest_point, s_id);
+            let tangents = hit_obj.get_tangents(rest_point, s_id);
+            hit_normal = hit_material.get_shading_normal(hit_normal, tangents, uv);
+        }
         stats.num_rays_reflection += 1;
         let mut reflected_ray = ray.get_reflection(hit_point, hit_normal);
-        if hit_material.ks.is_zero() {
-            let dir = reflected_ray.dir.normalize() + Vec3::gen_rnd_sphere(rnd_state);
-            reflected_ray.dir = dir.normalize();
+
+        /*
+         * The clearcoat reflects a Fresnel-weighted share of the light: pick
+         * it with that probability, and the base layer otherwise.
+         */
+        let cos_theta = ray.dir.normalize().dot(hit_normal).abs();
+        let kc = hit_material.get_clearcoat_weight(cos_theta);
+        if kc > 0.0 && fast_rand_float(rnd_state) < kc {
+            let roughness = hit_material.clearcoat_roughness;
+            let dir = reflected_ray.dir.normalize() + Vec3::gen_rnd_sphere(rnd_state) * roughness;
+            if dir.dot(hit_normal) * reflected_ray.dir.dot(hit_normal) > 0.0 {
+                reflected_ray = Ray::new_at(reflected_ray.orig, dir.normalize(), ray.time);
+            }
+            return self.trace_ray_path(stats, rnd_state, &reflected_ray, depth + 1, None);
         }
-        let c0 = self.trace_ray_path(stats, rnd_state, &reflected_ray, depth + 1);
-        if hit_material.ks.is_zero() {
-            c0 * hit_material.kd
+        /*
+         * A diffuse bounce takes the direct light by sampling the emitters,
+         * unless the path would end before reaching them anyway.
+         */
+        let is_diffuse = hit_material.ks.is_zero();
+        let use_light_sampling =
+            is_diffuse && self.cfg.use_light_sampling && depth < self.cfg.bounces.path_max_bounces;
+        // the side the path came from
+        let normal = if ray.dir.dot(hit_normal) < 0.0 {
+            hit_normal
+        } else {
+            -hit_normal
+        };
+        let lobe = reflected_ray.dir.normalize();
+        let direct = if use_light_sampling {
+            let time = ray.time;
+            self.sample_lights(stats, rnd_state, hit_point, geom_normal, normal, lobe, time)
+        } else {
+            RGB::zero()
+        };
+        let albedo = if is_diffuse {
+            let dir = reflected_ray.dir.normalize() + Vec3::gen_rnd_sphere(rnd_state);
+            // new ray so that inv_dir follows the direction
+            reflected_ray = Ray::new_at(reflected_ray.orig, dir.normalize(), ray.time);
+            hit_material.get_kd(hit_obj.get_texture_2d(rest_point, s_id))
         } else {
-            c0 * hit_material.ks
+            hit_material.ks
+        };
+        /*
+         * The light sampling only covers the directions above the surface:
+         * a bounce below it that still grazes past to an emitter counts it.
+         */
+        let bounce_pdf = (use_light_sampling && reflected_ray.dir.dot(normal) > 0.0).then(|| {
+            let pi = std::f64::consts::PI as Float;
+            lobe.dot(reflected_ray.dir).max(0.0) / pi
+        });
+        /*
+         * Russian roulette: the path goes on with a probability that follows
+         * the albedo, and the paths that survive make up for the others.
+         */
+        if let Some(rr_min_depth) = self.cfg.bounces.rr_min_depth
+            && depth >= rr_min_depth
+        {
+            let survival = albedo.max_component().min(1.0);
+            if fast_rand_float(rnd_state) >= survival {
+                stats.num_rays_reflection_max += 1;
+                return direct * albedo;
+            }
+            let c0 = self.trace_ray_path(stats, rnd_state, &reflected_ray, depth + 1, bounce_pdf);
+            return (direct + c0 / survival) * albedo;
+        }
+        let c0 = self.trace_ray_path(stats, rnd_state, &reflected_ray, depth + 1, bounce_pdf);
+        (direct + c0) * albedo
+    }
+
+    /*
+     * Random numbers for the samples of pixel x, y. With a seed, each pixel
+     * gets its own reproducible stream: the noise is the same from one run
+     * to the next, and from one frame to the next unless seed_per_frame.
+     */
+    fn get_pixel_rng(&self, x: u32, y: u32) -> StdRng {
+        self.get_pass_rng(x, y, 0)
+    }
+
+    // each pass of a progressive render draws other samples of the pixel
+    fn get_pass_rng(&self, x: u32, y: u32, pass: u32) -> StdRng {
+        match self.cfg.seed {
+            Some(seed) => {
+                let frame = if self.cfg.seed_per_frame {
+                    self.cfg.frame as u64
+                } else {
+                    0
+                };
+                let mut state = seed ^ (frame << 42) ^ ((y as u64) << 21) ^ x as u64;
+                // pass 0 draws the samples of a render in one go
+                if pass > 0 {
+                    state ^= fast_rand(&mut (pass as u64));
+                }
+                StdRng::seed_from_u64(fast_rand(&mut state))
+            }
+            None => StdRng::from_rng(rand::thread_rng()).unwrap(),
         }
     }
 
     fn trace_primary_ray(
         &self,
         stats: &mut RenderStats,
+        rng: &mut StdRng,
         pmap: &mut HashMap<u64, RGB>,
         u: Float,
         v: Float,
     ) -> RGB {
+        let (mut u, mut v) = (u, v);
         let mut key = 0;
-        if self.cfg.use_hashmap {
-            // need to use f64 otherwise the loss of precision bites us
-            key = (1e12 * (u as f64 + 0.5) + 1e6 * (v as f64 + 0.5)) as u64;
-            if self.cfg.use_adaptive_sampling {
-                if let Some(c) = pmap.get(&key) {
-                    return *c;
-                }
+        if self.cfg.use_hashmap && self.cfg.use_adaptive_sampling {
+            /*
+             * The corners of the adaptive boxes sit on a lattice 2^depth
+             * times finer than the pixels, that neighbouring boxes reach by
+             * different roundings: the sample is keyed by, and traced from,
+             * its lattice point, the same whichever box comes first.
+             */
+            let scale = (1u64 << (self.cfg.adaptive_max_depth + PATTERN_EXTRA_DEPTH)) as f64;
+            let scale_u = self.cfg.res_x as f64 * scale;
+            let scale_v = self.cfg.res_y as f64 * scale;
+            let ku = ((u as f64 + 0.5) * scale_u).round();
+            let kv = ((v as f64 + 0.5) * scale_v).round();
+            key = ((ku as u64) << 32) | kv as u64;
+            if let Some(c) = pmap.get(&key) {
+                return *c;
             }
+            u = (ku / scale_u - 0.5) as Float;
+            v = (kv / scale_v - 0.5) as Float;
         }
-        let ray = self.camera.get_ray(u, v);
-
-        stats.num_rays_sampling += 1;
+        let lens = if self.camera.aperture > 0.0 {
+            Some((rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)))
+        } else {
+            None
+        };
+        // the area lights pick their shadow rays from it, the same on every run
+        let mut rnd_state = rng.gen_range(0..u64::MAX);
+        let mut trace = |stats: &mut RenderStats, u: Float, v: Float| {
+            let ray = match lens {
+                Some((lens_u, lens_v)) => self.camera.get_lens_ray(u, v, lens_u, lens_v),
+                None => self.camera.get_ray(u, v),
+            };
+            stats.num_rays_sampling += 1;
+            self.trace_ray(stats, &mut rnd_state, &ray, 0 /* depth */, 1.0)
+        };
 
-        let c = self.trace_ray(stats, &ray, 0 /* depth */);
+        // one ray per channel with dispersion, through the same lens point
+        let c = if self.camera.dispersion > 0.0 {
+            let [r, g, b] = [0, 1, 2].map(|channel| {
+                let scale = self.camera.get_channel_scale(channel);
+                trace(stats, u * scale, v * scale)
+            });
+            RGB::new(r.r, g.g, b.b)
+        } else {
+            trace(stats, u, v)
+        };
         if self.cfg.use_hashmap && self.cfg.use_adaptive_sampling {
             pmap.insert(key, c);
         }
@@ -202,34 +1042,55 @@ impl RenderJob {
     }
 
     /*
+     * Mean of n path-traced samples over the pixel.
      * pos_u: -0.5 .. 0.5
      * pos_v: -0.5 .. 0.5
      */
+    #[allow(clippy::too_many_arguments)]
     fn calc_ray_box_path(
         &self,
         stats: &mut RenderStats,
+        rng: &mut StdRng,
         pos_u: Float,
         pos_v: Float,
         du: Float,
         dv: Float,
+        n: u32,
     ) -> RGB {
         assert!(!self.cfg.use_adaptive_sampling);
-        assert!(self.cfg.path_tracing > 1);
+        assert!(self.cfg.integrator == Integrator::Path);
 
         let mut c = RGB::zero();
-        let mut rng = rand::thread_rng();
         let mut rnd_state = rng.gen_range(0..u64::MAX);
 
-        for _i in 0..self.cfg.path_tracing {
-            let off_u = rng.gen_range(0.0..du);
-            let off_v = rng.gen_range(0.0..dv);
-            let ray = self.camera.get_ray(pos_u + off_u, pos_v + off_v);
+        for i in 0..n {
+            let (off_u, off_v) = get_stratified_offset(rng, i, n, du, dv);
+            let mut ray = if self.camera.aperture > 0.0 {
+                let lens_u = rng.gen_range(0.0..1.0);
+                let lens_v = rng.gen_range(0.0..1.0);
+                self.camera
+                    .get_lens_ray(pos_u + off_u, pos_v + off_v, lens_u, lens_v)
+            } else {
+                self.camera.get_ray(pos_u + off_u, pos_v + off_v)
+            };
+            if self.camera.shutter_time > 0.0 {
+                ray.time = rng.gen_range(0.0..self.camera.shutter_time);
+            }
 
             stats.num_rays_sampling += 1;
 
-            c += self.trace_ray_path(stats, &mut rnd_state, &ray, 0);
+            let sample = self.trace_ray_path(stats, &mut rnd_state, &ray, 0, None);
+            /*
+             * A rare path that finds a small bright light leaves a firefly
+             * until many samples average it out: clamping each sample
+             * trades that noise for a slightly darker, biased image.
+             */
+            c += match self.cfg.firefly_clamp {
+                Some(max) => sample.clamp_luminance(max),
+                None => sample,
+            };
         }
-        c / self.cfg.path_tracing as f32
+        c / n as f32
     }
 
     /*
@@ -240,6 +1101,7 @@ impl RenderJob {
     fn calc_ray_box(
         &self,
         stats: &mut RenderStats,
+        rng: &mut StdRng,
         pmap: &mut HashMap<u64, RGB>,
         pos_u: Float,
         pos_v: Float,
@@ -248,27 +1110,223 @@ impl RenderJob {
         lvl: u32,
     ) -> RGB {
         if !self.cfg.use_adaptive_sampling {
-            return self.trace_primary_ray(stats, pmap, pos_u + du / 2.0, pos_v + dv / 2.0);
-        }
-        let mut c00 = self.trace_primary_ray(stats, pmap, pos_u, pos_v);
-        let mut c01 = self.trace_primary_ray(stats, pmap, pos_u, pos_v + dv);
-        let mut c10 = self.trace_primary_ray(stats, pmap, pos_u + du, pos_v);
-        let mut c11 = self.trace_primary_ray(stats, pmap, pos_u + du, pos_v + dv);
-
-        if lvl < self.cfg.adaptive_max_depth {
-            let color_diff = RGB::difference(c00, c01, c10, c11) > 0.3;
-            if color_diff {
-                let du2 = du / 2.0;
-                let dv2 = dv / 2.0;
-                c00 = self.calc_ray_box(stats, pmap, pos_u, pos_v, du2, dv2, lvl + 1);
-                c01 = self.calc_ray_box(stats, pmap, pos_u, pos_v + dv2, du2, dv2, lvl + 1);
-                c10 = self.calc_ray_box(stats, pmap, pos_u + du2, pos_v, du2, dv2, lvl + 1);
-                c11 = self.calc_ray_box(stats, pmap, pos_u + du2, pos_v + dv2, du2, dv2, lvl + 1);
-            }
-        } else {
+            let (u, v) = (pos_u + du / 2.0, pos_v + dv / 2.0);
+            return self.trace_primary_ray(stats, rng, pmap, u, v);
+        }
+        let mut c00 = self.trace_primary_ray(stats, rng, pmap, pos_u, pos_v);
+        let mut c01 = self.trace_primary_ray(stats, rng, pmap, pos_u, pos_v + dv);
+        let mut c10 = self.trace_primary_ray(stats, rng, pmap, pos_u + du, pos_v);
+        let mut c11 = self.trace_primary_ray(stats, rng, pmap, pos_u + du, pos_v + dv);
+
+        let can_refine = lvl < self.cfg.adaptive_max_depth;
+        let color_diff =
+            can_refine && RGB::difference(c00, c01, c10, c11) > self.cfg.adaptive_threshold;
+        // a fine pattern may be refined further, and even with alike corners
+        let pattern = !color_diff
+            && lvl < self.cfg.adaptive_max_depth + PATTERN_EXTRA_DEPTH
+            && self.is_pattern_finer(stats, pos_u, pos_v, du, dv);
+        if color_diff || pattern {
+            let du2 = du / 2.0;
+            let dv2 = dv / 2.0;
+            let (u2, v2) = (pos_u + du2, pos_v + dv2);
+            let lvl = lvl + 1;
+            c00 = self.calc_ray_box(stats, rng, pmap, pos_u, pos_v, du2, dv2, lvl);
+            c01 = self.calc_ray_box(stats, rng, pmap, pos_u, v2, du2, dv2, lvl);
+            c10 = self.calc_ray_box(stats, rng, pmap, u2, pos_v, du2, dv2, lvl);
+            c11 = self.calc_ray_box(stats, rng, pmap, u2, v2, du2, dv2, lvl);
+        } else if !can_refine {
             stats.num_rays_sampling_max += 1;
         }
-        (c00 + c01 + c10 + c11) * 0.25
+        let c = (c00 + c01 + c10 + c11) * 0.25;
+        if lvl == 0 && self.cfg.use_bilinear {
+            return self.blend_bilinear(stats, rng, pmap, pos_u, pos_v, du, dv, c);
+        }
+        c
+    }
+
+    /*
+     * The mean of the bilinear interpolation of the corners over the box
+     * is their plain average: blending within the pixel changes nothing.
+     * Here the corners of the pixel and of its neighbours are joined
+     * bilinearly, then seen through a tent filter one pixel wide: per axis,
+     * the corners 1.5, 0.5, 0.5 and 1.5 pixels away from the center weigh
+     * 1, 23, 23 and 1 / 48. The box, refined or not, stands for its own
+     * corners; the twelve around it, clamped to the image, soften the
+     * steps along the edges.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn blend_bilinear(
+        &self,
+        stats: &mut RenderStats,
+        rng: &mut StdRng,
+        pmap: &mut HashMap<u64, RGB>,
+        pos_u: Float,
+        pos_v: Float,
+        du: Float,
+        dv: Float,
+        c_box: RGB,
+    ) -> RGB {
+        const WEIGHTS: [Float; 4] = [1.0 / 48.0, 23.0 / 48.0, 23.0 / 48.0, 1.0 / 48.0];
+        let w_box = (WEIGHTS[1] + WEIGHTS[2]) * (WEIGHTS[1] + WEIGHTS[2]);
+        let mut c = c_box * w_box;
+        for (i, wi) in WEIGHTS.iter().enumerate() {
+            for (j, wj) in WEIGHTS.iter().enumerate() {
+                if (1..3).contains(&i) && (1..3).contains(&j) {
+                    continue;
+                }
+                let u = (pos_u + du * (i as Float - 1.0)).clamp(-0.5, 0.5);
+                let v = (pos_v + dv * (j as Float - 1.0)).clamp(-0.5, 0.5);
+                c += self.trace_primary_ray(stats, rng, pmap, u, v) * (wi * wj);
+            }
+        }
+        c
+    }
+
+    /*
+     * Whether the procedural pattern (checker) seen at the center of the box
+     * changes faster than the box corners are spaced: they could then all
+     * land on alike cells and miss the edges in between, which shimmers on
+     * a distant checker floor. The footprint of the box is carried onto the
+     * surface along the ray, then into texture space.
+     */
+    fn is_pattern_finer(
+        &self,
+        stats: &mut RenderStats,
+        pos_u: Float,
+        pos_v: Float,
+        du: Float,
+        dv: Float,
+    ) -> bool {
+        if !self
+            .materials
+            .iter()
+            .any(|m| m.get_pattern_cell().is_some())
+        {
+            return false;
+        }
+        let (u, v) = (pos_u + du / 2.0, pos_v + dv / 2.0);
+        let (ray, footprint) = self.camera.get_ray_footprint(u, v, du, dv);
+        let Some(Hit { object, t, s_id }) = self.get_nearest_hit(stats, &ray) else {
+            return false;
+        };
+        let obj = &self.objects[object];
+        let Some(cell) = self.materials[obj.get_face_material_id(s_id)].get_pattern_cell() else {
+            return false;
+        };
+        let point = ray.orig + ray.dir * t - obj.get_velocity() * ray.time;
+        let normal = obj.get_normal(point, s_id);
+        let dir_n = ray.dir.dot(normal);
+        if dir_n.abs() < EPSILON {
+            return true; // edge-on
+        }
+        let uv = obj.get_texture_2d(point, s_id);
+        let offsets = [
+            footprint.dodu + footprint.dpdu * t,
+            footprint.dodv + footprint.dpdv * t,
+        ];
+        offsets.iter().any(|&offset| {
+            let offset = offset - ray.dir * (offset.dot(normal) / dir_n);
+            // a seam where the coordinates wrap around only costs more rays
+            let uv2 = obj.get_texture_2d(point + offset, s_id);
+            (uv2.x - uv.x).abs().max((uv2.y - uv.y).abs()) > cell
+        })
+    }
+
+    fn get_pixel_geom(&self, stats: &mut RenderStats, u: Float, v: Float) -> PixelGeom {
+        let ray = self.camera.get_ray(u, v);
+        match self.get_nearest_hit(stats, &ray) {
+            Some(Hit { object, t, s_id }) => {
+                let obj = &self.objects[object];
+                let point = ray.orig + ray.dir * t - obj.get_velocity() * ray.time;
+                PixelGeom {
+                    object: Some(object),
+                    depth: t,
+                    normal: obj.get_normal(point, s_id),
+                }
+            }
+            None => PixelGeom::miss(),
+        }
+    }
+
+    fn calc_ray_grid(
+        &self,
+        stats: &mut RenderStats,
+        rng: &mut StdRng,
+        pos_u: Float,
+        pos_v: Float,
+    ) -> RGB {
+        let mut pmap = HashMap::new();
+        let n = EDGE_AA_GRID;
+        let du = 1.0 / (self.cfg.res_x * n) as Float;
+        let dv = 1.0 / (self.cfg.res_y * n) as Float;
+        let mut c = RGB::zero();
+        for i in 0..n {
+            for j in 0..n {
+                let u = pos_u + (i as Float + 0.5) * du;
+                let v = pos_v + (j as Float + 0.5) * dv;
+                c += self.trace_primary_ray(stats, rng, &mut pmap, u, v);
+            }
+        }
+        c / (n * n) as f32
+    }
+
+    // pixel i: -0.5 .. 0.5 position of its corner, and its size du, dv
+    fn get_pixel_pos(&self, i: u32) -> (Float, Float, Float, Float) {
+        let (res_x, res_y) = (self.cfg.res_x, self.cfg.res_y);
+        let du = 1.0 / res_x as Float;
+        let dv = 1.0 / res_y as Float;
+        let (x, y) = (i % res_x, i / res_x);
+        (0.5 - x as Float * du, 0.5 - y as Float * dv, du, dv)
+    }
+
+    /*
+     * The primary hit through the center of each pixel. A pass of its own,
+     * so it is the same whatever the sampling and the scan order.
+     */
+    fn render_geom(&self) -> Vec<PixelGeom> {
+        (0..self.cfg.res_x * self.cfg.res_y)
+            .into_par_iter()
+            .map(|i| {
+                let mut stats: RenderStats = Default::default();
+                let (pos_u, pos_v, du, dv) = self.get_pixel_pos(i);
+                self.get_pixel_geom(&mut stats, pos_u + du / 2.0, pos_v + dv / 2.0)
+            })
+            .collect()
+    }
+
+    /*
+     * Second pass of --edge-aa: the image holds one sample per pixel, find
+     * the pixels on geometry or color edges and supersample only those.
+     */
+    fn render_edges(&self, exit_req: Arc<AtomicBool>) {
+        let res_x = self.cfg.res_x;
+        let res_y = self.cfg.res_y;
+        let colors = self.image.lock().unwrap().get_hdr().lock().unwrap().clone();
+        let edges = detect_edges(&self.geom, &colors, res_x, res_y);
+        let num_edges = edges.iter().filter(|&&e| e).count();
+
+        (0..res_x * res_y)
+            .into_par_iter()
+            .filter(|&i| edges[i as usize])
+            .for_each(|i| {
+                if exit_req.load(Ordering::SeqCst) {
+                    return;
+                }
+                let mut stats: RenderStats = Default::default();
+                let (pos_u, pos_v, _, _) = self.get_pixel_pos(i);
+                let mut rng = self.get_pixel_rng(i % res_x, i / res_x);
+                let c = self.calc_ray_grid(&mut stats, &mut rng, pos_u, pos_v);
+                self.image
+                    .lock()
+                    .unwrap()
+                    .push_pixel(i % res_x, i / res_x, c);
+                self.total_stats.lock().unwrap().add(stats);
+            });
+        println!(
+            "-- edge-aa: {} pixels supersampled ({:.1}%)",
+            num_edges,
+            100.0 * num_edges as f32 / (res_x * res_y).max(1) as f32
+        );
     }
 
     pub fn print_stats(&self) {
@@ -318,17 +1376,24 @@ impl RenderJob {
         );
         let intersect_stats = [
             ("Sphere", stats.num_intersects_sphere),
+            ("Cylinder", stats.num_intersects_cylinder),
+            ("Torus", stats.num_intersects_torus),
             ("Plane", stats.num_intersects_plane),
             ("Triangle", stats.num_intersects_triangle),
+            ("Box", stats.num_intersects_box),
             ("AABB", stats.num_intersects_aabb),
         ];
 
-        for (s, n) in intersect_stats {
-            println!(
-                "num_intersects {:<10}{:>12}",
-                format!("{s}:"),
-                pretty_print(n)
-            );
+        if cfg!(feature = "stats") {
+            for (s, n) in intersect_stats {
+                println!(
+                    "num_intersects {:<10}{:>12}",
+                    format!("{s}:"),
+                    pretty_print(n)
+                );
+            }
+        } else {
+            println!("num_intersects: not counted, built without the stats feature");
         }
 
         let num_pixels = (self.cfg.res_x * self.cfg.res_y) as u64;
@@ -360,7 +1425,20 @@ impl RenderJob {
         }
     }
 
-    fn render_pixel_box(&self, x0: u32, y0: u32, sz_x: u32, sz_y: u32, stats: &mut RenderStats) {
+    /*
+     * Renders the box row by row, and stops after a row once past the
+     * deadline: returns the first row left undone, the end of the box when
+     * it is complete.
+     */
+    fn render_pixel_box(
+        &self,
+        x0: u32,
+        y0: u32,
+        sz_x: u32,
+        sz_y: u32,
+        stats: &mut RenderStats,
+        deadline: Option<Instant>,
+    ) -> u32 {
         let u = 1.0;
         let v = 1.0;
         let du = u / self.cfg.res_x as Float;
@@ -369,18 +1447,119 @@ impl RenderJob {
         let x_max = (x0 + sz_x).min(self.cfg.res_x);
 
         let mut pmap = HashMap::new();
+        // only kept for the tile callback
+        let mut pixels = vec![];
+        let mut y_end = y_max;
 
         for y in y0..y_max {
             let pos_v = v / 2.0 - (y as Float) * dv;
+            let num_rays = stats.num_rays_sampling;
             for x in x0..x_max {
                 let pos_u = u / 2.0 - (x as Float) * du;
-                let c = if self.cfg.path_tracing > 1 {
-                    self.calc_ray_box_path(stats, pos_u, pos_v, du, dv)
+                let mut rng = self.get_pixel_rng(x, y);
+                let c = if self.cfg.integrator == Integrator::Path {
+                    let n = self.cfg.samples_per_pixel;
+                    self.calc_ray_box_path(stats, &mut rng, pos_u, pos_v, du, dv, n)
                 } else {
-                    self.calc_ray_box(stats, &mut pmap, pos_u, pos_v, du, dv, 0)
+                    self.calc_ray_box(stats, &mut rng, &mut pmap, pos_u, pos_v, du, dv, 0)
                 };
 
-                self.image.lock().unwrap().push_pixel(x, y, c);
+                if self.cfg.stats_only {
+                    std::hint::black_box(c);
+                } else {
+                    self.image.lock().unwrap().push_pixel(x, y, c);
+                }
+                if self.tile_func.is_some() {
+                    pixels.push(c);
+                }
+            }
+            self.report_progress(x_max - x0, stats.num_rays_sampling - num_rays);
+            if y + 1 < y_max && deadline.is_some_and(|d| Instant::now() > d) {
+                y_end = y + 1;
+                break;
+            }
+        }
+        if let Some(func) = &self.tile_func {
+            func(&Tile {
+                x: x0,
+                y: y0,
+                sz_x: x_max - x0,
+                sz_y: y_end - y0,
+                pixels: &pixels,
+            });
+        }
+        y_end
+    }
+
+    fn render_tile(
+        &self,
+        x0: u32,
+        y0: u32,
+        sz_x: u32,
+        sz_y: u32,
+        stats: &mut RenderStats,
+        deadline: Option<Instant>,
+    ) -> u32 {
+        let start = Instant::now();
+        let y_end = self.render_pixel_box(x0, y0, sz_x, sz_y, stats, deadline);
+        if self.cfg.timing_heatmap {
+            self.tile_timings.lock().unwrap().push(TileTiming {
+                x: x0,
+                y: y0,
+                sz_x,
+                sz_y: y_end - y0,
+                usec: start.elapsed().as_micros(),
+            });
+        }
+        y_end
+    }
+
+    /*
+     * The corner samples that the adaptive hashmap shares between pixels
+     * depend on the lens sample of the pixel that traced them first: a
+     * split would change the image there.
+     */
+    fn can_split_tiles(&self) -> bool {
+        !(self.camera.aperture > 0.0 && self.cfg.use_hashmap && self.cfg.use_adaptive_sampling)
+    }
+
+    /*
+     * Straggler tiles, eg. over dense geometry, would leave the other
+     * threads idle at the end of the render: past tile_split_time, a tile
+     * stops after its current row and spawns the rest as four quarters,
+     * which the idle threads steal. The pixels are the same either way.
+     */
+    fn render_split_tile<'a>(
+        &'a self,
+        scope: &rayon::Scope<'a>,
+        x0: u32,
+        y0: u32,
+        sz_x: u32,
+        sz_y: u32,
+        exit_req: &'a AtomicBool,
+    ) {
+        let x_max = (x0 + sz_x).min(self.cfg.res_x);
+        let y_max = (y0 + sz_y).min(self.cfg.res_y);
+        let (sz_x, sz_y) = (x_max - x0, y_max - y0);
+        if exit_req.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut stats: RenderStats = Default::default();
+        let deadline = self
+            .cfg
+            .tile_split_time
+            .filter(|_| self.can_split_tiles() && sz_x * sz_y >= TILE_SPLIT_MIN_PIXELS)
+            .map(|time| Instant::now() + time);
+        let y = self.render_tile(x0, y0, sz_x, sz_y, &mut stats, deadline);
+        self.total_stats.lock().unwrap().add(stats);
+
+        let (w, h) = (sz_x, y_max - y);
+        let (w0, h0) = (w.div_ceil(2), h.div_ceil(2));
+        for (x, sx) in [(x0, w0), (x0 + w0, w - w0)] {
+            for (y, sy) in [(y, h0), (y + h0, h - h0)] {
+                if sx > 0 && sy > 0 {
+                    scope.spawn(move |s| self.render_split_tile(s, x, y, sx, sy, exit_req));
+                }
             }
         }
     }
@@ -390,54 +1569,297 @@ impl RenderJob {
             let mut stats: RenderStats = Default::default();
 
             if exit_req.load(Ordering::SeqCst) {
-                self.report_progress(self.cfg.res_x);
                 return;
             }
-            self.render_pixel_box(0, y, self.cfg.res_x, 1, &mut stats);
-            self.report_progress(self.cfg.res_x);
+            self.render_tile(0, y, self.cfg.res_x, 1, &mut stats, None);
             self.total_stats.lock().unwrap().add(stats);
         });
     }
 
+    fn is_progressive(&self) -> bool {
+        self.cfg.progressive.is_some() && self.cfg.integrator == Integrator::Path
+    }
+
+    // no more passes than samples per pixel, one unless progressive
+    fn get_num_passes(&self) -> u32 {
+        match self.cfg.progressive {
+            Some(n) if self.is_progressive() => n.clamp(1, self.cfg.samples_per_pixel.max(1)),
+            _ => 1,
+        }
+    }
+
+    /*
+     * Every pass traces its share of the samples of every pixel, row by
+     * row, and adds them into the image: the preview is complete but noisy
+     * after the first pass and sharpens with each one. The tile callback
+     * gets each row with its pixels averaged over the passes so far.
+     */
+    fn render_image_progressive(&mut self, exit_req: Arc<AtomicBool>) {
+        let (res_x, res_y) = (self.cfg.res_x, self.cfg.res_y);
+        let spp = self.cfg.samples_per_pixel;
+        let num_passes = self.get_num_passes();
+
+        for pass in 0..num_passes {
+            let n = spp * (pass + 1) / num_passes - spp * pass / num_passes;
+            (0..res_y).into_par_iter().for_each(|y| {
+                if exit_req.load(Ordering::SeqCst) {
+                    return;
+                }
+                let mut stats: RenderStats = Default::default();
+                let mut pixels = vec![];
+                for x in 0..res_x {
+                    let (pos_u, pos_v, du, dv) = self.get_pixel_pos(y * res_x + x);
+                    let mut rng = self.get_pass_rng(x, y, pass);
+                    let c = self.calc_ray_box_path(&mut stats, &mut rng, pos_u, pos_v, du, dv, n);
+                    if self.cfg.stats_only {
+                        std::hint::black_box(c);
+                    } else {
+                        let mean = self.image.lock().unwrap().accumulate_pixel(x, y, c, n);
+                        pixels.push(mean);
+                    }
+                }
+                if let Some(func) = &self.tile_func
+                    && !self.cfg.stats_only
+                {
+                    func(&Tile {
+                        x: 0,
+                        y,
+                        sz_x: res_x,
+                        sz_y: 1,
+                        pixels: &pixels,
+                    });
+                }
+                self.report_progress(res_x, stats.num_rays_sampling);
+                self.total_stats.lock().unwrap().add(stats);
+            });
+            // the preview refreshes on every pass
+            if !exit_req.load(Ordering::SeqCst) {
+                let progress = self.progress.lock().unwrap();
+                self.notify_progress(self.get_fraction(&progress));
+            }
+        }
+    }
+
     fn render_image_box(&mut self, exit_req: Arc<AtomicBool>) {
         let mut step = 32;
-        if self.cfg.path_tracing > 1 {
+        if self.cfg.integrator == Integrator::Path {
             step = 10;
         }
         let ny = self.cfg.res_y.div_ceil(step);
         let nx = self.cfg.res_x.div_ceil(step);
-        (0..ny * nx).into_par_iter().for_each(|v| {
-            let mut stats: RenderStats = Default::default();
-            let x = (v % nx) * step;
-            let y = (v / nx) * step;
-
-            if exit_req.load(Ordering::SeqCst) {
-                self.report_progress(step * step);
-                return;
+        let job = &*self;
+        let exit_req = &*exit_req;
+        rayon::scope(|s| {
+            for v in 0..ny * nx {
+                let x = (v % nx) * step;
+                let y = (v / nx) * step;
+                s.spawn(move |s| job.render_split_tile(s, x, y, step, step, exit_req));
             }
-            self.render_pixel_box(x, y, step, step, &mut stats);
-            self.report_progress(step * step);
-            self.total_stats.lock().unwrap().add(stats);
         });
     }
 
+    /*
+     * Start over for another frame of an animation: the scene is kept, the
+     * stats, the timings and the progress are reset.
+     */
+    pub fn set_frame(&mut self, frame: u32) {
+        self.cfg.frame = frame;
+        *self.progress.get_mut().unwrap() = Progress::default();
+        *self.total_stats.get_mut().unwrap() = RenderStats::default();
+        self.tile_timings.get_mut().unwrap().clear();
+        self.start_ts = Instant::now();
+    }
+
+    /*
+     * With stats_only the image stays empty: nothing but the tracing
+     * itself shows in the timings.
+     */
     pub fn alloc_image(&mut self) {
-        self.image = Arc::new(Mutex::new(Image::new(
-            self.cfg.use_gamma,
-            self.cfg.res_x,
-            self.cfg.res_y,
-        )));
+        if self.cfg.stats_only {
+            return;
+        }
+        let mut image = Image::new(self.cfg.use_gamma, self.cfg.res_x, self.cfg.res_y);
+        image.set_tonemap(self.cfg.tonemap);
+        image.grade(self.cfg.grade);
+        self.image = Arc::new(Mutex::new(image));
     }
 
     pub fn render_scene(&mut self, exit_req: Arc<AtomicBool>) {
-        if self.cfg.use_lines {
-            self.render_image_lines(exit_req);
+        if self.is_progressive() {
+            self.render_image_progressive(exit_req.clone());
+        } else if self.cfg.use_lines {
+            self.render_image_lines(exit_req.clone());
         } else {
-            self.render_image_box(exit_req);
+            self.render_image_box(exit_req.clone());
+        }
+        // what was done before the stop, rather than the tiles left out
+        if exit_req.load(Ordering::SeqCst) {
+            let progress = self.progress.lock().unwrap();
+            self.notify_progress(self.get_fraction(&progress));
+        }
+        let use_edge_aa = self.cfg.use_edge_aa && self.cfg.integrator == Integrator::Whitted;
+        self.geom.clear();
+        if (use_edge_aa || !self.cfg.aovs.is_empty())
+            && !self.cfg.stats_only
+            && !exit_req.load(Ordering::SeqCst)
+        {
+            self.geom = self.render_geom();
+        }
+        // the edges are found from the colors of the image
+        if use_edge_aa && !self.geom.is_empty() && !exit_req.load(Ordering::SeqCst) {
+            self.render_edges(exit_req);
+        }
+    }
+
+    /*
+     * Time spent per pixel, averaged over its tile: shows where the render
+     * time concentrates.
+     */
+    fn save_timing_heatmap(&self) -> std::io::Result<()> {
+        let res_x = self.cfg.res_x;
+        let res_y = self.cfg.res_y;
+        let mut values = vec![0.0; (res_x * res_y) as usize];
+        let timings = self.tile_timings.lock().unwrap();
+
+        for tile in timings.iter() {
+            let x_max = (tile.x + tile.sz_x).min(res_x);
+            let y_max = (tile.y + tile.sz_y).min(res_y);
+            let num_pixels = (x_max - tile.x) * (y_max - tile.y);
+            let v = tile.usec as f32 / num_pixels as f32;
+            for y in tile.y..y_max {
+                for x in tile.x..x_max {
+                    values[(y * res_x + x) as usize] = v;
+                }
+            }
+        }
+        if let Some(slowest) = timings.iter().max_by_key(|t| t.usec) {
+            let total: u128 = timings.iter().map(|t| t.usec).sum();
+            println!(
+                "tiles: {} -- avg {:.2} msec -- slowest at {}x{}: {:.2} msec",
+                timings.len(),
+                total as Float / timings.len() as Float / 1000.0,
+                slowest.x,
+                slowest.y,
+                slowest.usec as Float / 1000.0
+            );
         }
+        let mut file = self.output_path();
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        file.set_file_name(format!("{stem}-timing.png"));
+        save_heatmap(&file, res_x, res_y, &values)
+    }
+
+    // image_file, or the expanded output template, inside output_dir if any
+    pub fn output_path(&self) -> PathBuf {
+        output_path(
+            self.cfg.output_dir.as_deref(),
+            &self.cfg.image_file,
+            self.cfg.output_template.as_deref(),
+            &self.cfg.scene_file,
+            self.cfg.frame,
+            self.cfg.samples_per_pixel,
+        )
     }
 
+    /*
+     * Each aov next to the image, eg. pic_depth.png. Nothing when the
+     * render was interrupted before the geometry pass.
+     */
+    fn save_aovs(&self, file: &Path) -> std::io::Result<()> {
+        if self.geom.is_empty() {
+            return Ok(());
+        }
+        let (res_x, res_y) = (self.cfg.res_x, self.cfg.res_y);
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let hits = || self.geom.iter().map(|g| g.object.map(|_| g));
+        for aov in &self.cfg.aovs {
+            match aov {
+                Aov::Depth => {
+                    let depth: Vec<_> = hits().map(|g| g.map(|g| g.depth)).collect();
+                    let file = file.with_file_name(format!("{stem}_depth.png"));
+                    save_depth(&file, res_x, res_y, &depth)?;
+                }
+                Aov::Normal => {
+                    let normals: Vec<_> = hits().map(|g| g.map(|g| g.normal)).collect();
+                    let file = file.with_file_name(format!("{stem}_normal.png"));
+                    save_normals(&file, res_x, res_y, &normals)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * The coverage of the matte object, eg. pic_matte.png: white where the
+     * primary ray hits it first, black elsewhere, anti-aliased by the same
+     * N x N grid of primary rays as the edges.
+     */
+    fn save_matte(&self, file: &Path, matte: &str) -> std::io::Result<()> {
+        let ids = get_matte_objects(&self.object_names, matte);
+        let n = EDGE_AA_GRID;
+        let coverage: Vec<f32> = (0..self.cfg.res_x * self.cfg.res_y)
+            .into_par_iter()
+            .map(|i| {
+                let mut stats: RenderStats = Default::default();
+                let (pos_u, pos_v, du, dv) = self.get_pixel_pos(i);
+                let mut num_hits = 0;
+                for a in 0..n {
+                    for b in 0..n {
+                        let u = pos_u + (a as Float + 0.5) / n as Float * du;
+                        let v = pos_v + (b as Float + 0.5) / n as Float * dv;
+                        let ray = self.camera.get_ray(u, v);
+                        let hit = self.get_nearest_hit(&mut stats, &ray);
+                        if hit.is_some_and(|h| ids.contains(&h.object)) {
+                            num_hits += 1;
+                        }
+                    }
+                }
+                num_hits as f32 / (n * n) as f32
+            })
+            .collect();
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let file = file.with_file_name(format!("{stem}_matte.png"));
+        save_matte(&file, self.cfg.res_x, self.cfg.res_y, &coverage)
+    }
+
+    /*
+     * Renders the scene without writing any file, eg. when embedded in
+     * another tool: returns the linear colors, before the tone mapping,
+     * the grading and the gamma of the saved image. The buffer is row-major
+     * from the top-left pixel, res_x * res_y long: pixel (x, y) is at
+     * y * res_x + x. A stopped render leaves the pixels it did not reach
+     * black, and stats_only an empty buffer.
+     */
+    pub fn render_to_buffer(&mut self, exit_req: Arc<AtomicBool>) -> Vec<RGB> {
+        self.alloc_image();
+        self.render_scene(exit_req);
+        self.image.lock().unwrap().pixels()
+    }
+
+    // writes what render_scene() or render_to_buffer() left in the image
     pub fn save_image(&mut self) -> std::io::Result<()> {
-        return self.image.lock().unwrap().save_image(&self.cfg.image_file);
+        if let Some(dir) = &self.cfg.output_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        if self.cfg.timing_heatmap {
+            self.save_timing_heatmap()?;
+        }
+        let file = self.output_path();
+        self.save_aovs(&file)?;
+        if let Some(matte) = &self.cfg.matte {
+            self.save_matte(&file, matte)?;
+        }
+        let mut image = self.image.lock().unwrap();
+        image.save_image(&file, self.cfg.jpeg_quality)
+    }
+
+    // mean linear luminance of the rendered image
+    pub fn average_luminance(&self) -> f32 {
+        let hdr = self.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap();
+        if pixels.is_empty() {
+            return 0.0;
+        }
+        pixels.iter().map(|c| c.luminance()).sum::<f32>() / pixels.len() as f32
     }
 }
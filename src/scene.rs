@@ -1,195 +1,1002 @@
 use colored::Colorize;
 
 use rand::Rng;
+use std::collections::BTreeSet;
 use std::fs;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use crate::ProgressFunc;
 use crate::Vec3;
+use crate::aabb::AABB;
+use crate::ao::bake_vertex_ao;
+use crate::ao::get_ao_cache_path;
+use crate::ao::get_ao_distance;
+use crate::ao::load_ao_cache;
+use crate::ao::save_ao_cache;
 use crate::camera::Camera;
+use crate::color::Gradient;
 use crate::color::RGB;
+use crate::grid::UniformGrid;
 use crate::image::Image;
 use crate::light::AmbientLight;
+use crate::light::AreaLight;
 use crate::light::Light;
+use crate::light::LightMarker;
+use crate::light::ObjectLight;
 use crate::light::SpotLight;
 use crate::light::VectorLight;
 use crate::material::Material;
+use crate::model::Model;
+use crate::model::compute_vertex_normals;
+use crate::model::load_model_file;
+use crate::object_bvh::ObjectBvh;
+use crate::render::Integrator;
+use crate::render::Progress;
 use crate::render::RenderConfig;
 use crate::render::RenderJob;
+use crate::render::default_background;
+use crate::render::get_matte_objects;
+use crate::texture::Environment;
+use crate::texture::Texture;
 use crate::vec3::Float;
+use crate::vec3::Matrix4;
 use crate::vec3::Point;
 
+use crate::three_d::Cuboid;
+use crate::three_d::Cylinder;
+use crate::three_d::Disk;
 use crate::three_d::Mesh;
 use crate::three_d::Object;
 use crate::three_d::Plane;
+use crate::three_d::Quad;
 use crate::three_d::Sphere;
+use crate::three_d::Torus;
+use crate::three_d::Transform;
+use crate::three_d::Transformable;
 use crate::three_d::Triangle;
 
+// radius of the light markers, per unit of distance to the camera
+const LIGHT_MARKER_SIZE: Float = 0.01;
+// surface samples per axis when estimating what an emitter shows the camera
+const EMITTER_CHECK_GRID: u32 = 4;
+// projected area below this fraction of the area counts as edge-on
+const EMITTER_MIN_PROJECTED: Float = 1e-4;
+
+/*
+ * Objects, lights and materials are stored in a stable order that does not
+ * depend on how the JSON map is iterated: each loader walks its array
+ * ("spheres") in order, or else its numbered keys ("sphere.0", "sphere.1",
+ * ...) by increasing index and stops at the first missing one. Objects are
+ * appended as planes, spheres, triangles, quads, boxes, disks, cylinders,
+ * tori, meshes then the rectangles of the area lights; lights as the
+ * "lights" array lists them, or spot-lights, area-lights then vec-lights,
+ * then ambient, then one per other emissive object in the object order;
+ * the scene materials come first, then those of the mesh files in the
+ * order of their MTL or glTF file, then the default material when an
+ * object names none, then one emissive material per area light.
+ */
+/// Builds a RenderJob from objects, lights, materials and a camera, either
+/// from Rust or from the JSON loaders below, which go through it too:
+///
+/// ```
+/// use rayflex::camera::Camera;
+/// use rayflex::color::RGB;
+/// use rayflex::light::SpotLight;
+/// use rayflex::material::Material;
+/// use rayflex::render::RenderConfig;
+/// use rayflex::scene::SceneBuilder;
+/// use rayflex::three_d::Plane;
+/// use rayflex::three_d::Sphere;
+/// use rayflex::vec3::{Point, Vec3};
+/// use std::sync::Arc;
+/// use std::sync::atomic::AtomicBool;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut builder = SceneBuilder::new();
+/// let red = builder.add_material(Material {
+///     kd: RGB::new(1.0, 0.0, 0.0),
+///     ..Default::default()
+/// });
+/// let white = builder.add_material(Material {
+///     kd: RGB::new(1.0, 1.0, 1.0),
+///     ..Default::default()
+/// });
+/// for (i, y) in [-1.5, 0.0, 1.5].into_iter().enumerate() {
+///     let mat = if i == 1 { white } else { red };
+///     builder.add_sphere(Sphere::new(Point::new(0.0, y, 0.5), 0.6, mat)?)?;
+/// }
+/// builder.add_plane(Plane::new(Point::zero(), Vec3::unity_z(), white)?)?;
+/// builder.add_light(Arc::new(SpotLight {
+///     name: "spot-light.0".to_owned(),
+///     pos: Point::new(-3.0, 2.0, 4.0),
+///     rgb: RGB::new(1.0, 1.0, 1.0),
+///     intensity: 20.0,
+/// }));
+/// builder.set_camera(Camera::new(
+///     Point::new(-6.0, 0.0, 1.5),
+///     Point::new(0.0, 0.0, 0.5),
+///     Vec3::unity_z(),
+///     40.0,
+///     1.0,
+/// ));
+/// let cfg = RenderConfig {
+///     res_x: 64,
+///     res_y: 64,
+///     ..Default::default()
+/// };
+/// let mut job = builder.build(cfg)?;
+/// job.alloc_image();
+/// job.render_scene(Arc::new(AtomicBool::new(false)));
+/// let hdr = job.image.lock().unwrap().get_hdr();
+/// assert!(hdr.lock().unwrap().iter().any(|c| c.r > 0.5 && c.g < 0.1));
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Default)]
-struct Scene {
+pub struct SceneBuilder {
+    camera: Option<Camera>,
+    background: Option<Gradient>,
+    environment: Option<Environment>,
     num_planes: u32,
     num_spheres: u32,
+    num_cylinders: u32,
+    num_tori: u32,
     num_triangles: usize,
+    num_quads: u32,
+    num_boxes: u32,
+    num_disks: u32,
     num_triangles_in_all_objs: usize,
     num_materials: u32,
     num_vec_lights: u32,
     num_spot_lights: u32,
+    num_area_lights: u32,
     num_objs: u32,
+    default_material_id: Option<usize>,
     lights: Vec<Arc<dyn Light + 'static + Send + Sync>>,
     materials: Vec<Arc<Material>>,
     objects: Vec<Arc<dyn Object + 'static + Send + Sync>>,
+    object_names: Vec<String>,
+    used_keys: BTreeSet<String>,
+}
+
+/*
+ * What is wrong with a scene. key and field name the offending entry the
+ * way the JSON does, eg. "sphere.2" or "spheres[2]", whether or not the
+ * scene came from a file.
+ */
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Json {
+        field: String,
+        source: serde_json::Error,
+    },
+    MissingCamera,
+    BadResolution,
+    MaterialIndexOutOfRange {
+        key: String,
+        id: usize,
+        num: usize,
+    },
+    UnknownMaterial {
+        key: String,
+        name: String,
+    },
+    Invalid {
+        key: String,
+        msg: String,
+    },
 }
 
-fn load_materials(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let s = format!("material.{}", scene.num_materials);
-        match serde_json::from_value::<Material>(json[&s].clone()) {
-            Err(_error) => break,
-            Ok(mat) => {
-                scene.materials.push(Arc::new(mat));
-                scene.num_materials += 1;
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{e}"),
+            SceneError::Json { field, source } => write!(f, "{field}: {source}"),
+            SceneError::MissingCamera => write!(f, "scene has no camera"),
+            SceneError::BadResolution => write!(f, "scene has no valid resolution"),
+            SceneError::MaterialIndexOutOfRange { key, id, num } => {
+                write!(f, "{key}: material_id {id} out of range ({num} materials)")
+            }
+            SceneError::UnknownMaterial { key, name } => {
+                write!(f, "{key}: unknown material '{name}'")
             }
+            SceneError::Invalid { key, msg } => write!(f, "{key}: {msg}"),
         }
     }
-    Ok(())
 }
 
-fn load_mesh(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let name = format!("obj.{}.path", scene.num_objs);
-        if json[&name].is_null() {
-            break;
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(e) => Some(e),
+            SceneError::Json { source, .. } => Some(source),
+            _ => None,
         }
-        let path = json[&name].as_str().unwrap();
-        let rxname = format!("obj.{}.rotx", scene.num_objs);
-        let ryname = format!("obj.{}.roty", scene.num_objs);
-        let rzname = format!("obj.{}.rotz", scene.num_objs);
-        let mut angle_x = 0.0;
-        let mut angle_y = 0.0;
-        let mut angle_z = 0.0;
-        let mut angle_x_rad = 0.0;
-        let mut angle_y_rad = 0.0;
-        let mut angle_z_rad = 0.0;
-        let mut num_triangles_in_obj = 0;
-        if let Some(alpha) = json[&rxname].as_f64() {
-            angle_x = alpha;
-            angle_x_rad = angle_x.to_radians() as Float;
+    }
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<SceneError> for std::io::Error {
+    fn from(e: SceneError) -> Self {
+        match e {
+            SceneError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
         }
-        if let Some(alpha) = json[&ryname].as_f64() {
-            angle_y = alpha;
-            angle_y_rad = angle_y.to_radians() as Float;
+    }
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    // returns the material_id to give objects
+    pub fn add_material(&mut self, mat: Material) -> usize {
+        self.materials.push(Arc::new(mat));
+        self.num_materials += 1;
+        self.materials.len() - 1
+    }
+    // the first material of that name: the scene ones come before the meshes'
+    pub fn get_material_id(&self, name: &str) -> Option<usize> {
+        self.materials
+            .iter()
+            .position(|m| m.name.as_deref() == Some(name))
+    }
+    fn check_material_id(&self, key: &str, id: usize) -> Result<(), SceneError> {
+        if id >= self.materials.len() {
+            return Err(SceneError::MaterialIndexOutOfRange {
+                key: key.to_owned(),
+                id,
+                num: self.materials.len(),
+            });
         }
-        if let Some(alpha) = json[&rzname].as_f64() {
-            angle_z = alpha;
-            angle_z_rad = angle_z.to_radians() as Float;
+        Ok(())
+    }
+    // returns the index of the object, named after its scene key
+    fn push_object(&mut self, key: &str, obj: Arc<dyn Object + 'static + Send + Sync>) -> usize {
+        self.objects.push(obj);
+        self.object_names.push(key.to_owned());
+        self.objects.len() - 1
+    }
+    pub fn add_plane(&mut self, plane: Plane) -> Result<usize, SceneError> {
+        let key = format!("plane.{}", self.num_planes);
+        plane.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, plane.material_id)?;
+        if let Some(id) = plane.back_material_id {
+            self.check_material_id(&key, id)?;
         }
-
-        let opt = tobj::LoadOptions {
-            triangulate: true, // converts polygon into triangles
-            ignore_lines: true,
-            ignore_points: true,
+        self.num_planes += 1;
+        Ok(self.push_object(&key, Arc::new(plane)))
+    }
+    pub fn add_sphere(&mut self, sphere: Sphere) -> Result<usize, SceneError> {
+        let key = format!("sphere.{}", self.num_spheres);
+        sphere.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, sphere.material_id)?;
+        self.num_spheres += 1;
+        Ok(self.push_object(&key, Arc::new(sphere)))
+    }
+    pub fn add_triangle(&mut self, mut triangle: Triangle) -> Result<usize, SceneError> {
+        let key = format!("triangle.{}", self.num_triangles);
+        triangle.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, triangle.material_id)?;
+        triangle.tangents = triangle.compute_tangents();
+        self.num_triangles += 1;
+        Ok(self.push_object(&key, Arc::new(triangle)))
+    }
+    // two triangle objects, returns the first index
+    pub fn add_quad(&mut self, quad: Quad) -> Result<usize, SceneError> {
+        let key = format!("quad.{}", self.num_quads);
+        quad.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, quad.material_id)?;
+        self.num_quads += 1;
+        let [t0, t1] = quad.to_triangles();
+        let id = self.push_object(&key, Arc::new(t0));
+        self.push_object(&key, Arc::new(t1));
+        Ok(id)
+    }
+    pub fn add_cuboid(&mut self, mut cuboid: Cuboid) -> Result<usize, SceneError> {
+        let key = format!("box.{}", self.num_boxes);
+        cuboid.init().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, cuboid.material_id)?;
+        self.num_boxes += 1;
+        Ok(self.push_object(&key, Arc::new(cuboid)))
+    }
+    pub fn add_disk(&mut self, mut disk: Disk) -> Result<usize, SceneError> {
+        let key = format!("disk.{}", self.num_disks);
+        disk.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, disk.material_id)?;
+        disk.normal = disk.normal.normalize();
+        self.num_disks += 1;
+        Ok(self.push_object(&key, Arc::new(disk)))
+    }
+    pub fn add_cylinder(&mut self, mut cylinder: Cylinder) -> Result<usize, SceneError> {
+        let key = format!("cylinder.{}", self.num_cylinders);
+        cylinder.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, cylinder.material_id)?;
+        cylinder.axis = cylinder.axis.normalize();
+        self.num_cylinders += 1;
+        Ok(self.push_object(&key, Arc::new(cylinder)))
+    }
+    pub fn add_torus(&mut self, mut torus: Torus) -> Result<usize, SceneError> {
+        let key = format!("torus.{}", self.num_tori);
+        torus.validate().map_err(|msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        })?;
+        self.check_material_id(&key, torus.material_id)?;
+        torus.axis = torus.axis.normalize();
+        self.num_tori += 1;
+        Ok(self.push_object(&key, Arc::new(torus)))
+    }
+    // the triangles keep their own material_id
+    pub fn add_mesh(&mut self, triangles: Vec<Triangle>) -> Result<usize, SceneError> {
+        let key = format!("mesh.{}", self.num_objs);
+        for t in &triangles {
+            self.check_material_id(&key, t.material_id)?;
+        }
+        self.num_triangles_in_all_objs += triangles.len();
+        self.num_objs += 1;
+        Ok(self.push_object(&key, Arc::new(Mesh::new(triangles, 0))))
+    }
+    // the rectangle also becomes a two-sided emissive object, of its own material
+    pub fn add_area_light(&mut self, mut area: AreaLight) -> Result<usize, SceneError> {
+        let key = format!("area-light.{}", self.num_area_lights);
+        let invalid = |msg| SceneError::Invalid {
+            key: key.clone(),
+            msg,
+        };
+        area.validate().map_err(invalid)?;
+        let mat = self.add_material(Material {
+            ke: area.rgb * area.intensity,
             ..Default::default()
+        });
+        let quad = Quad::from_edges(area.corner, area.edge_u, area.edge_v, mat).map_err(invalid)?;
+        let mesh = Mesh::new(quad.to_triangles().to_vec(), mat);
+        let id = self.push_object(&key, Arc::new(mesh));
+        area.object_id = Some(id);
+        self.num_area_lights += 1;
+        self.add_light(Arc::new(area));
+        Ok(id)
+    }
+    pub fn add_light(&mut self, light: Arc<dyn Light + 'static + Send + Sync>) {
+        self.lights.push(light);
+    }
+    // its aspect ratio is set from the resolution by build()
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = Some(camera);
+    }
+    pub fn set_background(&mut self, background: Gradient) {
+        self.background = Some(background);
+    }
+    // the environment is expected to be loaded
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = Some(environment);
+    }
+
+    /*
+     * Heuristics for emissive objects that cannot light the render: seen
+     * edge-on from eye, or enclosed in the bounds of an opaque object that
+     * does not also enclose eye, like a light sunk into a wall. Returns one
+     * message per suspicious object.
+     */
+    pub fn check_emitters(&self, eye: Point) -> Vec<String> {
+        let get_material = |obj: &Arc<dyn Object + 'static + Send + Sync>| {
+            self.materials.get(obj.get_material_id())
         };
-        let (models, materials) = tobj::load_obj(path, &opt).expect("tobj");
-        let base_mat_idx = scene.num_materials;
-        if let Ok(mat) = materials.clone() {
-            mat.iter().for_each(|m| {
-                println!("-- material {} -- {:?}", m.name.green(), m);
-                let mat = Material {
-                    ke: RGB::zero(),
-                    shininess: m.shininess, // floating point?
-                    ks: RGB::new(m.specular[0], m.specular[1], m.specular[2]),
-                    checkered: false,
-                    kd: RGB::new(m.diffuse[0], m.diffuse[1], m.diffuse[2]),
-                };
-                scene.materials.push(Arc::new(mat));
-                scene.num_materials += 1;
+        let mut warnings = vec![];
+        for (id, obj) in self.objects.iter().enumerate() {
+            if get_material(obj).is_none_or(|m| m.ke.is_zero()) {
+                continue;
+            }
+            let Some(aabb) = obj.get_aabb() else {
+                continue;
+            };
+            let min_projected = EMITTER_MIN_PROJECTED * obj.get_area();
+            if get_projected_area(obj.as_ref(), eye).is_some_and(|a| a <= min_projected) {
+                warnings.push(format!(
+                    "object.{id} is emissive but has no projected area toward the camera"
+                ));
+            }
+            // a flat box, eg. a quad of the same area light, encloses nothing
+            let encloses = |b: AABB| !b.is_flat() && b.contains(&aabb) && !b.contains_point(eye);
+            let enclosing = self.objects.iter().enumerate().find(|&(other_id, other)| {
+                other_id != id
+                    && get_material(other).is_some_and(|m| m.transparency <= 0.0)
+                    && other.get_aabb().is_some_and(encloses)
             });
-        } else {
+            if let Some((other_id, _)) = enclosing {
+                warnings.push(format!(
+                    "object.{id} is emissive but inside the bounds of object.{other_id}"
+                ));
+            }
+        }
+        warnings
+    }
+
+    pub fn build(mut self, cfg: RenderConfig) -> Result<RenderJob, SceneError> {
+        if cfg.res_x == 0 || cfg.res_y == 0 {
+            return Err(SceneError::BadResolution);
+        }
+        let mut camera = self.camera.take().ok_or(SceneError::MissingCamera)?;
+        camera.aspect = cfg.res_x as Float / cfg.res_y as Float;
+        camera.init();
+
+        load_object_lights(&mut self);
+        for msg in self.check_emitters(camera.pos) {
+            println!("{} {msg}", "warning:".yellow().bold());
+        }
+        // three rays per sample would make the paths too slow
+        if camera.dispersion > 0.0 && cfg.integrator == Integrator::Path {
             println!(
-                "{} {:?}",
-                "Error loading materials:".red().bold(),
-                materials.clone().unwrap_err()
+                "{} the camera dispersion is ignored by the path integrator",
+                "warning:".yellow().bold()
             );
         }
 
-        models.iter().for_each(|m| {
-            let mesh = &m.mesh;
-            let n = mesh.indices.len() / 3;
+        if let Some(matte) = &cfg.matte
+            && get_matte_objects(&self.object_names, matte).is_empty()
+        {
+            return Err(invalid_scene(matte, "no such object for the matte"));
+        }
+
+        camera.display();
+        self.lights.iter().for_each(|light| light.display());
+
+        let grid = cfg.use_grid.then(|| {
+            let start = Instant::now();
+            let grid = UniformGrid::new(&self.objects);
+            let [x, y, z] = grid.get_res();
+            let msec = start.elapsed().as_millis();
+            println!(
+                "-- grid: {x}x{y}x{z} cells over {} objects, {msec} msec",
+                self.objects.len()
+            );
+            grid
+        });
+
+        let bvh = (cfg.use_bvh && !cfg.use_grid).then(|| ObjectBvh::new(&self.objects));
+
+        let mut light_markers = vec![];
+        if cfg.show_lights {
+            // emissive objects are already visible, they need no marker
+            for light in self.lights.iter().filter(|l| l.get_object_id().is_none()) {
+                if let Some(pos) = light.get_position() {
+                    // keep the marker the same apparent size regardless of the scene scale
+                    let radius = LIGHT_MARKER_SIZE * (pos - camera.pos).norm();
+                    // a light sitting on the camera has no visible marker
+                    if let Ok(marker) = LightMarker::new(pos, radius, light.get_color()) {
+                        light_markers.push(marker);
+                    }
+                }
+            }
+        }
+
+        Ok(RenderJob {
+            camera,
+            image: Arc::new(Mutex::new(Image::new(false, 0, 0))),
+            objects: self.objects,
+            object_names: self.object_names,
+            lights: self.lights,
+            light_markers,
+            materials: self.materials,
+            cfg,
+            progress: Mutex::new(Progress::default()),
+            progress_func: ProgressFunc {
+                func: Box::new(|_| {}),
+                eta_func: None,
+            },
+            tile_func: None,
+            start_ts: Instant::now(),
+            total_stats: Mutex::new(Default::default()),
+            tile_timings: Mutex::new(vec![]),
+            background: self.background.unwrap_or_else(default_background),
+            environment: self.environment,
+            grid,
+            bvh,
+            geom: vec![],
+        })
+    }
+}
+
+/*
+ * Area of obj facing eye, from a grid of surface samples: 0 when seen
+ * edge-on. None for objects that cannot be sampled.
+ */
+fn get_projected_area(obj: &dyn Object, eye: Point) -> Option<Float> {
+    let n = EMITTER_CHECK_GRID;
+    let area = obj.get_area() / (n * n) as Float;
+    let mut projected = None;
+    for i in 0..n * n {
+        let u = ((i % n) as Float + 0.5) / n as Float;
+        let v = ((i / n) as Float + 0.5) / n as Float;
+        let (p, normal) = obj.sample_surface(u, v)?;
+        let dir = eye - p;
+        if dir.norm() > 0.0 {
+            *projected.get_or_insert(0.0) += normal.dot(dir.normalize()).abs() * area;
+        }
+    }
+    projected
+}
+
+fn invalid_scene(key: &str, msg: &str) -> SceneError {
+    SceneError::Invalid {
+        key: key.to_owned(),
+        msg: msg.to_owned(),
+    }
+}
+
+/*
+ * Numbered entries end at the first absent key; an entry that is present
+ * but does not parse is reported rather than taken as the end of the list.
+ */
+fn parse_json<T: serde::de::DeserializeOwned>(
+    field: &str,
+    v: serde_json::Value,
+) -> Result<T, SceneError> {
+    serde_json::from_value(v).map_err(|source| SceneError::Json {
+        field: field.to_owned(),
+        source,
+    })
+}
+
+/*
+ * The entries of one kind, with the name the errors give them: the
+ * elements of the array if the scene has one, eg. "spheres": [...] makes
+ * "spheres[0]", "spheres[1]", ... Otherwise the numbered keys "sphere.0",
+ * "sphere.1", ... up to the first absent one.
+ */
+fn get_entries(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    array: &str,
+    prefix: &str,
+) -> Result<Vec<(String, serde_json::Value)>, SceneError> {
+    match get_array_entries(scene, json, array)? {
+        Some(entries) => Ok(entries),
+        None => Ok(get_numbered_entries(scene, json, prefix)),
+    }
+}
+
+fn get_array_entries(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    array: &str,
+) -> Result<Option<Vec<(String, serde_json::Value)>>, SceneError> {
+    let Some(v) = json.get(array) else {
+        return Ok(None);
+    };
+    let items = v
+        .as_array()
+        .ok_or_else(|| invalid_scene(array, "must be an array"))?;
+    scene.used_keys.insert(array.to_owned());
+    let entries = items.iter().enumerate();
+    Ok(Some(
+        entries
+            .map(|(i, v)| (format!("{array}[{i}]"), v.clone()))
+            .collect(),
+    ))
+}
+
+fn get_numbered_entries(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    prefix: &str,
+) -> Vec<(String, serde_json::Value)> {
+    let mut entries = vec![];
+    for i in 0.. {
+        let key = format!("{prefix}.{i}");
+        if json[&key].is_null() {
+            break;
+        }
+        entries.push((key.clone(), json[&key].clone()));
+        scene.used_keys.insert(key);
+    }
+    entries
+}
+
+// the add_*() methods name the entries "sphere.N": use the name of the JSON
+fn rename_key(e: SceneError, key: &str) -> SceneError {
+    match e {
+        SceneError::Invalid { msg, .. } => SceneError::Invalid {
+            key: key.to_owned(),
+            msg,
+        },
+        SceneError::MaterialIndexOutOfRange { id, num, .. } => {
+            SceneError::MaterialIndexOutOfRange {
+                key: key.to_owned(),
+                id,
+                num,
+            }
+        }
+        e => e,
+    }
+}
+
+fn load_materials(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "materials", "material")? {
+        let mut mat = parse_json::<Material>(&key, v)?;
+        if let Some(name) = &mat.name
+            && scene.get_material_id(name).is_some()
+        {
+            return Err(invalid_scene(
+                &key,
+                &format!("material '{name}' is already defined"),
+            ));
+        }
+        if let Some(path) = &mat.diffuse_map {
+            mat.diffuse_texture = Some(Arc::new(Texture::load(path)?));
+        }
+        if let Some(path) = &mat.normal_map {
+            mat.normal_texture = Some(Arc::new(Texture::load(path)?));
+        }
+        scene.add_material(mat);
+    }
+    Ok(())
+}
+
+fn default_material() -> Material {
+    Material {
+        kd: RGB::new(0.5, 0.5, 0.5),
+        shininess: 10.0,
+        ..Default::default()
+    }
+}
+
+/*
+ * Index of the material used by objects without a material_id: the
+ * scene-level "default_material" if present, a plain gray otherwise.
+ * It is only added to the materials on first use.
+ */
+fn get_default_material_id(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+) -> Result<usize, SceneError> {
+    if let Some(id) = scene.default_material_id {
+        return Ok(id);
+    }
+    let mat = match json.get("default_material") {
+        Some(v) => parse_json::<Material>("default_material", v.clone())?,
+        None => default_material(),
+    };
+    let id = scene.add_material(mat);
+    scene.default_material_id = Some(id);
+    Ok(id)
+}
+
+/*
+ * The JSON of an object with its material names ("material": "red", and
+ * "back_material" of the planes) resolved to material ids, and the default
+ * material filled in if it names none.
+ */
+fn get_object_json(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    key: &str,
+    mut v: serde_json::Value,
+) -> Result<serde_json::Value, SceneError> {
+    let Some(map) = v.as_object_mut() else {
+        return Ok(v);
+    };
+    /*
+     * The default material comes after those of the scene: an explicit id
+     * reaching it is out of range, as it would be had no object needed it.
+     */
+    if let Some(num) = scene.default_material_id {
+        for id_field in ["material_id", "back_material_id"] {
+            if let Some(id) = map.get(id_field).and_then(|id| id.as_u64())
+                && id as usize >= num
+            {
+                return Err(SceneError::MaterialIndexOutOfRange {
+                    key: key.to_owned(),
+                    id: id as usize,
+                    num,
+                });
+            }
+        }
+    }
+    for (field, id_field) in [
+        ("material", "material_id"),
+        ("back_material", "back_material_id"),
+    ] {
+        let Some(name) = map.remove(field) else {
+            continue;
+        };
+        if map.contains_key(id_field) {
+            return Err(invalid_scene(key, &format!("both {field} and {id_field}")));
+        }
+        let name = name
+            .as_str()
+            .ok_or_else(|| invalid_scene(key, "not a material name"))?;
+        let id = scene
+            .get_material_id(name)
+            .ok_or_else(|| SceneError::UnknownMaterial {
+                key: key.to_owned(),
+                name: name.to_owned(),
+            })?;
+        map.insert(id_field.to_owned(), id.into());
+    }
+    if !map.contains_key("material_id") {
+        let id = get_default_material_id(scene, json)?;
+        map.insert("material_id".to_owned(), id.into());
+    }
+    Ok(v)
+}
+
+/*
+ * Ambient occlusion of the vertices of a mesh: baked with --bake-ao and
+ * written to the cache, otherwise read from the cache if there is one.
+ */
+fn get_mesh_ao(
+    scene: &SceneBuilder,
+    cfg: &RenderConfig,
+    model: &Model,
+    triangles: &[Triangle],
+    transform: &Matrix4,
+) -> Option<Vec<Float>> {
+    let path = get_ao_cache_path(&cfg.scene_file, scene.num_objs);
+    let num_vertices = model.positions.len();
+    if !cfg.bake_ao {
+        if !path.exists() {
+            return None;
+        }
+        return match load_ao_cache(&path) {
+            Ok(ao) if ao.len() == num_vertices => {
+                println!("-- ao: read {}", path.display());
+                Some(ao)
+            }
+            Ok(_) => {
+                println!("{}: {}: stale AO cache", "warning".yellow(), path.display());
+                None
+            }
+            Err(e) => {
+                println!("{}: {e}", "warning".yellow());
+                None
+            }
+        };
+    }
+
+    let start_time = Instant::now();
+    let positions: Vec<Point> = model
+        .positions
+        .iter()
+        .map(|&p| transform.transform_point(p))
+        .collect();
+    let normals: Vec<Vec3> = compute_vertex_normals(&model.positions, &model.indices)
+        .into_iter()
+        .map(|n| transform.transform_normal(n))
+        .collect();
+    let occluder = Mesh::new(triangles.to_vec(), 0);
+    let distance = get_ao_distance(&positions);
+    let num_samples = cfg.bounces.ao_num_samples;
+    let ao = bake_vertex_ao(
+        &scene.objects,
+        &occluder,
+        &positions,
+        &normals,
+        distance,
+        num_samples,
+    );
+    println!(
+        "-- ao: baked {} vertices -- {:.2} sec",
+        ao.len(),
+        start_time.elapsed().as_millis() as Float / 1000.0
+    );
+    if let Err(e) = save_ao_cache(&path, &ao) {
+        println!("{}: {}: {e}", "warning".yellow(), path.display());
+    }
+    Some(ao)
+}
+
+/*
+ * A file of meshes named by "obj.N.path", read with its materials before the
+ * objects so that they can name those materials too.
+ */
+struct ModelFile {
+    index: usize, // N of "obj.N"
+    path: String,
+    transform: Transform,
+    models: Vec<Model>,
+    base_mat_idx: usize, // index of the first material of the file
+}
+
+/*
+ * "obj.N.path" names an OBJ or a glTF file, whose meshes each become one
+ * Mesh of the scene, moved by "obj.N.transform" or only rotated by
+ * "obj.N.rotx", "obj.N.roty" and "obj.N.rotz". Its materials follow the
+ * scene materials.
+ */
+fn load_model_files(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    cfg: &RenderConfig,
+) -> Result<Vec<ModelFile>, SceneError> {
+    let mut files = vec![];
+    for i in 0.. {
+        let name = format!("obj.{i}.path");
+        if json[&name].is_null() {
+            break;
+        }
+        let path = json[&name]
+            .as_str()
+            .ok_or_else(|| invalid_scene(&name, "not a path"))?;
+        let rxname = format!("obj.{i}.rotx");
+        let ryname = format!("obj.{i}.roty");
+        let rzname = format!("obj.{i}.rotz");
+        let tname = format!("obj.{i}.transform");
+        for key in [&name, &rxname, &ryname, &rzname, &tname] {
+            scene.used_keys.insert(key.clone());
+        }
+        let [rx, ry, rz] =
+            [&rxname, &ryname, &rzname].map(|key| json[key].as_f64().unwrap_or(0.0) as Float);
+        let transform = match json.get(&tname) {
+            Some(_)
+                if [&rxname, &ryname, &rzname]
+                    .iter()
+                    .any(|key| json.get(key).is_some()) =>
+            {
+                return Err(invalid_scene(
+                    &tname,
+                    "both a transform and rotx, roty or rotz",
+                ));
+            }
+            Some(t) => {
+                let t = parse_json::<Transform>(&tname, t.clone())?;
+                t.validate().map_err(|msg| invalid_scene(&tname, &msg))?;
+                t
+            }
+            None => Transform {
+                rotate_xyz: Vec3::new(rx, ry, rz),
+                ..Default::default()
+            },
+        };
 
-            let mut material_str = "".to_owned();
-            if mesh.material_id.is_some() && materials.is_ok() {
-                material_str = materials.as_ref().unwrap()[mesh.material_id.unwrap()]
-                    .name
-                    .clone();
+        let (models, materials) = match load_model_file(Path::new(path)) {
+            Ok(loaded) => loaded,
+            Err(e) if cfg.skip_missing_meshes => {
+                println!(
+                    "{} {name}: {e}, skipping the mesh",
+                    "warning:".yellow().bold()
+                );
+                continue;
             }
+            Err(e) => return Err(invalid_scene(&name, &e.to_string())),
+        };
+        let base_mat_idx = scene.materials.len();
+        for mat in materials {
+            scene.add_material(mat);
+        }
+        files.push(ModelFile {
+            index: i,
+            path: path.to_owned(),
+            transform,
+            models,
+            base_mat_idx,
+        });
+    }
+    Ok(files)
+}
 
+fn load_mesh(
+    scene: &mut SceneBuilder,
+    cfg: &RenderConfig,
+    files: Vec<ModelFile>,
+) -> Result<(), SceneError> {
+    let use_smooth_normals = cfg.use_smooth_normals;
+    for file in files {
+        let transform = file.transform.to_matrix();
+        let mut num_triangles_in_obj = 0;
+        let base_mat_idx = file.base_mat_idx;
+
+        for mut model in file.models {
+            let n = model.num_triangles();
             println!(
-                "-- model {:12} has {} triangles w/ {} vertices -- {}",
-                m.name.blue(),
+                "-- model {:12} has {} triangles w/ {} vertices",
+                model.name.blue(),
                 n,
-                mesh.positions.len(),
-                material_str.green()
+                model.positions.len(),
             );
-            assert!(mesh.indices.len() % 3 == 0);
-            scene.num_triangles_in_all_objs += n;
             num_triangles_in_obj += n;
+            if cfg.fix_winding {
+                let num_flipped = model.fix_winding();
+                if num_flipped > 0 {
+                    println!("-- flipped the winding of {num_flipped} triangles");
+                }
+            }
+            if use_smooth_normals && model.normals.is_empty() {
+                let normals = compute_vertex_normals(&model.positions, &model.indices);
+                model.normals = model
+                    .indices
+                    .chunks_exact(3)
+                    .map(|f| Some([0, 1, 2].map(|k| normals[f[k] as usize])))
+                    .collect();
+            }
             let mut triangles = Vec::with_capacity(n);
+            let mut faces = Vec::with_capacity(n); // face index of each triangle
             let mut num_skipped = 0;
             for i in 0..n {
-                let i0 = mesh.indices[3 * i] as usize;
-                let i1 = mesh.indices[3 * i + 1] as usize;
-                let i2 = mesh.indices[3 * i + 2] as usize;
-                let x0 = mesh.positions[3 * i0] as Float;
-                let y0 = mesh.positions[3 * i0 + 1] as Float;
-                let z0 = mesh.positions[3 * i0 + 2] as Float;
-                let x1 = mesh.positions[3 * i1] as Float;
-                let y1 = mesh.positions[3 * i1 + 1] as Float;
-                let z1 = mesh.positions[3 * i1 + 2] as Float;
-                let x2 = mesh.positions[3 * i2] as Float;
-                let y2 = mesh.positions[3 * i2 + 1] as Float;
-                let z2 = mesh.positions[3 * i2 + 2] as Float;
-                let mut p0 = Point::new(x0, y0, z0);
-                let mut p1 = Point::new(x1, y1, z1);
-                let mut p2 = Point::new(x2, y2, z2);
-
-                if p0 == p1 || p0 == p2 || p1 == p2 {
+                let p0 = transform.transform_point(model.get_point(i, 0));
+                let p1 = transform.transform_point(model.get_point(i, 1));
+                let p2 = transform.transform_point(model.get_point(i, 2));
+                let mut mat_id = 0;
+                if let Some(id) = model.material_id {
+                    mat_id = base_mat_idx + id;
+                }
+                let Ok(mut triangle) = Triangle::new([p0, p1, p2], mat_id) else {
                     num_skipped += 1;
                     continue;
+                };
+                triangle.uvs = model.uvs.get(i).copied().flatten();
+                triangle.tangents = triangle.compute_tangents();
+                if use_smooth_normals {
+                    let normals = model.normals.get(i).copied().flatten();
+                    triangle.normals =
+                        normals.map(|normals| normals.map(|n| transform.transform_normal(n)));
                 }
-                p0 = p0.rotx(angle_x_rad).roty(angle_y_rad).rotz(angle_z_rad);
-                p1 = p1.rotx(angle_x_rad).roty(angle_y_rad).rotz(angle_z_rad);
-                p2 = p2.rotx(angle_x_rad).roty(angle_y_rad).rotz(angle_z_rad);
-                let mut mat_id = 0;
-                if let Some(id) = mesh.material_id {
-                    mat_id = base_mat_idx as usize + id;
-                }
-                let mut triangle = Triangle::new([p0, p1, p2], mat_id);
                 triangle.mesh_id = triangles.len();
                 triangles.push(triangle);
+                faces.push(i);
             }
             if num_skipped > 0 {
                 println!("-- skipped {num_skipped} malformed triangles");
             }
-            scene.objects.push(Arc::new(Mesh::new(triangles, 0)));
-            scene.num_objs += 1;
-        });
+            if let Some(ao) = get_mesh_ao(scene, cfg, &model, &triangles, &transform) {
+                for (triangle, i) in triangles.iter_mut().zip(&faces) {
+                    let vertex_ao = |k: usize| ao[model.indices[3 * i + k] as usize];
+                    triangle.ao = Some([vertex_ao(0), vertex_ao(1), vertex_ao(2)]);
+                }
+            }
+            let id = scene.add_mesh(triangles)?;
+            // --matte obj.N selects every mesh of the file
+            scene.object_names[id] = format!("obj.{}", file.index);
+        }
         println!(
-            "-- loaded {} w/ {} triangles -- rotx={} roty={} rotz={}",
-            path.green(),
+            "-- loaded {} w/ {} triangles -- translate={:?} rotate_xyz={:?} scale={:?}",
+            file.path.green(),
             num_triangles_in_obj,
-            angle_x,
-            angle_y,
-            angle_z
+            file.transform.translate,
+            file.transform.rotate_xyz,
+            file.transform.scale
         );
     }
     println!(
-        "-- mesh={} triangles={} spheres={} planes={} materials={}",
+        "-- mesh={} triangles={} spheres={} cylinders={} tori={} boxes={} disks={} planes={} \
+         materials={}",
         scene.num_objs,
         scene.num_triangles + scene.num_triangles_in_all_objs,
         scene.num_spheres,
+        scene.num_cylinders,
+        scene.num_tori,
+        scene.num_boxes,
+        scene.num_disks,
         scene.num_planes,
         scene.num_materials
     );
@@ -197,102 +1004,319 @@ fn load_mesh(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()>
     Ok(())
 }
 
-fn load_spheres(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let s = format!("sphere.{}", scene.num_spheres);
-        match serde_json::from_value::<Sphere>(json[s].clone()) {
-            Err(_error) => break,
-            Ok(o) => {
-                scene.objects.push(Arc::new(o));
-                scene.num_spheres += 1;
-            }
-        }
+/*
+ * The optional "transform" of an entry, taken out of it before it is
+ * parsed as an object.
+ */
+fn take_transform(key: &str, v: &mut serde_json::Value) -> Result<Option<Transform>, SceneError> {
+    let Some(t) = v.as_object_mut().and_then(|map| map.remove("transform")) else {
+        return Ok(None);
+    };
+    let t = parse_json::<Transform>(&format!("{key}.transform"), t)?;
+    t.validate().map_err(|msg| invalid_scene(key, &msg))?;
+    Ok(Some(t))
+}
+
+// an entry with its material names resolved and its transform applied
+fn parse_object<T: serde::de::DeserializeOwned + Transformable>(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    key: &str,
+    v: serde_json::Value,
+) -> Result<T, SceneError> {
+    let mut v = get_object_json(scene, json, key, v)?;
+    let transform = take_transform(key, &mut v)?;
+    let mut obj = parse_json::<T>(key, v)?;
+    if let Some(t) = transform {
+        obj.transform(&t).map_err(|msg| invalid_scene(key, &msg))?;
+    }
+    Ok(obj)
+}
+
+fn load_spheres(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "spheres", "sphere")? {
+        let obj = parse_object::<Sphere>(scene, json, &key, v)?;
+        scene.add_sphere(obj).map_err(|e| rename_key(e, &key))?;
     }
     Ok(())
 }
 
-fn load_triangles(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let s = format!("triangle.{}", scene.num_triangles);
-        match serde_json::from_value::<Triangle>(json[s].clone()) {
-            Err(_error) => break,
-            Ok(o) => {
-                scene.objects.push(Arc::new(o));
-                scene.num_triangles += 1;
-            }
-        }
+fn load_cylinders(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "cylinders", "cylinder")? {
+        let obj = parse_object::<Cylinder>(scene, json, &key, v)?;
+        scene.add_cylinder(obj).map_err(|e| rename_key(e, &key))?;
     }
     Ok(())
 }
 
-fn load_planes(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let s = format!("plane.{}", scene.num_planes);
-        match serde_json::from_value::<Plane>(json[s].clone()) {
-            Err(_error) => break,
-            Ok(p) => {
-                scene.objects.push(Arc::new(p));
-                scene.num_planes += 1;
-            }
+fn load_tori(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "tori", "torus")? {
+        let obj = parse_object::<Torus>(scene, json, &key, v)?;
+        scene.add_torus(obj).map_err(|e| rename_key(e, &key))?;
+    }
+    Ok(())
+}
+
+fn load_triangles(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "triangles", "triangle")? {
+        let obj = parse_object::<Triangle>(scene, json, &key, v)?;
+        scene.add_triangle(obj).map_err(|e| rename_key(e, &key))?;
+    }
+    Ok(())
+}
+
+// the other form of "quad.N": a corner and the two edges from it
+#[derive(serde::Deserialize)]
+struct QuadEdges {
+    corner: Point,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    material_id: usize,
+}
+
+fn load_quads(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "quads", "quad")? {
+        let mut v = get_object_json(scene, json, &key, v)?;
+        let transform = take_transform(&key, &mut v)?;
+        let mut quad = if v.get("corner").is_some() {
+            let q = parse_json::<QuadEdges>(&key, v)?;
+            Quad::from_edges(q.corner, q.edge_u, q.edge_v, q.material_id)
+                .map_err(|msg| invalid_scene(&key, &msg))?
+        } else {
+            parse_json::<Quad>(&key, v)?
+        };
+        if let Some(t) = transform {
+            quad.transform(&t)
+                .map_err(|msg| invalid_scene(&key, &msg))?;
         }
+        scene.add_quad(quad).map_err(|e| rename_key(e, &key))?;
     }
     Ok(())
 }
 
-fn load_lights(scene: &mut Scene, json: &serde_json::Value) -> std::io::Result<()> {
-    loop {
-        let s = format!("spot-light.{}", scene.num_spot_lights);
-        match serde_json::from_value::<SpotLight>(json[&s].clone()) {
-            Err(_error) => break,
-            Ok(mut spot) => {
-                spot.name = s;
-                scene.lights.push(Arc::new(spot));
-                scene.num_spot_lights += 1;
-            }
+fn load_cuboids(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "boxes", "box")? {
+        let obj = parse_object::<Cuboid>(scene, json, &key, v)?;
+        scene.add_cuboid(obj).map_err(|e| rename_key(e, &key))?;
+    }
+    Ok(())
+}
+
+fn load_disks(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "disks", "disk")? {
+        let obj = parse_object::<Disk>(scene, json, &key, v)?;
+        scene.add_disk(obj).map_err(|e| rename_key(e, &key))?;
+    }
+    Ok(())
+}
+
+fn load_planes(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    for (key, v) in get_entries(scene, json, "planes", "plane")? {
+        let obj = parse_object::<Plane>(scene, json, &key, v)?;
+        scene.add_plane(obj).map_err(|e| rename_key(e, &key))?;
+    }
+    Ok(())
+}
+
+fn load_light(
+    scene: &mut SceneBuilder,
+    key: String,
+    kind: &str,
+    v: serde_json::Value,
+) -> Result<(), SceneError> {
+    match kind {
+        "spot" => {
+            let mut spot = parse_json::<SpotLight>(&key, v)?;
+            spot.name = key;
+            scene.add_light(Arc::new(spot));
+            scene.num_spot_lights += 1;
+        }
+        "area" => {
+            let mut area = parse_json::<AreaLight>(&key, v)?;
+            area.name = key.clone();
+            scene
+                .add_area_light(area)
+                .map_err(|e| rename_key(e, &key))?;
+        }
+        "vec" => {
+            let mut vec = parse_json::<VectorLight>(&key, v)?;
+            vec.name = key;
+            vec.dir = vec.dir.normalize();
+            scene.add_light(Arc::new(vec));
+            scene.num_vec_lights += 1;
+        }
+        _ => {
+            let msg = format!("unknown light type '{kind}' (expected spot, area or vec)");
+            return Err(invalid_scene(&key, &msg));
         }
     }
-    loop {
-        let s = format!("vec-light.{}", scene.num_vec_lights);
-        match serde_json::from_value::<VectorLight>(json[&s].clone()) {
-            Err(_error) => break,
-            Ok(mut v) => {
-                v.name = s;
-                v.dir = v.dir.normalize();
-                scene.lights.push(Arc::new(v));
-                scene.num_vec_lights += 1;
+    Ok(())
+}
+
+/*
+ * The elements of a "lights" array say which light they are with a "type":
+ * spot, area or vec, like the numbered "spot-light.N" and so on.
+ */
+fn load_lights(scene: &mut SceneBuilder, json: &serde_json::Value) -> Result<(), SceneError> {
+    if let Some(entries) = get_array_entries(scene, json, "lights")? {
+        for (key, v) in entries {
+            let kind = v
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_owned();
+            load_light(scene, key, &kind, v)?;
+        }
+    } else {
+        for kind in ["spot", "area", "vec"] {
+            for (key, v) in get_numbered_entries(scene, json, &format!("{kind}-light")) {
+                load_light(scene, key, kind, v)?;
             }
         }
     }
-    if let Ok(ambient) = serde_json::from_value::<AmbientLight>(json["ambient"].clone()) {
-        scene.lights.push(Arc::new(ambient));
+    if !json["ambient"].is_null() {
+        let ambient = parse_json::<AmbientLight>("ambient", json["ambient"].clone())?;
+        scene.add_light(Arc::new(ambient));
+        scene.used_keys.insert("ambient".to_owned());
     }
     Ok(())
 }
 
-fn load_resolution(cfg: &mut RenderConfig, json: &serde_json::Value) -> std::io::Result<()> {
-    if cfg.res_x == 0 && cfg.res_y == 0 {
-        if let Some(array) = json[&"resolution".to_string()].as_array() {
-            cfg.res_x = array[0].as_u64().unwrap() as u32;
-            cfg.res_y = array[1].as_u64().unwrap() as u32;
+/*
+ * Emissive spheres and triangles also become lights, so that they light
+ * the scene with the ray-tracer and not only with the path-tracer.
+ */
+fn load_object_lights(scene: &mut SceneBuilder) {
+    for (id, obj) in scene.objects.iter().enumerate() {
+        let Some(mat) = scene.materials.get(obj.get_material_id()) else {
+            continue;
+        };
+        if mat.ke.is_zero() {
+            continue;
+        }
+        // the rectangle of an area light, which already lights the scene
+        if scene.lights.iter().any(|l| l.get_object_id() == Some(id)) {
+            continue;
         }
+        let name = format!("object.{id}");
+        if let Some(light) = ObjectLight::new(name, obj.clone(), id, mat.ke) {
+            scene.lights.push(Arc::new(light));
+        }
+    }
+}
+
+/*
+ * Scenes can hold several viewpoints as "camera.0", "camera.1", ... and
+ * index selects one of them. Without numbered cameras, the single "camera"
+ * block is camera 0. Returns the key of the selected camera.
+ */
+fn get_camera_key(
+    scene: &mut SceneBuilder,
+    json: &serde_json::Value,
+    index: usize,
+) -> Result<String, SceneError> {
+    let mut num_cameras = 0;
+    while !json[format!("camera.{num_cameras}")].is_null() {
+        scene.used_keys.insert(format!("camera.{num_cameras}"));
+        num_cameras += 1;
+    }
+    let key = if num_cameras > 0 {
+        format!("camera.{index}")
+    } else {
+        num_cameras = 1;
+        "camera".to_owned()
+    };
+    if index >= num_cameras {
+        let msg = format!("no camera {index} ({num_cameras} cameras)");
+        return Err(invalid_scene("camera", &msg));
+    }
+    Ok(key)
+}
+
+/*
+ * The resolution of the selected camera, else the top-level one: a portrait
+ * and a landscape camera can share a scene. The command line wins over both.
+ */
+fn load_resolution(
+    cfg: &mut RenderConfig,
+    json: &serde_json::Value,
+    camera_key: &str,
+) -> Result<(), SceneError> {
+    let resolution = match json[camera_key].get("resolution") {
+        Some(v) => v,
+        None => &json["resolution"],
+    };
+    if cfg.res_x == 0
+        && cfg.res_y == 0
+        && let Some(array) = resolution.as_array()
+    {
+        let dim = |i: usize| {
+            array
+                .get(i)
+                .and_then(|v| v.as_u64())
+                .ok_or(SceneError::BadResolution)
+        };
+        cfg.res_x = dim(0)? as u32;
+        cfg.res_y = dim(1)? as u32;
     }
     {
         let res_str = format!("{}x{}", cfg.res_x, cfg.res_y).bold();
         let mut smp_str = "".cyan();
         if cfg.use_adaptive_sampling {
             smp_str = " w/ adaptive sampling".cyan();
+        } else if cfg.use_edge_aa {
+            smp_str = " w/ edge anti-aliasing".cyan();
         }
         println!("-- img resolution: {res_str}{smp_str}");
     }
     Ok(())
 }
 
-pub fn load_scene(cfg: RenderConfig) -> std::io::Result<RenderJob> {
+// the scene can tune its anti-aliasing, over the config
+fn load_adaptive_threshold(
+    cfg: &mut RenderConfig,
+    json: &serde_json::Value,
+) -> Result<(), SceneError> {
+    if let Some(v) = json.get("adaptive_threshold") {
+        let threshold: f32 = parse_json("adaptive_threshold", v.clone())?;
+        if !(threshold >= 0.0 && threshold.is_finite()) {
+            let msg = format!("must be >= 0 ({threshold})");
+            return Err(invalid_scene("adaptive_threshold", &msg));
+        }
+        cfg.adaptive_threshold = threshold;
+    }
+    if cfg.use_adaptive_sampling {
+        println!("-- adaptive threshold: {}", cfg.adaptive_threshold);
+    }
+    Ok(())
+}
+
+/*
+ * Loaders stop at the first missing index, so a typo or a gap in the
+ * numbering silently drops the remaining entries: report every top-level
+ * key that no loader consumed.
+ */
+fn warn_unused_keys(scene: &SceneBuilder, json: &serde_json::Value) {
+    let Some(map) = json.as_object() else {
+        return;
+    };
+    map.keys()
+        .filter(|key| !scene.used_keys.contains(*key))
+        .for_each(|key| println!("{} unused key '{}'", "warning:".yellow().bold(), key));
+}
+
+pub fn load_scene(cfg: RenderConfig) -> Result<RenderJob, SceneError> {
     let mut cfg = cfg;
     if !cfg.scene_file.is_file() {
-        println!("file '{}' not found.", cfg.scene_file.display());
-        println!("pwd={}", std::env::current_dir()?.display());
-        panic!("scene file {} not present.", cfg.scene_file.display());
+        let msg = format!(
+            "scene file '{}' not found (pwd={})",
+            cfg.scene_file.display(),
+            std::env::current_dir()?.display()
+        );
+        return Err(SceneError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            msg,
+        )));
     }
     println!(
         "loading scene file {}",
@@ -300,40 +1324,112 @@ pub fn load_scene(cfg: RenderConfig) -> std::io::Result<RenderJob> {
     );
 
     let data = fs::read_to_string(&cfg.scene_file)?;
-    let json: serde_json::Value = serde_json::from_str(&data)?;
-    let mut scene: Scene = Default::default();
+    let json: serde_json::Value = serde_json::from_str(&data).map_err(|source| {
+        let field = cfg.scene_file.display().to_string();
+        SceneError::Json { field, source }
+    })?;
+    let mut scene = SceneBuilder::new();
 
-    load_resolution(&mut cfg, &json)?;
+    let camera_key = get_camera_key(&mut scene, &json, cfg.camera)?;
+    load_resolution(&mut cfg, &json, &camera_key)?;
+    load_adaptive_threshold(&mut cfg, &json)?;
 
-    let mut camera: Camera = serde_json::from_value(json["camera"].clone()).unwrap();
-    camera.aspect = cfg.res_x as Float / cfg.res_y as Float;
-    camera.init();
+    let mut camera: Camera = parse_json(&camera_key, json[&camera_key].clone())?;
+    if !(camera.dispersion >= 0.0 && camera.dispersion.is_finite()) {
+        return Err(invalid_scene(
+            &camera_key,
+            "dispersion must be positive or 0",
+        ));
+    }
+    // eg. moved around in the ui: the lens and the projection are kept
+    if let Some(view) = cfg.camera_view {
+        camera.set_view(view);
+    }
+    scene.set_camera(camera);
 
     load_materials(&mut scene, &json)?;
-    load_lights(&mut scene, &json)?;
+    let model_files = load_model_files(&mut scene, &json, &cfg)?;
     load_planes(&mut scene, &json)?;
     load_spheres(&mut scene, &json)?;
     load_triangles(&mut scene, &json)?;
-    load_mesh(&mut scene, &json)?;
-
-    camera.display();
-    scene.lights.iter().for_each(|light| light.display());
-
-    let job = RenderJob {
-        camera,
-        image: Arc::new(Mutex::new(Image::new(false, 0, 0))),
-        objects: scene.objects,
-        lights: scene.lights,
-        materials: scene.materials,
-        cfg,
-        progress_total: Mutex::new(0),
-        progress_func: ProgressFunc {
-            func: Box::new(|_| {}),
-        },
-        start_ts: Instant::now(),
-        total_stats: Mutex::new(Default::default()),
+    load_quads(&mut scene, &json)?;
+    load_cuboids(&mut scene, &json)?;
+    load_disks(&mut scene, &json)?;
+    load_cylinders(&mut scene, &json)?;
+    load_tori(&mut scene, &json)?;
+    load_mesh(&mut scene, &cfg, model_files)?;
+    load_lights(&mut scene, &json)?;
+
+    if let Some(v) = json.get("background") {
+        let background = parse_json::<Gradient>("background", v.clone())?;
+        background
+            .validate()
+            .map_err(|e| invalid_scene("background", &e))?;
+        scene.set_background(background);
+    }
+    if let Some(v) = json.get("environment") {
+        let mut environment = parse_json::<Environment>("environment", v.clone())?;
+        environment.load()?;
+        println!(
+            "-- environment: {} x{}",
+            environment.path.display(),
+            environment.intensity
+        );
+        scene.set_environment(environment);
+    }
+
+    if cfg.warn_unused_keys {
+        let keys = [
+            "resolution",
+            "adaptive_threshold",
+            "camera",
+            "background",
+            "environment",
+            "default_material",
+        ];
+        for key in keys {
+            scene.used_keys.insert(key.to_owned());
+        }
+        warn_unused_keys(&scene, &json);
+    }
+
+    scene.build(cfg)
+}
+
+/*
+ * Furnace test: a white diffuse sphere inside a uniformly emissive
+ * enclosure. With an albedo of 1 no energy is absorbed, so every pixel
+ * should come out at the emitter's radiance.
+ */
+pub fn generate_furnace_scene(scene_file: PathBuf, emission: RGB) -> std::io::Result<()> {
+    let res_x = 32;
+    let res_y = 32;
+    let mut json = serde_json::json!({ "resolution": [ res_x, res_y ] });
+    let emitter = Material {
+        ke: emission,
+        ..Default::default()
     };
-    Ok(job)
+    json["material.0"] = serde_json::to_value(emitter).unwrap();
+    let white = Material {
+        kd: RGB::new(1.0, 1.0, 1.0),
+        ..Default::default()
+    };
+    json["material.1"] = serde_json::to_value(white).unwrap();
+    let enclosure = Sphere::new(Point::zero(), 10.0, 0).unwrap();
+    json["sphere.0"] = serde_json::to_value(enclosure).unwrap();
+    let sphere = Sphere::new(Point::zero(), 1.5, 1).unwrap();
+    json["sphere.1"] = serde_json::to_value(sphere).unwrap();
+    let camera = Camera::new(
+        Point::new(-4.0, 0.0, 0.0),
+        Point::zero(),
+        Vec3::new(0.0, 0.0, 1.0),
+        40.0,
+        res_x as Float / res_y as Float,
+    );
+    json["camera"] = serde_json::to_value(camera).unwrap();
+
+    fs::write(&scene_file, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
 }
 
 pub fn generate_scene(
@@ -371,54 +1467,44 @@ pub fn generate_scene(
     {
         // white
         let mat = Material {
-            ks: RGB::zero(),
             shininess: 10.0,
-            checkered: false,
-            ke: RGB::zero(),
             kd: RGB::new(1.0, 1.0, 1.0),
+            ..Default::default()
         };
         json["material.0"] = serde_json::to_value(mat).unwrap();
         // white glossy
         let mat = Material {
-            ke: RGB::zero(),
             ks: RGB::new(0.5, 0.5, 0.5),
             shininess: 10.0,
-            checkered: false,
             kd: RGB::new(1.0, 1.0, 1.0),
+            ..Default::default()
         };
         json["material.1"] = serde_json::to_value(mat).unwrap();
         // red
         let mat = Material {
-            ke: RGB::zero(),
-            ks: RGB::zero(),
             shininess: 10.0,
-            checkered: false,
             kd: RGB::new(1.0, 0.0, 0.0),
+            ..Default::default()
         };
         json["material.2"] = serde_json::to_value(mat).unwrap();
         // green
         let mat = Material {
-            ke: RGB::zero(),
             shininess: 10.0,
-            ks: RGB::zero(),
-            checkered: false,
             kd: RGB::new(0.0, 1.0, 0.0),
+            ..Default::default()
         };
         json["material.3"] = serde_json::to_value(mat).unwrap();
         // blue
         let mat = Material {
-            ke: RGB::zero(),
             shininess: 10.0,
-            ks: RGB::zero(),
-            checkered: false,
             kd: RGB::new(0.0, 0.0, 1.0),
+            ..Default::default()
         };
         json["material.4"] = serde_json::to_value(mat).unwrap();
 
         for i in 5..10 {
             let name = format!("material.{i}");
             let mat = Material {
-                ke: RGB::zero(),
                 shininess: 10.0,
                 ks: RGB {
                     r: rng.gen_range(0.0..0.9),
@@ -431,6 +1517,7 @@ pub fn generate_scene(
                     g: rng.gen_range(0.0..1.0),
                     b: rng.gen_range(0.0..1.0),
                 },
+                ..Default::default()
             };
             json[name] = serde_json::to_value(mat).unwrap();
         }
@@ -465,37 +1552,8 @@ pub fn generate_scene(
     if false {
         let orig = Vec3::new(1.5, -1.5, -1.5);
         let sz = 0.5;
-        let a = Point::zero() * sz + orig; // a
-        let b = Point::new(1.0, 0.0, 0.0) * sz + orig; // b
-        let d = Point::new(0.0, 0.0, 1.0) * sz + orig; // d
-        let c = Point::new(1.0, 0.0, 1.0) * sz + orig; // c
-        let ap = Point::new(0.0, 1.0, 0.0) * sz + orig; //
-        let bp = Point::new(1.0, 1.0, 0.0) * sz + orig; //
-        let dp = Point::new(0.0, 1.0, 1.0) * sz + orig; //
-        let cp = Point::new(1.0, 1.0, 1.0) * sz + orig; //
-
-        let t0 = Triangle::new([a, b, c], 0);
-        let t1 = Triangle::new([a, c, d], 0);
-        let t2 = Triangle::new([a, d, dp], 0);
-        let t3 = Triangle::new([ap, a, dp], 0);
-        let t4 = Triangle::new([ap, bp, cp], 0);
-        let t5 = Triangle::new([ap, cp, dp], 0);
-        let t6 = Triangle::new([d, c, cp], 0);
-        let t7 = Triangle::new([d, cp, dp], 0);
-        let t8 = Triangle::new([a, bp, b], 0);
-        let t9 = Triangle::new([a, ap, bp], 0);
-
-        json["triangle.0"] = serde_json::to_value(t0).unwrap();
-        json["triangle.1"] = serde_json::to_value(t1).unwrap();
-        json["triangle.2"] = serde_json::to_value(t2).unwrap();
-        json["triangle.3"] = serde_json::to_value(t3).unwrap();
-        json["triangle.4"] = serde_json::to_value(t4).unwrap();
-        json["triangle.5"] = serde_json::to_value(t5).unwrap();
-        json["triangle.6"] = serde_json::to_value(t6).unwrap();
-        json["triangle.7"] = serde_json::to_value(t7).unwrap();
-        json["triangle.8"] = serde_json::to_value(t8).unwrap();
-        json["triangle.9"] = serde_json::to_value(t9).unwrap();
-        json["num_triangles"] = serde_json::json!(10);
+        let cuboid = Cuboid::new(orig, orig + Vec3::new(sz, sz, sz), 0).unwrap();
+        json["box.0"] = serde_json::to_value(cuboid).unwrap();
     }
 
     if add_box {
@@ -505,30 +1563,45 @@ pub fn generate_scene(
             point: Point::new(0.0, 0.0, -1.0), // bottom
             normal: Vec3::new(0.0, 0.0, 1.0),
             material_id: 1,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
         };
         json["plane.0"] = serde_json::to_value(&p0).unwrap();
         let p1 = Plane {
             point: Point::new(0.0, 0.0, 3.0), // top
             normal: Vec3::new(0.0, 0.0, -1.0),
             material_id: 0,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
         };
         json["plane.1"] = serde_json::to_value(&p1).unwrap();
         let p2 = Plane {
             point: Point::new(0.0, -3.0, 0.0), // right
             normal: Vec3::new(0.0, 1.0, 0.0),
             material_id: 3,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
         };
         json["plane.2"] = serde_json::to_value(&p2).unwrap();
         let p3 = Plane {
             point: Point::new(0.0, 3.0, 3.0), // left
             normal: Vec3::new(0.0, -1.0, 0.0),
             material_id: 2,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
         };
         json["plane.3"] = serde_json::to_value(&p3).unwrap();
         let p4 = Plane {
             point: Point::new(4.5, 0.0, 0.0), // front
             normal: Vec3::new(-1.0, 0.0, 0.0),
             material_id: 0,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
         };
         json["plane.4"] = serde_json::to_value(&p4).unwrap();
     }
@@ -543,6 +1616,8 @@ pub fn generate_scene(
             center,
             radius: rng.gen_range(0.2..0.4),
             material_id: rng.gen_range(0..10),
+            velocity: Vec3::zero(),
+            scale: Vec3::one(),
         };
         let name = format!("sphere.{i}");
         json[name] = serde_json::to_value(&sphere).unwrap();
@@ -0,0 +1,293 @@
+use std::sync::OnceLock;
+
+use crate::Ray;
+use crate::three_d::Triangle;
+use crate::vec3::EPSILON;
+use crate::vec3::Float;
+use crate::vec3::Point;
+use crate::vec3::Vec3;
+
+/*
+ * Triangle intersection kernels for the leaves of a BVH, selected at run
+ * time: prebuilt binaries use AVX2 when the CPU has it, without any
+ * target-feature compile flag. All the kernels compute the same
+ * Moller-Trumbore test as Triangle::intercept, operation for operation
+ * and without fused multiply-adds, so that they return the same hits down
+ * to the last bit.
+ */
+
+// width of the widest kernel, also the padding at the end of the arrays
+const LANES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimdLevel {
+    Scalar,
+    Avx2,
+}
+
+static SIMD_LEVEL: OnceLock<SimdLevel> = OnceLock::new();
+
+impl SimdLevel {
+    // best level supported by this CPU, detected once
+    pub fn detect() -> SimdLevel {
+        *SIMD_LEVEL.get_or_init(|| {
+            if SimdLevel::Avx2.is_available() {
+                SimdLevel::Avx2
+            } else {
+                SimdLevel::Scalar
+            }
+        })
+    }
+    pub fn is_available(self) -> bool {
+        match self {
+            SimdLevel::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            SimdLevel::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            SimdLevel::Avx2 => false,
+        }
+    }
+}
+
+/*
+ * Triangles as first vertex and two edges, one array per coordinate, in
+ * the order of the BVH leaves so that a leaf is a contiguous range.
+ */
+#[derive(Default)]
+pub struct LeafTriangles {
+    v0_x: Vec<Float>,
+    v0_y: Vec<Float>,
+    v0_z: Vec<Float>,
+    e1_x: Vec<Float>,
+    e1_y: Vec<Float>,
+    e1_z: Vec<Float>,
+    e2_x: Vec<Float>,
+    e2_y: Vec<Float>,
+    e2_z: Vec<Float>,
+}
+
+impl LeafTriangles {
+    pub fn new(triangles: &[Triangle], order: &[usize]) -> Self {
+        let mut leaf = LeafTriangles::default();
+        for &tid in order {
+            let p = triangles[tid].points;
+            let edge1 = p[1] - p[0];
+            let edge2 = p[2] - p[0];
+            leaf.push(p[0], edge1, edge2);
+        }
+        // lets the wide kernels load a full register past the last leaf
+        for _ in 0..LANES {
+            leaf.push(Point::zero(), Vec3::zero(), Vec3::zero());
+        }
+        leaf
+    }
+    fn push(&mut self, v0: Point, edge1: Vec3, edge2: Vec3) {
+        self.v0_x.push(v0.x);
+        self.v0_y.push(v0.y);
+        self.v0_z.push(v0.z);
+        self.e1_x.push(edge1.x);
+        self.e1_y.push(edge1.y);
+        self.e1_z.push(edge1.z);
+        self.e2_x.push(edge2.x);
+        self.e2_y.push(edge2.y);
+        self.e2_z.push(edge2.z);
+    }
+    pub fn len(&self) -> usize {
+        self.v0_x.len().saturating_sub(LANES)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/*
+ * Intersects the ray with triangles offset..offset+count. Returns the index
+ * of the closest hit and shrinks tmax, or the first hit found when any is
+ * set.
+ */
+pub type LeafInterceptFn =
+    fn(&LeafTriangles, &Ray, usize, usize, Float, &mut Float, bool) -> Option<usize>;
+
+pub fn get_leaf_intercept(level: SimdLevel) -> LeafInterceptFn {
+    match level {
+        #[cfg(target_arch = "x86_64")]
+        SimdLevel::Avx2 if level.is_available() => intercept_avx2,
+        _ => intercept_scalar,
+    }
+}
+
+fn intercept_scalar(
+    leaf: &LeafTriangles,
+    ray: &Ray,
+    offset: usize,
+    count: usize,
+    tmin: Float,
+    tmax: &mut Float,
+    any: bool,
+) -> Option<usize> {
+    let mut hit = None;
+    for i in offset..offset + count {
+        let v0 = Point::new(leaf.v0_x[i], leaf.v0_y[i], leaf.v0_z[i]);
+        let edge1 = Vec3::new(leaf.e1_x[i], leaf.e1_y[i], leaf.e1_z[i]);
+        let edge2 = Vec3::new(leaf.e2_x[i], leaf.e2_y[i], leaf.e2_z[i]);
+
+        let h = ray.dir.cross(edge2);
+        let a = edge1.dot(h);
+        if a.abs() < EPSILON {
+            continue;
+        }
+        let f = 1.0 / a;
+        let s = ray.orig - v0;
+        let u = f * s.dot(h);
+        if !(0.0..=1.0).contains(&u) {
+            continue;
+        }
+        let q = s.cross(edge1);
+        let v = f * ray.dir.dot(q);
+        if v < 0.0 || u + v > 1.0 {
+            continue;
+        }
+        let t = f * edge2.dot(q);
+        if t < EPSILON || t <= tmin || t >= *tmax {
+            continue;
+        }
+        *tmax = t;
+        hit = Some(i);
+        if any {
+            break;
+        }
+    }
+    hit
+}
+
+#[cfg(target_arch = "x86_64")]
+fn intercept_avx2(
+    leaf: &LeafTriangles,
+    ray: &Ray,
+    offset: usize,
+    count: usize,
+    tmin: Float,
+    tmax: &mut Float,
+    any: bool,
+) -> Option<usize> {
+    // SAFETY: only handed out by get_leaf_intercept() when AVX2 is present
+    unsafe { intercept_avx2_impl(leaf, ray, offset, count, tmin, tmax, any) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn intercept_avx2_impl(
+    leaf: &LeafTriangles,
+    ray: &Ray,
+    offset: usize,
+    count: usize,
+    tmin: Float,
+    tmax: &mut Float,
+    any: bool,
+) -> Option<usize> {
+    use std::arch::x86_64::*;
+
+    let dir_x = _mm256_set1_ps(ray.dir.x);
+    let dir_y = _mm256_set1_ps(ray.dir.y);
+    let dir_z = _mm256_set1_ps(ray.dir.z);
+    let orig_x = _mm256_set1_ps(ray.orig.x);
+    let orig_y = _mm256_set1_ps(ray.orig.y);
+    let orig_z = _mm256_set1_ps(ray.orig.z);
+    let zero = _mm256_setzero_ps();
+    let one = _mm256_set1_ps(1.0);
+    let epsilon = _mm256_set1_ps(EPSILON);
+    let sign_mask = _mm256_set1_ps(-0.0);
+    let lane_ids = _mm256_setr_ps(0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0);
+
+    let mut hit = None;
+    let end = offset + count;
+    let mut start = offset;
+    while start < end {
+        assert!(start + LANES <= leaf.v0_x.len());
+        // SAFETY: in bounds thanks to the padding, checked above
+        let load = |v: &[Float]| unsafe { _mm256_loadu_ps(v.as_ptr().add(start)) };
+        let v0_x = load(&leaf.v0_x);
+        let v0_y = load(&leaf.v0_y);
+        let v0_z = load(&leaf.v0_z);
+        let e1_x = load(&leaf.e1_x);
+        let e1_y = load(&leaf.e1_y);
+        let e1_z = load(&leaf.e1_z);
+        let e2_x = load(&leaf.e2_x);
+        let e2_y = load(&leaf.e2_y);
+        let e2_z = load(&leaf.e2_z);
+
+        // h = dir x edge2, a = edge1 . h
+        let h_x = _mm256_sub_ps(_mm256_mul_ps(dir_y, e2_z), _mm256_mul_ps(dir_z, e2_y));
+        let h_y = _mm256_sub_ps(_mm256_mul_ps(dir_z, e2_x), _mm256_mul_ps(dir_x, e2_z));
+        let h_z = _mm256_sub_ps(_mm256_mul_ps(dir_x, e2_y), _mm256_mul_ps(dir_y, e2_x));
+        let a = dot(e1_x, e1_y, e1_z, h_x, h_y, h_z);
+        let abs_a = _mm256_andnot_ps(sign_mask, a);
+        // the tests are negated so that NaNs are handled as in the scalar code
+        let mut valid = _mm256_cmp_ps::<_CMP_NLT_UQ>(abs_a, epsilon);
+
+        let f = _mm256_div_ps(one, a);
+        let s_x = _mm256_sub_ps(orig_x, v0_x);
+        let s_y = _mm256_sub_ps(orig_y, v0_y);
+        let s_z = _mm256_sub_ps(orig_z, v0_z);
+        let u = _mm256_mul_ps(f, dot(s_x, s_y, s_z, h_x, h_y, h_z));
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_GE_OQ>(u, zero));
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_LE_OQ>(u, one));
+
+        // q = s x edge1
+        let q_x = _mm256_sub_ps(_mm256_mul_ps(s_y, e1_z), _mm256_mul_ps(s_z, e1_y));
+        let q_y = _mm256_sub_ps(_mm256_mul_ps(s_z, e1_x), _mm256_mul_ps(s_x, e1_z));
+        let q_z = _mm256_sub_ps(_mm256_mul_ps(s_x, e1_y), _mm256_mul_ps(s_y, e1_x));
+        let v = _mm256_mul_ps(f, dot(dir_x, dir_y, dir_z, q_x, q_y, q_z));
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_NLT_UQ>(v, zero));
+        let u_v = _mm256_add_ps(u, v);
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_NGT_UQ>(u_v, one));
+
+        let t = _mm256_mul_ps(f, dot(e2_x, e2_y, e2_z, q_x, q_y, q_z));
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_NLT_UQ>(t, epsilon));
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_NLE_UQ>(t, _mm256_set1_ps(tmin)));
+        valid = _mm256_and_ps(
+            valid,
+            _mm256_cmp_ps::<_CMP_NGE_UQ>(t, _mm256_set1_ps(*tmax)),
+        );
+        // lanes past the leaf
+        let num_lanes = _mm256_set1_ps((end - start) as Float);
+        valid = _mm256_and_ps(valid, _mm256_cmp_ps::<_CMP_LT_OQ>(lane_ids, num_lanes));
+
+        let mut mask = _mm256_movemask_ps(valid) as u32;
+        if mask != 0 {
+            let mut ts = [0.0; LANES];
+            // SAFETY: ts holds LANES floats
+            unsafe { _mm256_storeu_ps(ts.as_mut_ptr(), t) };
+            // in lane order, as the scalar loop would find them
+            while mask != 0 {
+                let lane = mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                if ts[lane] >= *tmax {
+                    continue;
+                }
+                *tmax = ts[lane];
+                hit = Some(start + lane);
+                if any {
+                    return hit;
+                }
+            }
+        }
+        start += LANES;
+    }
+    hit
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+fn dot(
+    a_x: std::arch::x86_64::__m256,
+    a_y: std::arch::x86_64::__m256,
+    a_z: std::arch::x86_64::__m256,
+    b_x: std::arch::x86_64::__m256,
+    b_y: std::arch::x86_64::__m256,
+    b_z: std::arch::x86_64::__m256,
+) -> std::arch::x86_64::__m256 {
+    use std::arch::x86_64::*;
+    let xy = _mm256_add_ps(_mm256_mul_ps(a_x, b_x), _mm256_mul_ps(a_y, b_y));
+    _mm256_add_ps(xy, _mm256_mul_ps(a_z, b_z))
+}
@@ -0,0 +1,198 @@
+use crate::color::RGB;
+use crate::distribution::Distribution2D;
+use crate::vec3::Float;
+use crate::vec3::Vec2;
+use crate::vec3::Vec3;
+use ::image::codecs::hdr::HdrDecoder;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/*
+ * Image texture kept in memory as RGB in 0..1, sampled with bilinear
+ * filtering and wrap-around addressing.
+ */
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<RGB>,
+}
+
+fn invalid_image(path: &Path, e: ::image::ImageError) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{}: {e}", path.display()),
+    )
+}
+
+fn open_image(path: &Path) -> std::io::Result<::image::DynamicImage> {
+    ::image::open(path).map_err(|e| invalid_image(path, e))
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Texture: {}x{}", self.width, self.height)
+    }
+}
+
+impl Texture {
+    pub fn new(width: u32, height: u32, pixels: Vec<RGB>) -> Self {
+        assert!(width > 0 && height > 0);
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let img = open_image(path)?.to_rgb8();
+        let pixels = img
+            .pixels()
+            .map(|p| RGB::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0)
+            .collect();
+        Ok(Self::new(img.width(), img.height(), pixels))
+    }
+    // keeps the values above 1 of .hdr and .exr files
+    pub fn load_hdr(path: &Path) -> std::io::Result<Self> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        if ext.eq_ignore_ascii_case("hdr") {
+            // image::open() tone maps .hdr files down to 8 bits
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            let invalid = |e| invalid_image(path, e);
+            let decoder = HdrDecoder::new(file).map_err(invalid)?;
+            let meta = decoder.metadata();
+            let pixels = decoder.read_image_hdr().map_err(invalid)?;
+            let pixels = pixels.iter().map(|p| RGB::new(p[0], p[1], p[2])).collect();
+            return Ok(Self::new(meta.width, meta.height, pixels));
+        }
+        let img = open_image(path)?.to_rgb32f();
+        let pixels = img.pixels().map(|p| RGB::new(p[0], p[1], p[2])).collect();
+        Ok(Self::new(img.width(), img.height(), pixels))
+    }
+    fn texel(&self, x: i64, y: i64) -> RGB {
+        let x = x.rem_euclid(self.width as i64) as u32;
+        let y = y.rem_euclid(self.height as i64) as u32;
+        self.pixels[(y * self.width + x) as usize]
+    }
+    // uv: v goes up as in OBJ files, while the image rows go down
+    pub fn sample(&self, uv: Vec2) -> RGB {
+        let x = uv.x * self.width as f32 - 0.5;
+        let y = (1.0 - uv.y) * self.height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = RGB::lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), fx);
+        let bottom = RGB::lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), fx);
+        RGB::lerp(top, bottom, fy)
+    }
+    /*
+     * Weights to importance-sample the texture as a lat-long map: the
+     * luminance of the texels, times the sine of their polar angle for the
+     * smaller solid angle of the rows near the poles.
+     */
+    pub fn get_latlong_distribution(&self) -> Distribution2D {
+        let pi = std::f64::consts::PI as Float;
+        let (width, height) = (self.width as usize, self.height as usize);
+        let func: Vec<Float> = self
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let theta = pi * ((i / width) as Float + 0.5) / height as Float;
+                p.luminance() * theta.sin()
+            })
+            .collect();
+        Distribution2D::new(&func, width, height)
+    }
+    // wraps around in u only: the first and last rows are the poles
+    pub fn sample_latlong(&self, uv: Vec2) -> RGB {
+        let half = 0.5 / self.height as f32;
+        self.sample(Vec2 {
+            x: uv.x,
+            y: uv.y.clamp(half, 1.0 - half),
+        })
+    }
+}
+
+/*
+ * Equirectangular map of the light coming from infinitely far away, seen
+ * by the rays that miss the scene. z is up as for the cameras: the
+ * longitude goes around z from +x, and the rows from +z down to -z.
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Environment {
+    pub path: PathBuf,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+    // loaded from path
+    #[serde(skip)]
+    pub texture: Option<Arc<Texture>>,
+    // built from texture on load, for the light sampling of the path-tracer
+    #[serde(skip)]
+    pub distribution: Option<Arc<Distribution2D>>,
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl Environment {
+    pub fn load(&mut self) -> std::io::Result<()> {
+        let texture = Texture::load_hdr(&self.path)?;
+        self.distribution = Some(Arc::new(texture.get_latlong_distribution()));
+        self.texture = Some(Arc::new(texture));
+        Ok(())
+    }
+    pub fn get_uv(dir: Vec3) -> Vec2 {
+        let pi = std::f64::consts::PI as Float;
+        let dir = dir.normalize();
+        Vec2 {
+            x: 0.5 + dir.y.atan2(dir.x) / (2.0 * pi),
+            y: 0.5 + dir.z.clamp(-1.0, 1.0).asin() / pi,
+        }
+    }
+    // black until loaded
+    pub fn sample(&self, dir: Vec3) -> RGB {
+        match &self.texture {
+            Some(texture) => texture.sample_latlong(Self::get_uv(dir)) * self.intensity,
+            None => RGB::zero(),
+        }
+    }
+    /*
+     * A direction picked in proportion to the light of the map for u, v in
+     * 0..1: its density per solid angle, and the light coming from it.
+     * None without a distribution.
+     */
+    pub fn sample_dir(&self, u: Float, v: Float) -> Option<(Vec3, Float, RGB)> {
+        let pi = std::f64::consts::PI as Float;
+        let (x, y, pdf) = self.distribution.as_ref()?.sample(u, v);
+        // the inverse of get_uv()
+        let theta = pi * y;
+        let phi = 2.0 * pi * (x - 0.5);
+        let sin_theta = theta.sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let dir = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), theta.cos());
+        Some((dir, pdf / (2.0 * pi * pi * sin_theta), self.sample(dir)))
+    }
+    // density of sample_dir() per solid angle, 0 without a distribution
+    pub fn pdf(&self, dir: Vec3) -> Float {
+        let pi = std::f64::consts::PI as Float;
+        let Some(distribution) = &self.distribution else {
+            return 0.0;
+        };
+        let uv = Self::get_uv(dir);
+        let z = dir.normalize().z;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        distribution.pdf(uv.x, 1.0 - uv.y) / (2.0 * pi * pi * sin_theta)
+    }
+}
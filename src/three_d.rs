@@ -3,8 +3,10 @@ use std::sync::Arc;
 use crate::Ray;
 use crate::RenderStats;
 use crate::aabb::AABB;
+use crate::aabb::Bvh;
 use crate::vec3::EPSILON;
 use crate::vec3::Float;
+use crate::vec3::Matrix4;
 use crate::vec3::Point;
 use crate::vec3::Vec2;
 use crate::vec3::Vec3;
@@ -22,8 +24,106 @@ pub trait Object {
         oid: &mut usize,
     ) -> bool;
     fn get_normal(&self, point: Point, oid: usize) -> Vec3;
-    fn get_texture_2d(&self, point: Point) -> Vec2;
+    // oid as returned by intercept()
+    fn get_texture_2d(&self, point: Point, oid: usize) -> Vec2;
     fn get_material_id(&self) -> usize;
+    // material of the face that was hit: oid as returned by intercept()
+    fn get_face_material_id(&self, _oid: usize) -> usize {
+        self.get_material_id()
+    }
+    fn get_velocity(&self) -> Vec3 {
+        Vec3::zero()
+    }
+    /*
+     * The ray came from inside the object: get_normal() then points inward,
+     * against the ray, and a refracted ray leaves the object.
+     */
+    fn is_inside_hit(&self, _oid: usize) -> bool {
+        false
+    }
+    /*
+     * Point and normal on the surface for u, v in 0..1, uniformly
+     * distributed over the area unless is_sampled_uniformly() says
+     * otherwise. None for unbounded objects.
+     */
+    fn sample_surface(&self, _u: Float, _v: Float) -> Option<(Point, Vec3)> {
+        None
+    }
+    fn is_sampled_uniformly(&self) -> bool {
+        true
+    }
+    fn get_area(&self) -> Float {
+        0.0
+    }
+    // None for unbounded objects
+    fn get_aabb(&self) -> Option<AABB> {
+        None
+    }
+    // baked ambient occlusion, 1.0 when open: oid as returned by intercept()
+    fn get_ao(&self, _point: Point, _oid: usize) -> Float {
+        1.0
+    }
+    // dP/du and dP/dv, which orient the normal maps: None without a uv mapping
+    fn get_tangents(&self, _point: Point, _oid: usize) -> Option<[Vec3; 2]> {
+        None
+    }
+}
+
+/*
+ * The optional "transform" of an object of the scene: scaled, then rotated
+ * by rotate_xyz degrees about x, y then z, then translated.
+ */
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transform {
+    pub translate: Vec3,
+    pub rotate_xyz: Vec3,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translate: Vec3::zero(),
+            rotate_xyz: Vec3::zero(),
+            scale: Vec3::one(),
+        }
+    }
+}
+
+impl Transform {
+    pub fn validate(&self) -> Result<(), String> {
+        let [t, r, s] = [self.translate, self.rotate_xyz, self.scale];
+        if !t.is_finite() || !r.is_finite() || !s.is_finite() {
+            return Err("transform with non-finite values".to_owned());
+        }
+        if s.x == 0.0 || s.y == 0.0 || s.z == 0.0 {
+            return Err(format!("transform with a zero scale ({s:?})"));
+        }
+        Ok(())
+    }
+    pub fn to_matrix(&self) -> Matrix4 {
+        let angles = self.rotate_xyz * (std::f64::consts::PI as Float / 180.0);
+        Matrix4::translation(self.translate)
+            * Matrix4::rotation_xyz(angles)
+            * Matrix4::scaling(self.scale)
+    }
+    fn is_rotated(&self) -> bool {
+        !is_zero_rotation(&self.rotate_xyz)
+    }
+    // the objects defined by a radius only scale uniformly
+    fn get_uniform_scale(&self, kind: &str) -> Result<Float, String> {
+        let s = self.scale;
+        if s.x != s.y || s.y != s.z {
+            return Err(format!("{kind} with a non-uniform transform scale ({s:?})"));
+        }
+        Ok(s.x.abs())
+    }
+}
+
+// applied to the geometry while loading the scene
+pub trait Transformable {
+    fn transform(&mut self, t: &Transform) -> Result<(), String>;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +131,15 @@ pub struct Sphere {
     pub center: Point,
     pub radius: Float,
     pub material_id: usize,
+    #[serde(default)]
+    pub velocity: Vec3,
+    // per-axis scale of the radius: an ellipsoid when non-uniform
+    #[serde(default = "Vec3::one", skip_serializing_if = "is_unit_scale")]
+    pub scale: Vec3,
+}
+
+fn is_unit_scale(scale: &Vec3) -> bool {
+    *scale == Vec3::one()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,16 +147,120 @@ pub struct Plane {
     pub point: Point,
     pub normal: Vec3,
     pub material_id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub back_material_id: Option<usize>,
+    // sizes of a rectangle centered on point along the basis of the plane, infinite if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub u_extent: Option<Float>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v_extent: Option<Float>,
+}
+
+// flat, lit from both sides like the planes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Disk {
+    pub center: Point,
+    pub normal: Vec3,
+    pub radius: Float,
+    pub material_id: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub base: Point,
+    pub axis: Vec3,
+    pub radius: Float,
+    pub height: Float,
+    pub material_id: usize,
+}
+
+// ring around axis through center, swept by a circle of minor_radius
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Torus {
+    pub center: Point,
+    pub axis: Vec3,
+    pub major_radius: Float,
+    pub minor_radius: Float,
+    pub material_id: usize,
+}
+
+/*
+ * Axis-aligned box, optionally rotated about its center by `rotation`:
+ * degrees around x, then y, then z, like the meshes.
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cuboid {
+    pub p_min: Point,
+    pub p_max: Point,
+    pub material_id: usize,
+    #[serde(default, skip_serializing_if = "is_zero_rotation")]
+    pub rotation: Vec3,
+    // world directions of the local axes, set by init()
+    #[serde(skip)]
+    axes: [Vec3; 3],
+}
+
+fn is_zero_rotation(rotation: &Vec3) -> bool {
+    *rotation == Vec3::zero()
 }
 
+// oid values returned by Cuboid::intercept()
+const CUBOID_OUTSIDE: usize = 0;
+const CUBOID_INSIDE: usize = 1;
+
+// oid values returned by Plane::intercept()
+const PLANE_FRONT: usize = 0;
+const PLANE_BACK: usize = 1;
+
+// oid values returned by Cylinder::intercept()
+const CYLINDER_SIDE: usize = 0;
+const CYLINDER_BOTTOM: usize = 1;
+const CYLINDER_TOP: usize = 2;
+
+// oid values returned by Torus::intercept()
+const TORUS_OUTSIDE: usize = 0;
+const TORUS_INSIDE: usize = 1;
+
+// oid values returned by Sphere::intercept()
+const SPHERE_OUTSIDE: usize = 0;
+const SPHERE_INSIDE: usize = 1;
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Triangle {
     pub points: [Point; 3],
     pub material_id: usize,
+    // per-vertex normals, interpolated over the face for smooth shading
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals: Option<[Vec3; 3]>,
+    // per-vertex texture coordinates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs: Option<[Vec2; 3]>,
+    // per-vertex ambient occlusion, see ao.rs
+    #[serde(skip)]
+    pub ao: Option<[Float; 3]>,
+    // dP/du and dP/dv from the uvs, for the normal maps: see compute_tangents()
+    #[serde(skip)]
+    pub tangents: Option<[Vec3; 2]>,
     #[serde(skip)]
     pub mesh_id: usize,
 }
 
+/*
+ * Four coplanar corners in order around a convex outline. It is rendered
+ * as the triangles p0 p1 p2 and p0 p2 p3; the corners get the uvs (0,0),
+ * (1,0), (1,1) and (0,1) unless given.
+ */
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Quad {
+    pub points: [Point; 4],
+    pub material_id: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uvs: Option<[Vec2; 4]>,
+}
+
+// max distance of the 4th corner to the plane of the others, relative to the size
+const QUAD_PLANAR_TOLERANCE: Float = 1e-4;
+
 pub struct Triangles {
     pub point_x: Vec<Float>,
     pub point_y: Vec<Float>,
@@ -83,6 +296,10 @@ impl Triangles {
         Triangle {
             points: [p0, p1, p2],
             material_id: self.material_id[idx],
+            normals: None,
+            uvs: None,
+            ao: None,
+            tangents: None,
             mesh_id: 0,
         }
     }
@@ -92,7 +309,7 @@ pub struct Mesh {
     pub material_id: usize,
     pub triangles: Arc<Vec<Triangle>>,
     pub triangles_soa: Arc<Triangles>,
-    pub aabb: AABB,
+    pub bvh: Bvh,
 }
 
 impl Mesh {
@@ -107,36 +324,301 @@ impl Mesh {
             });
         });
         let arc_triangles = Arc::new(triangles);
-        let triangles_soa_arc = Arc::new(triangles_soa);
         let mut m = Mesh {
             triangles: arc_triangles.clone(),
             material_id: mat_id,
-            aabb: AABB::new(arc_triangles, triangles_soa_arc.clone()),
-            triangles_soa: triangles_soa_arc,
+            bvh: Bvh::new(arc_triangles),
+            triangles_soa: Arc::new(triangles_soa),
         };
-        m.aabb.init();
+        m.bvh.init();
         m
     }
 }
 
 impl Triangle {
-    pub fn new(points: [Point; 3], material_id: usize) -> Self {
-        Self {
+    pub fn new(points: [Point; 3], material_id: usize) -> Result<Self, String> {
+        let t = Self {
             points,
             material_id,
+            normals: None,
+            uvs: None,
+            ao: None,
+            tangents: None,
             mesh_id: 0,
+        };
+        t.validate()?;
+        Ok(t)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if self.is_degenerate() {
+            return Err("triangle with non-finite or collinear points".to_owned());
         }
+        Ok(())
+    }
+    /*
+     * Non-finite vertices yield NaN normals, and a (near) zero area gives a
+     * meaningless normal. The area test is relative to the edge lengths so
+     * that finely tessellated meshes at a small scale are left alone.
+     */
+    pub fn is_degenerate(&self) -> bool {
+        if !self.points.iter().all(|p| p.is_finite()) {
+            return true;
+        }
+        let edge1 = self.points[1] - self.points[0];
+        let edge2 = self.points[2] - self.points[0];
+        let area2 = edge1.cross(edge2).norm();
+        area2 <= EPSILON * edge1.norm() * edge2.norm()
+    }
+    // weights of the 3 vertices for a point in the plane of the triangle
+    pub fn barycentric(&self, point: Point) -> [Float; 3] {
+        let v0 = self.points[1] - self.points[0];
+        let v1 = self.points[2] - self.points[0];
+        let v2 = point - self.points[0];
+        let d00 = v0.dot(v0);
+        let d01 = v0.dot(v1);
+        let d11 = v1.dot(v1);
+        let d20 = v2.dot(v0);
+        let d21 = v2.dot(v1);
+        let denom = d00 * d11 - d01 * d01;
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        [1.0 - v - w, v, w]
+    }
+    /*
+     * The directions in which u and v grow over the face: the edges solved
+     * against their uv deltas. None without uvs, or when they are
+     * degenerate.
+     */
+    pub fn compute_tangents(&self) -> Option<[Vec3; 2]> {
+        let [uv0, uv1, uv2] = self.uvs?;
+        let edge1 = self.points[1] - self.points[0];
+        let edge2 = self.points[2] - self.points[0];
+        let (du1, dv1) = (uv1.x - uv0.x, uv1.y - uv0.y);
+        let (du2, dv2) = (uv2.x - uv0.x, uv2.y - uv0.y);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let dpdu = (edge1 * dv2 - edge2 * dv1) / det;
+        let dpdv = (edge2 * du1 - edge1 * du2) / det;
+        Some([dpdu, dpdv])
     }
 }
 
+impl Quad {
+    pub fn new(points: [Point; 4], material_id: usize) -> Result<Self, String> {
+        let q = Self {
+            points,
+            material_id,
+            uvs: None,
+        };
+        q.validate()?;
+        Ok(q)
+    }
+    // the parallelogram spanned by the edges: u and v run along them
+    pub fn from_edges(
+        corner: Point,
+        edge_u: Vec3,
+        edge_v: Vec3,
+        material_id: usize,
+    ) -> Result<Self, String> {
+        let points = [
+            corner,
+            corner + edge_u,
+            corner + edge_u + edge_v,
+            corner + edge_v,
+        ];
+        Self::new(points, material_id)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        let [t0, t1] = self.to_triangles();
+        if t0.is_degenerate() || t1.is_degenerate() {
+            return Err("quad with non-finite or collinear points".to_owned());
+        }
+        let n0 = t0.get_normal(t0.points[0], 0);
+        let n1 = t1.get_normal(t1.points[0], 0);
+        let p = self.points;
+        let size = (p[2] - p[0]).norm().max((p[3] - p[1]).norm());
+        if (p[3] - p[0]).dot(n0).abs() > QUAD_PLANAR_TOLERANCE * size {
+            return Err("quad with non-coplanar points".to_owned());
+        }
+        if n0.dot(n1) <= 0.0 {
+            return Err("quad with a concave or self-intersecting outline".to_owned());
+        }
+        Ok(())
+    }
+    pub fn to_triangles(&self) -> [Triangle; 2] {
+        let p = self.points;
+        let uv = self.uvs.unwrap_or([
+            Vec2 { x: 0.0, y: 0.0 },
+            Vec2 { x: 1.0, y: 0.0 },
+            Vec2 { x: 1.0, y: 1.0 },
+            Vec2 { x: 0.0, y: 1.0 },
+        ]);
+        [[0, 1, 2], [0, 2, 3]].map(|k| {
+            let mut t = Triangle {
+                points: k.map(|i| p[i]),
+                material_id: self.material_id,
+                normals: None,
+                uvs: Some(k.map(|i| uv[i])),
+                ao: None,
+                tangents: None,
+                mesh_id: 0,
+            };
+            t.tangents = t.compute_tangents();
+            t
+        })
+    }
+}
+
+impl Cuboid {
+    pub fn new(p_min: Point, p_max: Point, material_id: usize) -> Result<Self, String> {
+        let mut c = Self {
+            p_min,
+            p_max,
+            material_id,
+            rotation: Vec3::zero(),
+            axes: [Vec3::zero(); 3],
+        };
+        c.init()?;
+        Ok(c)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.p_min.is_finite() || !self.p_max.is_finite() || !self.rotation.is_finite() {
+            return Err("box with non-finite corners or rotation".to_owned());
+        }
+        let d = self.p_max - self.p_min;
+        if !(d.x > 0.0 && d.y > 0.0 && d.z > 0.0) {
+            return Err(format!("box with p_max <= p_min ({d:?})"));
+        }
+        Ok(())
+    }
+    // validates, and takes the rotation into account
+    pub fn init(&mut self) -> Result<(), String> {
+        self.validate()?;
+        let r = self.rotation * (std::f64::consts::PI as Float / 180.0);
+        let rotate = |v: Vec3| v.rotx(r.x).roty(r.y).rotz(r.z);
+        self.axes = [Vec3::unity_x(), Vec3::unity_y(), Vec3::unity_z()].map(rotate);
+        Ok(())
+    }
+    fn get_center(&self) -> Point {
+        (self.p_min + self.p_max) / 2.0
+    }
+    fn is_rotated(&self) -> bool {
+        !is_zero_rotation(&self.rotation)
+    }
+    // world to local: around the center, in the frame of the axes
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        let [ax, ay, az] = self.axes;
+        Vec3::new(v.dot(ax), v.dot(ay), v.dot(az))
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        let [ax, ay, az] = self.axes;
+        ax * v.x + ay * v.y + az * v.z
+    }
+    fn get_local_point(&self, point: Point) -> Point {
+        if !self.is_rotated() {
+            return point;
+        }
+        let center = self.get_center();
+        center + self.to_local(point - center)
+    }
+    // local axis of the face under point, and its side: -1 or 1
+    fn get_face(&self, point: Point) -> (usize, Float) {
+        let p = self.get_local_point(point) - self.get_center();
+        let half = (self.p_max - self.p_min) / 2.0;
+        let d = [p.x / half.x, p.y / half.y, p.z / half.z];
+        let axis = (0..3)
+            .max_by(|&a, &b| d[a].abs().total_cmp(&d[b].abs()))
+            .unwrap();
+        (axis, if d[axis] < 0.0 { -1.0 } else { 1.0 })
+    }
+}
+
+/*
+ * Two unit vectors orthogonal to axis and to each other, for the angles
+ * around it.
+ */
+fn get_perpendicular_frame(axis: Vec3) -> (Vec3, Vec3) {
+    let ref_axis = if axis.x.abs() < 0.9 {
+        Vec3::unity_x()
+    } else {
+        Vec3::unity_y()
+    };
+    let e1 = ref_axis.cross(axis).normalize();
+    (e1, axis.cross(e1))
+}
+
+// t and side of the hit of ray on the plane through point, if in tmin..tmax
+fn intercept_plane(
+    point: Point,
+    normal: Vec3,
+    ray: &Ray,
+    tmin: Float,
+    tmax: Float,
+) -> Option<(Float, usize)> {
+    let d = ray.dir.dot(normal);
+    if d.abs() < EPSILON {
+        return None;
+    }
+    let v = point - ray.orig;
+    let t0 = v.dot(normal) / d;
+    if t0 <= tmin || t0 >= tmax {
+        return None;
+    }
+    Some((t0, if d < 0.0 { PLANE_FRONT } else { PLANE_BACK }))
+}
+
 impl Plane {
-    pub fn new(point: Point, normal: Vec3, material_id: usize) -> Self {
-        let n = normal.normalize();
-        Self {
+    pub fn new(point: Point, normal: Vec3, material_id: usize) -> Result<Self, String> {
+        let mut p = Self {
             point,
-            normal: n,
+            normal,
             material_id,
+            back_material_id: None,
+            u_extent: None,
+            v_extent: None,
+        };
+        p.validate()?;
+        p.normal = normal.normalize();
+        Ok(p)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.point.is_finite() || !self.normal.is_finite() {
+            return Err("plane with non-finite point or normal".to_owned());
+        }
+        if self.normal.norm() < EPSILON {
+            return Err("plane with a zero normal".to_owned());
         }
+        for extent in [self.u_extent, self.v_extent].into_iter().flatten() {
+            if !extent.is_finite() || extent <= 0.0 {
+                return Err(format!("plane with a non-positive extent {extent}"));
+            }
+        }
+        Ok(())
+    }
+    pub fn is_bounded(&self) -> bool {
+        self.u_extent.is_some() || self.v_extent.is_some()
+    }
+    /*
+     * The in-plane basis of the extents: x then y for a floor, y then z for
+     * a wall facing x, so that u, v and the normal stay right-handed.
+     */
+    pub fn get_basis(&self) -> (Vec3, Vec3) {
+        let n = self.normal.normalize();
+        let ref_axis = if n.z.abs() < 0.9 {
+            Vec3::unity_z()
+        } else {
+            Vec3::unity_y()
+        };
+        let u = ref_axis.cross(n).normalize();
+        (u, n.cross(u))
+    }
+    // coordinates of a point of the plane along its basis, from self.point
+    fn get_coords(&self, point: Point) -> (Float, Float) {
+        let (u, v) = self.get_basis();
+        let d = point - self.point;
+        (d.dot(u), d.dot(v))
     }
 }
 impl Object for Plane {
@@ -150,48 +632,694 @@ impl Object for Plane {
         tmin: Float,
         tmax: &mut Float,
         _any: bool,
-        _oid: &mut usize,
+        oid: &mut usize,
     ) -> bool {
-        stats.num_intersects_plane += 1;
-        let d = ray.dir.dot(self.normal);
-        if d.abs() < EPSILON {
-            return false;
-        }
-        let v = self.point - ray.orig;
-        let t0 = v.dot(self.normal) / d;
-        if t0 <= tmin || t0 >= *tmax {
+        stats.count(|s| s.num_intersects_plane += 1);
+        let Some((t, side)) = intercept_plane(self.point, self.normal, ray, tmin, *tmax) else {
             return false;
+        };
+        if self.is_bounded() {
+            let (u, v) = self.get_coords(ray.orig + ray.dir * t);
+            let outside =
+                |c: Float, extent: Option<Float>| extent.is_some_and(|e| c.abs() > e / 2.0);
+            if outside(u, self.u_extent) || outside(v, self.v_extent) {
+                return false;
+            }
         }
-        *tmax = t0;
+        *tmax = t;
+        *oid = side;
         true
     }
-    fn get_normal(&self, _point: Point, _oid: usize) -> Vec3 {
-        self.normal
+    // the normal faces the incoming ray: planes are lit from both sides
+    fn get_normal(&self, _point: Point, oid: usize) -> Vec3 {
+        if oid == PLANE_BACK {
+            self.normal * -1.0
+        } else {
+            self.normal
+        }
     }
-    fn get_texture_2d(&self, point: Point) -> Vec2 {
+    fn get_face_material_id(&self, oid: usize) -> usize {
+        match self.back_material_id {
+            Some(id) if oid == PLANE_BACK => id,
+            _ => self.material_id,
+        }
+    }
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        let fold = |c: Float| if c < 0.0 { -c + 0.125 } else { c };
+        if self.is_bounded() {
+            // from the corner of the rectangle, so that its cells line up with the edges
+            let (u, v) = self.get_coords(point);
+            let from_edge = |c: Float, extent: Option<Float>| c + extent.unwrap_or(0.0) / 2.0;
+            return Vec2 {
+                x: fold(from_edge(u, self.u_extent)),
+                y: fold(from_edge(v, self.v_extent)),
+            };
+        }
         let v = point - self.point;
-        let mut v_x = v.dot(Vec3::unity_y());
-        let mut v_y = v.dot(Vec3::unity_z());
-        if v_x < 0.0 {
-            v_x = -v_x + 0.125;
+        Vec2 {
+            x: fold(v.dot(Vec3::unity_y())),
+            y: fold(v.dot(Vec3::unity_z())),
         }
-        if v_y < 0.0 {
-            v_y = -v_y + 0.125;
+    }
+    fn get_material_id(&self) -> usize {
+        self.material_id
+    }
+    fn get_aabb(&self) -> Option<AABB> {
+        let (Some(u_extent), Some(v_extent)) = (self.u_extent, self.v_extent) else {
+            return None;
+        };
+        let (u, v) = self.get_basis();
+        let mut aabb = AABB::empty();
+        for (su, sv) in [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)] {
+            aabb.grow_point(self.point + u * (su * u_extent / 2.0) + v * (sv * v_extent / 2.0));
+        }
+        Some(aabb)
+    }
+}
+
+impl Disk {
+    pub fn new(
+        center: Point,
+        normal: Vec3,
+        radius: Float,
+        material_id: usize,
+    ) -> Result<Self, String> {
+        let mut d = Self {
+            center,
+            normal,
+            radius,
+            material_id,
+        };
+        d.validate()?;
+        d.normal = normal.normalize();
+        Ok(d)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.center.is_finite() || !self.normal.is_finite() {
+            return Err("disk with non-finite center or normal".to_owned());
+        }
+        if self.normal.norm() < EPSILON {
+            return Err("disk with a zero normal".to_owned());
+        }
+        if !(self.radius > 0.0 && self.radius.is_finite()) {
+            return Err(format!("disk with radius <= 0 ({})", self.radius));
+        }
+        Ok(())
+    }
+}
+
+impl Object for Disk {
+    fn display(&self) {
+        println!(
+            "disk: {:?} normal={:?} radius={}",
+            self.center, self.normal, self.radius
+        );
+    }
+    // the plane test, then the radius
+    fn intercept(
+        &self,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmin: Float,
+        tmax: &mut Float,
+        _any: bool,
+        oid: &mut usize,
+    ) -> bool {
+        stats.count(|s| s.num_intersects_plane += 1);
+        let Some((t, side)) = intercept_plane(self.center, self.normal, ray, tmin, *tmax) else {
+            return false;
+        };
+        let v = ray.orig + ray.dir * t - self.center;
+        if v.dot(v) > self.radius * self.radius {
+            return false;
+        }
+        *tmax = t;
+        *oid = side;
+        true
+    }
+    fn get_normal(&self, _point: Point, oid: usize) -> Vec3 {
+        if oid == PLANE_BACK {
+            self.normal * -1.0
+        } else {
+            self.normal
+        }
+    }
+    // polar: the distance to the center over the radius, and the angle
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        let pi = std::f64::consts::PI as Float;
+        let (e1, e2) = get_perpendicular_frame(self.normal);
+        let p = point - self.center;
+        Vec2 {
+            x: p.norm() / self.radius,
+            y: (1.0 + p.dot(e2).atan2(p.dot(e1)) / pi) * 0.5,
         }
-        Vec2 { x: v_x, y: v_y }
     }
     fn get_material_id(&self) -> usize {
         self.material_id
     }
+    fn get_area(&self) -> Float {
+        std::f64::consts::PI as Float * self.radius * self.radius
+    }
+    // sqrt(u) for a uniform density over the area
+    fn sample_surface(&self, u: Float, v: Float) -> Option<(Point, Vec3)> {
+        let (e1, e2) = get_perpendicular_frame(self.normal);
+        let r = self.radius * u.sqrt();
+        let phi = 2.0 * std::f64::consts::PI as Float * v;
+        let point = self.center + e1 * (r * phi.cos()) + e2 * (r * phi.sin());
+        Some((point, self.normal))
+    }
+    // the extent along each axis is radius * sin of its angle to the normal
+    fn get_aabb(&self) -> Option<AABB> {
+        let n = self.normal;
+        let extent = |c: Float| self.radius * (1.0 - c * c).max(0.0).sqrt();
+        let e = Vec3::new(extent(n.x), extent(n.y), extent(n.z));
+        Some(AABB {
+            p_min: self.center - e,
+            p_max: self.center + e,
+        })
+    }
 }
 
 impl Sphere {
-    pub fn new(center: Point, radius: Float, material_id: usize) -> Self {
-        Self {
+    pub fn new(center: Point, radius: Float, material_id: usize) -> Result<Self, String> {
+        let s = Self {
             center,
             radius,
             material_id,
+            velocity: Vec3::zero(),
+            scale: Vec3::one(),
+        };
+        s.validate()?;
+        Ok(s)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.center.is_finite() || !self.velocity.is_finite() {
+            return Err("sphere with non-finite center or velocity".to_owned());
+        }
+        if !(self.radius > 0.0 && self.radius.is_finite()) {
+            return Err(format!("sphere with radius <= 0 ({})", self.radius));
+        }
+        let s = self.scale;
+        if !(s.x > 0.0 && s.y > 0.0 && s.z > 0.0 && s.is_finite()) {
+            return Err(format!("sphere with scale <= 0 ({s:?})"));
+        }
+        Ok(())
+    }
+    pub fn is_ellipsoid(&self) -> bool {
+        !is_unit_scale(&self.scale)
+    }
+    /*
+     * Ellipsoid normal: the unit-space normal taken through the
+     * inverse-transpose of the scale, ie. divided by it once more.
+     */
+    fn get_outward_normal(&self, point: Point) -> Vec3 {
+        let normal = (point - self.center) / self.scale / self.scale / self.radius;
+        if self.is_ellipsoid() {
+            normal.normalize()
+        } else {
+            normal
+        }
+    }
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point,
+        axis: Vec3,
+        radius: Float,
+        height: Float,
+        material_id: usize,
+    ) -> Result<Self, String> {
+        let mut c = Self {
+            base,
+            axis,
+            radius,
+            height,
+            material_id,
+        };
+        c.validate()?;
+        c.axis = axis.normalize();
+        Ok(c)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.base.is_finite() || !self.axis.is_finite() {
+            return Err("cylinder with non-finite base or axis".to_owned());
+        }
+        if self.axis.norm() < EPSILON {
+            return Err("cylinder with a zero axis".to_owned());
+        }
+        if !(self.radius > 0.0 && self.radius.is_finite()) {
+            return Err(format!("cylinder with radius <= 0 ({})", self.radius));
+        }
+        if !(self.height > 0.0 && self.height.is_finite()) {
+            return Err(format!("cylinder with height <= 0 ({})", self.height));
+        }
+        Ok(())
+    }
+    // t along the ray where it crosses the cap at height h, if within the disk
+    fn intercept_cap(&self, orig: Vec3, dir: Vec3, h: Float) -> Option<Float> {
+        let d = dir.dot(self.axis);
+        if d.abs() < EPSILON {
+            return None;
         }
+        let t = (h - orig.dot(self.axis)) / d;
+        let p = orig + dir * t - self.axis * h;
+        (p.dot(p) <= self.radius * self.radius).then_some(t)
+    }
+}
+
+impl Torus {
+    pub fn new(
+        center: Point,
+        axis: Vec3,
+        major_radius: Float,
+        minor_radius: Float,
+        material_id: usize,
+    ) -> Result<Self, String> {
+        let mut t = Self {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            material_id,
+        };
+        t.validate()?;
+        t.axis = axis.normalize();
+        Ok(t)
+    }
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.center.is_finite() || !self.axis.is_finite() {
+            return Err("torus with non-finite center or axis".to_owned());
+        }
+        if self.axis.norm() < EPSILON {
+            return Err("torus with a zero axis".to_owned());
+        }
+        if !(self.minor_radius > 0.0 && self.minor_radius.is_finite()) {
+            return Err(format!(
+                "torus with minor_radius <= 0 ({})",
+                self.minor_radius
+            ));
+        }
+        if !(self.major_radius > self.minor_radius && self.major_radius.is_finite()) {
+            return Err(format!(
+                "torus with major_radius <= minor_radius ({} <= {})",
+                self.major_radius, self.minor_radius
+            ));
+        }
+        Ok(())
+    }
+}
+
+// polynomial of coefficients c, highest degree first
+fn eval_poly(c: &[f64], x: f64) -> f64 {
+    c.iter().fold(0.0, |acc, &a| acc * x + a)
+}
+
+/*
+ * Roots of the polynomial c of degree 1 to 4 in lo..hi, in increasing
+ * order. The roots of the derivative split the range into monotonic
+ * pieces, each crossing zero at most once, which is then found by
+ * bisection: slower than the closed form of the quartic but without its
+ * cancellations, that leave holes in the torus at grazing angles. A root
+ * where the polynomial only touches zero is missed, a grazing ray.
+ */
+fn get_poly_roots(c: &[f64], lo: f64, hi: f64) -> ([f64; 4], usize) {
+    let mut roots = [0.0; 4];
+    let mut num_roots = 0;
+    let degree = c.len() - 1;
+    if degree == 1 {
+        let x = -c[1] / c[0];
+        if c[0] != 0.0 && x > lo && x < hi {
+            roots[0] = x;
+            num_roots = 1;
+        }
+        return (roots, num_roots);
+    }
+    let mut derivative = [0.0; 4];
+    for (i, d) in derivative[..degree].iter_mut().enumerate() {
+        *d = c[i] * (degree - i) as f64;
+    }
+    let (crit, num_crit) = get_poly_roots(&derivative[..degree], lo, hi);
+
+    let mut a = lo;
+    let mut fa = eval_poly(c, a);
+    for &b in crit[..num_crit].iter().chain([hi].iter()) {
+        let fb = eval_poly(c, b);
+        if (fa < 0.0) != (fb < 0.0) {
+            let (mut x0, mut x1) = (a, b);
+            // 2^-50 of the range, about the precision of an f64
+            for _ in 0..50 {
+                let x = 0.5 * (x0 + x1);
+                if (eval_poly(c, x) < 0.0) == (fa < 0.0) {
+                    x0 = x;
+                } else {
+                    x1 = x;
+                }
+            }
+            roots[num_roots] = 0.5 * (x0 + x1);
+            num_roots += 1;
+        }
+        a = b;
+        fa = fb;
+    }
+    (roots, num_roots)
+}
+
+impl Object for Cuboid {
+    fn get_material_id(&self) -> usize {
+        self.material_id
+    }
+    fn display(&self) {
+        println!(
+            "box: {:?} {:?} rotation={:?}",
+            self.p_min, self.p_max, self.rotation
+        );
+    }
+    fn get_normal(&self, point: Point, oid: usize) -> Vec3 {
+        let (axis, side) = self.get_face(point);
+        let mut normal = Vec3::zero();
+        match axis {
+            0 => normal.x = side,
+            1 => normal.y = side,
+            _ => normal.z = side,
+        }
+        let normal = if self.is_rotated() {
+            self.to_world(normal)
+        } else {
+            normal
+        };
+        if oid == CUBOID_INSIDE {
+            normal * -1.0
+        } else {
+            normal
+        }
+    }
+    fn is_inside_hit(&self, oid: usize) -> bool {
+        oid == CUBOID_INSIDE
+    }
+    // 0..1 over each face, along the two other axes
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        let (axis, _) = self.get_face(point);
+        let p = self.get_local_point(point) - self.p_min;
+        let d = self.p_max - self.p_min;
+        let uv = [p.x / d.x, p.y / d.y, p.z / d.z];
+        let (u, v) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+        Vec2 { x: uv[u], y: uv[v] }
+    }
+    fn get_area(&self) -> Float {
+        AABB {
+            p_min: self.p_min,
+            p_max: self.p_max,
+        }
+        .get_surface_area()
+    }
+    // u picks the face in proportion to its area, then the point on it
+    fn sample_surface(&self, u: Float, v: Float) -> Option<(Point, Vec3)> {
+        let d = self.p_max - self.p_min;
+        let areas = [d.y * d.z, d.x * d.z, d.x * d.y];
+        let mut u = u * (areas[0] + areas[1] + areas[2]);
+        let mut axis = 0;
+        while axis < 2 && u >= areas[axis] {
+            u -= areas[axis];
+            axis += 1;
+        }
+        // the first half of the face's share is its min side
+        let u = (u / areas[axis] * 2.0).min(2.0);
+        let (side, u) = if u < 1.0 { (0.0, u) } else { (1.0, u - 1.0) };
+        let f = match axis {
+            0 => Vec3::new(side, u, v),
+            1 => Vec3::new(u, side, v),
+            _ => Vec3::new(u, v, side),
+        };
+        let local = self.p_min + f * d;
+        let point = if self.is_rotated() {
+            let center = self.get_center();
+            center + self.to_world(local - center)
+        } else {
+            local
+        };
+        Some((point, self.get_normal(point, CUBOID_OUTSIDE)))
+    }
+    fn get_aabb(&self) -> Option<AABB> {
+        let center = self.get_center();
+        let half = (self.p_max - self.p_min) / 2.0;
+        let mut aabb = AABB::empty();
+        for i in 0..8 {
+            let corner = |bit: usize, h: Float| if i & bit != 0 { h } else { -h };
+            let p = Vec3::new(corner(1, half.x), corner(2, half.y), corner(4, half.z));
+            aabb.grow_point(center + self.to_world(p));
+        }
+        Some(aabb)
+    }
+
+    fn intercept(
+        &self,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmin: Float,
+        tmax: &mut Float,
+        _any: bool,
+        oid: &mut usize,
+    ) -> bool {
+        stats.count(|s| s.num_intersects_box += 1);
+        let aabb = AABB {
+            p_min: self.p_min,
+            p_max: self.p_max,
+        };
+        // the rotation doesn't scale: t is the same in local space
+        let (t0, t1) = if self.is_rotated() {
+            let center = self.get_center();
+            let local = Ray::new(
+                center + self.to_local(ray.orig - center),
+                self.to_local(ray.dir),
+            );
+            aabb.get_slab_range(&local)
+        } else {
+            aabb.get_slab_range(ray)
+        };
+        if t0 > t1 {
+            return false;
+        }
+        // the exit only when the ray starts inside the box
+        for (t, id) in [(t0, CUBOID_OUTSIDE), (t1, CUBOID_INSIDE)] {
+            if t > tmin && t < *tmax {
+                *tmax = t;
+                *oid = id;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl Object for Cylinder {
+    fn get_material_id(&self) -> usize {
+        self.material_id
+    }
+    // the boxes of the two caps, grown by the radius along every axis
+    fn get_aabb(&self) -> Option<AABB> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let top = self.base + self.axis * self.height;
+        let mut aabb = AABB::empty();
+        for p in [self.base - r, self.base + r, top - r, top + r] {
+            aabb.grow_point(p);
+        }
+        Some(aabb)
+    }
+    fn display(&self) {
+        println!(
+            "cylinder: {:?} axis={:?} radius={:?} height={:?}",
+            self.base, self.axis, self.radius, self.height
+        );
+    }
+    fn get_normal(&self, point: Point, oid: usize) -> Vec3 {
+        match oid {
+            CYLINDER_BOTTOM => self.axis * -1.0,
+            CYLINDER_TOP => self.axis,
+            _ => {
+                let p = point - self.base;
+                (p - self.axis * p.dot(self.axis)).normalize()
+            }
+        }
+    }
+    // side only: angle around the axis and height
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        let pi = std::f64::consts::PI as Float;
+        let (e1, e2) = get_perpendicular_frame(self.axis);
+        let p = point - self.base;
+        let x = (1.0 + p.dot(e2).atan2(p.dot(e1)) / pi) * 0.5;
+        let y = p.dot(self.axis) / self.height;
+        Vec2 { x, y }
+    }
+
+    fn intercept(
+        &self,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmin: Float,
+        tmax: &mut Float,
+        _any: bool,
+        oid: &mut usize,
+    ) -> bool {
+        stats.count(|s| s.num_intersects_cylinder += 1);
+        let orig = ray.orig - self.base;
+        let mut hit = None;
+
+        // infinite cylinder: components orthogonal to the axis
+        let d = ray.dir - self.axis * ray.dir.dot(self.axis);
+        let o = orig - self.axis * orig.dot(self.axis);
+        let a = d.dot(d);
+        let half_b = d.dot(o);
+        let c = o.dot(o) - self.radius * self.radius;
+        let delta = half_b * half_b - a * c;
+        if a > EPSILON && delta >= 0.0 {
+            let delta_sqrt = delta.sqrt();
+            for t in [(-half_b - delta_sqrt) / a, (-half_b + delta_sqrt) / a] {
+                let h = (orig + ray.dir * t).dot(self.axis);
+                if t > tmin && t < *tmax && (0.0..=self.height).contains(&h) {
+                    hit = Some((t, CYLINDER_SIDE));
+                    break;
+                }
+            }
+        }
+        for (h, cap) in [(0.0, CYLINDER_BOTTOM), (self.height, CYLINDER_TOP)] {
+            if let Some(t) = self.intercept_cap(orig, ray.dir, h)
+                && t > tmin
+                && t < hit.map_or(*tmax, |(t_hit, _)| t_hit)
+            {
+                hit = Some((t, cap));
+            }
+        }
+
+        match hit {
+            Some((t, id)) => {
+                *tmax = t;
+                *oid = id;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Object for Torus {
+    fn get_material_id(&self) -> usize {
+        self.material_id
+    }
+    // the ring spans major_radius * sin(angle to the axis) along each axis
+    fn get_aabb(&self) -> Option<AABB> {
+        let axis = self.axis;
+        let extent =
+            |a: Float| self.minor_radius + self.major_radius * (1.0 - a * a).max(0.0).sqrt();
+        let e = Vec3::new(extent(axis.x), extent(axis.y), extent(axis.z));
+        let mut aabb = AABB::empty();
+        aabb.grow_point(self.center - e);
+        aabb.grow_point(self.center + e);
+        Some(aabb)
+    }
+    fn display(&self) {
+        println!(
+            "torus: {:?} axis={:?} major_radius={:?} minor_radius={:?}",
+            self.center, self.axis, self.major_radius, self.minor_radius
+        );
+    }
+    // away from the nearest point of the core circle
+    fn get_normal(&self, point: Point, oid: usize) -> Vec3 {
+        let p = point - self.center;
+        let radial = p - self.axis * p.dot(self.axis);
+        let normal = (p - radial.normalize() * self.major_radius).normalize();
+        if oid == TORUS_INSIDE {
+            normal * -1.0
+        } else {
+            normal
+        }
+    }
+    fn is_inside_hit(&self, oid: usize) -> bool {
+        oid == TORUS_INSIDE
+    }
+    // angle around the axis, then around the tube
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        let pi = std::f64::consts::PI as Float;
+        let (e1, e2) = get_perpendicular_frame(self.axis);
+        let p = point - self.center;
+        let h = p.dot(self.axis);
+        let (px, py) = (p.dot(e1), p.dot(e2));
+        let x = (1.0 + py.atan2(px) / pi) * 0.5;
+        let y = (1.0 + h.atan2((px * px + py * py).sqrt() - self.major_radius) / pi) * 0.5;
+        Vec2 { x, y }
+    }
+
+    fn intercept(
+        &self,
+        stats: &mut RenderStats,
+        ray: &Ray,
+        tmin: Float,
+        tmax: &mut Float,
+        _any: bool,
+        oid: &mut usize,
+    ) -> bool {
+        stats.count(|s| s.num_intersects_torus += 1);
+        /*
+         * In the frame of the torus, with the axis as z, the surface is
+         * (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2): a quartic in t once
+         * p = o + t d. It is solved in f64, within the bounding sphere.
+         */
+        let (e1, e2) = get_perpendicular_frame(self.axis);
+        let local = |v: Vec3| [v.dot(e1) as f64, v.dot(e2) as f64, v.dot(self.axis) as f64];
+        let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+        let mut o = local(ray.orig - self.center);
+        let d = local(ray.dir);
+        let big_r = self.major_radius as f64;
+        let r = self.minor_radius as f64;
+
+        let dd = dot(d, d);
+        let half_b = dot(o, d);
+        let c = dot(o, o) - (big_r + r) * (big_r + r);
+        let delta = half_b * half_b - dd * c;
+        if delta < 0.0 {
+            return false;
+        }
+        let delta_sqrt = delta.sqrt();
+        let lo = ((-half_b - delta_sqrt) / dd).max(tmin as f64);
+        let hi = ((-half_b + delta_sqrt) / dd).min(*tmax as f64);
+        if lo >= hi {
+            return false;
+        }
+        // from where the ray enters the sphere, for smaller coefficients
+        for (oi, di) in o.iter_mut().zip(d) {
+            *oi += di * lo;
+        }
+
+        let od = dot(o, d);
+        let g = dot(o, o) + big_r * big_r - r * r;
+        let k = 4.0 * big_r * big_r;
+        let coeffs = [
+            dd * dd,
+            4.0 * dd * od,
+            4.0 * od * od + 2.0 * dd * g - k * (d[0] * d[0] + d[1] * d[1]),
+            4.0 * od * g - 2.0 * k * (o[0] * d[0] + o[1] * d[1]),
+            g * g - k * (o[0] * o[0] + o[1] * o[1]),
+        ];
+        let (roots, num_roots) = get_poly_roots(&coeffs, 0.0, hi - lo);
+        if num_roots == 0 {
+            return false;
+        }
+        let t = (lo + roots[0]) as Float;
+        if t <= tmin || t >= *tmax {
+            return false;
+        }
+        *tmax = t;
+        // negative inside the tube
+        *oid = if coeffs[4] < 0.0 {
+            TORUS_INSIDE
+        } else {
+            TORUS_OUTSIDE
+        };
+        true
     }
 }
 
@@ -199,16 +1327,60 @@ impl Object for Sphere {
     fn get_material_id(&self) -> usize {
         self.material_id
     }
+    fn get_velocity(&self) -> Vec3 {
+        self.velocity
+    }
+    fn sample_surface(&self, u: Float, v: Float) -> Option<(Point, Vec3)> {
+        let pi = std::f64::consts::PI as Float;
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * pi * v;
+        let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+        if self.is_ellipsoid() {
+            // not uniform over the area, but close for a mild scale
+            let point = self.center + normal * self.scale * self.radius;
+            return Some((point, self.get_outward_normal(point)));
+        }
+        Some((self.center + normal * self.radius, normal))
+    }
+    fn is_sampled_uniformly(&self) -> bool {
+        !self.is_ellipsoid()
+    }
+    fn get_area(&self) -> Float {
+        let pi = std::f64::consts::PI as Float;
+        if self.is_ellipsoid() {
+            // Knud Thomsen's approximation, within ~1%
+            let p = 1.6075;
+            let a = self.scale * self.radius;
+            let sum = (a.x * a.y).powf(p) + (a.x * a.z).powf(p) + (a.y * a.z).powf(p);
+            return 4.0 * pi * (sum / 3.0).powf(1.0 / p);
+        }
+        4.0 * pi * self.radius * self.radius
+    }
+    fn get_aabb(&self) -> Option<AABB> {
+        let r = self.scale * self.radius;
+        Some(AABB {
+            p_min: self.center - r,
+            p_max: self.center + r,
+        })
+    }
     fn display(&self) {
         println!("sphere: {:?} radius={:?}", self.center, self.radius);
     }
-    fn get_normal(&self, point: Point, _oid: usize) -> Vec3 {
-        let normal = point - self.center;
-        normal / self.radius
+    fn get_normal(&self, point: Point, oid: usize) -> Vec3 {
+        let normal = self.get_outward_normal(point);
+        if oid == SPHERE_INSIDE {
+            normal * -1.0
+        } else {
+            normal
+        }
+    }
+    fn is_inside_hit(&self, oid: usize) -> bool {
+        oid == SPHERE_INSIDE
     }
-    fn get_texture_2d(&self, point: Point) -> Vec2 {
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
         let pi = std::f64::consts::PI as Float;
-        let v = (point - self.center) / self.radius;
+        let v = (point - self.center) / self.scale / self.radius;
         let x = (1.0 + v.y.atan2(v.x) / pi) * 0.5;
         let y = v.z.acos() / pi;
         Vec2 { x, y }
@@ -221,17 +1393,26 @@ impl Object for Sphere {
         tmin: Float,
         tmax: &mut Float,
         _any: bool,
-        _oid: &mut usize,
+        oid: &mut usize,
     ) -> bool {
-        stats.num_intersects_sphere += 1;
-        let a = ray.dir.dot(ray.dir);
-        let v0 = ray.orig - self.center;
-        let half_b = ray.dir.dot(v0);
-        let v1 = self.center - ray.orig;
-        let c = v1.dot(v1) - self.radius * self.radius;
+        stats.count(|s| s.num_intersects_sphere += 1);
+        let center = self.center + self.velocity * ray.time;
+        /*
+         * An ellipsoid is intersected as a plain sphere in the space where
+         * its scale is undone. The transform is linear, t is unchanged.
+         */
+        let dir = ray.dir / self.scale;
+        let a = dir.dot(dir);
+        let v0 = (ray.orig - center) / self.scale;
+        let half_b = dir.dot(v0);
+        let r2 = self.radius * self.radius;
+        let c = v0.dot(v0) - r2;
 
+        // quick reject: origin outside the sphere and center behind the ray
+        if c > 0.0 && half_b > 0.0 {
+            return false;
+        }
         let delta = half_b * half_b - a * c;
-
         if delta < 0.0 {
             return false;
         }
@@ -239,12 +1420,14 @@ impl Object for Sphere {
         let t1 = (-half_b - delta_sqrt) / a;
         let t2 = (-half_b + delta_sqrt) / a;
 
-        let t_vals = [t1, t2];
-        if let Some(t_opt) = t_vals.iter().find(|&&t| t > tmin && t < *tmax) {
-            *tmax = *t_opt;
-            return true;
+        // the far root only when the ray starts inside the sphere
+        for (t, id) in [(t1, SPHERE_OUTSIDE), (t2, SPHERE_INSIDE)] {
+            if t > tmin && t < *tmax {
+                *tmax = t;
+                *oid = id;
+                return true;
+            }
         }
-
         false
     }
 }
@@ -253,19 +1436,62 @@ impl Object for Triangle {
     fn get_material_id(&self) -> usize {
         self.material_id
     }
+    fn sample_surface(&self, u: Float, v: Float) -> Option<(Point, Vec3)> {
+        let su = u.sqrt();
+        let b0 = 1.0 - su;
+        let b1 = v * su;
+        let p = self.points[0] * b0 + self.points[1] * b1 + self.points[2] * (1.0 - b0 - b1);
+        Some((p, self.get_normal(p, 0)))
+    }
+    fn get_area(&self) -> Float {
+        let edge1 = self.points[1] - self.points[0];
+        let edge2 = self.points[2] - self.points[0];
+        edge1.cross(edge2).norm() / 2.0
+    }
+    fn get_aabb(&self) -> Option<AABB> {
+        Some(AABB::from_triangle(self))
+    }
     fn display(&self) {
         println!(
             "triangle: {:?} {:?} {:?}",
             self.points[0], self.points[1], self.points[2]
         );
     }
-    fn get_normal(&self, _point: Point, _oid: usize) -> Vec3 {
+    fn get_normal(&self, point: Point, _oid: usize) -> Vec3 {
+        if let Some(normals) = self.normals {
+            let b = self.barycentric(point);
+            let n = normals[0] * b[0] + normals[1] * b[1] + normals[2] * b[2];
+            if n.norm() > EPSILON {
+                return n.normalize();
+            }
+        }
         let edge1 = self.points[1] - self.points[0];
         let edge2 = self.points[2] - self.points[0];
         edge1.cross(edge2).normalize()
     }
-    fn get_texture_2d(&self, _point: Point) -> Vec2 {
-        Vec2 { x: 0.0, y: 0.0 }
+    fn get_texture_2d(&self, point: Point, _oid: usize) -> Vec2 {
+        match self.uvs {
+            Some(uvs) => {
+                let b = self.barycentric(point);
+                Vec2 {
+                    x: uvs[0].x * b[0] + uvs[1].x * b[1] + uvs[2].x * b[2],
+                    y: uvs[0].y * b[0] + uvs[1].y * b[1] + uvs[2].y * b[2],
+                }
+            }
+            None => Vec2 { x: 0.0, y: 0.0 },
+        }
+    }
+    fn get_ao(&self, point: Point, _oid: usize) -> Float {
+        match self.ao {
+            Some(ao) => {
+                let b = self.barycentric(point);
+                ao[0] * b[0] + ao[1] * b[1] + ao[2] * b[2]
+            }
+            None => 1.0,
+        }
+    }
+    fn get_tangents(&self, _point: Point, _oid: usize) -> Option<[Vec3; 2]> {
+        self.tangents
     }
 
     // cf wikipedia
@@ -278,7 +1504,7 @@ impl Object for Triangle {
         _any: bool,
         _oid: &mut usize,
     ) -> bool {
-        stats.num_intersects_triangle += 1;
+        stats.count(|s| s.num_intersects_triangle += 1);
         let edge1 = self.points[1] - self.points[0];
         let edge2 = self.points[2] - self.points[0];
         let h = ray.dir.cross(edge2);
@@ -316,14 +1542,27 @@ impl Object for Mesh {
     fn get_material_id(&self) -> usize {
         self.material_id
     }
+    // from the materials of the model file
+    fn get_face_material_id(&self, oid: usize) -> usize {
+        self.triangles[oid].material_id
+    }
+    fn get_aabb(&self) -> Option<AABB> {
+        self.bvh.nodes.first().map(|node| node.aabb)
+    }
     fn display(&self) {
         println!("mesh: n={:?}", self.triangles.len());
     }
-    fn get_normal(&self, _point: Point, oid: usize) -> Vec3 {
-        self.triangles[oid].get_normal(_point, 0)
+    fn get_normal(&self, point: Point, oid: usize) -> Vec3 {
+        self.triangles[oid].get_normal(point, 0)
+    }
+    fn get_texture_2d(&self, point: Point, oid: usize) -> Vec2 {
+        self.triangles[oid].get_texture_2d(point, 0)
+    }
+    fn get_ao(&self, point: Point, oid: usize) -> Float {
+        self.triangles[oid].get_ao(point, 0)
     }
-    fn get_texture_2d(&self, _point: Point) -> Vec2 {
-        Vec2 { x: 0.0, y: 0.0 }
+    fn get_tangents(&self, point: Point, oid: usize) -> Option<[Vec3; 2]> {
+        self.triangles[oid].get_tangents(point, 0)
     }
 
     fn intercept(
@@ -335,6 +1574,109 @@ impl Object for Mesh {
         any: bool,
         oid: &mut usize,
     ) -> bool {
-        self.aabb.intercept(stats, ray, tmin, tmax, any, oid)
+        self.bvh.intercept(stats, ray, tmin, tmax, any, oid)
+    }
+}
+
+impl Transformable for Sphere {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let s = t.get_uniform_scale("sphere")?;
+        if t.is_rotated() && !is_unit_scale(&self.scale) {
+            return Err(
+                "ellipsoid with a transform rotation, its axes stay the world's".to_owned(),
+            );
+        }
+        let m = t.to_matrix();
+        self.center = m.transform_point(self.center);
+        self.velocity = m.transform_vector(self.velocity);
+        self.radius *= s;
+        Ok(())
+    }
+}
+
+impl Transformable for Plane {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        if self.is_bounded() && (t.is_rotated() || !is_unit_scale(&t.scale)) {
+            return Err("bounded plane with a transform other than translate".to_owned());
+        }
+        let m = t.to_matrix();
+        self.point = m.transform_point(self.point);
+        self.normal = m.transform_normal(self.normal);
+        Ok(())
+    }
+}
+
+impl Transformable for Disk {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let s = t.get_uniform_scale("disk")?;
+        let m = t.to_matrix();
+        self.center = m.transform_point(self.center);
+        self.normal = m.transform_normal(self.normal);
+        self.radius *= s;
+        Ok(())
+    }
+}
+
+impl Transformable for Cylinder {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let s = t.get_uniform_scale("cylinder")?;
+        let m = t.to_matrix();
+        self.base = m.transform_point(self.base);
+        self.axis = m.transform_vector(self.axis);
+        self.radius *= s;
+        self.height *= s;
+        Ok(())
+    }
+}
+
+impl Transformable for Torus {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let s = t.get_uniform_scale("torus")?;
+        let m = t.to_matrix();
+        self.center = m.transform_point(self.center);
+        self.axis = m.transform_vector(self.axis);
+        self.major_radius *= s;
+        self.minor_radius *= s;
+        Ok(())
+    }
+}
+
+/*
+ * The box stays a box: scaled about its center and rotated by its own
+ * rotation, which a rotated box cannot combine with another one.
+ */
+impl Transformable for Cuboid {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        if self.is_rotated() && (t.is_rotated() || t.get_uniform_scale("box").is_err()) {
+            return Err("rotated box with a transform rotation or non-uniform scale".to_owned());
+        }
+        let s = t.scale;
+        let half = (self.p_max - self.p_min) * Vec3::new(s.x.abs(), s.y.abs(), s.z.abs()) / 2.0;
+        let center = t.to_matrix().transform_point(self.get_center());
+        self.p_min = center - half;
+        self.p_max = center + half;
+        if t.is_rotated() {
+            self.rotation = t.rotate_xyz;
+        }
+        Ok(())
+    }
+}
+
+impl Transformable for Triangle {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let m = t.to_matrix();
+        self.points = self.points.map(|p| m.transform_point(p));
+        self.normals = self
+            .normals
+            .map(|normals| normals.map(|n| m.transform_normal(n)));
+        Ok(())
+    }
+}
+
+impl Transformable for Quad {
+    fn transform(&mut self, t: &Transform) -> Result<(), String> {
+        let m = t.to_matrix();
+        self.points = self.points.map(|p| m.transform_point(p));
+        Ok(())
     }
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::ops::{Add, AddAssign, Div, Mul, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
 
 pub type Float = f32;
 pub const EPSILON: Float = 1e-6;
@@ -11,13 +11,18 @@ fn u128_fold(v: u128) -> u64 {
 
 // wyhash
 // tried using the crate nanorand::WyRnd but this resulting in 5% degradation
-fn fast_rand(rnd_state: &mut u64) -> u64 {
+pub fn fast_rand(rnd_state: &mut u64) -> u64 {
     *rnd_state = (*rnd_state).wrapping_add(0x60bee2bee120fc15);
     let mut tmp = *rnd_state as u128 * 0xa3b195354a39b70d;
     tmp = u128_fold(tmp) as u128 * 0x1b03738712fad5c9;
     u128_fold(tmp)
 }
 
+// uniform in 0..1
+pub fn fast_rand_float(rnd_state: &mut u64) -> Float {
+    fast_rand(rnd_state) as Float / u64::MAX as Float
+}
+
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
 pub struct Vec3 {
     pub x: Float,
@@ -25,7 +30,7 @@ pub struct Vec3 {
     pub z: Float,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -101,6 +106,36 @@ impl Mul<Float> for Vec3 {
     }
 }
 
+// scalar on the left, eg. 2.0 * v
+impl Mul<Vec3> for Float {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        rhs * self
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Vec3 {
+        Vec3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+impl Mul<Vec3> for Vec3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x * rhs.x,
+            y: self.y * rhs.y,
+            z: self.z * rhs.z,
+        }
+    }
+}
+
 impl AddAssign<Vec3> for Vec3 {
     fn add_assign(&mut self, other: Vec3) {
         *self = Vec3 {
@@ -135,7 +170,17 @@ impl Vec3 {
         Vec3::new(0.0, 0.0, 1.0)
     }
     pub fn norm(self) -> Float {
-        self.dot(self).sqrt()
+        self.length_squared().sqrt()
+    }
+    // no sqrt, eg. for an inverse-square falloff
+    pub fn length_squared(self) -> Float {
+        self.dot(self)
+    }
+    pub fn distance(self, other: Vec3) -> Float {
+        (other - self).norm()
+    }
+    pub fn is_finite(self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
     }
     pub fn dot(self, rhs: Vec3) -> Float {
         self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
@@ -148,6 +193,49 @@ impl Vec3 {
     pub fn reflect(self, normal: Vec3) -> Self {
         self - normal * self.dot(normal) * 2.0
     }
+    /*
+     * Snell's law: self and normal are unit vectors, with the normal facing
+     * the incoming ray; eta is the ratio of the indices n_from / n_to.
+     * None on total internal reflection.
+     */
+    pub fn refract(self, normal: Vec3, eta: Float) -> Option<Self> {
+        let cos_i = -self.dot(normal);
+        let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+        if k < 0.0 {
+            return None;
+        }
+        Some(self * eta + normal * (eta * cos_i - k.sqrt()))
+    }
+    // self at t = 0, other at t = 1
+    pub fn lerp(self, other: Vec3, t: Float) -> Vec3 {
+        self * (1.0 - t) + other * t
+    }
+    /*
+     * Spherical interpolation between the directions a and b: the result is
+     * a unit vector turning at constant speed along the great circle.
+     * Opposite directions turn around an arbitrary perpendicular axis.
+     */
+    pub fn slerp(a: Vec3, b: Vec3, t: Float) -> Vec3 {
+        let a = a.normalize();
+        let b = b.normalize();
+        let cos = a.dot(b).clamp(-1.0, 1.0);
+        if cos > 1.0 - 1e-6 {
+            return Vec3::lerp(a, b, t).normalize();
+        }
+        // unit vector perpendicular to a in the plane of rotation
+        let perp = if cos < -1.0 + 1e-6 {
+            let axis = if a.x.abs() < 0.9 {
+                Vec3::unity_x()
+            } else {
+                Vec3::unity_y()
+            };
+            a.cross(axis).normalize()
+        } else {
+            (b - a * cos).normalize()
+        };
+        let theta = cos.acos() * t;
+        a * theta.cos() + perp * theta.sin()
+    }
     pub fn cross(self, rhs: Vec3) -> Vec3 {
         Vec3 {
             x: self.y * rhs.z - self.z * rhs.y,
@@ -190,13 +278,17 @@ impl Vec3 {
         };
         self.multiply(m)
     }
+    /*
+     * Uniform over the unit sphere: the points of the unit ball, drawn
+     * from the cube around it, are projected onto the sphere.
+     */
     pub fn gen_rnd_sphere(rnd_state: &mut u64) -> Self {
         let max = u64::MAX as Float;
         loop {
             let v = Vec3 {
-                x: fast_rand(rnd_state) as Float / max - 0.5,
-                y: fast_rand(rnd_state) as Float / max - 0.5,
-                z: fast_rand(rnd_state) as Float / max - 0.5,
+                x: 2.0 * fast_rand(rnd_state) as Float / max - 1.0,
+                y: 2.0 * fast_rand(rnd_state) as Float / max - 1.0,
+                z: 2.0 * fast_rand(rnd_state) as Float / max - 1.0,
             };
 
             let n = v.norm();
@@ -206,3 +298,96 @@ impl Vec3 {
         }
     }
 }
+
+/*
+ * Affine transform, column-major like Matrix3: the last column is the
+ * translation and the last row stays 0 0 0 1. `a * b` applies b first.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    mat: [Float; 16],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        Matrix4::from_columns(
+            [Vec3::unity_x(), Vec3::unity_y(), Vec3::unity_z()],
+            Vec3::zero(),
+        )
+    }
+    fn from_columns(axes: [Vec3; 3], translation: Vec3) -> Self {
+        let mut mat = [0.0; 16];
+        for (j, c) in axes.iter().chain([&translation]).enumerate() {
+            mat[4 * j..4 * j + 3].copy_from_slice(&[c.x, c.y, c.z]);
+        }
+        mat[15] = 1.0;
+        Matrix4 { mat }
+    }
+    // the columns of a full 4x4 matrix, as glTF stores its node transforms
+    pub fn from_cols_array(cols: [[Float; 4]; 4]) -> Self {
+        let mut mat = [0.0; 16];
+        for (j, col) in cols.iter().enumerate() {
+            mat[4 * j..4 * j + 4].copy_from_slice(col);
+        }
+        Matrix4 { mat }
+    }
+    pub fn translation(t: Vec3) -> Self {
+        Matrix4::from_columns([Vec3::unity_x(), Vec3::unity_y(), Vec3::unity_z()], t)
+    }
+    pub fn scaling(s: Vec3) -> Self {
+        let axes = [
+            Vec3::new(s.x, 0.0, 0.0),
+            Vec3::new(0.0, s.y, 0.0),
+            Vec3::new(0.0, 0.0, s.z),
+        ];
+        Matrix4::from_columns(axes, Vec3::zero())
+    }
+    // radians about x, then y, then z, as v.rotx(x).roty(y).rotz(z)
+    pub fn rotation_xyz(angles: Vec3) -> Self {
+        let rotate = |v: Vec3| v.rotx(angles.x).roty(angles.y).rotz(angles.z);
+        let axes = [Vec3::unity_x(), Vec3::unity_y(), Vec3::unity_z()].map(rotate);
+        Matrix4::from_columns(axes, Vec3::zero())
+    }
+    fn get(&self, i: usize, j: usize) -> Float {
+        self.mat[i + 4 * j]
+    }
+    pub fn transform_point(&self, p: Point) -> Point {
+        self.transform_vector(p) + Vec3::new(self.get(0, 3), self.get(1, 3), self.get(2, 3))
+    }
+    // no translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let row = |i: usize| self.get(i, 0) * v.x + self.get(i, 1) * v.y + self.get(i, 2) * v.z;
+        Vec3::new(row(0), row(1), row(2))
+    }
+    /*
+     * Normals go through the inverse transpose, which is the cofactor
+     * matrix up to the determinant: only its sign matters once normalized.
+     */
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let col = |j: usize| Vec3::new(self.get(0, j), self.get(1, j), self.get(2, j));
+        let [c0, c1, c2] = [col(0), col(1), col(2)];
+        let cofactors = [c1.cross(c2), c2.cross(c0), c0.cross(c1)];
+        let cofactor = Matrix4::from_columns(cofactors, Vec3::zero());
+        let det = c0.dot(c1.cross(c2));
+        let v = cofactor.transform_vector(n);
+        // a degenerate normal stays zero rather than becoming NaN
+        if v == Vec3::zero() {
+            return v;
+        }
+        let v = v.normalize();
+        if det < 0.0 { -v } else { v }
+    }
+}
+
+impl Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut mat = [0.0; 16];
+        for i in 0..4 {
+            for j in 0..4 {
+                mat[i + 4 * j] = (0..4).map(|k| self.get(i, k) * rhs.get(k, j)).sum();
+            }
+        }
+        Matrix4 { mat }
+    }
+}
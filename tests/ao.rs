@@ -0,0 +1,58 @@
+use rayflex::ao::AO_NUM_SAMPLES;
+use rayflex::ao::bake_vertex_ao;
+use rayflex::ao::get_ao_distance;
+use rayflex::ao::load_ao_cache;
+use rayflex::ao::save_ao_cache;
+use rayflex::three_d::Mesh;
+use rayflex::three_d::Object;
+use rayflex::three_d::Plane;
+use rayflex::three_d::Triangle;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+
+#[test]
+fn bake_open_and_covered() {
+    let p = [
+        Point::new(0.0, 0.0, 0.0),
+        Point::new(1.0, 0.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+    ];
+    let mesh = Mesh::new(vec![Triangle::new(p, 0).unwrap()], 0);
+    let normals = [Vec3::unity_z(); 3];
+    assert!((get_ao_distance(&p) - 0.1 * Float::sqrt(2.0)).abs() < 1e-5);
+
+    // nothing around: fully open
+    let ao = bake_vertex_ao(&[], &mesh, &p, &normals, 1.0, AO_NUM_SAMPLES);
+    assert_eq!(ao, vec![1.0; 3]);
+
+    // a ceiling closer than the occlusion distance hides the whole hemisphere
+    let ceiling = Plane::new(Point::new(0.0, 0.0, 0.01), Vec3::unity_z() * -1.0, 0).unwrap();
+    let objects: Vec<Arc<dyn Object + Send + Sync>> = vec![Arc::new(ceiling)];
+    let ao = bake_vertex_ao(&objects, &mesh, &p, &normals, 1.0, AO_NUM_SAMPLES);
+    assert_eq!(ao, vec![0.0; 3]);
+
+    // and is out of reach with a shorter distance
+    let ao = bake_vertex_ao(&objects, &mesh, &p, &normals, 0.005, AO_NUM_SAMPLES);
+    assert_eq!(ao, vec![1.0; 3]);
+}
+
+#[test]
+fn ao_cache_round_trip() {
+    let path = std::env::temp_dir().join("rayflex-ao-round-trip.ao");
+    let ao = vec![0.0, 0.25, 1.0];
+    save_ao_cache(&path, &ao).unwrap();
+    assert_eq!(load_ao_cache(&path).unwrap(), ao);
+
+    // the caches written before the version 2 of the format
+    let mut data = std::fs::read(&path).unwrap();
+    data[0..4].copy_from_slice(b"RFAO");
+    std::fs::write(&path, data).unwrap();
+    let err = load_ao_cache(&path).unwrap_err();
+    assert!(err.to_string().contains("version"), "{err}");
+
+    std::fs::write(&path, b"RFA2").unwrap();
+    assert!(load_ao_cache(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
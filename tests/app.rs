@@ -0,0 +1,27 @@
+use rayflex::app::AppSettings;
+
+#[test]
+fn app_settings_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join("rayflex-settings");
+    let file = dir.join("rayflex").join("settings.json");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // the directory is created on the first save
+    let settings = AppSettings::default();
+    settings.save(&file)?;
+    assert_eq!(AppSettings::load(&file)?, settings);
+
+    // keys missing from an older file keep their defaults
+    std::fs::write(&file, r#"{ "width": 320, "tonemap": "Reinhard" }"#)?;
+    let settings = AppSettings::load(&file)?;
+    assert_ne!(settings, AppSettings::default());
+    let json = serde_json::to_value(&settings)?;
+    assert_eq!(json["width"], 320);
+    assert_eq!(json["tonemap"], "Reinhard");
+    assert_eq!(json["scene_file"], "scenes/cornell-box.json");
+
+    std::fs::write(&file, "{ not json")?;
+    assert!(AppSettings::load(&file).is_err());
+    assert!(AppSettings::load(&dir.join("missing.json")).is_err());
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use rayflex::camera::Camera;
+use rayflex::camera::CameraKeyframe;
+use rayflex::camera::CameraPath;
+use rayflex::camera::PathInterpolation;
+use rayflex::camera::Projection;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+
+#[test]
+fn footprint_grows_with_distance() {
+    let camera = Camera::new(
+        Point::zero(),
+        Point::new(0.0, 0.0, -1.0),
+        Vec3::unity_y(),
+        60.0,
+        1.0,
+    );
+    let du = 1.0 / 100.0;
+    let dv = 1.0 / 100.0;
+    let (ray, footprint) = camera.get_ray_footprint(0.1, -0.2, du, dv);
+
+    let near = footprint.at(1.0);
+    let far = footprint.at(10.0);
+    assert!(near.x > 0.0 && near.y > 0.0);
+    assert!(far.x > near.x && far.y > near.y);
+
+    // the footprint matches the distance to the neighboring pixel ray
+    let next = camera.get_ray(0.1 + du, -0.2);
+    let t = 10.0;
+    let d = (next.orig + next.dir * t) - (ray.orig + ray.dir * t);
+    assert!((d.norm() - far.x).abs() < 1e-4);
+}
+
+#[test]
+fn aperture_blades() {
+    let mut camera = Camera::new(
+        Point::zero(),
+        Point::new(0.0, 0.0, -1.0),
+        Vec3::unity_y(),
+        60.0,
+        1.0,
+    );
+    let pi = std::f32::consts::PI;
+    let n = 6;
+    // distance from the center to the edge of the hexagon along a direction
+    let max_dot = |x: f32, y: f32| {
+        (0..n)
+            .map(|k| {
+                let a = 2.0 * pi * (k as f32 + 0.5) / n as f32;
+                x * a.cos() + y * a.sin()
+            })
+            .fold(f32::MIN, f32::max)
+    };
+    let apothem = (pi / n as f32).cos();
+
+    let samples = 32;
+    let mut outside = 0;
+    for i in 0..samples {
+        for j in 0..samples {
+            let u = (i as f32 + 0.5) / samples as f32;
+            let v = (j as f32 + 0.5) / samples as f32;
+            let p = camera.sample_aperture(u, v);
+            assert!(p.x * p.x + p.y * p.y <= 1.0 + 1e-4);
+            if max_dot(p.x, p.y) > apothem + 1e-4 {
+                outside += 1;
+            }
+        }
+    }
+    // the disk spills over the hexagon edges
+    assert!(outside > 0);
+
+    camera.aperture_blades = n;
+    for i in 0..samples {
+        for j in 0..samples {
+            let u = (i as f32 + 0.5) / samples as f32;
+            let v = (j as f32 + 0.5) / samples as f32;
+            let p = camera.sample_aperture(u, v);
+            assert!(max_dot(p.x, p.y) <= apothem + 1e-4);
+        }
+    }
+}
+
+#[test]
+fn camera_up_fallback() {
+    let basis_ok = |camera: &Camera| {
+        let u = camera.screen_u.normalize();
+        let v = camera.screen_v.normalize();
+        assert!(u.is_finite() && v.is_finite());
+        assert!(u.dot(v).abs() < 1e-5);
+        assert!(u.dot(camera.dir).abs() < 1e-5);
+        assert!(v.dot(camera.dir).abs() < 1e-5);
+    };
+    let look_at = Point::new(0.0, 0.0, -1.0);
+
+    // zero and parallel up vectors still give a valid basis
+    let camera = Camera::new(Point::zero(), look_at, Vec3::zero(), 60.0, 1.0);
+    basis_ok(&camera);
+    let camera = Camera::new(Point::zero(), look_at, Vec3::unity_z(), 60.0, 1.0);
+    basis_ok(&camera);
+
+    // a slightly tilted up gives the same basis as the exact one
+    let exact = Camera::new(Point::zero(), look_at, Vec3::unity_y(), 60.0, 1.0);
+    let tilted = Camera::new(Point::zero(), look_at, Vec3::new(0.0, 1.0, 0.1), 60.0, 1.0);
+    basis_ok(&tilted);
+    assert!((exact.screen_u - tilted.screen_u).norm() < 1e-5);
+    assert!((exact.screen_v - tilted.screen_v).norm() < 1e-5);
+
+    // up defaults to +z
+    let json = r#"{ "pos": { "x": 0, "y": 0, "z": 0 },
+                    "look_at": { "x": 1, "y": 0, "z": 0 }, "vfov": 60.0 }"#;
+    let camera: Camera = serde_json::from_str(json).unwrap();
+    assert!(camera.up == Vec3::unity_z());
+}
+
+#[test]
+fn lens_rays_converge_at_focus() {
+    let mut camera = Camera::new(
+        Point::zero(),
+        Point::new(0.0, 0.0, -1.0),
+        Vec3::unity_y(),
+        60.0,
+        1.0,
+    );
+    // a pinhole camera ignores the lens sample
+    let pinhole = camera.get_ray(0.1, -0.2);
+    let ray = camera.get_lens_ray(0.1, -0.2, 0.9, 0.3);
+    assert!(ray.orig == pinhole.orig && ray.dir == pinhole.dir);
+
+    camera.aperture = 0.5;
+    camera.focus_dist = 5.0;
+    let focus = pinhole.orig + pinhole.dir * 5.0;
+    for (lens_u, lens_v) in [(0.1, 0.2), (0.5, 0.9), (0.95, 0.05)] {
+        let ray = camera.get_lens_ray(0.1, -0.2, lens_u, lens_v);
+        assert!(ray.orig.norm() <= 0.25 + 1e-5);
+        // every lens ray goes through the same point of the focal plane
+        let d = ray.dir.normalize();
+        let t = (focus - ray.orig).dot(d);
+        assert!((ray.orig + d * t - focus).norm() < 1e-4);
+    }
+}
+
+#[test]
+fn orthographic_projection() {
+    let mut camera = Camera::new(
+        Point::zero(),
+        Point::new(0.0, 0.0, -1.0),
+        Vec3::unity_y(),
+        60.0,
+        2.0,
+    );
+    camera.projection = Projection::Orthographic { scale: 4.0 };
+    camera.init();
+
+    // parallel rays, the origins span scale x (scale * aspect)
+    let center = camera.get_ray(0.0, 0.0);
+    let corner = camera.get_ray(0.5, 0.5);
+    assert!(center.dir == camera.dir && corner.dir == camera.dir);
+    assert!(center.orig == Point::zero());
+    let d = corner.orig - center.orig;
+    assert!((d.dot(camera.screen_u.normalize()).abs() - 4.0).abs() < 1e-5);
+    assert!((d.dot(camera.screen_v.normalize()).abs() - 2.0).abs() < 1e-5);
+    assert!(corner.inv_dir.z == -1.0);
+
+    // the pixel footprint doesn't grow with distance
+    let footprint = camera.pixel_footprint(0.01, 0.01);
+    let near = footprint.at(1.0);
+    let far = footprint.at(10.0);
+    assert!((near.x - 0.08).abs() < 1e-5 && (near.y - 0.04).abs() < 1e-5);
+    assert!(near == far);
+
+    let json = r#"{ "pos": { "x": 0, "y": 0, "z": 0 },
+                    "look_at": { "x": 1, "y": 0, "z": 0 }, "vfov": 60.0,
+                    "projection": { "Orthographic": { "scale": 3.0 } } }"#;
+    let camera: Camera = serde_json::from_str(json).unwrap();
+    assert!(camera.projection == Projection::Orthographic { scale: 3.0 });
+}
+
+#[test]
+fn camera_path() {
+    let key = |x: f32, vfov: f32| CameraKeyframe {
+        pos: Point::new(x, 0.0, 0.0),
+        look_at: Point::new(x, 1.0, 0.0),
+        up: Vec3::unity_z(),
+        vfov,
+    };
+    let keyframes = vec![key(0.0, 40.0), key(1.0, 50.0), key(4.0, 60.0)];
+    assert!(CameraPath::new(vec![], PathInterpolation::Linear).is_err());
+
+    let linear = CameraPath::new(keyframes.clone(), PathInterpolation::Linear).unwrap();
+    assert!(linear.at(0.25).pos == Point::new(0.5, 0.0, 0.0));
+    assert!(linear.at(0.75).pos == Point::new(2.5, 0.0, 0.0));
+    assert!((linear.at(0.25).vfov - 45.0).abs() < 1e-5);
+
+    // the spline goes through every keyframe, but bends in between
+    let spline = CameraPath::new(keyframes, PathInterpolation::CatmullRom).unwrap();
+    for (t, x) in [(0.0, 0.0), (0.5, 1.0), (1.0, 4.0)] {
+        assert!((spline.at(t).pos.x - x).abs() < 1e-5);
+    }
+    assert!(spline.at(0.75).pos.x != 2.5);
+
+    let mut camera = Camera::new(Point::zero(), Vec3::unity_x(), Vec3::unity_z(), 60.0, 1.0);
+    spline.apply(&mut camera, 1.0);
+    assert!(camera.pos == Point::new(4.0, 0.0, 0.0) && camera.vfov == 60.0);
+    assert!((camera.dir - Vec3::unity_y()).norm() < 1e-6);
+
+    assert!("catmull-rom".parse::<PathInterpolation>() == Ok(PathInterpolation::CatmullRom));
+    assert!("cubic".parse::<PathInterpolation>().is_err());
+}
+
+#[test]
+fn camera_view_moves() {
+    let close = |a: Vec3, b: Vec3| (a - b).norm() < 1e-4;
+    let view = CameraKeyframe {
+        pos: Point::new(4.0, 0.0, 0.0),
+        look_at: Point::new(0.0, 0.0, 0.0),
+        up: Vec3::unity_z(),
+        vfov: 40.0,
+    };
+    let mut camera = Camera::new(Point::zero(), Vec3::unity_x(), Vec3::unity_z(), 60.0, 1.0);
+    camera.set_view(view);
+    assert!(camera.pos == view.pos && camera.vfov == 40.0);
+    assert!(camera.get_view().look_at == view.look_at);
+
+    // a quarter turn around up, at the same distance
+    let pi = std::f32::consts::PI;
+    let turned = view.orbit(pi / 2.0, 0.0);
+    assert!(close(turned.pos, Point::new(0.0, 4.0, 0.0)));
+    assert!(turned.look_at == view.look_at);
+    assert!(close(view.orbit(2.0 * pi, 0.0).pos, view.pos));
+    // pitching up stops short of the pole
+    let above = view.orbit(0.0, pi);
+    assert!(above.pos.z < 4.0 && above.pos.z > 3.99);
+    assert!(above.pos.x > 0.0);
+    let raised = view.orbit(0.0, pi / 4.0);
+    assert!(close(
+        raised.pos,
+        Point::new(8.0_f32.sqrt(), 0.0, 8.0_f32.sqrt())
+    ));
+
+    // dolly: along the view direction, never onto look_at
+    assert!(close(view.dolly(0.5).pos, Point::new(2.0, 0.0, 0.0)));
+    assert!(view.dolly(0.0).pos.x > 0.0);
+
+    // pan: both points move, to the left of the image when the scene is dragged right
+    let moved = view.pan(0.5, 0.0);
+    assert!(close(moved.pos - view.pos, moved.look_at - view.look_at));
+    let left = Camera::new(view.pos, view.look_at, view.up, view.vfov, 1.0).screen_u;
+    assert!((moved.look_at - view.look_at).dot(left) > 0.0);
+    // half a view height at look_at
+    let height = 2.0 * 4.0 * (20.0_f32).to_radians().tan();
+    assert!(((moved.look_at - view.look_at).norm() - height / 2.0).abs() < 1e-4);
+    // dragged down, the camera rises
+    assert!(view.pan(0.0, 0.5).look_at.z > 0.0);
+}
+
+#[test]
+fn dispersion_scales_channels() {
+    let look_at = Point::new(0.0, 1.0, 0.0);
+    let mut camera = Camera::new(Point::zero(), look_at, Vec3::unity_z(), 60.0, 1.0);
+    assert!((0..3).all(|channel| camera.get_channel_scale(channel) == 1.0));
+    camera.dispersion = 0.02;
+    // red images larger than green, blue smaller
+    assert_eq!(camera.get_channel_scale(0), 1.02);
+    assert_eq!(camera.get_channel_scale(1), 1.0);
+    assert_eq!(camera.get_channel_scale(2), 0.98);
+}
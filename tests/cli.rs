@@ -3,35 +3,42 @@ use std::process::Command;
 
 #[test]
 fn scene_teapot() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l").arg("scenes/teapot.json").assert().success();
 
     Ok(())
 }
 #[test]
 fn scene_trolley() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l").arg("scenes/trolley.json").assert().success();
 
     Ok(())
 }
 #[test]
 fn scene_buddha() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l").arg("scenes/buddha.json").assert().success();
 
     Ok(())
 }
 #[test]
 fn scene_cow() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l").arg("scenes/cow.json").assert().success();
 
     Ok(())
 }
 #[test]
+fn scene_gltf_box() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l").arg("scenes/gltf-box.json").assert().success();
+
+    Ok(())
+}
+#[test]
 fn scene_sphere_box() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l")
         .arg("scenes/sphere-box.json")
         .assert()
@@ -41,7 +48,7 @@ fn scene_sphere_box() -> Result<(), Box<dyn std::error::Error>> {
 }
 #[test]
 fn scene_sphere_no_box() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l")
         .arg("scenes/sphere-nobox.json")
         .assert()
@@ -50,8 +57,406 @@ fn scene_sphere_no_box() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 #[test]
+fn scene_ellipsoid() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/ellipsoid.json")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn scene_boxes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l").arg("scenes/boxes.json").assert().success();
+
+    Ok(())
+}
+#[test]
+fn scene_environment() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/environment.json")
+        .arg("--integrator")
+        .arg("path")
+        .arg("--spp")
+        .arg("2")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn info() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    // the default scene.json doesn't exist: nothing gets loaded
+    let output = cmd.arg("--info").output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(&format!("rayflex {}", env!("CARGO_PKG_VERSION"))));
+    assert!(stdout.contains("float: f32"));
+    assert!(stdout.contains("num_threads: "));
+    assert!(!stdout.contains("loading scene"));
+
+    Ok(())
+}
+#[test]
+fn scene_disks() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l").arg("scenes/disks.json").assert().success();
+
+    Ok(())
+}
+#[test]
+fn scene_grid() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("--grid")
+        .arg("-l")
+        .arg("scenes/sphere-box.json")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("-- grid: "));
+
+    Ok(())
+}
+#[test]
+fn scene_reflectivity() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/reflectivity.json")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn scene_glass() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l").arg("scenes/glass.json").assert().success();
+
+    Ok(())
+}
+#[test]
+fn scene_dof() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l").arg("scenes/dof.json").assert().success();
+
+    Ok(())
+}
+#[test]
+fn edge_aa() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg("scenes/glass.json")
+        .arg("--edge-aa")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("edge-aa:"));
+
+    Ok(())
+}
+#[test]
+fn scene_ortho() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/ortho.json")
+        .arg("--camera")
+        .arg("1")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn scene_car_paint() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/car-paint.json")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn stats_only() -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::env::temp_dir().join("rayflex-stats-only.png");
+    let _ = std::fs::remove_file(&file);
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg("scenes/sphere-box.json")
+        .arg("--stats-only")
+        .arg("--img-file")
+        .arg(&file)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("num_rays_sampling"));
+    assert!(!file.exists());
+
+    Ok(())
+}
+#[test]
+fn unknown_image_format() -> Result<(), Box<dyn std::error::Error>> {
+    // refused before the render
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg("scenes/sphere-box.json")
+        .arg("--img-file")
+        .arg(std::env::temp_dir().join("rayflex-pic.xyz"))
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("unknown image format"), "{stderr}");
+
+    Ok(())
+}
+#[test]
+fn seeded_render_reproducible() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let render = |seed: &str, name: &str| -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let file = dir.join(name);
+        let mut cmd = Command::cargo_bin("rayflex")?;
+        cmd.arg("-l")
+            .arg("scenes/cornell-box.json")
+            .arg("-x")
+            .arg("32")
+            .arg("-y")
+            .arg("24")
+            .arg("--integrator")
+            .arg("path")
+            .arg("--spp")
+            .arg("4")
+            .arg("--seed")
+            .arg(seed)
+            .arg("--img-file")
+            .arg(&file)
+            .assert()
+            .success();
+        Ok(std::fs::read(file)?)
+    };
+    let first = render("42", "rayflex-seed-a.png")?;
+    assert!(first == render("42", "rayflex-seed-b.png")?);
+    assert!(first != render("43", "rayflex-seed-c.png")?);
+
+    Ok(())
+}
+#[test]
+fn animation_frames() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let path = dir.join("rayflex-camera-path.json");
+    std::fs::write(
+        &path,
+        r#"[ { "pos": [-3, 0, 0.8], "look_at": [2, 0, 0], "vfov": 50 },
+             { "pos": [-3, 2, 1.5], "look_at": [2, 0, 0], "vfov": 40 } ]"#,
+    )?;
+    let file = dir.join("rayflex-anim.png");
+    let frames = [3, 4, 5].map(|f| dir.join(format!("rayflex-anim_000{f}.png")));
+    frames.iter().for_each(|f| {
+        let _ = std::fs::remove_file(f);
+    });
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg("scenes/glass.json")
+        .arg("-x")
+        .arg("16")
+        .arg("-y")
+        .arg("12")
+        .arg("--frames")
+        .arg("3")
+        .arg("--frame-start")
+        .arg("3")
+        .arg("--camera-path")
+        .arg(&path)
+        .arg("--camera-interp")
+        .arg("catmull-rom")
+        .arg("--img-file")
+        .arg(&file)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    // the scene is loaded once for the whole sequence
+    assert_eq!(stdout.matches("lights[0]").count(), 1);
+    for f in &frames {
+        assert!(f.exists());
+        std::fs::remove_file(f)?;
+    }
+    assert!(!file.exists());
+
+    Ok(())
+}
+#[test]
+fn contact_sheet() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let file = dir.join("rayflex-batch.png");
+    let sheet = dir.join("rayflex-batch-sheet.png");
+    let _ = std::fs::remove_file(&sheet);
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg("scenes/glass.json")
+        .arg("-x")
+        .arg("16")
+        .arg("-y")
+        .arg("12")
+        .arg("--frames")
+        .arg("3")
+        .arg("--img-file")
+        .arg(&file)
+        .arg("--contact-sheet")
+        .arg(&sheet)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("3 thumbnails"));
+    // 2 x 2 cells
+    assert_eq!(image::image_dimensions(&sheet)?, (332, 360));
+    for f in 0..3 {
+        std::fs::remove_file(dir.join(format!("rayflex-batch_000{f}.png")))?;
+    }
+    std::fs::remove_file(&sheet)?;
+    Ok(())
+}
+
+#[test]
+fn aov_files() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let file = dir.join("rayflex-aov.png");
+    let aovs = ["depth", "normal"].map(|a| dir.join(format!("rayflex-aov_{a}.png")));
+    aovs.iter().for_each(|f| {
+        let _ = std::fs::remove_file(f);
+    });
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/glass.json")
+        .arg("--aov")
+        .arg("depth,normal")
+        .arg("--img-file")
+        .arg(&file)
+        .assert()
+        .success();
+    for f in &aovs {
+        assert_eq!(image::image_dimensions(f)?, (400, 300));
+        std::fs::remove_file(f)?;
+    }
+    std::fs::remove_file(&file)?;
+
+    Ok(())
+}
+#[test]
+fn matte_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let file = dir.join("rayflex-matte.png");
+    let matte = dir.join("rayflex-matte_matte.png");
+    let _ = std::fs::remove_file(&matte);
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/glass.json")
+        .arg("-x")
+        .arg("40")
+        .arg("-y")
+        .arg("30")
+        .arg("--matte")
+        .arg("sphere.0")
+        .arg("--img-file")
+        .arg(&file)
+        .assert()
+        .success();
+    let img = image::open(&matte)?;
+    assert_eq!(img.color(), image::ColorType::L8);
+    let pixels = img.to_luma8().into_raw();
+    assert_eq!(pixels.len(), 40 * 30);
+    // inside, outside and along the edge of the sphere
+    assert!(pixels.contains(&255) && pixels.contains(&0));
+    assert!(pixels.iter().any(|&p| p > 0 && p < 255));
+    std::fs::remove_file(&matte)?;
+    std::fs::remove_file(&file)?;
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/glass.json")
+        .arg("--matte")
+        .arg("sphere.9")
+        .arg("--img-file")
+        .arg(&file)
+        .assert()
+        .failure();
+
+    Ok(())
+}
+#[test]
+fn tonemap_and_exr() -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::env::temp_dir().join("rayflex-cli.exr");
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/car-paint.json")
+        .arg("--tonemap")
+        .arg("aces")
+        .arg("--img-file")
+        .arg(&file)
+        .assert()
+        .success();
+    assert!(file.exists());
+    std::fs::remove_file(&file)?;
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("--tonemap").arg("filmic").assert().failure();
+
+    Ok(())
+}
+#[test]
+fn scene_inside_sphere() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/inside-sphere.json")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn scene_cornell_box_area() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/cornell-box-area.json")
+        .arg("--shadow-samples")
+        .arg("4")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn scene_textured_quad() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/textured-quad.json")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn furnace_test() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd.arg("--furnace-test").output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("furnace test: ratio="));
+
+    Ok(())
+}
+#[test]
 fn scene_cornell_box() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("raymax")?;
+    let mut cmd = Command::cargo_bin("rayflex")?;
     cmd.arg("-l")
         .arg("scenes/cornell-box.json")
         .arg("-p")
@@ -61,3 +466,160 @@ fn scene_cornell_box() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+#[test]
+fn scene_unused_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["spheer.0"] = json["sphere.0"].clone();
+    let path = std::env::temp_dir().join("rayflex-unused-keys.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg(&path)
+        .arg("--warn-unused-keys")
+        .arg("-x")
+        .arg("16")
+        .arg("-y")
+        .arg("16")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("unused key 'spheer.0'"));
+    assert!(!stdout.contains("unused key 'sphere.0'"));
+
+    Ok(())
+}
+#[test]
+fn scene_malformed_mesh() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let obj = dir.join("rayflex-malformed.obj");
+    std::fs::write(
+        &obj,
+        "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 2 0 0\nv nan 0 1\nv 3 1e-9 0\n\
+         f 1 2 3\nf 1 2 4\nf 1 5 3\nf 1 2 6\n",
+    )?;
+    let data = std::fs::read_to_string("scenes/test.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["obj.0.path"] = serde_json::json!(obj);
+    let scene = dir.join("rayflex-malformed.json");
+    std::fs::write(&scene, serde_json::to_string(&json)?)?;
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg(&scene)
+        .arg("-x")
+        .arg("16")
+        .arg("-y")
+        .arg("16")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("skipped 3 malformed triangles"));
+
+    Ok(())
+}
+#[test]
+fn scene_missing_mesh() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let data = std::fs::read_to_string("scenes/test.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["obj.0.path"] = serde_json::json!("obj/does-not-exist.obj");
+    let scene = dir.join("rayflex-missing-mesh.json");
+    std::fs::write(&scene, serde_json::to_string(&json)?)?;
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd.arg("-l").arg(&scene).arg("--stats-only").output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("obj/does-not-exist.obj"));
+    assert!(!stderr.contains("panicked"));
+
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    let output = cmd
+        .arg("-l")
+        .arg(&scene)
+        .arg("--stats-only")
+        .arg("--skip-missing-meshes")
+        .arg("-x")
+        .arg("16")
+        .arg("-y")
+        .arg("16")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("obj/does-not-exist.obj"));
+    assert!(stdout.contains("skipping the mesh"));
+
+    Ok(())
+}
+#[test]
+fn scene_motion_blur() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/motion-blur.json")
+        .arg("--integrator")
+        .arg("path")
+        .arg("--spp")
+        .arg("4")
+        .arg("-x")
+        .arg("64")
+        .arg("-y")
+        .arg("48")
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn scene_cornell_box_spp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("rayflex")?;
+    cmd.arg("-l")
+        .arg("scenes/cornell-box.json")
+        .arg("--integrator")
+        .arg("path")
+        .arg("--sample-count")
+        .arg("4")
+        .arg("-x")
+        .arg("64")
+        .arg("-y")
+        .arg("64")
+        .assert()
+        .success();
+
+    Ok(())
+}
+#[test]
+fn bake_ao_cache() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let scene = dir.join("rayflex-bake-ao.json");
+    let cache = dir.join("rayflex-bake-ao.mesh.0.ao");
+    std::fs::copy("scenes/cow.json", &scene)?;
+    let _ = std::fs::remove_file(&cache);
+
+    let render = |bake: bool| -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("rayflex")?;
+        cmd.arg("-l")
+            .arg(&scene)
+            .arg("-x")
+            .arg("32")
+            .arg("-y")
+            .arg("32");
+        if bake {
+            cmd.arg("--bake-ao");
+        }
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    assert!(!render(false)?.contains("-- ao:"));
+    assert!(render(true)?.contains("-- ao: baked"));
+    assert!(cache.exists());
+    assert!(render(false)?.contains("-- ao: read"));
+
+    std::fs::remove_file(&cache)?;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use rayflex::color::Gradient;
+use rayflex::color::RGB;
+use rayflex::image::Image;
+
+#[test]
+fn rgb_lerp() {
+    let a = RGB::new(0.0, 0.5, 1.0);
+    let b = RGB::new(1.0, 0.5, 0.0);
+    assert!(RGB::lerp(a, b, 0.0) == a);
+    assert!(RGB::lerp(a, b, 1.0) == b);
+    assert!(RGB::lerp(a, b, 0.5) == RGB::new(0.5, 0.5, 0.5));
+}
+
+#[test]
+fn gradient_matches_sky() {
+    let cmax = RGB::new(1.0, 1.0, 1.0);
+    let cyan = RGB::new(0.4, 0.6, 0.9);
+    let g = Gradient::two_stops(cyan, cmax);
+    for i in 0..=16 {
+        let s = i as f32 / 16.0;
+        assert!(g.eval(s) == cmax * s + cyan * (1.0 - s));
+    }
+}
+
+#[test]
+fn gradient_stops() {
+    let r = RGB::new(1.0, 0.0, 0.0);
+    let g = RGB::new(0.0, 1.0, 0.0);
+    let b = RGB::new(0.0, 0.0, 1.0);
+    let ramp = Gradient::new(vec![(0.0, r), (0.5, g), (1.0, b)]);
+    assert!(ramp.eval(-1.0) == r);
+    assert!(ramp.eval(0.25) == RGB::new(0.5, 0.5, 0.0));
+    assert!(ramp.eval(0.5) == g);
+    assert!(ramp.eval(0.75) == RGB::new(0.0, 0.5, 0.5));
+    assert!(ramp.eval(2.0) == b);
+}
+
+#[test]
+fn rgb_small_negatives() {
+    // eg. a blend that rounds just below zero
+    let c = RGB::new(0.5, 0.5, 0.5) * RGB::new(-1e-7, 0.2, 1.0);
+    assert!(c.r < 0.0);
+    let mut sum = c / 2.0;
+    sum.add(&RGB::new(-1e-7, 0.0, 0.0));
+    assert!(sum.r < 0.0);
+    assert!(sum.clamp_non_negative() == RGB::new(0.0, 0.05, 0.25));
+    assert!(RGB::new(f32::NAN, 1.0, 0.0).clamp_non_negative() == RGB::new(0.0, 1.0, 0.0));
+
+    // the stored pixel is clamped
+    let mut image = Image::new(false, 1, 1);
+    image.push_pixel(0, 0, c);
+    assert!(image.get_hdr().lock().unwrap()[0] == c.clamp_non_negative());
+}
+
+#[test]
+fn rgb_clamp_luminance() {
+    let c = RGB::new(40.0, 20.0, 10.0);
+    let clamped = c.clamp_luminance(2.0);
+    assert!((clamped.luminance() - 2.0).abs() < 1e-5);
+    // the hue is kept
+    assert!((clamped.r / clamped.g - 2.0).abs() < 1e-5);
+    assert!((clamped.g / clamped.b - 2.0).abs() < 1e-5);
+    let dim = RGB::new(0.5, 0.5, 0.5);
+    assert!(dim.clamp_luminance(2.0) == dim);
+}
@@ -0,0 +1,40 @@
+use rayflex::distribution::Distribution1D;
+use rayflex::distribution::Distribution2D;
+
+#[test]
+fn distribution_1d() {
+    let d = Distribution1D::new(&[1.0, 3.0]);
+    let (x, pdf, idx) = d.sample(0.1);
+    assert_eq!(idx, 0);
+    assert!(x < 0.5);
+    assert!((pdf - 0.5).abs() < 1e-6);
+    let (x, pdf, idx) = d.sample(0.9);
+    assert_eq!(idx, 1);
+    assert!(x >= 0.5);
+    assert!((pdf - 1.5).abs() < 1e-6);
+}
+
+#[test]
+fn distribution_2d_favors_bright_pixels() {
+    // a dark 8x4 image with one bright "sun" pixel
+    let (w, h) = (8, 4);
+    let mut lum = vec![0.01; w * h];
+    lum[2 * w + 5] = 100.0;
+    let d = Distribution2D::new(&lum, w, h);
+
+    let n = 64;
+    let mut hits = 0;
+    for i in 0..n {
+        for j in 0..n {
+            let u = (i as f32 + 0.5) / n as f32;
+            let v = (j as f32 + 0.5) / n as f32;
+            let (x, y, pdf) = d.sample(u, v);
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            assert!((pdf - d.pdf(x, y)).abs() <= 1e-3 * pdf);
+            if (x * w as f32) as usize == 5 && (y * h as f32) as usize == 2 {
+                hits += 1;
+            }
+        }
+    }
+    assert!(hits as f32 / (n * n) as f32 > 0.9);
+}
@@ -0,0 +1,91 @@
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayflex::Ray;
+use rayflex::RenderStats;
+use rayflex::grid::UniformGrid;
+use rayflex::render::get_nearest_hit;
+use rayflex::three_d::Object;
+use rayflex::three_d::Plane;
+use rayflex::three_d::Sphere;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+
+fn get_random_objects(rng: &mut StdRng, n: usize) -> Vec<Arc<dyn Object + Send + Sync>> {
+    let mut objects: Vec<Arc<dyn Object + Send + Sync>> = vec![];
+    for _ in 0..n {
+        let center = Point::new(
+            rng.gen_range(-5.0..5.0),
+            rng.gen_range(-5.0..5.0),
+            rng.gen_range(-5.0..5.0),
+        );
+        let radius = rng.gen_range(0.05..0.6);
+        objects.push(Arc::new(Sphere::new(center, radius, 0).unwrap()));
+    }
+    let floor = Plane::new(Point::new(0.0, 0.0, -6.0), Vec3::unity_z(), 0).unwrap();
+    objects.push(Arc::new(floor));
+    objects
+}
+
+#[test]
+fn grid_matches_linear_scan() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let objects = get_random_objects(&mut rng, 500);
+    let grid = UniformGrid::new(&objects);
+    assert!(grid.get_res().iter().all(|&n| n > 1));
+
+    let mut stats = RenderStats::default();
+    let mut grid_stats = RenderStats::default();
+    let mut num_hits = 0;
+    for _ in 0..2000 {
+        // from outside and from inside the grid bounds
+        let orig = Point::new(
+            rng.gen_range(-8.0..8.0),
+            rng.gen_range(-8.0..8.0),
+            rng.gen_range(-8.0..8.0),
+        );
+        let dir = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let ray = Ray::new(orig, dir.normalize());
+        let hit = get_nearest_hit(&objects, &mut stats, &ray);
+        let grid_hit = grid.get_nearest_hit(&objects, &mut grid_stats, &ray);
+        match (hit, grid_hit) {
+            (Some(a), Some(b)) => {
+                assert_eq!(a.object, b.object);
+                assert!((a.t - b.t).abs() < 1e-4);
+                num_hits += 1;
+            }
+            (None, None) => {}
+            _ => panic!("grid and linear scan disagree on {:?}", ray.dir),
+        }
+    }
+    assert!(num_hits > 500);
+    if cfg!(feature = "stats") {
+        assert!(grid_stats.num_intersects_sphere * 10 < stats.num_intersects_sphere);
+    }
+}
+
+#[test]
+fn grid_occlusion() {
+    let a = Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, 0).unwrap();
+    let b = Sphere::new(Point::new(4.0, 0.0, 0.0), 1.0, 0).unwrap();
+    let objects: Vec<Arc<dyn Object + Send + Sync>> = vec![Arc::new(a), Arc::new(b)];
+    let grid = UniformGrid::new(&objects);
+    let mut stats = RenderStats::default();
+
+    let ray = Ray::new(Point::new(-3.0, 0.0, 0.0), Vec3::unity_x());
+    assert!(grid.is_occluded(&objects, &mut stats, &ray, 10.0, None));
+    // too short to reach the first sphere
+    assert!(!grid.is_occluded(&objects, &mut stats, &ray, 1.5, None));
+    // the second sphere blocks once the first one is skipped
+    assert!(grid.is_occluded(&objects, &mut stats, &ray, 10.0, Some(0)));
+    assert!(!grid.is_occluded(&objects, &mut stats, &ray, 5.0, Some(0)));
+
+    let miss = Ray::new(Point::new(-3.0, 2.0, 0.0), Vec3::unity_x());
+    assert!(!grid.is_occluded(&objects, &mut stats, &miss, Float::MAX, None));
+}
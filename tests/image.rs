@@ -0,0 +1,257 @@
+use rayflex::color::RGB;
+use rayflex::image::ContactSheet;
+use rayflex::image::GAMMA;
+use rayflex::image::GammaLut;
+use rayflex::image::Grade;
+use rayflex::image::Image;
+use rayflex::image::OutputFormat;
+use rayflex::image::ToneMap;
+use rayflex::image::expand_template;
+use rayflex::image::frame_file_name;
+use rayflex::image::gamma_encode;
+use rayflex::image::luminance_histogram;
+use rayflex::image::output_path;
+use rayflex::image::write_ppm;
+use std::path::Path;
+
+#[test]
+fn gamma_lut_matches_powf() {
+    let lut = GammaLut::new(GAMMA, 8);
+    let n = 100_000;
+    for i in 0..=n {
+        let v = i as f32 / n as f32;
+        let expected = (255.0 * gamma_encode(v, GAMMA)).clamp(0.0, 255.0) as i32;
+        let got = lut.encode(v) as i32;
+        assert!(
+            (got - expected).abs() <= 1,
+            "v={v} lut={got} powf={expected}"
+        );
+    }
+    assert_eq!(lut.encode(-1.0), 0);
+    assert_eq!(lut.encode(2.0), 255);
+}
+
+#[test]
+fn output_template() {
+    assert_eq!(
+        expand_template("{scene}_{frame:04}_{spp}.png", "cow", 7, 64),
+        "cow_0007_64.png"
+    );
+    assert_eq!(
+        expand_template("{other}-{frame}.png", "cow", 3, 1),
+        "{other}-3.png"
+    );
+    assert_eq!(expand_template("abc{def.png", "cow", 1, 1), "abc{def.png");
+    assert_eq!(
+        expand_template("x_{frame:04}_{oops.png", "cow", 1, 1),
+        "x_0001_{oops.png"
+    );
+
+    let dir = Path::new("out");
+    for frame in 0..2 {
+        let path = output_path(
+            Some(dir),
+            Path::new("pic.png"),
+            Some("anim_{frame:04}.png"),
+            Path::new("scenes/anim.json"),
+            frame,
+            1,
+        );
+        assert_eq!(path, dir.join(format!("anim_000{frame}.png")));
+    }
+    assert_eq!(
+        frame_file_name(Path::new("out/pic.png"), 12),
+        Path::new("out/pic_0012.png")
+    );
+    assert_eq!(frame_file_name(Path::new("pic"), 3), Path::new("pic_0003"));
+}
+
+#[test]
+fn histogram_skips_black() {
+    let pixels = [
+        RGB::zero(),
+        RGB::new(0.5, 0.5, 0.5),
+        RGB::new(0.5, 0.5, 0.5),
+        RGB::new(2.0, 2.0, 2.0),
+    ];
+    let h = luminance_histogram(&pixels, 14);
+    assert_eq!(h.bins.iter().sum::<u32>(), 3);
+    // one bin per stop from -10 EV: 0.5 is -1 EV, 2.0 is +1 EV
+    assert_eq!(h.bins[9], 2);
+    assert_eq!(h.bins[11], 1);
+    assert!((h.mean_ev + 1.0 / 3.0).abs() < 1e-4);
+    assert!((h.clipped - 1.0 / 3.0).abs() < 1e-4);
+}
+
+#[test]
+fn tonemap_curves() {
+    let dim = RGB::new(0.0, 0.25, 0.5);
+    assert!(ToneMap::Clamp.apply(dim) == dim);
+    let hot = RGB::new(4.0, 16.0, -1.0);
+    assert!(ToneMap::Clamp.apply(hot) == RGB::new(1.0, 1.0, 0.0));
+
+    for tonemap in [ToneMap::Reinhard, ToneMap::ACESFilmic] {
+        assert!(tonemap.apply(RGB::zero()).r.abs() < 1e-3);
+        // monotonic, and the highlights keep some detail below 1
+        let mut prev = -1.0;
+        for i in 0..=100 {
+            let v = tonemap.apply(RGB::new(i as f32 * 0.2, 0.0, 0.0)).r;
+            assert!(v >= prev && v <= 1.0);
+            prev = v;
+        }
+        assert!(tonemap.apply(RGB::new(4.0, 4.0, 4.0)).r < tonemap.apply(hot).g);
+    }
+    assert!("aces".parse::<ToneMap>() == Ok(ToneMap::ACESFilmic));
+    assert!("filmic".parse::<ToneMap>().is_err());
+}
+
+#[test]
+fn save_hdr_keeps_linear_values() {
+    let mut image = Image::new(true, 2, 1);
+    image.set_tonemap(ToneMap::Reinhard);
+    image.push_pixel(0, 0, RGB::new(4.0, 0.5, 0.0));
+    image.push_pixel(1, 0, RGB::new(0.0, 0.0, 12.5));
+
+    let file = std::env::temp_dir().join("rayflex-save-hdr.exr");
+    image.save_hdr(&file).unwrap();
+    let exr = ::image::open(&file).unwrap().to_rgb32f();
+    std::fs::remove_file(&file).unwrap();
+
+    assert_eq!(exr.dimensions(), (2, 1));
+    assert_eq!(exr.get_pixel(0, 0).0, [4.0, 0.5, 0.0]);
+    assert_eq!(exr.get_pixel(1, 0).0, [0.0, 0.0, 12.5]);
+}
+
+#[test]
+fn grade_adjustments() {
+    let c = RGB::new(0.6, 0.3, 0.1);
+    assert_eq!(Grade::default().apply(c), c);
+
+    let gray = Grade {
+        saturation: 0.0,
+        ..Default::default()
+    }
+    .apply(c);
+    assert!((gray.r - gray.g).abs() < 1e-6 && (gray.g - gray.b).abs() < 1e-6);
+    assert!((gray.r - c.luminance()).abs() < 1e-6);
+
+    // contrast pivots on mid-gray
+    let contrast = Grade {
+        contrast: 1.5,
+        ..Default::default()
+    };
+    let mid = RGB::new(0.18, 0.18, 0.18);
+    assert!((contrast.apply(mid).r - 0.18).abs() < 1e-6);
+    assert!(contrast.apply(c).r > c.r && contrast.apply(c).b < c.b);
+
+    let warm = Grade {
+        temperature: 1.0,
+        ..Default::default()
+    }
+    .apply(c);
+    assert!(warm.r > c.r && warm.b < c.b && warm.g == c.g);
+
+    // regrading the image re-encodes the pixels from the linear values
+    let mut image = Image::new(false, 1, 1);
+    image.push_pixel(0, 0, c);
+    let before = image.to_color_image().pixels[0];
+    image.grade(Grade {
+        saturation: 0.0,
+        ..Default::default()
+    });
+    let after = image.to_color_image().pixels[0];
+    assert_ne!(before, after);
+    assert_eq!(after.r(), after.g());
+    assert_eq!(image.get_hdr().lock().unwrap()[0], c);
+}
+
+#[test]
+fn accumulate_pixel_samples() {
+    let mut image = Image::new(false, 2, 1);
+    assert_eq!(image.get_sample_count(0, 0), 0);
+    // a pass of 1 sample, then one of 3: the mean weighs them by their counts
+    image.accumulate_pixel(0, 0, RGB::new(4.0, 0.0, 0.0), 1);
+    let mean = image.accumulate_pixel(0, 0, RGB::new(0.0, 2.0, 0.0), 3);
+    assert_eq!(mean, RGB::new(1.0, 1.5, 0.0));
+    assert_eq!(image.get_sample_count(0, 0), 4);
+    assert_eq!(image.get_hdr().lock().unwrap()[0], mean);
+    assert!(image.get_hdr().lock().unwrap()[1].is_zero());
+
+    // pushing starts the pixel over
+    image.push_pixel(0, 0, RGB::new(0.5, 0.5, 0.5));
+    assert_eq!(image.get_sample_count(0, 0), 1);
+    let mean = image.accumulate_pixel(0, 0, RGB::new(1.5, 1.5, 1.5), 1);
+    assert_eq!(mean, RGB::new(1.0, 1.0, 1.0));
+}
+
+#[test]
+fn contact_sheet_grid() {
+    let mut sheet = ContactSheet::default();
+    assert!(sheet.is_empty());
+    for (i, c) in [
+        RGB::new(1.0, 0.0, 0.0),
+        RGB::new(0.0, 1.0, 0.0),
+        RGB::new(0.0, 0.0, 1.0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut image = Image::new(false, 40, 20);
+        for y in 0..20 {
+            for x in 0..40 {
+                image.push_pixel(x, y, c);
+            }
+        }
+        sheet.add(&format!("pic_{i}.png"), &image);
+    }
+    assert_eq!(sheet.len(), 3);
+
+    // 2 x 2 cells of 160 pixels plus the margins and the labels
+    let img = sheet.to_rgb_image();
+    assert_eq!(img.dimensions(), (2 * 164 + 4, 2 * (160 + 18) + 4));
+    // the wide thumbnails are centered in their cell
+    assert_eq!(img.get_pixel(4 + 80, 4 + 80).0, [255, 0, 0]);
+    assert_eq!(img.get_pixel(168 + 80, 4 + 80).0, [0, 255, 0]);
+    assert_eq!(img.get_pixel(4 + 80, 182 + 80).0, [0, 0, 255]);
+    assert_eq!(img.get_pixel(4 + 80, 4 + 10).0, [32, 32, 32]);
+    assert_eq!(img.get_pixel(168 + 80, 182 + 80).0, [32, 32, 32]);
+    // the labels are burned in below
+    let label_row =
+        |y0: u32| (y0..y0 + 10).any(|y| (4..164).any(|x| img.get_pixel(x, y).0 == [255, 255, 255]));
+    assert!(label_row(4 + 164));
+    assert!(!label_row(4 + 120));
+}
+
+#[test]
+fn ppm_round_trip() {
+    let img = image::RgbImage::from_fn(5, 3, |x, y| image::Rgb([x as u8 * 50, y as u8 * 100, 7]));
+    let mut data = vec![];
+    write_ppm(&mut data, &img).unwrap();
+    let header = b"P6\n5 3\n255\n";
+    assert!(data.starts_with(header));
+    assert_eq!(data.len(), header.len() + 5 * 3 * 3);
+    let decoded = image::load_from_memory(&data).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (5, 3));
+    assert!(decoded == img);
+}
+
+#[test]
+fn save_image_by_extension() {
+    let mut image = Image::new(false, 4, 2);
+    image.push_pixel(1, 1, RGB::new(1.0, 0.5, 0.0));
+    let dir = std::env::temp_dir();
+    for ext in ["ppm", "jpg", "JPEG", "png", "exr"] {
+        let file = dir.join(format!("rayflex-save.{ext}"));
+        image.save_image(&file, 90).unwrap();
+        assert_eq!(image::image_dimensions(&file).unwrap(), (4, 2), "{ext}");
+    }
+    let ppm = image::open(dir.join("rayflex-save.ppm")).unwrap().to_rgb8();
+    assert!(ppm == image.to_rgb_image());
+
+    let err = image
+        .save_image(&dir.join("rayflex-save.xyz"), 90)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("rayflex-save.xyz"), "{err}");
+    assert!(OutputFormat::from_path(std::path::Path::new("pic")).is_err());
+}
@@ -0,0 +1,95 @@
+use rayflex::color::RGB;
+use rayflex::light::AreaLight;
+use rayflex::light::Light;
+use rayflex::light::ObjectLight;
+use rayflex::three_d::Plane;
+use rayflex::three_d::Sphere;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+
+#[test]
+fn object_light_sphere() {
+    let center = Point::new(1.0, 2.0, 3.0);
+    let sphere = Sphere::new(center, 0.5, 0).unwrap();
+    let light = ObjectLight::new(
+        "object.0".to_owned(),
+        Arc::new(sphere),
+        0,
+        RGB::new(1.0, 1.0, 1.0),
+    )
+    .unwrap();
+    let pos = light.get_position().unwrap();
+    assert!((pos - center).norm() < 1e-4);
+    assert_eq!(light.get_object_id(), Some(0));
+    assert!(light.is_spot());
+}
+
+#[test]
+fn object_light_samples() {
+    let mut sphere = Sphere::new(Point::zero(), 0.5, 0).unwrap();
+    sphere.velocity = Vec3::new(4.0, 0.0, 0.0);
+    let ke = RGB::new(1.0, 1.0, 1.0);
+    let light = ObjectLight::new("object.0".to_owned(), Arc::new(sphere), 0, ke).unwrap();
+    assert!(light.is_sampled());
+    // the points are where the sphere is at the time of the sample
+    let point = Point::new(2.0, 0.0, 10.0);
+    let mut rnd_state = 1;
+    for time in [0.0, 0.5, 1.0] {
+        let center = Point::new(4.0 * time, 0.0, 0.0);
+        for _ in 0..16 {
+            let (dir, dist, pdf, _) = light.sample(point, time, &mut rnd_state).unwrap();
+            let light_point = point + dir * dist;
+            assert!(((light_point - center).norm() - 0.5).abs() < 1e-4);
+            assert!(pdf > 0.0);
+        }
+    }
+
+    // the samples of an ellipsoid are not uniform: it is only hit
+    let mut ellipsoid = Sphere::new(Point::zero(), 0.5, 0).unwrap();
+    ellipsoid.scale = Vec3::new(4.0, 1.0, 1.0);
+    let light = ObjectLight::new("object.0".to_owned(), Arc::new(ellipsoid), 0, ke).unwrap();
+    assert!(!light.is_sampled());
+}
+
+#[test]
+fn object_light_unbounded() {
+    let plane = Plane::new(Point::zero(), Vec3::unity_z(), 0).unwrap();
+    let light = ObjectLight::new(
+        "object.0".to_owned(),
+        Arc::new(plane),
+        0,
+        RGB::new(1.0, 1.0, 1.0),
+    );
+    assert!(light.is_none());
+}
+
+#[test]
+fn area_light_samples() {
+    let light = AreaLight {
+        name: "area-light.0".to_owned(),
+        corner: Point::new(0.0, 0.0, 2.0),
+        edge_u: Vec3::new(2.0, 0.0, 0.0),
+        edge_v: Vec3::new(0.0, 1.0, 0.0),
+        rgb: RGB::new(1.0, 1.0, 1.0),
+        intensity: 1.0,
+        object_id: None,
+    };
+    assert!(light.validate().is_ok());
+    assert!((light.get_area() - 2.0).abs() < 1e-4);
+    assert!((light.get_position().unwrap() - Point::new(1.0, 0.5, 2.0)).norm() < 1e-4);
+    assert!(light.is_spot());
+    assert_eq!(light.get_num_samples(16), 16);
+
+    // the samples spread over the rectangle
+    let point = Point::zero();
+    let mut rnd_state = 1;
+    let samples: Vec<Point> = (0..64)
+        .map(|_| point - light.sample_vector(point, &mut rnd_state))
+        .collect();
+    for p in &samples {
+        assert!((0.0..=2.0).contains(&p.x) && (0.0..=1.0).contains(&p.y));
+        assert!((p.z - 2.0).abs() < 1e-4);
+    }
+    assert!(samples.iter().any(|p| p.x < 0.5) && samples.iter().any(|p| p.x > 1.5));
+}
@@ -0,0 +1,124 @@
+use rayflex::color::RGB;
+use rayflex::material::CLEARCOAT_F0;
+use rayflex::material::Material;
+use rayflex::material::fresnel_schlick;
+use rayflex::texture::Texture;
+use rayflex::three_d::Triangle;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec2;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+
+#[test]
+fn fresnel_schlick_limits() {
+    let f0 = 0.04;
+    assert!((fresnel_schlick(1.0, f0) - f0).abs() < 1e-6);
+    assert!(fresnel_schlick(0.0, f0) > 0.999);
+    assert!(fresnel_schlick(0.05, f0) > fresnel_schlick(0.5, f0));
+    assert!(fresnel_schlick(0.5, f0) > fresnel_schlick(0.9, f0));
+}
+
+#[test]
+fn clearcoat_weight() {
+    let json = r#"{ "kd": { "r": 0.8, "g": 0.0, "b": 0.0 } }"#;
+    let mut mat: Material = serde_json::from_str(json).unwrap();
+    assert!(mat.clearcoat == 0.0);
+    assert!(mat.get_clearcoat_weight(0.0) == 0.0);
+
+    mat.clearcoat = 0.5;
+    assert!((mat.get_clearcoat_weight(1.0) - 0.5 * CLEARCOAT_F0).abs() < 1e-6);
+    assert!(mat.get_clearcoat_weight(0.1) > mat.get_clearcoat_weight(0.9));
+    assert!(mat.get_clearcoat_weight(0.0) <= 0.5);
+
+    // a rougher coat spreads the highlight
+    mat.clearcoat_roughness = 0.05;
+    let smooth = mat.get_clearcoat_exponent();
+    mat.clearcoat_roughness = 0.5;
+    let rough = mat.get_clearcoat_exponent();
+    assert!(smooth > rough && rough >= 1.0);
+}
+
+#[test]
+fn reflectance_from_ks() {
+    let matte: Material =
+        serde_json::from_str(r#"{ "kd": { "r": 0.8, "g": 0.2, "b": 0.2 } }"#).unwrap();
+    assert!(matte.get_reflectance(1.0).is_zero());
+
+    let json = r#"{ "ks": { "r": 1.0, "g": 0.78, "b": 0.34 } }"#;
+    let gold: Material = serde_json::from_str(json).unwrap();
+    let k = gold.get_reflectance(1.0);
+    assert!(k.r == 1.0 && (k.g - 0.78).abs() < 1e-6 && (k.b - 0.34).abs() < 1e-6);
+    // grazing angles reflect everything, in every channel
+    let k = gold.get_reflectance(0.0);
+    assert!(k.g > 0.999 && k.b > 0.999);
+
+    // a ks above 1 would add energy
+    let json = r#"{ "ks": { "r": 2.0, "g": 0.5, "b": 0.5 } }"#;
+    let hot: Material = serde_json::from_str(json).unwrap();
+    assert!(hot.get_reflectance(1.0).max_component() == 1.0);
+
+    // dielectrics reflect from their ior, whatever the ks
+    let json = r#"{ "ks": { "r": 0.5, "g": 0.5, "b": 0.5 }, "ior": 1.5, "transparency": 0.9 }"#;
+    let glass: Material = serde_json::from_str(json).unwrap();
+    assert!((glass.get_reflectance(1.0).r - 0.04).abs() < 1e-6);
+}
+
+#[test]
+fn ks_scalar_or_rgb() {
+    let rgb = |json: &str| serde_json::from_str::<Material>(json).unwrap().ks;
+    assert!(rgb(r#"{ "ks": 0.5 }"#) == RGB::new(0.5, 0.5, 0.5));
+    assert!(rgb(r#"{ "ks": [1.0, 0.78, 0.34] }"#) == RGB::new(1.0, 0.78, 0.34));
+    assert!(rgb(r#"{ "ks": { "r": 0.2, "g": 0.3, "b": 0.4 } }"#) == RGB::new(0.2, 0.3, 0.4));
+    assert!(rgb("{}").is_zero());
+    assert!(serde_json::from_str::<Material>(r#"{ "ks": "shiny" }"#).is_err());
+    assert!(RGB::one() == RGB::new(1.0, 1.0, 1.0));
+}
+
+#[test]
+fn normal_map_tangent_space() {
+    // a wall facing -y, with u along x and v along z
+    let mut t = Triangle::new(
+        [
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(2.0, 0.0, 0.0),
+            Point::new(0.0, 0.0, 2.0),
+        ],
+        0,
+    )
+    .unwrap();
+    assert!(t.compute_tangents().is_none());
+    t.uvs = Some([
+        Vec2 { x: 0.0, y: 0.0 },
+        Vec2 { x: 1.0, y: 0.0 },
+        Vec2 { x: 0.0, y: 1.0 },
+    ]);
+    let [dpdu, dpdv] = t.compute_tangents().unwrap();
+    assert!(dpdu == Vec3::new(2.0, 0.0, 0.0) && dpdv == Vec3::new(0.0, 0.0, 2.0));
+
+    let normal = Vec3::new(0.0, -1.0, 0.0);
+    let uv = Vec2 { x: 0.5, y: 0.5 };
+    let near = |a: Vec3, b: Vec3| a.distance(b) < 1e-3;
+    let with_map = |c: RGB| Material {
+        normal_texture: Some(Arc::new(Texture::new(1, 1, vec![c]))),
+        ..Default::default()
+    };
+    let flat = with_map(RGB::new(0.5, 0.5, 1.0));
+    assert!(near(
+        flat.get_shading_normal(normal, Some([dpdu, dpdv]), uv),
+        normal
+    ));
+    // 45 degrees towards u, then towards v
+    let bent_u = with_map(RGB::new(1.0, 0.5, 1.0));
+    let n = bent_u.get_shading_normal(normal, Some([dpdu, dpdv]), uv);
+    assert!(near(n, Vec3::new(1.0, -1.0, 0.0).normalize()));
+    let bent_v = with_map(RGB::new(0.5, 1.0, 1.0));
+    let n = bent_v.get_shading_normal(normal, Some([dpdu, dpdv]), uv);
+    assert!(near(n, Vec3::new(0.0, -1.0, 1.0).normalize()));
+    // mirrored uvs flip the bitangent
+    let n = bent_v.get_shading_normal(normal, Some([dpdu, -dpdv]), uv);
+    assert!(near(n, Vec3::new(0.0, -1.0, -1.0).normalize()));
+
+    // left alone without tangents or without a map
+    assert!(bent_u.get_shading_normal(normal, None, uv) == normal);
+    assert!(Material::default().get_shading_normal(normal, Some([dpdu, dpdv]), uv) == normal);
+}
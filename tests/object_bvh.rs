@@ -0,0 +1,116 @@
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayflex::Ray;
+use rayflex::RenderStats;
+use rayflex::object_bvh::ObjectBvh;
+use rayflex::render::get_nearest_hit;
+use rayflex::three_d::Object;
+use rayflex::three_d::Plane;
+use rayflex::three_d::Sphere;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+
+fn get_random_objects(rng: &mut StdRng, n: usize) -> Vec<Arc<dyn Object + Send + Sync>> {
+    let mut objects: Vec<Arc<dyn Object + Send + Sync>> = vec![];
+    for _ in 0..n {
+        let center = Point::new(
+            rng.gen_range(-5.0..5.0),
+            rng.gen_range(-5.0..5.0),
+            rng.gen_range(-5.0..5.0),
+        );
+        let radius = rng.gen_range(0.05..0.6);
+        objects.push(Arc::new(Sphere::new(center, radius, 0).unwrap()));
+    }
+    let floor = Plane::new(Point::new(0.0, 0.0, -6.0), Vec3::unity_z(), 0).unwrap();
+    objects.push(Arc::new(floor));
+    objects
+}
+
+#[test]
+fn bvh_matches_linear_scan() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let objects = get_random_objects(&mut rng, 500);
+    let bvh = ObjectBvh::new(&objects);
+
+    let mut stats = RenderStats::default();
+    let mut bvh_stats = RenderStats::default();
+    let mut num_hits = 0;
+    for _ in 0..2000 {
+        // from outside and from inside the bvh bounds
+        let orig = Point::new(
+            rng.gen_range(-8.0..8.0),
+            rng.gen_range(-8.0..8.0),
+            rng.gen_range(-8.0..8.0),
+        );
+        let dir = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let ray = Ray::new(orig, dir.normalize());
+        let hit = get_nearest_hit(&objects, &mut stats, &ray);
+        let bvh_hit = bvh.get_nearest_hit(&objects, &mut bvh_stats, &ray);
+        match (hit, bvh_hit) {
+            (Some(a), Some(b)) => {
+                assert_eq!(a.object, b.object);
+                assert!((a.t - b.t).abs() < 1e-4);
+                num_hits += 1;
+            }
+            (None, None) => {}
+            _ => panic!("bvh and linear scan disagree on {:?}", ray.dir),
+        }
+    }
+    assert!(num_hits > 500);
+    if cfg!(feature = "stats") {
+        assert!(bvh_stats.num_intersects_sphere * 10 < stats.num_intersects_sphere);
+    }
+}
+
+#[test]
+fn bvh_occlusion() {
+    let a = Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, 0).unwrap();
+    let b = Sphere::new(Point::new(4.0, 0.0, 0.0), 1.0, 0).unwrap();
+    let objects: Vec<Arc<dyn Object + Send + Sync>> = vec![Arc::new(a), Arc::new(b)];
+    let bvh = ObjectBvh::new(&objects);
+    let mut stats = RenderStats::default();
+
+    let ray = Ray::new(Point::new(-3.0, 0.0, 0.0), Vec3::unity_x());
+    assert!(bvh.is_occluded(&objects, &mut stats, &ray, 10.0, None));
+    // too short to reach the first sphere
+    assert!(!bvh.is_occluded(&objects, &mut stats, &ray, 1.5, None));
+    // the second sphere blocks once the first one is skipped
+    assert!(bvh.is_occluded(&objects, &mut stats, &ray, 10.0, Some(0)));
+    assert!(!bvh.is_occluded(&objects, &mut stats, &ray, 5.0, Some(0)));
+
+    let miss = Ray::new(Point::new(-3.0, 2.0, 0.0), Vec3::unity_x());
+    assert!(!bvh.is_occluded(&objects, &mut stats, &miss, Float::MAX, None));
+}
+
+#[test]
+fn bvh_moving_and_unbounded() {
+    let mut moving = Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, 0).unwrap();
+    moving.velocity = Vec3::new(0.0, 0.0, 2.0);
+    let floor = Plane::new(Point::new(0.0, 0.0, -1.0), Vec3::unity_z(), 0).unwrap();
+    let still = Sphere::new(Point::new(4.0, 0.0, 0.0), 1.0, 0).unwrap();
+    let objects: Vec<Arc<dyn Object + Send + Sync>> =
+        vec![Arc::new(moving), Arc::new(floor), Arc::new(still)];
+    let bvh = ObjectBvh::new(&objects);
+    let mut stats = RenderStats::default();
+
+    // the moving sphere, late in the shutter, is above its bounds at rest
+    let mut ray = Ray::new(Point::new(0.0, -3.0, 2.5), Vec3::unity_y());
+    ray.time = 1.0;
+    let linear = get_nearest_hit(&objects, &mut stats, &ray);
+    assert_eq!(linear.map(|h| h.object), Some(0));
+    assert_eq!(bvh.get_nearest_hit(&objects, &mut stats, &ray), linear);
+    let down = Ray::new(Point::new(4.0, 0.0, 3.0), Vec3::unity_z() * -1.0);
+    assert_eq!(
+        bvh.get_nearest_hit(&objects, &mut stats, &down)
+            .map(|h| h.object),
+        Some(2)
+    );
+    assert!(bvh.is_occluded(&objects, &mut stats, &down, 10.0, Some(2)));
+}
@@ -0,0 +1,1072 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rayflex::Ray;
+use rayflex::RenderStats;
+use rayflex::color::RGB;
+use rayflex::image::ToneMap;
+use rayflex::material::Material;
+use rayflex::render::Aov;
+use rayflex::render::BounceConfig;
+use rayflex::render::Integrator;
+use rayflex::render::PixelGeom;
+use rayflex::render::RenderConfig;
+use rayflex::render::detect_edges;
+use rayflex::render::get_matte_objects;
+use rayflex::render::get_nearest_hit;
+use rayflex::render::get_stratified_offset;
+use rayflex::scene::load_scene;
+use rayflex::three_d::Object;
+use rayflex::three_d::Sphere;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec2;
+use rayflex::vec3::Vec3;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+#[test]
+fn edges_on_silhouette() {
+    // 4x3 image: an object in the two left columns, background elsewhere
+    let (res_x, res_y) = (4, 3);
+    let surface = PixelGeom {
+        object: Some(0),
+        depth: 2.0,
+        normal: Vec3::unity_z(),
+    };
+    let mut geom = vec![PixelGeom::miss(); res_x * res_y];
+    for y in 0..res_y {
+        geom[y * res_x] = surface;
+        geom[y * res_x + 1] = surface;
+    }
+    let colors = vec![RGB::zero(); res_x * res_y];
+
+    let edges = detect_edges(&geom, &colors, res_x as u32, res_y as u32);
+    for y in 0..res_y {
+        let row: Vec<bool> = (0..res_x).map(|x| edges[y * res_x + x]).collect();
+        assert_eq!(row, [false, true, true, false]);
+    }
+
+    // a depth step within the same object, then a normal crease
+    let mut far = surface;
+    far.depth = 3.0;
+    geom[0] = far;
+    assert!(detect_edges(&geom, &colors, res_x as u32, res_y as u32)[0]);
+    let mut tilted = surface;
+    tilted.normal = Vec3::new(1.0, 0.0, 1.0).normalize();
+    geom[0] = tilted;
+    assert!(detect_edges(&geom, &colors, res_x as u32, res_y as u32)[0]);
+
+    // flat geometry with a shadow edge
+    let geom = vec![surface; res_x * res_y];
+    let mut colors = vec![RGB::new(0.8, 0.8, 0.8); res_x * res_y];
+    assert!(!detect_edges(&geom, &colors, res_x as u32, res_y as u32).contains(&true));
+    colors[res_x + 3] = RGB::new(0.1, 0.1, 0.1);
+    let edges = detect_edges(&geom, &colors, res_x as u32, res_y as u32);
+    assert_eq!(edges.iter().filter(|&&e| e).count(), 4);
+}
+
+// never hit, but scribbles over the oid it is given
+struct SloppyObject;
+
+impl Object for SloppyObject {
+    fn display(&self) {}
+    fn intercept(
+        &self,
+        _stats: &mut RenderStats,
+        _ray: &Ray,
+        _tmin: Float,
+        _tmax: &mut Float,
+        _any: bool,
+        oid: &mut usize,
+    ) -> bool {
+        *oid = 1; // the id a sphere uses for hits from inside
+        false
+    }
+    fn get_normal(&self, _point: Point, _oid: usize) -> Vec3 {
+        Vec3::unity_z()
+    }
+    fn get_texture_2d(&self, _point: Point, _oid: usize) -> Vec2 {
+        Vec2 { x: 0.0, y: 0.0 }
+    }
+    fn get_material_id(&self) -> usize {
+        0
+    }
+}
+
+#[test]
+fn nearest_hit_of_overlapping_spheres() {
+    let mut stats = RenderStats::default();
+    // the ray starts inside the big sphere and hits the small one first
+    let big = Sphere::new(Point::zero(), 3.0, 0).unwrap();
+    let small = Sphere::new(Point::new(1.5, 0.0, 0.0), 1.0, 0).unwrap();
+    let ray = Ray::new(Point::zero(), Vec3::unity_x());
+    let big: Arc<dyn Object + Send + Sync> = Arc::new(big);
+    let small: Arc<dyn Object + Send + Sync> = Arc::new(small);
+    let sloppy: Arc<dyn Object + Send + Sync> = Arc::new(SloppyObject);
+
+    let objects = vec![small.clone(), big.clone(), sloppy.clone()];
+    let hit = get_nearest_hit(&objects, &mut stats, &ray).unwrap();
+    assert_eq!(hit.object, 0);
+    assert!((hit.t - 0.5).abs() < 1e-5);
+    assert!(!small.is_inside_hit(hit.s_id));
+
+    let objects = vec![big, sloppy, small];
+    let hit = get_nearest_hit(&objects, &mut stats, &ray).unwrap();
+    assert_eq!(hit.object, 2);
+    assert!((hit.t - 0.5).abs() < 1e-5);
+    assert!(!objects[2].is_inside_hit(hit.s_id));
+
+    let miss = Ray::new(Point::new(0.0, 10.0, 0.0), Vec3::unity_y());
+    assert!(get_nearest_hit(&objects, &mut stats, &miss).is_none());
+}
+
+#[test]
+fn stratified_offsets() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let (du, dv) = (0.5, 0.25);
+    for n in [1, 4, 7, 9, 16] {
+        let k = (n as Float).sqrt() as u32;
+        let mut cells = vec![0; (k * k) as usize];
+        for i in 0..n {
+            let (u, v) = get_stratified_offset(&mut rng, i, n, du, dv);
+            assert!((0.0..=du).contains(&u) && (0.0..=dv).contains(&v));
+            if i < k * k {
+                let cu = ((u / du * k as Float) as u32).min(k - 1);
+                let cv = ((v / dv * k as Float) as u32).min(k - 1);
+                cells[(cv * k + cu) as usize] += 1;
+            }
+        }
+        // one sample per cell of the grid
+        assert!(cells.iter().all(|&c| c == 1), "n={n}: {cells:?}");
+    }
+}
+
+#[test]
+fn path_samples_per_pixel() {
+    for spp in [5, 9] {
+        let cfg = RenderConfig::default()
+            .with_scene_file("scenes/cornell-box.json")
+            .with_resolution(16, 12)
+            .with_integrator(Integrator::Path, spp);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let stats = job.total_stats.lock().unwrap();
+        assert_eq!(stats.num_rays_sampling, 16 * 12 * spp as u64);
+    }
+}
+
+#[test]
+fn aovs_box_and_lines() {
+    let render = |use_lines: bool, stats_only: bool| {
+        let cfg = RenderConfig {
+            use_lines,
+            stats_only,
+            aovs: vec![Aov::Depth, Aov::Normal],
+            ..Default::default()
+        }
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(16, 12);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        job.geom
+    };
+    let geom = render(false, false);
+    assert_eq!(geom.len(), 16 * 12);
+    assert!(geom == render(true, false));
+    assert!(render(false, true).is_empty());
+
+    // the plane below the camera, the sky above
+    let (bottom, top) = (geom[11 * 16 + 8], geom[8]);
+    assert!(bottom.object.is_some() && bottom.depth > 0.0);
+    assert!((bottom.normal - Vec3::unity_z()).norm() < 1e-6);
+    assert!(top.object.is_none());
+    assert!(
+        "depth,normal"
+            .split(',')
+            .map(str::parse::<Aov>)
+            .all(|a| a.is_ok())
+    );
+}
+
+#[test]
+fn torus_silhouette_and_shadow() {
+    let cfg = RenderConfig {
+        aovs: vec![Aov::Normal],
+        ..Default::default()
+    }
+    .with_scene_file("scenes/torus.json")
+    .with_resolution(64, 64);
+    let mut job = load_scene(cfg).unwrap();
+    job.alloc_image();
+    job.render_scene(Arc::new(AtomicBool::new(false)));
+    let hdr = job.image.lock().unwrap().get_hdr();
+    let pixels = hdr.lock().unwrap().clone();
+
+    // the middle row: sky, ring, the hole, ring, sky
+    let row = &job.geom[32 * 64..33 * 64];
+    let runs: Vec<bool> = row.iter().map(|g| g.object.is_some()).collect();
+    let changes = runs.windows(2).filter(|w| w[0] != w[1]).count();
+    assert_eq!(changes, 4);
+    assert!(!runs[0] && !runs[32] && !runs[63]);
+
+    /*
+     * The light is far on the right, level with the ring: the inner side of
+     * the left half faces it as the outer side of the right half does, but
+     * sits in the shadow of the right half.
+     */
+    let facing: Vec<(usize, Float)> = (0..64)
+        .filter(|&x| row[x].object.is_some() && row[x].normal.x > 0.5 && row[x].normal.z > 0.5)
+        .map(|x| (x, pixels[32 * 64 + x].r))
+        .collect();
+    let (left, right): (Vec<_>, Vec<_>) = facing.iter().partition(|(x, _)| *x < 32);
+    assert!(!left.is_empty() && !right.is_empty());
+    assert!(left.iter().all(|(_, r)| *r < 0.2));
+    assert!(right.iter().all(|(_, r)| *r > 0.3));
+}
+
+#[test]
+fn matte_objects_by_name() {
+    let cfg = RenderConfig::default()
+        .with_scene_file("scenes/gltf-box.json")
+        .with_resolution(16, 12);
+    let job = load_scene(cfg).unwrap();
+    let meshes = get_matte_objects(&job.object_names, "obj.0");
+    assert!(!meshes.is_empty());
+    assert!(meshes.iter().all(|&id| job.object_names[id] == "obj.0"));
+
+    let cfg = RenderConfig::default()
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(16, 12);
+    let job = load_scene(cfg).unwrap();
+    assert_eq!(
+        job.object_names,
+        ["plane.0", "sphere.0", "sphere.1", "sphere.2"]
+    );
+    assert_eq!(get_matte_objects(&job.object_names, "sphere.1"), [2]);
+    assert_eq!(get_matte_objects(&job.object_names, "object.0"), [0]);
+    assert!(get_matte_objects(&job.object_names, "cylinder.0").is_empty());
+    let cfg = RenderConfig {
+        matte: Some("sphere.3".to_owned()),
+        ..Default::default()
+    };
+    let cfg = cfg
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(16, 12);
+    assert!(
+        load_scene(cfg)
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("sphere.3: ")
+    );
+}
+
+#[test]
+fn tile_callback_covers_image() {
+    let render = |use_lines: bool| {
+        let cfg = RenderConfig {
+            use_lines,
+            ..Default::default()
+        }
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(40, 35);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        let tiles = Arc::new(Mutex::new(vec![]));
+        let tiles_clone = tiles.clone();
+        job.set_tile_func(Box::new(move |tile| {
+            let rect = (tile.x, tile.y, tile.sz_x, tile.sz_y);
+            tiles_clone
+                .lock()
+                .unwrap()
+                .push((rect, tile.pixels.to_vec()));
+        }));
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let hdr = hdr.lock().unwrap().clone();
+        (tiles.lock().unwrap().clone(), hdr)
+    };
+    for use_lines in [false, true] {
+        let (tiles, hdr) = render(use_lines);
+        let mut seen = vec![0; 40 * 35];
+        for ((x0, y0, sz_x, sz_y), pixels) in &tiles {
+            assert!(x0 + sz_x <= 40 && y0 + sz_y <= 35);
+            assert_eq!(pixels.len(), (sz_x * sz_y) as usize);
+            for (i, c) in pixels.iter().enumerate() {
+                let (x, y) = (x0 + i as u32 % sz_x, y0 + i as u32 / sz_x);
+                let idx = (y * 40 + x) as usize;
+                seen[idx] += 1;
+                assert!(*c == hdr[idx]);
+            }
+        }
+        assert!(seen.iter().all(|&n| n == 1));
+    }
+}
+
+#[test]
+fn progressive_passes() {
+    let render = |progressive: Option<u32>| {
+        let cfg = RenderConfig {
+            progressive,
+            seed: Some(1),
+            ..Default::default()
+        }
+        .with_integrator(Integrator::Path, 8)
+        .with_scene_file("scenes/cornell-box.json")
+        .with_resolution(24, 18);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        // the times each pixel is reported to the tile callback
+        let num_pixels = Arc::new(Mutex::new(vec![0; 24 * 18]));
+        let num_pixels_clone = num_pixels.clone();
+        job.set_tile_func(Box::new(move |tile| {
+            let mut num_pixels = num_pixels_clone.lock().unwrap();
+            for y in tile.y..tile.y + tile.sz_y {
+                for x in tile.x..tile.x + tile.sz_x {
+                    num_pixels[(y * 24 + x) as usize] += 1;
+                }
+            }
+        }));
+        let progress = Arc::new(Mutex::new(vec![]));
+        let progress_clone = progress.clone();
+        job.set_progress_func(Box::new(move |pct| {
+            progress_clone.lock().unwrap().push(pct)
+        }));
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        assert!((0..24).all(|x| job.image.lock().unwrap().get_sample_count(x, 17) > 0));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap().clone();
+        let num_pixels = num_pixels.lock().unwrap().clone();
+        let progress = progress.lock().unwrap().clone();
+        (pixels, num_pixels, progress, job.average_luminance())
+    };
+    let (whole, _, _, luminance) = render(None);
+    // a single pass draws the samples of the plain render
+    assert!(render(Some(1)).0 == whole);
+
+    let (pixels, num_pixels, progress, progressive_luminance) = render(Some(4));
+    assert!(pixels != whole);
+    assert!(num_pixels.iter().all(|&n| n == 4));
+    assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+    assert!(progress.iter().all(|&pct| pct <= 1.0));
+    assert_eq!(progress.last(), Some(&1.0));
+    // other samples of the same image
+    let ratio = progressive_luminance / luminance;
+    assert!((0.8..1.25).contains(&ratio), "{ratio}");
+}
+
+#[test]
+fn progress_by_rays_and_stop() {
+    let render = |stop_at: Option<f32>| {
+        let cfg = RenderConfig {
+            use_adaptive_sampling: true,
+            ..Default::default()
+        }
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(64, 48);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        let exit_req = Arc::new(AtomicBool::new(false));
+        let exit_req_clone = exit_req.clone();
+        let progress = Arc::new(Mutex::new(vec![]));
+        let progress_clone = progress.clone();
+        job.set_progress_func(Box::new(move |pct| {
+            progress_clone.lock().unwrap().push(pct);
+            if stop_at.is_some_and(|v| pct >= v) {
+                exit_req_clone.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        }));
+        let etas = Arc::new(Mutex::new(vec![]));
+        let etas_clone = etas.clone();
+        job.set_eta_func(Box::new(move |pct, eta| {
+            etas_clone.lock().unwrap().push((pct, eta))
+        }));
+        job.render_scene(exit_req);
+        let progress = progress.lock().unwrap().clone();
+        let etas = etas.lock().unwrap().clone();
+        (progress, etas, job.get_progress())
+    };
+    let (progress, etas, done) = render(None);
+    // reported per row of the tiles rather than per tile
+    assert!(progress.len() > 20, "{}", progress.len());
+    assert!(progress.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(progress.last(), Some(&1.0));
+    assert_eq!(done, 1.0);
+    assert_eq!(etas.len(), progress.len());
+    assert!(
+        etas.iter()
+            .all(|(pct, eta)| *pct < 1.0 || eta.remaining == Some(Duration::ZERO))
+    );
+
+    // the tiles left out on a stop are not counted as done
+    let (progress, _, stopped) = render(Some(0.3));
+    assert!((0.3..1.0).contains(&stopped), "{stopped}");
+    assert_eq!(progress.last(), Some(&stopped));
+}
+
+#[test]
+fn split_tiles_same_image() {
+    let render = |tile_split_time: Option<Duration>| {
+        let cfg = RenderConfig {
+            use_adaptive_sampling: true,
+            use_hashmap: true,
+            timing_heatmap: true,
+            tile_split_time,
+            ..Default::default()
+        }
+        .with_scene_file("scenes/glass.json")
+        .with_resolution(64, 48);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        let num_pixels = Arc::new(Mutex::new(vec![0; 64 * 48]));
+        let num_pixels_clone = num_pixels.clone();
+        job.set_tile_func(Box::new(move |tile| {
+            let mut num_pixels = num_pixels_clone.lock().unwrap();
+            for y in tile.y..tile.y + tile.sz_y {
+                for x in tile.x..tile.x + tile.sz_x {
+                    num_pixels[(y * 64 + x) as usize] += 1;
+                }
+            }
+        }));
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let hdr = hdr.lock().unwrap().clone();
+        assert!(num_pixels.lock().unwrap().iter().all(|&n| n == 1));
+        let num_tiles = job.tile_timings.lock().unwrap().len();
+        (hdr, num_tiles)
+    };
+    // every tile splits after its first row, down to the smallest
+    let (split, num_split) = render(Some(Duration::ZERO));
+    let (whole, num_whole) = render(None);
+    assert!(split == whole);
+    assert!(num_split > num_whole);
+}
+
+#[test]
+fn emissive_sphere_both_integrators() -> Result<(), Box<dyn std::error::Error>> {
+    // no light but the sphere
+    let json = serde_json::json!({
+        "resolution": [16, 12],
+        "camera": {
+            "pos": { "x": -4.0, "y": 0.0, "z": 1.0 },
+            "look_at": { "x": 0.0, "y": 0.0, "z": 0.5 },
+            "vfov": 40.0
+        },
+        "materials": [
+            { "kd": { "r": 0.8, "g": 0.8, "b": 0.8 } },
+            { "ke": { "r": 2.0, "g": 2.0, "b": 2.0 } }
+        ],
+        "planes": [
+            {
+                "point": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                "normal": { "x": 0.0, "y": 0.0, "z": 1.0 },
+                "material_id": 0
+            }
+        ],
+        "spheres": [
+            { "center": { "x": 0.0, "y": 0.0, "z": 0.5 }, "radius": 0.5, "material_id": 1 }
+        ]
+    });
+    let path = std::env::temp_dir().join("rayflex-emissive.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    let render = |integrator: Integrator| {
+        let cfg = RenderConfig {
+            seed: Some(1),
+            ..Default::default()
+        }
+        .with_integrator(integrator, 4)
+        .with_scene_file(&path);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        hdr.lock().unwrap().clone()
+    };
+    for integrator in [Integrator::Whitted, Integrator::Path] {
+        let pixels = render(integrator);
+        // the sphere shows its ke once, the floor in front is lit by it
+        let sphere = pixels[6 * 16 + 8];
+        assert!(
+            sphere == RGB::new(2.0, 2.0, 2.0),
+            "{integrator:?}: {sphere:?}"
+        );
+        assert!(pixels[11 * 16 + 8].r > 0.0, "{integrator:?}");
+    }
+    Ok(())
+}
+
+#[test]
+fn path_firefly_clamp() {
+    // a tiny, very bright light: the few paths that find it make fireflies
+    let json = serde_json::json!({
+        "resolution": [24, 18],
+        "camera": {
+            "pos": { "x": -4.0, "y": 0.0, "z": 1.0 },
+            "look_at": { "x": 0.0, "y": 0.0, "z": 0.5 },
+            "vfov": 40.0
+        },
+        "materials": [
+            { "kd": { "r": 0.8, "g": 0.8, "b": 0.8 } },
+            { "ke": { "r": 2000.0, "g": 2000.0, "b": 2000.0 } }
+        ],
+        "planes": [
+            {
+                "point": { "x": 0.0, "y": 0.0, "z": 0.0 },
+                "normal": { "x": 0.0, "y": 0.0, "z": 1.0 },
+                "material_id": 0
+            }
+        ],
+        "spheres": [
+            { "center": { "x": 0.0, "y": 2.0, "z": 3.0 }, "radius": 0.2, "material_id": 1 }
+        ]
+    });
+    let path = std::env::temp_dir().join("rayflex-firefly.json");
+    std::fs::write(&path, serde_json::to_string(&json).unwrap()).unwrap();
+    let render = |firefly_clamp: Option<f32>| {
+        let cfg = RenderConfig {
+            firefly_clamp,
+            seed: Some(1),
+            ..Default::default()
+        }
+        .with_integrator(Integrator::Path, 16)
+        .with_scene_file(&path);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap().clone();
+        pixels.iter().map(|c| c.luminance()).fold(0.0, f32::max)
+    };
+    let unclamped = render(None);
+    let clamped = render(Some(1.0));
+    assert!(unclamped > 1.0, "{unclamped}");
+    // the mean of samples of at most 1
+    assert!(clamped <= 1.0 + 1e-5, "{clamped}");
+}
+
+#[test]
+fn path_sky_light() {
+    // no emissive object and no spot light: only the sky can light the scene
+    let render = |use_sky_light: bool| {
+        let cfg = RenderConfig {
+            use_sky_light,
+            seed: Some(1),
+            ..Default::default()
+        }
+        .with_integrator(Integrator::Path, 4)
+        .with_scene_file("scenes/ellipsoid.json")
+        .with_resolution(16, 12);
+        let mut job = load_scene(cfg).unwrap();
+        job.lights.retain(|l| !l.is_spot());
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        hdr.lock().unwrap().clone()
+    };
+    assert!(render(false).iter().all(|c| c.is_zero()));
+    let hdr = render(true);
+    // the floor below the camera, the sky above
+    let floor = hdr[11 * 16 + 8];
+    assert!(floor.r > 0.05 && floor.b > floor.r);
+    assert!(hdr[8].b > floor.b);
+}
+
+#[test]
+fn preview_matches_saved_png() {
+    let file = std::env::temp_dir().join("rayflex-preview.png");
+    let cfg = RenderConfig {
+        use_gamma: true,
+        tonemap: ToneMap::Reinhard,
+        seed: Some(1),
+        ..Default::default()
+    }
+    .with_integrator(Integrator::Path, 4)
+    .with_scene_file("scenes/cornell-box.json")
+    .with_resolution(24, 18)
+    .with_image_file(&file);
+    let mut job = load_scene(cfg).unwrap();
+    job.alloc_image();
+    job.render_scene(Arc::new(AtomicBool::new(false)));
+    job.save_image().unwrap();
+
+    let preview = job.image.lock().unwrap().to_color_image();
+    let saved = ::image::open(&file).unwrap().to_rgb8();
+    assert_eq!(preview.size, [24, 18]);
+    for (p, s) in preview.pixels.iter().zip(saved.pixels()) {
+        assert_eq!([p.r(), p.g(), p.b()], s.0);
+    }
+    assert!(preview.pixels.iter().any(|p| p.r() > 0));
+}
+
+#[test]
+fn path_russian_roulette() {
+    // lit by the sky, on surfaces that reflect less than all of the light
+    let render = |rr_min_depth: Option<u32>| {
+        let cfg = RenderConfig {
+            use_sky_light: true,
+            seed: Some(1),
+            bounces: BounceConfig {
+                path_max_bounces: 8,
+                rr_min_depth,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .with_integrator(Integrator::Path, 64)
+        .with_scene_file("scenes/ellipsoid.json")
+        .with_resolution(16, 12);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let num_rays = job.total_stats.lock().unwrap().num_rays_reflection;
+        (job.average_luminance(), num_rays)
+    };
+    let (lum, num_rays) = render(None);
+    let (rr_lum, rr_num_rays) = render(Some(1));
+    assert!(rr_num_rays < num_rays);
+    assert!((rr_lum / lum - 1.0).abs() < 0.05, "{rr_lum} vs {lum}");
+}
+
+#[test]
+fn adaptive_refines_fine_checker() {
+    // a striped floor that recedes to the horizon, in uniform light
+    let scene = r#"{
+        "resolution": [32, 16],
+        "camera": {
+            "pos": { "x": 0, "y": 0, "z": 1 },
+            "look_at": { "x": 0, "y": 100, "z": 0 },
+            "up": { "x": 0, "y": 0, "z": 1 },
+            "vfov": 30.0
+        },
+        "ambient": { "intensity": 1.0, "rgb": { "r": 1.0, "g": 1.0, "b": 1.0 } },
+        "material.0": { "checkered": true, "kd": { "r": 0.9, "g": 0.9, "b": 0.9 } },
+        "plane.0": {
+            "point": { "x": 0, "y": 0, "z": 0 },
+            "normal": { "x": 0, "y": 0, "z": 1 },
+            "material_id": 0
+        }
+    }"#;
+    let file = std::env::temp_dir().join("rayflex-fine-checker.json");
+    std::fs::write(&file, scene).unwrap();
+    let render = |use_adaptive_sampling: bool, scale: u32| {
+        let cfg = RenderConfig {
+            use_adaptive_sampling,
+            ..Default::default()
+        }
+        .with_scene_file(&file)
+        .with_resolution(32 * scale, 16 * scale);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        hdr.lock().unwrap().clone()
+    };
+    let scale = 16;
+    let reference = render(false, scale);
+    let error = |hdr: &[RGB]| {
+        let mut sum = 0.0;
+        for (i, c) in hdr.iter().enumerate() {
+            let (x, y) = (i as u32 % 32, i as u32 / 32);
+            let mut r = 0.0;
+            for j in 0..scale * scale {
+                let (rx, ry) = (x * scale + j % scale, y * scale + j / scale);
+                r += reference[(ry * 32 * scale + rx) as usize].r;
+            }
+            sum += (c.r - r / (scale * scale) as f32).abs();
+        }
+        sum / hdr.len() as f32
+    };
+    let plain = render(false, 1);
+    let adaptive = render(true, 1);
+    // the corners of the boxes alone often miss the stripes near the horizon
+    assert!(error(&adaptive) < 0.5 * error(&plain));
+}
+
+#[test]
+fn bilinear_softens_edges() {
+    // a white emissive disk on black: its outline is the only contrast
+    let scene = r#"{
+        "camera": {
+            "pos": { "x": -5.0, "y": 0.0, "z": 0.0 },
+            "look_at": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "up": { "x": 0, "y": 0, "z": 1 },
+            "vfov": 20.0
+        },
+        "background": { "stops": [[0.0, { "r": 0, "g": 0, "b": 0 }]] },
+        "material.0": { "ke": { "r": 1.0, "g": 1.0, "b": 1.0 } },
+        "sphere.0": {
+            "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "radius": 0.5,
+            "material_id": 0
+        }
+    }"#;
+    let file = std::env::temp_dir().join("rayflex-bilinear-edge.json");
+    std::fs::write(&file, scene).unwrap();
+    let render = |use_bilinear: bool| {
+        let cfg = RenderConfig {
+            use_adaptive_sampling: true,
+            use_hashmap: true,
+            use_bilinear,
+            ..Default::default()
+        }
+        .with_scene_file(&file)
+        .with_resolution(32, 32);
+        let mut job = load_scene(cfg).unwrap();
+        job.render_to_buffer(Arc::new(AtomicBool::new(false)))
+    };
+    // the sum of the squared steps between neighbouring pixels
+    let roughness = |pixels: &[RGB]| {
+        let mut sum = 0.0;
+        for y in 0..31 {
+            for x in 0..31 {
+                let c = pixels[y * 32 + x].r;
+                sum += (pixels[y * 32 + x + 1].r - c).powi(2);
+                sum += (pixels[(y + 1) * 32 + x].r - c).powi(2);
+            }
+        }
+        sum
+    };
+    let (flat, blended) = (render(false), render(true));
+    assert!(
+        roughness(&blended) < 0.95 * roughness(&flat),
+        "{} vs {}",
+        roughness(&blended),
+        roughness(&flat)
+    );
+    // away from the outline, nothing to blend
+    assert_eq!(flat[16 * 32 + 16], blended[16 * 32 + 16]);
+    assert_eq!(flat[0], blended[0]);
+}
+
+#[test]
+fn motion_blur_streak() {
+    // the red sphere moves across the view while the shutter is open
+    let render = |shutter_time: Float| {
+        let cfg = RenderConfig {
+            seed: Some(1),
+            ..Default::default()
+        }
+        .with_integrator(Integrator::Path, 8)
+        .with_scene_file("scenes/motion-blur.json")
+        .with_resolution(40, 30);
+        let mut job = load_scene(cfg).unwrap();
+        job.camera.shutter_time = shutter_time;
+        // a black floor, which the sphere cannot tint red with bounced light
+        job.materials[0] = Arc::new(Material::default());
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap().clone();
+        // the width of the columns where the sphere shows
+        let red: Vec<usize> = (0..40 * 30)
+            .filter(|&i| pixels[i].r > 2.0 * pixels[i].g)
+            .map(|i| i % 40)
+            .collect();
+        red.iter().max().unwrap() - red.iter().min().unwrap()
+    };
+    let sharp = render(0.0);
+    let blurred = render(1.0);
+    assert!(sharp > 0);
+    assert!(blurred > sharp + 8, "{blurred} vs {sharp}");
+}
+
+#[test]
+fn normal_map_adds_detail() {
+    let render = |use_map: bool| {
+        let cfg = RenderConfig::default()
+            .with_scene_file("scenes/brick-wall.json")
+            .with_resolution(80, 60);
+        let mut job = load_scene(cfg).unwrap();
+        if !use_map {
+            let mut mat = (*job.materials[1]).clone();
+            mat.normal_texture = None;
+            job.materials[1] = Arc::new(mat);
+        }
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap().clone();
+        (pixels, job.objects.len())
+    };
+    // the differences between neighbors across the middle of the wall
+    let roughness = |pixels: &[RGB]| -> f32 {
+        let row = |y: usize| (20..60).map(move |x| pixels[y * 80 + x].luminance());
+        (20..40)
+            .map(|y| {
+                row(y)
+                    .zip(row(y + 1))
+                    .map(|(a, b)| (a - b).abs())
+                    .sum::<f32>()
+            })
+            .sum()
+    };
+    let (flat, num_flat) = render(false);
+    let (bumped, num_bumped) = render(true);
+    assert_eq!(num_flat, num_bumped);
+    let (r_flat, r_bumped) = (roughness(&flat), roughness(&bumped));
+    assert!(r_bumped > 3.0 * r_flat, "{r_bumped} vs {r_flat}");
+}
+
+#[test]
+fn chromatic_aberration_fringes() {
+    let render = |dispersion: Float| {
+        let cfg = RenderConfig::default()
+            .with_scene_file("scenes/chromatic.json")
+            .with_resolution(80, 60);
+        let mut job = load_scene(cfg).unwrap();
+        job.camera.dispersion = dispersion;
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels = hdr.lock().unwrap().clone();
+        let num_rays = job.total_stats.lock().unwrap().num_rays_sampling;
+        (pixels, num_rays)
+    };
+    let fringe = |pixels: &[RGB]| pixels.iter().map(|c| (c.r - c.b).abs()).fold(0.0, f32::max);
+    let (sharp, num_sharp) = render(0.0);
+    let (fringed, num_fringed) = render(0.015);
+    // a gray scene: only the dispersion colors it
+    assert!(fringe(&sharp) < 1e-4);
+    assert!(fringe(&fringed) > 0.5, "{}", fringe(&fringed));
+    assert_eq!(num_fringed, 3 * num_sharp);
+}
+
+#[test]
+fn shadow_bias_removes_acne() {
+    let render = |shadow_bias: Float| {
+        let cfg = RenderConfig {
+            shadow_bias,
+            ..RenderConfig::default()
+                .with_scene_file("scenes/far-from-origin.json")
+                .with_resolution(80, 60)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        hdr.lock().unwrap().clone()
+    };
+    // pixels darker than with a bias well past the rounding errors
+    let reference = render(0.01);
+    let num_acne = |pixels: &[RGB]| {
+        let darker = |(c, r): (&RGB, &RGB)| c.luminance() < 0.8 * r.luminance();
+        pixels.iter().zip(&reference).filter(|&p| darker(p)).count()
+    };
+    let num_small = num_acne(&render(1e-4));
+    let num_default = num_acne(&render(RenderConfig::default().shadow_bias));
+    assert!(num_small > 20, "{num_small}");
+    // the bias moves the terminator of the sphere by a pixel at most
+    assert!(num_default <= 2, "{num_default} vs {num_small}");
+}
+
+#[test]
+fn render_to_buffer_without_files() {
+    let scene = r#"{
+        "resolution": [16, 16],
+        "camera": {
+            "pos": { "x": -4.0, "y": 0.0, "z": 0.0 },
+            "look_at": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "up": { "x": 0, "y": 0, "z": 1 },
+            "vfov": 40.0
+        },
+        "spot-light.0": {
+            "intensity": 10.0,
+            "pos": { "x": -3.0, "y": 1.0, "z": 2.0 },
+            "rgb": { "r": 1.0, "g": 1.0, "b": 1.0 }
+        },
+        "material.0": { "kd": { "r": 1.0, "g": 0.1, "b": 0.1 } },
+        "sphere.0": {
+            "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "radius": 1.0,
+            "material_id": 0
+        }
+    }"#;
+    let file = std::env::temp_dir().join("rayflex-render-to-buffer.json");
+    std::fs::write(&file, scene).unwrap();
+    let image_file = std::env::temp_dir().join("rayflex-render-to-buffer.png");
+    let _ = std::fs::remove_file(&image_file);
+    let cfg = RenderConfig::default()
+        .with_scene_file(&file)
+        .with_image_file(&image_file);
+    let mut job = load_scene(cfg).unwrap();
+    let pixels = job.render_to_buffer(Arc::new(AtomicBool::new(false)));
+
+    assert_eq!(pixels.len(), 16 * 16);
+    // the red sphere, not the bluish background
+    let center = pixels[8 * 16 + 8];
+    assert!(center.r > 2.0 * center.b, "{center:?}");
+    let corner = pixels[0];
+    assert!(corner.b > corner.r, "{corner:?}");
+    assert!(!image_file.exists());
+}
+
+#[test]
+fn light_sampling_converges_faster() {
+    let render = |use_light_sampling: bool, seed: u64, spp: u32| {
+        let cfg = RenderConfig {
+            use_light_sampling,
+            seed: Some(seed),
+            ..RenderConfig::default()
+                .with_scene_file("scenes/cornell-box-small-light.json")
+                .with_resolution(32, 32)
+                .with_integrator(Integrator::Path, spp)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        job.render_to_buffer(Arc::new(AtomicBool::new(false)))
+    };
+    let mean = |pixels: &[RGB]| pixels.iter().map(|c| c.luminance()).sum::<f32>() / 1024.0;
+    // two renders of other seeds differ by about twice their noise
+    let noise = |a: &[RGB], b: &[RGB]| {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a.luminance() - b.luminance()).abs())
+            .sum::<f32>()
+            / 1024.0
+    };
+    let (bounced, bounced_2) = (render(false, 1, 16), render(false, 2, 16));
+    let (sampled, sampled_2) = (render(true, 1, 16), render(true, 2, 16));
+    let (noise_bounced, noise_sampled) = (noise(&bounced, &bounced_2), noise(&sampled, &sampled_2));
+    assert!(
+        noise_sampled * 4.0 < noise_bounced,
+        "{noise_sampled} vs {noise_bounced}"
+    );
+    // the same image on average: the lights are not counted twice
+    let (m_bounced, m_sampled) = (mean(&render(false, 3, 256)), mean(&sampled));
+    assert!(
+        (m_sampled / m_bounced - 1.0).abs() < 0.05,
+        "{m_sampled} vs {m_bounced}"
+    );
+}
+
+#[test]
+fn path_area_light() {
+    // the rectangle light is only reached by aiming at it
+    let render = |integrator: Integrator| {
+        let cfg = RenderConfig {
+            seed: Some(1),
+            ..RenderConfig::default()
+                .with_scene_file("scenes/cornell-box-area.json")
+                .with_resolution(40, 40)
+                .with_integrator(integrator, 16)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        job.render_to_buffer(Arc::new(AtomicBool::new(false)))
+    };
+    let mean = |pixels: &[RGB]| pixels.iter().map(|c| c.luminance()).sum::<f32>() / 1600.0;
+    let (whitted, path) = (
+        mean(&render(Integrator::Whitted)),
+        mean(&render(Integrator::Path)),
+    );
+    assert!(whitted > 0.0, "{whitted}");
+    assert!(path > 0.1 * whitted, "{path} vs {whitted}");
+}
+
+#[test]
+fn area_light_shadows_are_seeded() {
+    // the penumbra is sampled from the pixel's own random state
+    let render = || {
+        let cfg = RenderConfig {
+            seed: Some(1),
+            num_shadow_samples: 4,
+            ..RenderConfig::default()
+                .with_scene_file("scenes/cornell-box-area.json")
+                .with_resolution(40, 40)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        job.render_to_buffer(Arc::new(AtomicBool::new(false)))
+    };
+    assert!(render() == render());
+}
+
+#[test]
+fn reflection_cutoff_skips_faint_mirrors() {
+    // a gray mirror that reflects a red sphere behind the camera
+    let scene = r#"{
+        "resolution": [16, 16],
+        "camera": {
+            "pos": { "x": -4.0, "y": 0.0, "z": 0.0 },
+            "look_at": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "up": { "x": 0, "y": 0, "z": 1 },
+            "vfov": 20.0
+        },
+        "spot-light.0": {
+            "intensity": 10.0,
+            "pos": { "x": -3.0, "y": 1.0, "z": 2.0 },
+            "rgb": { "r": 1.0, "g": 1.0, "b": 1.0 }
+        },
+        "material.0": {
+            "kd": { "r": 0.2, "g": 0.2, "b": 0.2 },
+            "ks": { "r": 0.5, "g": 0.5, "b": 0.5 }
+        },
+        "material.1": { "kd": { "r": 1.0, "g": 0.0, "b": 0.0 } },
+        "sphere.0": {
+            "center": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "radius": 1.0,
+            "material_id": 0
+        },
+        "sphere.1": {
+            "center": { "x": -10.0, "y": 0.0, "z": 0.0 },
+            "radius": 4.0,
+            "material_id": 1
+        }
+    }"#;
+    let file = std::env::temp_dir().join("rayflex-reflection-cutoff.json");
+    std::fs::write(&file, scene).unwrap();
+    let render = |reflection_cutoff: f32| {
+        let cfg = RenderConfig {
+            reflection_cutoff,
+            ..RenderConfig::default().with_scene_file(&file)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        let pixels = job.render_to_buffer(Arc::new(AtomicBool::new(false)));
+        pixels[8 * 16 + 8]
+    };
+    let mirror = render(0.0);
+    let cut = render(0.9);
+    assert!(mirror.r > 2.0 * mirror.g, "{mirror:?}");
+    assert!((cut.r - cut.g).abs() < 1e-3, "{cut:?}");
+    assert!(mirror.r > cut.r + 0.1, "{mirror:?} vs {cut:?}");
+}
+
+#[test]
+fn environment_sampling_converges_faster() {
+    // without the sampling, only the bounces that happen to reach the sun see it
+    let render = |use_light_sampling: bool, seed: u64, spp: u32| {
+        let cfg = RenderConfig {
+            use_light_sampling,
+            seed: Some(seed),
+            ..RenderConfig::default()
+                .with_scene_file("scenes/environment.json")
+                .with_resolution(32, 24)
+                .with_integrator(Integrator::Path, spp)
+        };
+        let mut job = load_scene(cfg).unwrap();
+        job.render_to_buffer(Arc::new(AtomicBool::new(false)))
+    };
+    let n = 32.0 * 24.0;
+    let mean = |pixels: &[RGB]| pixels.iter().map(|c| c.luminance()).sum::<f32>() / n;
+    let noise = |a: &[RGB], b: &[RGB]| {
+        a.iter()
+            .zip(b)
+            .map(|(a, b)| (a.luminance() - b.luminance()).abs())
+            .sum::<f32>()
+            / n
+    };
+    let (bounced, bounced_2) = (render(false, 1, 16), render(false, 2, 16));
+    let (sampled, sampled_2) = (render(true, 1, 16), render(true, 2, 16));
+    let (noise_bounced, noise_sampled) = (noise(&bounced, &bounced_2), noise(&sampled, &sampled_2));
+    assert!(
+        noise_sampled * 1.5 < noise_bounced,
+        "{noise_sampled} vs {noise_bounced}"
+    );
+    // the same image on average: the sky is weighted, not counted twice
+    let (m_bounced, m_sampled) = (mean(&render(false, 3, 256)), mean(&sampled));
+    assert!(
+        (m_sampled / m_bounced - 1.0).abs() < 0.02,
+        "{m_sampled} vs {m_bounced}"
+    );
+}
@@ -0,0 +1,739 @@
+use rayflex::Ray;
+use rayflex::RenderStats;
+use rayflex::camera::Camera;
+use rayflex::color::RGB;
+use rayflex::material::Material;
+use rayflex::model::load_model_file;
+use rayflex::render::Integrator;
+use rayflex::render::RenderConfig;
+use rayflex::render::get_nearest_hit;
+use rayflex::scene::SceneBuilder;
+use rayflex::scene::SceneError;
+use rayflex::scene::load_scene;
+use rayflex::three_d::Sphere;
+use rayflex::three_d::Triangle;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+fn test_config(scene_file: &str) -> RenderConfig {
+    RenderConfig::default().with_scene_file(scene_file)
+}
+
+#[test]
+fn scene_order_is_stable() -> Result<(), Box<dyn std::error::Error>> {
+    let job0 = load_scene(test_config("scenes/sphere-box.json"))?;
+    let job1 = load_scene(test_config("scenes/sphere-box.json"))?;
+
+    let objs0: Vec<usize> = job0.objects.iter().map(|o| o.get_material_id()).collect();
+    let objs1: Vec<usize> = job1.objects.iter().map(|o| o.get_material_id()).collect();
+    assert_eq!(objs0, objs1);
+
+    let mats0: Vec<String> = job0.materials.iter().map(|m| format!("{m:?}")).collect();
+    let mats1: Vec<String> = job1.materials.iter().map(|m| format!("{m:?}")).collect();
+    assert_eq!(mats0, mats1);
+
+    let lights0: Vec<_> = job0.lights.iter().map(|l| l.get_color()).collect();
+    let lights1: Vec<_> = job1.lights.iter().map(|l| l.get_color()).collect();
+    assert!(lights0 == lights1);
+
+    // planes come first, then spheres, each sorted by key index
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let json: serde_json::Value = serde_json::from_str(&data)?;
+    let mut expected = vec![];
+    for prefix in ["plane", "sphere"] {
+        let mut i = 0;
+        while let Some(id) = json[format!("{prefix}.{i}")]["material_id"].as_u64() {
+            expected.push(id as usize);
+            i += 1;
+        }
+    }
+    assert_eq!(objs0, expected);
+
+    Ok(())
+}
+
+#[test]
+fn scene_zero_radius_sphere() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["sphere.3"]["radius"] = serde_json::json!(0.0);
+    let path = std::env::temp_dir().join("rayflex-zero-radius.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    let res = load_scene(test_config(path.to_str().unwrap()));
+    let err = res.err().expect("scene should fail to load");
+    let msg = err.to_string();
+    assert!(msg.contains("sphere.3") && msg.contains("radius"), "{msg}");
+
+    Ok(())
+}
+
+#[test]
+fn scene_default_material() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    let num_materials = json
+        .as_object()
+        .unwrap()
+        .keys()
+        .filter(|k| k.starts_with("material."))
+        .count();
+    json["sphere.3"]
+        .as_object_mut()
+        .unwrap()
+        .remove("material_id");
+    json["default_material"] = serde_json::json!({ "kd": { "r": 0.1, "g": 0.2, "b": 0.3 } });
+    let path = std::env::temp_dir().join("rayflex-default-material.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    let job = load_scene(test_config(path.to_str().unwrap()))?;
+    assert_eq!(job.materials.len(), num_materials + 1);
+    let mat = &job.materials[num_materials];
+    assert!((mat.kd.g - 0.2).abs() < 1e-6);
+    assert!(
+        job.objects
+            .iter()
+            .any(|o| o.get_material_id() == num_materials)
+    );
+
+    // explicit ids must still name an existing material
+    json["sphere.3"]["material_id"] = serde_json::json!(num_materials + 5);
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    let err = load_scene(test_config(path.to_str().unwrap()))
+        .err()
+        .expect("out of range id");
+    let msg = err.to_string();
+    assert!(
+        msg.contains("sphere.3") && msg.contains("material_id"),
+        "{msg}"
+    );
+
+    // nor the default material, once an earlier object has added it
+    json["sphere.3"]
+        .as_object_mut()
+        .unwrap()
+        .remove("material_id");
+    json["sphere.4"]["material_id"] = serde_json::json!(num_materials);
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    match load_scene(test_config(path.to_str().unwrap())) {
+        Err(SceneError::MaterialIndexOutOfRange { key, id, num }) => {
+            assert_eq!(
+                (key.as_str(), id, num),
+                ("sphere.4", num_materials, num_materials)
+            )
+        }
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("material_id of the default material accepted"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn scene_camera_index() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    let mut second = json["camera"].clone();
+    second["pos"]["x"] = serde_json::json!(-5.0);
+    json["camera.0"] = json["camera"].take();
+    json["camera.1"] = second;
+    let path = std::env::temp_dir().join("rayflex-cameras.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    let mut cfg = test_config(path.to_str().unwrap());
+    cfg.camera = 1;
+    let job = load_scene(cfg)?;
+    assert!((job.camera.pos.x + 5.0).abs() < 1e-6);
+
+    let mut cfg = test_config(path.to_str().unwrap());
+    cfg.camera = 2;
+    assert!(load_scene(cfg).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn scene_camera_resolution() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/ortho.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["camera.1"]["resolution"] = serde_json::json!([300, 400]);
+    let path = std::env::temp_dir().join("rayflex-camera-resolution.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    // camera 0 falls back to the top-level resolution
+    let job = load_scene(test_config(path.to_str().unwrap()))?;
+    assert_eq!((job.cfg.res_x, job.cfg.res_y), (400, 300));
+    assert!((job.camera.aspect - 4.0 / 3.0).abs() < 1e-6);
+
+    let mut cfg = test_config(path.to_str().unwrap());
+    cfg.camera = 1;
+    let job = load_scene(cfg)?;
+    assert_eq!((job.cfg.res_x, job.cfg.res_y), (300, 400));
+    assert!((job.camera.aspect - 0.75).abs() < 1e-6);
+
+    // the command line still wins
+    let mut cfg = test_config(path.to_str().unwrap()).with_resolution(64, 32);
+    cfg.camera = 1;
+    let job = load_scene(cfg)?;
+    assert_eq!((job.cfg.res_x, job.cfg.res_y), (64, 32));
+
+    json["camera.1"]["resolution"] = serde_json::json!([300]);
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    let mut cfg = test_config(path.to_str().unwrap());
+    cfg.camera = 1;
+    assert!(matches!(load_scene(cfg), Err(SceneError::BadResolution)));
+
+    Ok(())
+}
+
+#[test]
+fn seeded_noise_per_frame() -> Result<(), Box<dyn std::error::Error>> {
+    let render = |seed: Option<u64>, seed_per_frame: bool, frame: u32| {
+        let cfg = RenderConfig {
+            seed,
+            seed_per_frame,
+            frame,
+            ..test_config("scenes/cornell-box.json")
+        }
+        .with_resolution(16, 12)
+        .with_integrator(Integrator::Path, 4);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        let pixels: Vec<RGB> = hdr.lock().unwrap().clone();
+        pixels
+    };
+
+    // static noise: same image for every frame
+    let frame0 = render(Some(7), false, 0);
+    assert!(frame0 == render(Some(7), false, 0));
+    assert!(frame0 == render(Some(7), false, 1));
+    assert!(frame0 != render(Some(8), false, 0));
+
+    // animated noise: reproducible, but different per frame
+    let frame1 = render(Some(7), true, 1);
+    assert!(frame1 == render(Some(7), true, 1));
+    assert!(frame1 != render(Some(7), true, 2));
+
+    Ok(())
+}
+
+#[test]
+fn scene_disks_and_edge_quads() -> Result<(), Box<dyn std::error::Error>> {
+    let job = load_scene(test_config("scenes/disks.json"))?;
+    // the quad is two triangles, the emissive disk a light
+    assert_eq!(job.objects.len(), 6);
+    let ray = Ray::new(Point::new(3.0, 0.0, 0.0), Vec3::unity_z());
+    let hit = get_nearest_hit(&job.objects, &mut RenderStats::default(), &ray).unwrap();
+    assert!((hit.t - 2.0).abs() < 1e-5);
+    assert_eq!(job.objects[hit.object].get_material_id(), 2);
+    Ok(())
+}
+
+#[test]
+fn scene_camera_view() -> Result<(), Box<dyn std::error::Error>> {
+    let job = load_scene(test_config("scenes/dof.json"))?;
+    let view = job.camera.get_view().orbit(1.0, 0.2).dolly(0.5);
+    let cfg = RenderConfig {
+        camera_view: Some(view),
+        ..test_config("scenes/dof.json")
+    };
+    let moved = load_scene(cfg)?;
+    assert!(moved.camera.pos == view.pos && moved.camera.look_at == view.look_at);
+    // the lens of the scene camera is kept
+    assert!(moved.camera.aperture == job.camera.aperture && moved.camera.aperture > 0.0);
+    assert!(moved.camera.aspect == job.camera.aspect);
+    Ok(())
+}
+
+#[test]
+fn scene_bounded_floor() -> Result<(), Box<dyn std::error::Error>> {
+    let job = load_scene(test_config("scenes/bounded-floor.json"))?;
+    let mut stats = RenderStats::default();
+    // straight down, on the floor then past its edges
+    let down = |x: Float, y: Float| Ray::new(Point::new(x, y, 5.0), Vec3::unity_z() * -1.0);
+    let hit = get_nearest_hit(&job.objects, &mut stats, &down(1.9, 1.4)).unwrap();
+    assert!((hit.t - 5.0).abs() < 1e-5);
+    assert!(get_nearest_hit(&job.objects, &mut stats, &down(2.1, 0.0)).is_none());
+    assert!(get_nearest_hit(&job.objects, &mut stats, &down(0.0, -1.6)).is_none());
+    Ok(())
+}
+
+#[test]
+fn scene_object_transform() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/test.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    let load = |json: &serde_json::Value| {
+        let path = std::env::temp_dir().join("rayflex-transform.json");
+        std::fs::write(&path, serde_json::to_string(json).unwrap()).unwrap();
+        load_scene(test_config(path.to_str().unwrap()))
+    };
+    let aabb = load(&json)?.objects[0].get_aabb().unwrap();
+    let transform = serde_json::json!({
+        "translate": { "x": 10.0, "y": 0.0, "z": 0.0 },
+        "scale": { "x": 2.0, "y": 2.0, "z": 2.0 }
+    });
+    json["obj.0.transform"] = transform.clone();
+    let moved = load(&json)?.objects[0].get_aabb().unwrap();
+    let offset = Vec3::new(10.0, 0.0, 0.0);
+    assert!(moved.p_min.distance(aabb.p_min * 2.0 + offset) < 1e-4);
+    assert!(moved.p_max.distance(aabb.p_max * 2.0 + offset) < 1e-4);
+
+    json.as_object_mut().unwrap().remove("obj.0.path");
+    json["sphere.0"] = serde_json::json!({
+        "center": { "x": 0.0, "y": 0.0, "z": 1.0 },
+        "radius": 1.0,
+        "material_id": 0,
+        "transform": transform
+    });
+    let job = load(&json)?;
+    // scaled to radius 2 about (0, 0, 2), then moved to x = 10
+    let ray = Ray::new(Point::new(10.0, 0.0, 20.0), Vec3::unity_z() * -1.0);
+    let hit = get_nearest_hit(&job.objects, &mut RenderStats::default(), &ray).unwrap();
+    assert!((hit.t - 16.0).abs() < 1e-4);
+
+    json["sphere.0"]["transform"]["scale"]["x"] = serde_json::json!(3.0);
+    let msg = load(&json)
+        .err()
+        .expect("non-uniform sphere scale")
+        .to_string();
+    assert!(
+        msg.contains("sphere.0") && msg.contains("non-uniform"),
+        "{msg}"
+    );
+    Ok(())
+}
+
+#[test]
+fn scene_quads_match_planes() {
+    let render = |scene_file: &str| {
+        let cfg = test_config(scene_file).with_resolution(32, 32);
+        let mut job = load_scene(cfg).unwrap();
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        hdr.lock().unwrap().clone()
+    };
+    let planes = render("scenes/cornell-box.json");
+    let quads = render("scenes/cornell-box-quads.json");
+    assert!(
+        planes
+            .iter()
+            .zip(&quads)
+            .all(|(a, b)| a.distance2(*b) < 1e-6)
+    );
+}
+
+#[test]
+fn scene_builder_errors() {
+    let pos = Point::new(-5.0, 0.0, 0.0);
+    let camera = || Camera::new(pos, Point::zero(), Vec3::unity_z(), 40.0, 1.0);
+    let cfg = || RenderConfig::default().with_resolution(8, 8);
+
+    let mut builder = SceneBuilder::new();
+    let mat = builder.add_material(Material::default());
+    assert_eq!(
+        builder
+            .add_sphere(Sphere::new(Point::zero(), 1.0, mat).unwrap())
+            .unwrap(),
+        0
+    );
+    let err = builder.add_sphere(Sphere::new(Point::zero(), 1.0, mat + 1).unwrap());
+    match err {
+        Err(SceneError::MaterialIndexOutOfRange { key, id, num }) => {
+            assert_eq!(key, "sphere.1");
+            assert_eq!((id, num), (mat + 1, 1));
+        }
+        _ => panic!("out of range material accepted"),
+    }
+    assert!(matches!(
+        builder.build(cfg()),
+        Err(SceneError::MissingCamera)
+    ));
+
+    let mut builder = SceneBuilder::new();
+    builder.set_camera(camera());
+    let res = builder.build(RenderConfig::default());
+    assert!(matches!(res, Err(SceneError::BadResolution)));
+
+    let mut builder = SceneBuilder::new();
+    builder.set_camera(camera());
+    let job = builder.build(cfg()).unwrap();
+    assert!(job.objects.is_empty());
+    assert!((job.camera.aspect - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn scene_gltf_model() -> Result<(), Box<dyn std::error::Error>> {
+    let (models, materials) = load_model_file(Path::new("obj/box.gltf"))?;
+    assert_eq!(models.len(), 1);
+    let model = &models[0];
+    assert_eq!(model.num_triangles(), 12);
+    assert_eq!((model.normals.len(), model.uvs.len()), (12, 12));
+    assert_eq!(model.material_id, Some(0));
+    // the node lifts the unit box onto z = 0
+    let z_min = model
+        .positions
+        .iter()
+        .map(|p| p.z)
+        .fold(Float::MAX, Float::min);
+    assert!(z_min.abs() < 1e-5);
+
+    // red plastic: no metal, so no mirror reflection
+    assert_eq!(materials.len(), 1);
+    let mat = &materials[0];
+    assert!(mat.kd.r > 0.7 && mat.kd.g < 0.2);
+    assert!(mat.ks.is_zero());
+
+    let job = load_scene(test_config("scenes/gltf-box.json"))?;
+    assert_eq!(job.materials.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn scene_obj_mtl_glass() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    std::fs::write(
+        dir.join("rayflex-glass.mtl"),
+        "newmtl glass\nKd 1 1 1\nNi 1.5\nd 0.1\n\
+         newmtl tr\nKd 1 1 1\nTr 0.8\n\
+         newmtl opaque\nKd 1 1 1\nNi 1.52\n",
+    )?;
+    let obj = dir.join("rayflex-glass.obj");
+    std::fs::write(
+        &obj,
+        "mtllib rayflex-glass.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl glass\nf 1 2 3\n",
+    )?;
+    let (_, materials) = load_model_file(&obj)?;
+    assert_eq!(materials.len(), 3);
+    let (glass, tr, opaque) = (&materials[0], &materials[1], &materials[2]);
+    assert!((glass.get_ior() - 1.5).abs() < 1e-6);
+    assert!((glass.transparency - 0.9).abs() < 1e-6);
+    assert!((tr.transparency - 0.8).abs() < 1e-6);
+    assert_eq!(opaque.transparency, 0.0);
+    Ok(())
+}
+
+#[test]
+fn scene_emitter_checks() {
+    let eye = Point::new(-5.0, 0.0, 0.0);
+    let mut builder = SceneBuilder::new();
+    let white = builder.add_material(Material {
+        kd: RGB::new(0.8, 0.8, 0.8),
+        ..Default::default()
+    });
+    let light = builder.add_material(Material {
+        ke: RGB::new(4.0, 4.0, 4.0),
+        ..Default::default()
+    });
+    // a visible light, and one seen edge-on: in the plane z = 0 of the eye
+    builder
+        .add_sphere(Sphere::new(Point::new(0.0, 2.0, 2.0), 0.5, light).unwrap())
+        .unwrap();
+    let points = [
+        Point::new(0.0, -1.0, 0.0),
+        Point::new(1.0, -1.0, 0.0),
+        Point::new(0.0, -2.0, 0.0),
+    ];
+    builder
+        .add_triangle(Triangle::new(points, light).unwrap())
+        .unwrap();
+    assert_eq!(
+        builder.check_emitters(eye),
+        ["object.1 is emissive but has no projected area toward the camera"]
+    );
+
+    // a light sunk into an opaque sphere
+    builder
+        .add_sphere(Sphere::new(Point::new(3.0, 0.0, 0.0), 0.2, light).unwrap())
+        .unwrap();
+    builder
+        .add_sphere(Sphere::new(Point::new(3.0, 0.0, 0.0), 1.0, white).unwrap())
+        .unwrap();
+    let warnings = builder.check_emitters(eye);
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(
+        warnings[1],
+        "object.2 is emissive but inside the bounds of object.3"
+    );
+    // not when the camera is inside the same bounds
+    assert_eq!(builder.check_emitters(Point::new(3.0, 0.5, 0.0)).len(), 1);
+}
+
+#[test]
+fn scene_file_missing() {
+    let err = load_scene(test_config("scenes/no-such-scene.json"))
+        .err()
+        .expect("missing file");
+    assert!(matches!(err, SceneError::Io(e) if e.kind() == std::io::ErrorKind::NotFound));
+}
+
+#[test]
+fn scene_adaptive_threshold() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/glass.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    let path = std::env::temp_dir().join("rayflex-adaptive-threshold.json");
+    let render = |json: &serde_json::Value| -> Result<(f32, u64), Box<dyn std::error::Error>> {
+        std::fs::write(&path, serde_json::to_string(json)?)?;
+        let cfg = RenderConfig {
+            use_adaptive_sampling: true,
+            ..test_config(path.to_str().unwrap())
+        }
+        .with_resolution(32, 24);
+        let mut job = load_scene(cfg)?;
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let num_rays = job.total_stats.lock().unwrap().num_rays_sampling;
+        Ok((job.cfg.adaptive_threshold, num_rays))
+    };
+
+    let (threshold, num_rays) = render(&json)?;
+    assert_eq!(threshold, 0.3);
+    // a lower threshold subdivides more
+    json["adaptive_threshold"] = serde_json::json!(0.05);
+    let (threshold, num_rays_fine) = render(&json)?;
+    assert_eq!(threshold, 0.05);
+    assert!(num_rays_fine > num_rays);
+
+    json["adaptive_threshold"] = serde_json::json!(-1.0);
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    let res = load_scene(test_config(path.to_str().unwrap()));
+    assert!(matches!(res, Err(SceneError::Invalid { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn scene_background_checks() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    let path = std::env::temp_dir().join("rayflex-background.json");
+    let white = serde_json::json!({ "r": 1.0, "g": 1.0, "b": 1.0 });
+    let black = serde_json::json!({ "r": 0.0, "g": 0.0, "b": 0.0 });
+
+    json["background"] = serde_json::json!({ "stops": [[0.0, black], [1.0, white]] });
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    load_scene(test_config(path.to_str().unwrap()))?;
+
+    // no stop to evaluate, or stops the evaluation would walk past
+    for stops in [
+        serde_json::json!([]),
+        serde_json::json!([[1.0, white], [0.0, black]]),
+    ] {
+        json["background"] = serde_json::json!({ "stops": stops });
+        std::fs::write(&path, serde_json::to_string(&json)?)?;
+        match load_scene(test_config(path.to_str().unwrap())) {
+            Err(SceneError::Invalid { key, .. }) => assert_eq!(key, "background"),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("background {stops} accepted"),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn scene_malformed_entry() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read_to_string("scenes/sphere-box.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["sphere.1"]["radius"] = serde_json::json!("large");
+    let path = std::env::temp_dir().join("rayflex-malformed-entry.json");
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+
+    // a malformed entry is reported, not taken as the end of the list
+    match load_scene(test_config(path.to_str().unwrap())) {
+        Err(SceneError::Json { field, .. }) => assert_eq!(field, "sphere.1"),
+        Err(e) => panic!("unexpected error: {e}"),
+        Ok(_) => panic!("malformed sphere accepted"),
+    }
+
+    json["resolution"] = serde_json::json!([640]);
+    std::fs::write(&path, serde_json::to_string(&json)?)?;
+    let res = load_scene(test_config(path.to_str().unwrap()));
+    assert!(matches!(res, Err(SceneError::BadResolution)));
+
+    Ok(())
+}
+
+#[test]
+fn scene_arrays() -> Result<(), Box<dyn std::error::Error>> {
+    // glass.json uses the arrays: write it back with numbered keys
+    let data = std::fs::read_to_string("scenes/glass.json")?;
+    let json: serde_json::Value = serde_json::from_str(&data)?;
+    let mut numbered = json.as_object().unwrap().clone();
+    for (array, prefix) in [
+        ("materials", "material"),
+        ("planes", "plane"),
+        ("spheres", "sphere"),
+    ] {
+        let items = numbered.remove(array).unwrap();
+        for (i, v) in items.as_array().unwrap().iter().enumerate() {
+            numbered.insert(format!("{prefix}.{i}"), v.clone());
+        }
+    }
+    let mut spot = numbered.remove("lights").unwrap()[0].clone();
+    spot.as_object_mut().unwrap().remove("type");
+    numbered.insert("spot-light.0".to_owned(), spot);
+    let path = std::env::temp_dir().join("rayflex-numbered.json");
+    std::fs::write(&path, serde_json::to_string(&numbered)?)?;
+
+    let render = |file: &str| -> Result<_, Box<dyn std::error::Error>> {
+        let mut job = load_scene(test_config(file).with_resolution(16, 12))?;
+        assert_eq!(
+            (job.objects.len(), job.materials.len(), job.lights.len()),
+            (4, 4, 2)
+        );
+        job.alloc_image();
+        job.render_scene(Arc::new(AtomicBool::new(false)));
+        let hdr = job.image.lock().unwrap().get_hdr();
+        Ok(hdr.lock().unwrap().clone())
+    };
+    assert!(render("scenes/glass.json")? == render(path.to_str().unwrap())?);
+
+    // errors name the element of the array
+    let path = std::env::temp_dir().join("rayflex-arrays.json");
+    let load = |json: &serde_json::Value| {
+        std::fs::write(&path, serde_json::to_string(json).unwrap()).unwrap();
+        load_scene(test_config(path.to_str().unwrap()))
+            .err()
+            .expect("invalid scene")
+            .to_string()
+    };
+    let mut bad = json.clone();
+    bad["spheres"][1]["radius"] = serde_json::json!("big");
+    let msg = load(&bad);
+    assert!(msg.starts_with("spheres[1]: "), "{msg}");
+    bad["spheres"][1]["radius"] = serde_json::json!(0.0);
+    let msg = load(&bad);
+    assert!(
+        msg.starts_with("spheres[1]: ") && msg.contains("radius"),
+        "{msg}"
+    );
+    bad["spheres"][1]["radius"] = serde_json::json!(1.0);
+    bad["spheres"][1]["material_id"] = serde_json::json!(9);
+    assert!(load(&bad).starts_with("spheres[1]: material_id 9"));
+
+    let mut bad = json.clone();
+    bad["lights"][0]["type"] = serde_json::json!("laser");
+    let msg = load(&bad);
+    assert!(
+        msg.starts_with("lights[0]: unknown light type 'laser'"),
+        "{msg}"
+    );
+    bad["lights"] = serde_json::json!({ "type": "spot" });
+    assert!(load(&bad).starts_with("lights: must be an array"));
+
+    Ok(())
+}
+
+#[test]
+fn scene_material_names() -> Result<(), Box<dyn std::error::Error>> {
+    // the cornell box brings its MTL materials after material.0
+    let data = std::fs::read_to_string("scenes/test.json")?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    json["material.0"]["name"] = serde_json::json!("white");
+    json["sphere.0"] = serde_json::json!({
+        "center": { "x": 0.0, "y": 1.0, "z": 0.0 },
+        "radius": 0.5,
+        "material": "BloodyRed"
+    });
+    json["plane.0"] = serde_json::json!({
+        "point": { "x": 0.0, "y": 0.0, "z": 0.0 },
+        "normal": { "x": 0.0, "y": 1.0, "z": 0.0 },
+        "material": "white",
+        "back_material": "Glass"
+    });
+    let path = std::env::temp_dir().join("rayflex-material-names.json");
+    let load = |json: &serde_json::Value| {
+        std::fs::write(&path, serde_json::to_string(json).unwrap()).unwrap();
+        load_scene(test_config(path.to_str().unwrap()).with_resolution(16, 16))
+    };
+    let job = load(&json)?;
+    let name = |id: usize| job.materials[id].name.clone().unwrap_or_default();
+    assert_eq!(name(job.objects[0].get_material_id()), "white");
+    assert_eq!(name(job.objects[0].get_face_material_id(1)), "Glass");
+    assert_eq!(name(job.objects[1].get_material_id()), "BloodyRed");
+    // no default material was needed
+    assert!(job.materials.iter().all(|m| m.name.is_some()));
+
+    json["sphere.0"]["material"] = serde_json::json!("red");
+    match load(&json) {
+        Err(SceneError::UnknownMaterial { key, name }) => {
+            assert_eq!((key.as_str(), name.as_str()), ("sphere.0", "red"));
+        }
+        _ => panic!("expected an unknown material"),
+    }
+    json["sphere.0"]["material"] = serde_json::json!("white");
+    json["sphere.0"]["material_id"] = serde_json::json!(0);
+    assert!(load(&json).is_err());
+    json["sphere.0"]
+        .as_object_mut()
+        .unwrap()
+        .remove("material_id");
+    json["material.1"] = serde_json::json!({ "name": "white" });
+    let msg = load(&json).err().expect("duplicate name").to_string();
+    assert_eq!(msg, "material.1: material 'white' is already defined");
+
+    Ok(())
+}
+
+#[test]
+fn mesh_fix_winding() -> Result<(), Box<dyn std::error::Error>> {
+    let flip = |indices: &mut Vec<u32>, i: usize| indices.swap(3 * i + 1, 3 * i + 2);
+
+    // a cow with every third triangle facing inward
+    let (mut models, _) = load_model_file(Path::new("obj/cow.obj"))?;
+    let cow = &mut models[0];
+    let indices = cow.indices.clone();
+    assert_eq!(cow.fix_winding(), 0);
+    let n = cow.num_triangles();
+    (0..n).step_by(3).for_each(|i| flip(&mut cow.indices, i));
+    assert_eq!(cow.fix_winding(), n.div_ceil(3));
+    assert!(cow.indices == indices);
+
+    // a box turned inside out, with its vertices repeated per face
+    let (mut models, _) = load_model_file(Path::new("obj/box.gltf"))?;
+    let model = &mut models[0];
+    let (indices, normals) = (model.indices.clone(), model.normals.clone());
+    (0..12).for_each(|i| flip(&mut model.indices, i));
+    let swapped = model.normals[0].map(|[a, b, c]| [a, c, b]);
+    model
+        .normals
+        .iter_mut()
+        .for_each(|n| *n = n.map(|[a, b, c]| [a, c, b]));
+    assert!(model.normals[0] == swapped);
+    assert_eq!(model.fix_winding(), 12);
+    assert!(model.indices == indices && model.normals == normals);
+
+    // the walls of the cornell box are open sheets, left alone
+    let (mut models, _) = load_model_file(Path::new("obj/cornell-box.obj"))?;
+    assert!(models.iter_mut().all(|m| m.fix_winding() == 0));
+    Ok(())
+}
+
+#[test]
+fn area_light_is_visible() -> Result<(), Box<dyn std::error::Error>> {
+    let mut job = load_scene(test_config("scenes/cornell-box-area.json").with_resolution(40, 40))?;
+    // the rectangle comes last, and lights the scene once
+    let id = job.objects.len() - 1;
+    assert_eq!(job.object_names[id], "area-light.0");
+    assert!(job.materials[job.objects[id].get_material_id()].ke.r > 0.0);
+    let emitters: Vec<_> = job
+        .lights
+        .iter()
+        .filter_map(|l| l.get_object_id())
+        .collect();
+    assert_eq!(emitters, [id]);
+
+    // the camera sees it at the top of the image
+    job.alloc_image();
+    job.render_scene(Arc::new(AtomicBool::new(false)));
+    let hdr = job.image.lock().unwrap().get_hdr();
+    let pixel = hdr.lock().unwrap()[4 * 40 + 20];
+    assert!(pixel.r > 1.0 && pixel.b > 1.0, "{pixel:?}");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use rayflex::color::RGB;
+use rayflex::texture::Environment;
+use rayflex::texture::Texture;
+use rayflex::vec3::Vec2;
+use rayflex::vec3::Vec3;
+use std::path::Path;
+use std::sync::Arc;
+
+#[test]
+fn texture_bilinear_wrap() {
+    // 2x1: black then white
+    let texture = Texture::new(2, 1, vec![RGB::zero(), RGB::new(1.0, 1.0, 1.0)]);
+    let at = |x: f32| texture.sample(Vec2 { x, y: 0.5 }).r;
+
+    // texel centers
+    assert!(at(0.25).abs() < 1e-6);
+    assert!((at(0.75) - 1.0).abs() < 1e-6);
+    // halfway between them
+    assert!((at(0.5) - 0.5).abs() < 1e-6);
+    // the left edge blends with the last texel
+    assert!((at(0.0) - 0.5).abs() < 1e-6);
+    assert!((at(1.25) - at(0.25)).abs() < 1e-6);
+    assert!((at(-0.25) - at(0.75)).abs() < 1e-6);
+}
+
+#[test]
+fn environment_directions() {
+    let uv = Environment::get_uv(Vec3::unity_x());
+    assert!((uv.x - 0.5).abs() < 1e-6 && (uv.y - 0.5).abs() < 1e-6);
+    let uv = Environment::get_uv(Vec3::unity_y() * 2.0);
+    assert!((uv.x - 0.75).abs() < 1e-6);
+    assert!((Environment::get_uv(Vec3::unity_z()).y - 1.0).abs() < 1e-6);
+    assert!(Environment::get_uv(Vec3::unity_z() * -1.0).y.abs() < 1e-6);
+
+    // 1x2: bright sky over a dark ground
+    let sky = RGB::new(4.0, 4.0, 4.0);
+    let mut env = Environment {
+        path: "sky.hdr".into(),
+        intensity: 0.5,
+        texture: None,
+        distribution: None,
+    };
+    assert!(env.sample(Vec3::unity_z()) == RGB::zero());
+    env.texture = Some(Arc::new(Texture::new(1, 2, vec![sky, RGB::zero()])));
+    assert!(env.sample(Vec3::unity_z()) == sky * 0.5);
+    assert!(env.sample(Vec3::unity_z() * -1.0) == RGB::zero());
+}
+
+#[test]
+fn texture_load_hdr() {
+    let texture = Texture::load_hdr(Path::new("textures/sky.hdr")).unwrap();
+    let mut env = Environment {
+        path: "textures/sky.hdr".into(),
+        intensity: 1.0,
+        texture: None,
+        distribution: None,
+    };
+    env.load().unwrap();
+    // the sun is well above 1
+    let sun = Vec3::new(0.5, -0.866, 0.7);
+    assert!(env.sample(sun).r > 4.0);
+    assert!(format!("{texture:?}") == "Texture: 128x64");
+}
+
+#[test]
+fn environment_importance_sampling() {
+    let mut env = Environment {
+        path: "textures/sky.hdr".into(),
+        intensity: 1.0,
+        texture: None,
+        distribution: None,
+    };
+    assert!(env.sample_dir(0.5, 0.5).is_none());
+    env.load().unwrap();
+
+    // the power of the sky, by importance and by a uniform grid over the sphere
+    let n = 256;
+    let pi = std::f32::consts::PI;
+    let (mut sampled, mut uniform, mut num_mismatched) = (0.0, 0.0, 0);
+    for i in 0..n {
+        for j in 0..n {
+            let u = (i as f32 + 0.5) / n as f32;
+            let v = (j as f32 + 0.5) / n as f32;
+            let (dir, pdf, radiance) = env.sample_dir(u, v).unwrap();
+            assert!((dir.norm() - 1.0).abs() < 1e-4);
+            if (pdf - env.pdf(dir)).abs() > 1e-3 * pdf {
+                num_mismatched += 1;
+            }
+            sampled += radiance.luminance() / pdf;
+            let z = 1.0 - 2.0 * v;
+            let r = (1.0 - z * z).sqrt();
+            let phi = 2.0 * pi * u;
+            let dir = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            uniform += env.sample(dir).luminance() * 4.0 * pi;
+        }
+    }
+    // only the samples on the edge of a texel may read its neighbor
+    assert!(num_mismatched < n * n / 100, "{num_mismatched}");
+    let (sampled, uniform) = (sampled / (n * n) as f32, uniform / (n * n) as f32);
+    assert!(
+        (sampled / uniform - 1.0).abs() < 0.02,
+        "{sampled} vs {uniform}"
+    );
+}
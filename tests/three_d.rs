@@ -0,0 +1,534 @@
+use rayflex::Ray;
+use rayflex::RenderStats;
+use rayflex::simd::SimdLevel;
+use rayflex::three_d::Cuboid;
+use rayflex::three_d::Cylinder;
+use rayflex::three_d::Disk;
+use rayflex::three_d::Mesh;
+use rayflex::three_d::Object;
+use rayflex::three_d::Plane;
+use rayflex::three_d::Quad;
+use rayflex::three_d::Sphere;
+use rayflex::three_d::Torus;
+use rayflex::three_d::Triangle;
+use rayflex::vec3::EPSILON;
+use rayflex::vec3::Float;
+use rayflex::vec3::Point;
+use rayflex::vec3::Vec3;
+
+#[test]
+fn sphere_motion() {
+    let mut stats = RenderStats::default();
+    let mut sphere = Sphere::new(Point::zero(), 0.5, 0).unwrap();
+    sphere.velocity = Vec3::new(2.0, 0.0, 0.0);
+
+    let ray = Ray::new_at(Point::new(2.0, 0.0, -5.0), Vec3::unity_z(), 0.0);
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    assert!(!sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+
+    let ray = Ray::new_at(Point::new(2.0, 0.0, -5.0), Vec3::unity_z(), 1.0);
+    assert!(sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 4.5).abs() < 1e-4);
+}
+
+#[test]
+fn sphere_ellipsoid() {
+    let mut stats = RenderStats::default();
+    let mut sphere = Sphere::new(Point::zero(), 1.0, 0).unwrap();
+    sphere.scale = Vec3::new(2.0, 1.0, 1.0);
+    assert!(sphere.validate().is_ok());
+
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::unity_x());
+    assert!(sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 3.0).abs() < 1e-4);
+    t = Float::MAX;
+    let ray = Ray::new(Point::new(0.0, -5.0, 0.0), Vec3::unity_y());
+    assert!(sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 4.0).abs() < 1e-4);
+
+    // on x^2/4 + y^2 = 1 the gradient is (x/4, y)
+    let p = Point::new(Float::sqrt(2.0), Float::sqrt(0.5), 0.0);
+    let n = sphere.get_normal(p, oid);
+    assert!((n - Vec3::new(1.0, 2.0, 0.0).normalize()).norm() < 1e-5);
+    let aabb = sphere.get_aabb().unwrap();
+    assert!(aabb.p_max == Point::new(2.0, 1.0, 1.0));
+
+    let json = r#"{ "center": [0, 0, 0], "radius": 1, "material_id": 0 }"#;
+    let plain: Sphere = serde_json::from_str(json).unwrap();
+    assert!(plain.scale == Vec3::one() && !plain.is_ellipsoid());
+    sphere.scale.y = 0.0;
+    assert!(sphere.validate().is_err());
+}
+
+#[test]
+fn cuboid_hits() {
+    let mut stats = RenderStats::default();
+    let cuboid = Cuboid::new(Point::new(-1.0, -2.0, -3.0), Point::new(1.0, 2.0, 3.0), 0).unwrap();
+
+    let ray = Ray::new(Point::new(-5.0, 0.5, 0.5), Vec3::unity_x());
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    assert!(cuboid.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 4.0).abs() < 1e-5);
+    assert!(!cuboid.is_inside_hit(oid));
+    let p = ray.orig + ray.dir * t;
+    assert!(cuboid.get_normal(p, oid) == Vec3::new(-1.0, 0.0, 0.0));
+    let uv = cuboid.get_texture_2d(p, oid);
+    assert!((uv.x - 0.625).abs() < 1e-5 && (uv.y - 7.0 / 12.0).abs() < 1e-5);
+
+    // from inside, the exit with the normal facing the ray
+    let ray = Ray::new(Point::zero(), Vec3::unity_z());
+    let mut t = Float::MAX;
+    assert!(cuboid.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 3.0).abs() < 1e-5);
+    assert!(cuboid.is_inside_hit(oid));
+    assert!(cuboid.get_normal(ray.orig + ray.dir * t, oid) == Vec3::new(0.0, 0.0, -1.0));
+
+    let miss = Ray::new(Point::new(-5.0, 2.5, 0.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(!cuboid.intercept(&mut stats, &miss, EPSILON, &mut t, false, &mut oid));
+    let behind = Ray::new(Point::new(5.0, 0.0, 0.0), Vec3::unity_x());
+    assert!(!cuboid.intercept(&mut stats, &behind, EPSILON, &mut t, false, &mut oid));
+    // counted only with the stats feature
+    assert_eq!(
+        stats.num_intersects_box,
+        if cfg!(feature = "stats") { 4 } else { 0 }
+    );
+
+    assert!((cuboid.get_area() - 88.0).abs() < 1e-4);
+    for (u, v) in [(0.0, 0.5), (0.3, 0.2), (0.7, 0.9), (0.99, 0.1)] {
+        let (p, n) = cuboid.sample_surface(u, v).unwrap();
+        assert!(n == cuboid.get_normal(p, 0));
+        assert!(cuboid.get_aabb().unwrap().contains_point(p));
+    }
+    assert!(Cuboid::new(Point::zero(), Point::new(1.0, 0.0, 1.0), 0).is_err());
+}
+
+#[test]
+fn cuboid_rotated() {
+    let mut stats = RenderStats::default();
+    let json = r#"{ "p_min": [-1, -1, -1], "p_max": [1, 1, 1], "material_id": 0,
+                    "rotation": [0, 0, 45] }"#;
+    let mut cuboid: Cuboid = serde_json::from_str(json).unwrap();
+    cuboid.init().unwrap();
+
+    // the edge of the rotated cube now faces the ray
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    assert!(cuboid.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - (5.0 - Float::sqrt(2.0))).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t + Vec3::new(0.0, 0.1, 0.0);
+    let n = cuboid.get_normal(p, oid);
+    assert!((n - Vec3::new(-1.0, 1.0, 0.0).normalize()).norm() < 1e-5);
+
+    let aabb = cuboid.get_aabb().unwrap();
+    assert!((aabb.p_max.x - Float::sqrt(2.0)).abs() < 1e-5);
+    assert!((aabb.p_max.z - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn plane_two_sided() {
+    let mut stats = RenderStats::default();
+    let mut plane = Plane::new(Point::zero(), Vec3::unity_z(), 1).unwrap();
+    plane.back_material_id = Some(2);
+
+    let above = Ray::new(Point::new(0.0, 0.0, 1.0), Vec3::unity_z() * -1.0);
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    assert!(plane.intercept(&mut stats, &above, EPSILON, &mut t, false, &mut oid));
+    let p = above.orig + above.dir * t;
+    assert!(plane.get_normal(p, oid) == Vec3::unity_z());
+    assert_eq!(plane.get_face_material_id(oid), 1);
+
+    let below = Ray::new(Point::new(0.0, 0.0, -1.0), Vec3::unity_z());
+    let mut t = Float::MAX;
+    assert!(plane.intercept(&mut stats, &below, EPSILON, &mut t, false, &mut oid));
+    let p = below.orig + below.dir * t;
+    assert!(plane.get_normal(p, oid) == Vec3::unity_z() * -1.0);
+    assert_eq!(plane.get_face_material_id(oid), 2);
+}
+
+#[test]
+fn plane_bounded() {
+    let mut stats = RenderStats::default();
+    let mut plane = Plane::new(Point::new(1.0, 0.0, 0.0), Vec3::unity_z(), 0).unwrap();
+    plane.u_extent = Some(4.0);
+    plane.v_extent = Some(2.0);
+    assert!(plane.validate().is_ok());
+    // a floor: u along x, v along y
+    assert!(plane.get_basis() == (Vec3::unity_x(), Vec3::unity_y()));
+
+    let mut hits = |x: Float, y: Float| {
+        let ray = Ray::new(Point::new(x, y, 1.0), Vec3::unity_z() * -1.0);
+        let mut t = Float::MAX;
+        let mut oid = 0;
+        plane.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid)
+    };
+    assert!(hits(1.0, 0.0));
+    assert!(hits(2.9, 0.9));
+    assert!(!hits(3.1, 0.0));
+    assert!(!hits(-1.1, 0.0));
+    assert!(!hits(1.0, 1.1));
+
+    // the texture starts at the corner of the rectangle
+    let uv = plane.get_texture_2d(Point::new(-1.0, -1.0, 0.0), 0);
+    assert!(uv.x.abs() < 1e-6 && uv.y.abs() < 1e-6);
+    let aabb = plane.get_aabb().unwrap();
+    assert!(aabb.p_min == Point::new(-1.0, -1.0, 0.0));
+    assert!(aabb.p_max == Point::new(3.0, 1.0, 0.0));
+
+    plane.v_extent = Some(0.0);
+    assert!(plane.validate().is_err());
+    // one extent: a strip, with no bounds
+    plane.v_extent = None;
+    assert!(plane.get_aabb().is_none());
+}
+
+#[test]
+fn constructors_validate() {
+    assert!(Sphere::new(Point::zero(), 0.0, 0).is_err());
+    assert!(Sphere::new(Point::zero(), -1.0, 0).is_err());
+    assert!(Plane::new(Point::zero(), Vec3::zero(), 0).is_err());
+    let collinear = [Point::zero(), Vec3::unity_x(), Vec3::unity_x() * 2.0];
+    assert!(Triangle::new(collinear, 0).is_err());
+    let ok = [Point::zero(), Vec3::unity_x(), Vec3::unity_y()];
+    assert!(Triangle::new(ok, 0).is_ok());
+}
+
+#[test]
+fn triangle_smooth_normals() {
+    let points = [Point::zero(), Vec3::unity_x(), Vec3::unity_y()];
+    let mut triangle = Triangle::new(points, 0).unwrap();
+    let flat = triangle.get_normal(Point::new(0.25, 0.25, 0.0), 0);
+    assert!(flat == Vec3::unity_z());
+
+    let tilted = Vec3::new(1.0, 0.0, 1.0).normalize();
+    triangle.normals = Some([Vec3::unity_z(), tilted, Vec3::unity_z()]);
+    // at a vertex: that vertex normal
+    let n = triangle.get_normal(Vec3::unity_x(), 0);
+    assert!((n - tilted).norm() < 1e-5);
+    // halfway along an edge: the normalized average
+    let n = triangle.get_normal(Point::new(0.5, 0.0, 0.0), 0);
+    let expected = (Vec3::unity_z() + tilted).normalize();
+    assert!((n - expected).norm() < 1e-5);
+}
+
+#[test]
+fn quad_triangles() {
+    let points = [
+        Point::zero(),
+        Point::new(2.0, 0.0, 0.0),
+        Point::new(2.0, 1.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+    ];
+    let quad = Quad::new(points, 3).unwrap();
+    let [t0, t1] = quad.to_triangles();
+    assert!(t0.points[0] == points[0] && t1.points[2] == points[3]);
+    assert_eq!((t0.material_id, t1.material_id), (3, 3));
+
+    // one normal and planar uvs over the whole quad
+    let p = Point::new(0.5, 0.75, 0.0);
+    for t in [t0, t1] {
+        assert!(t.get_normal(p, 0) == Vec3::unity_z());
+    }
+    let uv = t1.get_texture_2d(p, 0);
+    assert!((uv.x - 0.25).abs() < 1e-6 && (uv.y - 0.75).abs() < 1e-6);
+
+    let mut bent = points;
+    bent[3].z = 0.5;
+    assert!(Quad::new(bent, 0).is_err());
+    let bowtie = [points[0], points[1], points[3], points[2]];
+    assert!(Quad::new(bowtie, 0).is_err());
+}
+
+#[test]
+fn quad_from_edges() {
+    let edge_u = Vec3::new(2.0, 0.0, 0.0);
+    let edge_v = Vec3::new(0.0, 1.0, 0.0);
+    let quad = Quad::from_edges(Point::new(1.0, 1.0, 0.0), edge_u, edge_v, 0).unwrap();
+    assert!(quad.points[2] == Point::new(3.0, 2.0, 0.0));
+    let [_, t1] = quad.to_triangles();
+    let uv = t1.get_texture_2d(Point::new(1.5, 1.75, 0.0), 0);
+    assert!((uv.x - 0.25).abs() < 1e-6 && (uv.y - 0.75).abs() < 1e-6);
+    assert!(Quad::from_edges(Point::zero(), edge_u, edge_u, 0).is_err());
+}
+
+#[test]
+fn disk_hits() {
+    let mut stats = RenderStats::default();
+    let disk = Disk::new(Point::zero(), Vec3::new(0.0, 0.0, 2.0), 1.0, 0).unwrap();
+    assert!(disk.normal == Vec3::unity_z());
+
+    let down = Vec3::unity_z() * -1.0;
+    let ray = Ray::new(Point::new(0.5, 0.5, 1.0), down);
+    let mut t = Float::MAX;
+    let mut oid = 0;
+    assert!(disk.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 1.0).abs() < 1e-6);
+    assert!(disk.get_normal(ray.orig + ray.dir * t, oid) == Vec3::unity_z());
+    // outside the radius, though on the plane
+    let ray = Ray::new(Point::new(0.8, 0.8, 1.0), down);
+    let mut t = Float::MAX;
+    assert!(!disk.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    // from below: lit from both sides
+    let ray = Ray::new(Point::new(0.0, 0.5, -1.0), Vec3::unity_z());
+    assert!(disk.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!(disk.get_normal(ray.orig + ray.dir * t, oid) == down);
+
+    let uv = disk.get_texture_2d(Point::new(0.0, 0.5, 0.0), oid);
+    assert!((uv.x - 0.5).abs() < 1e-6 && (0.0..=1.0).contains(&uv.y));
+    let pi = std::f64::consts::PI as Float;
+    assert!((disk.get_area() - pi).abs() < 1e-6);
+    let aabb = disk.get_aabb().unwrap();
+    assert!(aabb.p_max == Point::new(1.0, 1.0, 0.0));
+    let (p, n) = disk.sample_surface(0.25, 0.3).unwrap();
+    assert!((p.norm() - 0.5).abs() < 1e-6 && p.z.abs() < 1e-6 && n == Vec3::unity_z());
+    assert!(Disk::new(Point::zero(), Vec3::zero(), 1.0, 0).is_err());
+    assert!(Disk::new(Point::zero(), Vec3::unity_z(), 0.0, 0).is_err());
+}
+
+#[test]
+fn cylinder_side_and_caps() {
+    let mut stats = RenderStats::default();
+    let cylinder = Cylinder::new(Point::zero(), Vec3::unity_z(), 1.0, 2.0, 0).unwrap();
+    let mut oid = 0;
+
+    // side
+    let ray = Ray::new(Point::new(-5.0, 0.0, 1.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(cylinder.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 4.0).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t;
+    assert!((cylinder.get_normal(p, oid) - Vec3::unity_x() * -1.0).norm() < 1e-4);
+
+    // above the top: misses the side
+    let ray = Ray::new(Point::new(-5.0, 0.0, 2.5), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(!cylinder.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+
+    // top cap
+    let ray = Ray::new(Point::new(0.5, 0.0, 5.0), Vec3::unity_z() * -1.0);
+    let mut t = Float::MAX;
+    assert!(cylinder.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 3.0).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t;
+    assert!(cylinder.get_normal(p, oid) == Vec3::unity_z());
+
+    assert!(Cylinder::new(Point::zero(), Vec3::zero(), 1.0, 1.0, 0).is_err());
+    assert!(Cylinder::new(Point::zero(), Vec3::unity_z(), 1.0, 0.0, 0).is_err());
+}
+
+#[test]
+fn mesh_bvh_matches_brute_force() {
+    let mut stats = RenderStats::default();
+    let mut rnd_state = 42;
+    let triangles: Vec<Triangle> = (0..500)
+        .map(|_| {
+            let center = Vec3::gen_rnd_sphere(&mut rnd_state) * 2.0;
+            let p0 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            let p1 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            let p2 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            Triangle::new([p0, p1, p2], 0)
+        })
+        .filter_map(Result::ok)
+        .collect();
+    let mesh = Mesh::new(triangles.clone(), 0);
+
+    // rays from outside and from inside the mesh bounds
+    for orig_scale in [5.0, 0.5] {
+        for _ in 0..200 {
+            let orig = Vec3::gen_rnd_sphere(&mut rnd_state) * orig_scale;
+            let dir = Vec3::gen_rnd_sphere(&mut rnd_state);
+            let ray = Ray::new(orig, dir);
+
+            let mut t_brute = Float::MAX;
+            let mut oid = 0;
+            let hit_brute = triangles
+                .iter()
+                .filter(|t| t.intercept(&mut stats, &ray, EPSILON, &mut t_brute, false, &mut oid))
+                .count()
+                > 0;
+
+            let mut t = Float::MAX;
+            let hit = mesh.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid);
+            assert_eq!(hit, hit_brute);
+            if hit {
+                assert!((t - t_brute).abs() < 1e-5);
+                let mut t_any = Float::MAX;
+                assert!(mesh.intercept(&mut stats, &ray, EPSILON, &mut t_any, true, &mut oid));
+            }
+        }
+    }
+}
+
+#[test]
+fn mesh_flat_floor() {
+    let mut stats = RenderStats::default();
+    // a planar grid of quads: zero extent along z
+    let n = 20;
+    let mut triangles = vec![];
+    for i in 0..n {
+        for j in 0..n {
+            let p = |di: i32, dj: i32| Point::new((i + di) as Float, (j + dj) as Float, 0.0);
+            triangles.push(Triangle::new([p(0, 0), p(1, 0), p(1, 1)], 0).unwrap());
+            triangles.push(Triangle::new([p(0, 0), p(1, 1), p(0, 1)], 0).unwrap());
+        }
+    }
+    let mesh = Mesh::new(triangles, 0);
+
+    let mut oid = 0;
+    for k in 0..n {
+        let x = k as Float + 0.3;
+        let ray = Ray::new(Point::new(x, 7.6, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut t = Float::MAX;
+        assert!(mesh.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+        assert!((t - 5.0).abs() < 1e-5);
+    }
+    // rays in the plane of the floor or away from it miss
+    let ray = Ray::new(Point::new(-1.0, 3.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let mut t = Float::MAX;
+    assert!(!mesh.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    let ray = Ray::new(Point::new(3.5, 3.5, 1.0), Vec3::unity_z());
+    assert!(!mesh.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+}
+
+#[test]
+fn mesh_simd_matches_scalar() {
+    let mut stats = RenderStats::default();
+    let mut rnd_state = 7;
+    let mut triangles: Vec<Triangle> = (0..500)
+        .map(|_| {
+            let center = Vec3::gen_rnd_sphere(&mut rnd_state) * 2.0;
+            let p0 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            let p1 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            let p2 = center + Vec3::gen_rnd_sphere(&mut rnd_state) * 0.2;
+            Triangle::new([p0, p1, p2], 0)
+        })
+        .filter_map(Result::ok)
+        .collect();
+    // triangles sharing a centroid end up in a leaf wider than a register
+    for i in 0..12 {
+        let s = 0.1 + i as Float * 0.05;
+        let p = [
+            Point::new(-s, -s, 0.0),
+            Point::new(2.0 * s, -s, 0.0),
+            Point::new(-s, 2.0 * s, 0.0),
+        ];
+        triangles.push(Triangle::new(p, 0).unwrap());
+    }
+    let mut mesh = Mesh::new(triangles, 0);
+    let level = SimdLevel::detect();
+    assert_eq!(mesh.bvh.get_simd_level(), level);
+    assert!(level.is_available());
+
+    for orig_scale in [5.0, 0.5] {
+        for _ in 0..500 {
+            let orig = Vec3::gen_rnd_sphere(&mut rnd_state) * orig_scale;
+            let dir = Vec3::gen_rnd_sphere(&mut rnd_state);
+            let ray = Ray::new(orig, dir);
+            for any in [false, true] {
+                mesh.bvh.set_simd_level(SimdLevel::Scalar);
+                let (mut t_ref, mut oid_ref) = (Float::MAX, 0);
+                let hit_ref =
+                    mesh.intercept(&mut stats, &ray, EPSILON, &mut t_ref, any, &mut oid_ref);
+
+                mesh.bvh.set_simd_level(level);
+                let (mut t, mut oid) = (Float::MAX, 0);
+                let hit = mesh.intercept(&mut stats, &ray, EPSILON, &mut t, any, &mut oid);
+                assert_eq!(hit, hit_ref);
+                assert_eq!(t.to_bits(), t_ref.to_bits());
+                assert_eq!(oid, oid_ref);
+            }
+        }
+    }
+}
+
+#[test]
+fn sphere_inside_hit() {
+    let mut stats = RenderStats::default();
+    let sphere = Sphere::new(Point::zero(), 2.0, 0).unwrap();
+    let mut oid = 0;
+
+    // from outside: near root, outward normal
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 3.0).abs() < 1e-5);
+    assert!(!sphere.is_inside_hit(oid));
+    let p = ray.orig + ray.dir * t;
+    assert!(sphere.get_normal(p, oid).dot(ray.dir) < 0.0);
+
+    // from inside: far root, the normal still opposes the ray
+    let ray = Ray::new(Point::new(0.5, 0.0, 0.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(sphere.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 1.5).abs() < 1e-5);
+    assert!(sphere.is_inside_hit(oid));
+    let p = ray.orig + ray.dir * t;
+    assert!((sphere.get_normal(p, oid) - Vec3::unity_x() * -1.0).norm() < 1e-5);
+}
+
+#[test]
+fn torus_hole_and_normals() {
+    let mut stats = RenderStats::default();
+    let torus = Torus::new(Point::zero(), Vec3::unity_z(), 1.0, 0.25, 0).unwrap();
+    let mut oid = 0;
+
+    // across the ring: the outer side first
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 3.75).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t;
+    assert!((torus.get_normal(p, oid) - Vec3::unity_x() * -1.0).norm() < 1e-4);
+    assert!(!torus.is_inside_hit(oid));
+
+    // from the hole: the inner side, facing the axis
+    let ray = Ray::new(Point::zero(), Vec3::unity_x());
+    let mut t = Float::MAX;
+    assert!(torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 0.75).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t;
+    assert!((torus.get_normal(p, oid) - Vec3::unity_x() * -1.0).norm() < 1e-4);
+
+    // through the hole along the axis, and above the ring
+    let ray = Ray::new(Point::new(0.0, 0.0, 5.0), Vec3::unity_z() * -1.0);
+    let mut t = Float::MAX;
+    assert!(!torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.3), Vec3::unity_x());
+    assert!(!torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+
+    // down onto the top of the tube, then out of it from within
+    let ray = Ray::new(Point::new(0.0, 1.0, 5.0), Vec3::unity_z() * -1.0);
+    let mut t = Float::MAX;
+    assert!(torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 4.75).abs() < 1e-4);
+    let p = ray.orig + ray.dir * t;
+    assert!((torus.get_normal(p, oid) - Vec3::unity_z()).norm() < 1e-4);
+    let ray = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::unity_y());
+    let mut t = Float::MAX;
+    assert!(torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    assert!((t - 0.25).abs() < 1e-4);
+    assert!(torus.is_inside_hit(oid));
+    let p = ray.orig + ray.dir * t;
+    assert!((torus.get_normal(p, oid) - Vec3::unity_y() * -1.0).norm() < 1e-4);
+
+    // tmax before the hit
+    let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::unity_x());
+    let mut t = 3.0;
+    assert!(!torus.intercept(&mut stats, &ray, EPSILON, &mut t, false, &mut oid));
+    if cfg!(feature = "stats") {
+        assert_eq!(stats.num_intersects_torus, 7);
+    }
+
+    let aabb = torus.get_aabb().unwrap();
+    assert!((aabb.p_max - Point::new(1.25, 1.25, 0.25)).norm() < 1e-5);
+
+    assert!(Torus::new(Point::zero(), Vec3::zero(), 1.0, 0.25, 0).is_err());
+    assert!(Torus::new(Point::zero(), Vec3::unity_z(), 1.0, 0.0, 0).is_err());
+    assert!(Torus::new(Point::zero(), Vec3::unity_z(), 1.0, 1.5, 0).is_err());
+}
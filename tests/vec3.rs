@@ -0,0 +1,129 @@
+use rayflex::three_d::Transform;
+use rayflex::vec3::Matrix4;
+use rayflex::vec3::Vec3;
+
+#[test]
+fn vec3_lerp() {
+    let a = Vec3::new(0.0, 2.0, -1.0);
+    let b = Vec3::new(4.0, 2.0, 1.0);
+    assert!(Vec3::lerp(a, b, 0.0) == a);
+    assert!(Vec3::lerp(a, b, 1.0) == b);
+    assert!(Vec3::lerp(a, b, 0.5) == Vec3::new(2.0, 2.0, 0.0));
+    assert!(a.lerp(b, 0.25) == Vec3::new(1.0, 2.0, -0.5));
+}
+
+#[test]
+fn vec3_neg() {
+    let v = Vec3::new(1.0, -2.0, 0.5);
+    assert!(-v == Vec3::new(-1.0, 2.0, -0.5));
+    assert!(-v == v * -1.0);
+    assert!(-(-v) == v);
+    assert!(-Vec3::zero() == Vec3::zero());
+}
+
+#[test]
+fn vec3_scalar_mul_left() {
+    let v = Vec3::new(1.0, -2.0, 0.5);
+    assert!(2.0 * v == Vec3::new(2.0, -4.0, 1.0));
+    assert!(2.0 * v == v * 2.0);
+    assert!(0.0 * v == Vec3::zero());
+}
+
+#[test]
+fn vec3_length_and_distance() {
+    let v = Vec3::new(3.0, 4.0, 12.0);
+    assert_eq!(v.length_squared(), 169.0);
+    assert_eq!(v.norm(), 13.0);
+    assert_eq!(Vec3::zero().length_squared(), 0.0);
+    let a = Vec3::new(1.0, 1.0, 1.0);
+    assert_eq!(a.distance(a + v), 13.0);
+    assert_eq!((a + v).distance(a), 13.0);
+    assert_eq!(a.distance(a), 0.0);
+}
+
+#[test]
+fn vec3_slerp() {
+    let close = |a: Vec3, b: Vec3| (a - b).norm() < 1e-5;
+    let x = Vec3::unity_x();
+    let y = Vec3::unity_y();
+    assert!(close(Vec3::slerp(x, y, 0.0), x));
+    assert!(close(Vec3::slerp(x, y, 1.0), y));
+    let h = 0.5_f32.sqrt();
+    assert!(close(Vec3::slerp(x, y, 0.5), Vec3::new(h, h, 0.0)));
+    // constant angular speed, unlike a normalized lerp
+    let q = Vec3::slerp(x, y, 0.25);
+    let a = std::f32::consts::FRAC_PI_8;
+    assert!(close(q, Vec3::new(a.cos(), a.sin(), 0.0)));
+    // inputs need not be unit vectors
+    assert!(close(
+        Vec3::slerp(x * 3.0, y * 0.5, 0.5),
+        Vec3::new(h, h, 0.0)
+    ));
+
+    // identical and opposite directions
+    assert!(close(Vec3::slerp(y, y, 0.3), y));
+    let m = Vec3::slerp(x, x * -1.0, 0.5);
+    assert!((m.norm() - 1.0).abs() < 1e-5);
+    assert!(m.dot(x).abs() < 1e-5);
+    assert!(close(Vec3::slerp(x, x * -1.0, 1.0), x * -1.0));
+}
+
+#[test]
+fn matrix4_transform() {
+    let near = |a: Vec3, b: Vec3| a.distance(b) < 1e-5;
+    let t = Transform {
+        translate: Vec3::new(1.0, 2.0, 3.0),
+        rotate_xyz: Vec3::new(0.0, 0.0, 90.0),
+        scale: Vec3::new(2.0, 1.0, 1.0),
+    };
+    let m = t.to_matrix();
+    // scaled to (2, 0, 0), rotated to (0, -2, 0), then translated
+    assert!(near(
+        m.transform_point(Vec3::unity_x()),
+        Vec3::new(1.0, 0.0, 3.0)
+    ));
+    assert!(near(
+        m.transform_vector(Vec3::unity_x()),
+        Vec3::new(0.0, -2.0, 0.0)
+    ));
+    let angles = Vec3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2);
+    let (s, r) = (Matrix4::scaling(t.scale), Matrix4::rotation_xyz(angles));
+    let composed = Matrix4::translation(t.translate) * r * s;
+    assert!(near(
+        composed.transform_point(Vec3::unity_x()),
+        Vec3::new(1.0, 0.0, 3.0)
+    ));
+    // the other order scales y after the rotation
+    let rotated_first = Matrix4::translation(t.translate) * s * r;
+    assert!(near(
+        rotated_first.transform_point(Vec3::unity_x()),
+        Vec3::new(1.0, 1.0, 3.0)
+    ));
+    // same convention as the mesh rotations
+    let v = Vec3::new(0.3, -1.0, 2.0);
+    let angles = Vec3::new(0.4, -1.1, 2.5);
+    let rotate = |v: Vec3| v.rotx(angles.x).roty(angles.y).rotz(angles.z);
+    assert!(near(
+        Matrix4::rotation_xyz(angles).transform_vector(v),
+        rotate(v)
+    ));
+    assert!(Matrix4::identity() * m == m);
+    // glTF stores the translation in the last column
+    let cols = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [1.0, 2.0, 3.0, 1.0],
+    ];
+    assert!(Matrix4::from_cols_array(cols) == Matrix4::translation(Vec3::new(1.0, 2.0, 3.0)));
+    // normals stay perpendicular to the scaled surface
+    let m = Matrix4::scaling(Vec3::new(1.0, 4.0, 1.0));
+    let edge = m.transform_vector(Vec3::new(1.0, -1.0, 0.0));
+    let normal = m.transform_normal(Vec3::new(1.0, 1.0, 0.0));
+    assert!(edge.dot(normal).abs() < 1e-5 && (normal.norm() - 1.0).abs() < 1e-5);
+    let mirror = Matrix4::scaling(Vec3::new(-1.0, 1.0, 1.0));
+    assert!(near(
+        mirror.transform_normal(Vec3::unity_x()),
+        Vec3::new(-1.0, 0.0, 0.0)
+    ));
+}